@@ -1,6 +1,6 @@
 {
   "variables": {
-    "triplet%": "<!(node -e \"const os = require('os'); const arch = os.arch(); const platform = os.platform(); let triplet = 'x64-linux'; if (platform === 'win32') { triplet = 'x64-windows-static'; } else if (platform === 'darwin') { triplet = arch === 'arm64' ? 'arm64-osx' : 'x64-osx'; } else if (platform === 'linux') { triplet = 'x64-linux'; } console.log(triplet);\")"
+    "triplet%": "<!(node -e \"const os = require('os'); const arch = os.arch(); const platform = os.platform(); const prefix = arch === 'arm64' ? 'arm64' : (arch === 'ia32' ? 'x86' : 'x64'); let triplet = prefix + '-linux'; if (platform === 'win32') { triplet = prefix + '-windows-static'; } else if (platform === 'darwin') { triplet = arch === 'arm64' ? 'arm64-osx' : 'x64-osx'; } console.log(triplet);\")"
   },
   "targets": [
     {
@@ -8,24 +8,25 @@
       "sources": [
         "./addon_src/binding.c",
         "./addon_src/ffmpeg.c",
-        "./ffmpeg/fftools/cmdutils.c",
-        "./ffmpeg/fftools/ffmpeg_dec.c",
-        "./ffmpeg/fftools/ffmpeg_demux.c",
-        "./ffmpeg/fftools/ffmpeg_enc.c",
-        "./ffmpeg/fftools/ffmpeg_filter.c",
-        "./ffmpeg/fftools/ffmpeg_hw.c",
-        "./ffmpeg/fftools/ffmpeg_mux_init.c",
-        "./ffmpeg/fftools/ffmpeg_mux.c",
-        "./ffmpeg/fftools/ffmpeg_opt.c",
-        "./ffmpeg/fftools/ffmpeg_sched.c",
-        "./ffmpeg/fftools/opt_common.c",
-        "./ffmpeg/fftools/sync_queue.c",
-        "./ffmpeg/fftools/thread_queue.c",
-        "./ffmpeg/fftools/objpool.c"
+        "./addon_src/cmdutils.c",
+        "./addon_src/ffmpeg_dec.c",
+        "./addon_src/ffmpeg_demux.c",
+        "./addon_src/ffmpeg_enc.c",
+        "./addon_src/ffmpeg_filter.c",
+        "./addon_src/ffmpeg_hw.c",
+        "./addon_src/ffmpeg_mux_init.c",
+        "./addon_src/ffmpeg_mux.c",
+        "./addon_src/ffmpeg_opt.c",
+        "./addon_src/ffmpeg_sched.c",
+        "./addon_src/opt_common.c",
+        "./addon_src/sync_queue.c",
+        "./addon_src/thread_queue.c",
+        "./addon_src/objpool.c"
       ],
       "include_dirs": [
         "<!@(node -p \"require('node-addon-api').include\")",
         "<!@(node -p \"require('path').dirname(process.execPath) + '/include/node'\")",
+        "./addon_src",
         "./ffmpeg",
         "./ffmpeg/fftools",
         "<(module_root_dir)/vcpkg/installed/<(triplet)/include"
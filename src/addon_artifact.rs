@@ -0,0 +1,241 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Component, Path, PathBuf};
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use tar::{Archive, Builder, Entries};
+
+use crate::addon_preparer::{AddonPreparer, BUILD_FILES, FFMPEG_PKG_CONFIG_PACKAGES};
+use crate::error::VcpkgFfError;
+use crate::ffmpeg_version::FfmpegVersion;
+use crate::fs_retry;
+use crate::marker;
+use crate::progress::say;
+use crate::tarball::{self, append_data};
+
+/// Layout version of the artifact, unpacking refuses other ones
+const ARTIFACT_FORMAT: u32 = 1;
+
+/// First entry of the artifact
+const MANIFEST_NAME: &str = "vcpkg_ff-artifact.json";
+
+/// Directory below the base directory the artifact is unpacked to before it replaces addon_src
+const STAGING_NAME: &str = ".vcpkg_ff-artifact";
+
+/// Missing headers and libraries listed before the rest is summed up
+const LISTED_MISSING: usize = 5;
+
+/// What an addon_src artifact was prepared from and needs from vcpkg's installed tree on the machine unpacking it
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct ArtifactManifest {
+    pub format: u32,
+    pub tool_version: String,
+    pub triplet: String,
+    pub ffmpeg_version: String,
+    /// Hash of the inputs of the preparation, the same on every machine with the same ffmpeg sources, vcpkg build,
+    /// patches, templates, vcpkg_ff.toml and options
+    pub inputs_hash: String,
+    /// Build files in the artifact besides addon_src/
+    pub build_files: Vec<String>,
+    /// Header directories below installed/<triplet> the addon compiles against
+    pub headers: Vec<String>,
+    /// Libraries below installed/<triplet> the addon links
+    pub libs: Vec<String>,
+}
+
+/// Packs a prepared addon_src with its build files into a tarball another machine unpacks instead of running the
+/// prepare-addon step (`pack-addon` and `unpack-addon`)
+///
+/// The artifact only holds what preparation generates. The machine unpacking it still needs the ffmpeg sources and
+/// the vcpkg libraries to build the addon, its manifest lists the headers and libraries the build uses.
+pub struct AddonArtifact<'a> {
+    preparer: &'a AddonPreparer,
+}
+
+impl<'a> AddonArtifact<'a> {
+    pub fn new(preparer: &'a AddonPreparer) -> Self {
+        Self { preparer }
+    }
+
+    /// Write addon_src and the build files to `output`, by default
+    /// dist/addon_src-<ffmpeg version>-<triplet>-<inputs hash>.tar.gz. Returns the tarball
+    pub fn pack(&self, output: Option<&Path>) -> Result<PathBuf, VcpkgFfError> {
+        let Some(inputs_hash) = self.preparer.prepared_inputs_hash() else {
+            return Err(VcpkgFfError::NotPrepared { path: self.preparer.get_addon_src_dir().to_path_buf(), step: "prepare-addon" });
+        };
+        let base_dir = self.preparer.get_base_dir();
+        let ffmpeg_version = FfmpegVersion::detect(self.preparer.get_ffmpeg_source_dir())?.to_string();
+        let installed = self.preparer.get_installed_dir();
+        let manifest = ArtifactManifest {
+            format: ARTIFACT_FORMAT,
+            tool_version: marker::TOOL_VERSION.to_string(),
+            triplet: self.preparer.get_triplet().to_string(),
+            ffmpeg_version,
+            inputs_hash,
+            build_files: BUILD_FILES.iter().filter(|name| base_dir.join(name).is_file()).map(|name| name.to_string()).collect(),
+            headers: FFMPEG_PKG_CONFIG_PACKAGES.iter()
+                .map(|package| format!("include/{}", package))
+                .filter(|header_dir| installed.join(header_dir).is_dir())
+                .collect(),
+            libs: ["lib", "debug/lib"].iter().flat_map(|lib_dir| library_files(&installed, lib_dir)).collect(),
+        };
+
+        let path = output.map(Path::to_path_buf).unwrap_or_else(|| base_dir.join("dist").join(format!(
+            "addon_src-{}-{}-{}.tar.gz", manifest.ffmpeg_version, manifest.triplet, &manifest.inputs_hash[..12])));
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut builder = Builder::new(GzEncoder::new(File::create(&path)?, Compression::default()));
+        append_data(&mut builder, MANIFEST_NAME, &serde_json::to_vec_pretty(&manifest)?)?;
+        builder.append_dir_all("addon_src", self.preparer.get_addon_src_dir())?;
+        for build_file in &manifest.build_files {
+            builder.append_path_with_name(base_dir.join(build_file), build_file)?;
+        }
+        builder.into_inner()?.finish()?;
+
+        say!("✓ addon_src for ffmpeg {} ({}) packed into {}", manifest.ffmpeg_version, manifest.triplet, path.display());
+        Ok(path)
+    }
+
+    /// Replace addon_src and the build files with those of the artifact at `archive`. When the artifact was prepared
+    /// from the same inputs as this project has, the preparation is recorded as done and true is returned; otherwise
+    /// the next run prepares addon_src again
+    ///
+    /// The artifact is unpacked into a staging directory first, so a damaged artifact leaves addon_src as it was.
+    pub fn unpack(&self, archive: &Path) -> Result<bool, VcpkgFfError> {
+        let invalid = |message: String| VcpkgFfError::InvalidFile { path: archive.to_path_buf(), message };
+        let mut tarball = Archive::new(GzDecoder::new(BufReader::new(File::open(archive)?)));
+        let mut entries = tarball.entries()?;
+        let manifest: ArtifactManifest = tarball::read_manifest(&mut entries, archive, MANIFEST_NAME, "an addon_src artifact")?;
+        if manifest.format != ARTIFACT_FORMAT {
+            return Err(invalid(format!("artifact format {}, vcpkg_ff {} unpacks format {}",
+                manifest.format, marker::TOOL_VERSION, ARTIFACT_FORMAT)));
+        }
+        if manifest.triplet != self.preparer.get_triplet() {
+            return Err(invalid(format!("prepared for {}, this project builds for {}",
+                manifest.triplet, self.preparer.get_triplet())));
+        }
+
+        let installed = self.preparer.get_installed_dir();
+        let missing: Vec<&String> = manifest.headers.iter().chain(&manifest.libs).filter(|path| !installed.join(path).exists()).collect();
+        if !missing.is_empty() {
+            let listed: Vec<&str> = missing.iter().take(LISTED_MISSING).map(|path| path.as_str()).collect();
+            say!("⚠ {} of the headers and libraries the addon builds with are not installed in {}: {}{}",
+                missing.len(), installed.display(), listed.join(", "),
+                if missing.len() > LISTED_MISSING { ", ..." } else { "" });
+            say!("  run the install-packages step before building the addon");
+        }
+
+        let base_dir = self.preparer.get_base_dir();
+        let staging = base_dir.join(STAGING_NAME);
+        if staging.exists() {
+            fs_retry::remove_dir_all(&staging)?;
+        }
+        let staged = unpack_entries(entries, archive, &manifest, &staging).inspect_err(|_| {
+            let _ = fs_retry::remove_dir_all(&staging);
+        })?;
+
+        // 替换失败时把已经移动的文件移回来，addon_src 不会只剩一半
+        let mut moved = Vec::new();
+        if let Err(e) = self.swap_in(&staging, &staged, &mut moved) {
+            for (from, to) in moved.iter().rev() {
+                let _ = fs_retry::rename(to, from);
+            }
+            let _ = fs_retry::remove_dir_all(&staging);
+            return Err(e);
+        }
+        fs_retry::remove_dir_all(&staging)?;
+        say!("✓ addon_src for ffmpeg {} ({}) unpacked from {}", manifest.ffmpeg_version, manifest.triplet, archive.display());
+
+        if self.preparer.prepare_inputs_hash() != manifest.inputs_hash {
+            say!("⚠ The artifact was prepared from other inputs than this project has (ffmpeg sources, vcpkg build, patches, \
+                templates, vcpkg_ff.toml, options or vcpkg_ff {}), the next run prepares addon_src again", manifest.tool_version);
+            return Ok(false);
+        }
+        self.preparer.record_prepared(&manifest.inputs_hash)?;
+        say!("✓ Inputs match, the prepare-addon step is skipped");
+        Ok(true)
+    }
+
+    /// Move the `staged` paths (addon_src and build files, relative to `staging`) to addon_src and the base
+    /// directory, the files they replace to staging/previous, recording each move in `moved`
+    fn swap_in(&self, staging: &Path, staged: &[PathBuf], moved: &mut Vec<(PathBuf, PathBuf)>) -> Result<(), VcpkgFfError> {
+        let previous = staging.join("previous");
+        fs::create_dir_all(&previous)?;
+        for relative in staged {
+            let target = if relative == Path::new("addon_src") {
+                self.preparer.get_addon_src_dir().to_path_buf()
+            } else {
+                self.preparer.get_base_dir().join(relative)
+            };
+            if fs::symlink_metadata(&target).is_ok() {
+                let backup = previous.join(relative);
+                fs_retry::rename(&target, &backup)?;
+                moved.push((target.clone(), backup));
+            } else if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            let from = staging.join(relative);
+            fs_retry::rename(&from, &target)?;
+            moved.push((from, target));
+        }
+        Ok(())
+    }
+}
+
+/// Unpack the entries of `archive` after the manifest into `staging`. Returns what is to be moved out of it:
+/// addon_src and the build files the artifact has
+fn unpack_entries<R: Read>(entries: Entries<R>, archive: &Path, manifest: &ArtifactManifest, staging: &Path)
+    -> Result<Vec<PathBuf>, VcpkgFfError> {
+    fs::create_dir_all(staging.join("addon_src"))?;
+    let mut staged = vec![PathBuf::from("addon_src")];
+    for entry in entries {
+        let mut entry = entry?;
+        let path = entry.path()?.into_owned();
+        let relative = artifact_path(&path, manifest).ok_or_else(|| VcpkgFfError::InvalidFile {
+            path: archive.to_path_buf(),
+            message: format!("unexpected entry {}", path.display()),
+        })?;
+        let target = staging.join(&relative);
+        if entry.header().entry_type().is_dir() {
+            fs::create_dir_all(&target)?;
+            continue;
+        }
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        entry.unpack(&target)?;
+        if !relative.starts_with("addon_src") {
+            staged.push(relative);
+        }
+    }
+    Ok(staged)
+}
+
+/// `path` of an artifact entry, when it is below addon_src/ or one of the manifest's build files
+fn artifact_path(path: &Path, manifest: &ArtifactManifest) -> Option<PathBuf> {
+    let mut relative = PathBuf::new();
+    for component in path.components() {
+        match component {
+            Component::Normal(name) => relative.push(name),
+            Component::CurDir => {}
+            _ => return None,
+        }
+    }
+    let below_addon_src = relative.starts_with("addon_src");
+    let build_file = manifest.build_files.iter().any(|name| relative == Path::new(name));
+    (below_addon_src || build_file).then_some(relative)
+}
+
+/// Files of `lib_dir` below `installed`, as paths relative to `installed`
+fn library_files(installed: &Path, lib_dir: &str) -> Vec<String> {
+    let mut names: Vec<String> = fs::read_dir(installed.join(lib_dir)).into_iter().flatten().flatten()
+        .filter(|entry| entry.file_type().is_ok_and(|file_type| file_type.is_file()))
+        .map(|entry| format!("{}/{}", lib_dir, entry.file_name().to_string_lossy()))
+        .collect();
+    names.sort();
+    names
+}
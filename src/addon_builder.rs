@@ -0,0 +1,568 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::command_runner::{CommandOutput, CommandRunner, CommandSpec, SystemRunner};
+use crate::error::VcpkgFfError;
+use crate::progress::{say, say_error};
+
+/// Lines of captured stderr quoted in the error when a command fails
+const ERROR_TAIL_LINES: usize = 20;
+
+/// Containers `verify --full` transcodes the test clip into when none are given
+pub const VERIFY_CONTAINERS: &[&str] = &["mp4", "webm", "mkv"];
+
+/// How `verify --full` encodes the test clip into a container and what ffprobe has to report for the result
+struct MediaCase {
+    container: &'static str,
+    /// Part of ffprobe's format_name, e.g. "mov,mp4,m4a,3gp,3g2,mj2" for mp4
+    format: &'static str,
+    /// Encoder and the codec_name ffprobe reports for its stream
+    video: (&'static str, &'static str),
+    audio: Option<(&'static str, &'static str)>,
+}
+
+const MEDIA_CASES: &[MediaCase] = &[
+    MediaCase { container: "mp4", format: "mp4", video: ("libx264", "h264"), audio: Some(("aac", "aac")) },
+    // webm 只允许 Vorbis/Opus 音频，默认的 feature 里没有对应的编码器
+    MediaCase { container: "webm", format: "webm", video: ("libvpx", "vp8"), audio: None },
+    MediaCase { container: "mkv", format: "matroska", video: ("libx265", "hevc"), audio: Some(("aac", "aac")) },
+];
+
+/// JavaScript runtime the smoke test loads the built addon in (`--smoke-runtime node|bun`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Default)]
+pub enum SmokeRuntime {
+    #[default]
+    Node,
+    /// Bun's N-API implementation, for addons generated with `--bun`
+    Bun,
+}
+
+impl SmokeRuntime {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "node" => Ok(SmokeRuntime::Node),
+            "bun" => Ok(SmokeRuntime::Bun),
+            other => Err(format!("invalid --smoke-runtime `{}`, expected node or bun", other)),
+        }
+    }
+
+    pub fn program(&self) -> &'static str {
+        match self {
+            SmokeRuntime::Node => "node",
+            SmokeRuntime::Bun => "bun",
+        }
+    }
+}
+
+/// Runs npm in addon_src to install dependencies and compile the addon (`--build-addon`)
+pub struct AddonBuilder {
+    addon_src_dir: PathBuf,
+    log_dir: PathBuf,
+    runtime: SmokeRuntime,
+}
+
+impl AddonBuilder {
+    pub fn new(addon_src_dir: &Path, log_dir: &Path) -> Self {
+        Self {
+            addon_src_dir: addon_src_dir.to_path_buf(),
+            log_dir: log_dir.to_path_buf(),
+            runtime: SmokeRuntime::Node,
+        }
+    }
+
+    /// Run the smoke test in `runtime` instead of Node.js
+    pub fn with_runtime(mut self, runtime: SmokeRuntime) -> Self {
+        self.runtime = runtime;
+        self
+    }
+
+    /// `npm install` (dependencies only, retried for network failures), then `npm run rebuild`
+    pub fn build(&self) -> Result<(), VcpkgFfError> {
+        let package_json = self.addon_src_dir.join("package.json");
+        if !package_json.exists() {
+            return Err(VcpkgFfError::NotPrepared { path: package_json, step: "prepare-addon" });
+        }
+        fs::create_dir_all(&self.log_dir)?;
+
+        say!("Building Node.js addon in: {}", self.addon_src_dir.display());
+        self.install_dependencies()?;
+        self.run_npm_with_retry(&["run", "rebuild"], "npm-rebuild", 1).map_err(|mut e| {
+            let log = fs::read_to_string(self.log_dir.join("npm-rebuild.log")).unwrap_or_default();
+            if let (VcpkgFfError::NpmFailed { message, .. }, Some(cause)) = (&mut e, diagnose_build_failure(&log)) {
+                message.push_str(&format!("\n⚠ likely cause: {}", cause));
+            }
+            e
+        })?;
+
+        say!("✓ Addon built successfully");
+        Ok(())
+    }
+
+    /// `deno task build` in addon_src, compiling the shared library of the Deno target with CMake
+    pub fn build_deno(&self) -> Result<(), VcpkgFfError> {
+        let deno_json = self.addon_src_dir.join("deno.json");
+        if !deno_json.exists() {
+            return Err(VcpkgFfError::NotPrepared { path: deno_json, step: "prepare-addon" });
+        }
+        fs::create_dir_all(&self.log_dir)?;
+
+        say!("Building Deno FFI library in: {}", self.addon_src_dir.display());
+        let log_path = self.log_dir.join("deno-build.log");
+        self.run_captured("deno", &["task", "build"], &log_path)
+            .map_err(|e| command_failed("deno task build", &log_path, e))?;
+
+        say!("✓ Library built successfully");
+        Ok(())
+    }
+
+    /// Configure and build the Emscripten project of the wasm target into build-wasm/ next to addon_src
+    pub fn build_wasm(&self) -> Result<(), VcpkgFfError> {
+        let exported_functions = self.addon_src_dir.join("exported_functions.json");
+        if !exported_functions.exists() {
+            return Err(VcpkgFfError::NotPrepared { path: exported_functions, step: "prepare-addon" });
+        }
+        if env::var_os("EMSDK").is_none() {
+            return Err(VcpkgFfError::ToolNotFound { tool: "emsdk", hint: "activate emsdk (emsdk_env) before building the wasm target" });
+        }
+        fs::create_dir_all(&self.log_dir)?;
+
+        say!("Building WebAssembly module in: {}", self.addon_src_dir.display());
+        let configure_log = self.log_dir.join("wasm-configure.log");
+        self.run_captured("cmake", &["-S", "..", "-B", "../build-wasm", "-DCMAKE_BUILD_TYPE=Release"], &configure_log)
+            .map_err(|e| command_failed("cmake configure", &configure_log, e))?;
+        let build_log = self.log_dir.join("wasm-build.log");
+        self.run_captured("cmake", &["--build", "../build-wasm"], &build_log)
+            .map_err(|e| command_failed("cmake build", &build_log, e))?;
+
+        say!("✓ WebAssembly module built successfully");
+        Ok(())
+    }
+
+    /// `npm install` without the package's install script (retried for network failures)
+    fn install_dependencies(&self) -> Result<(), VcpkgFfError> {
+        // 安装脚本会触发编译，这里先只装依赖，编译单独执行以便区分失败原因
+        self.run_npm_with_retry(&["install", "--ignore-scripts", "--no-audit", "--no-fund"], "npm-install", 3)
+    }
+
+    /// Run prebuildify once per target, each adding its binary under prebuilds/ in the project directory.
+    /// All targets link the same vcpkg-built ffmpeg; a failing target doesn't stop the others
+    pub fn prebuild_matrix(&self, targets: &[PrebuildTarget]) -> Result<PrebuildReport, VcpkgFfError> {
+        let command = self.prebuildify_command()?;
+        fs::create_dir_all(&self.log_dir)?;
+        self.install_dependencies()?;
+
+        let mut results = Vec::new();
+        for target in targets {
+            say!("Building prebuild for {}...", target);
+            let mut args: Vec<&str> = vec!["exec", "--"];
+            args.extend(command.iter().map(String::as_str));
+            let target_name = target.to_string();
+            args.extend(["--target", target_name.as_str()]);
+
+            let log_name = format!("prebuild-{}-{}", target.runtime, target.version);
+            let error = self.run_npm_with_retry(&args, &log_name, 1).err().map(|e| {
+                let log = fs::read_to_string(self.log_dir.join(format!("{}.log", log_name))).unwrap_or_default();
+                match diagnose_build_failure(&log) {
+                    Some(cause) => format!("{}\n⚠ likely cause: {}", e, cause),
+                    None => e.to_string(),
+                }
+            });
+            results.push((target.clone(), error));
+        }
+
+        // prebuildify --cwd .. 写到项目目录的 prebuilds/
+        let prebuilds_dir = self.addon_src_dir.parent().unwrap_or(&self.addon_src_dir).join("prebuilds");
+        Ok(PrebuildReport { results, prebuilds_dir })
+    }
+
+    /// The prebuildify command line of addon_src/package.json without its `--target`,
+    /// which matches the build system and flags the sources were prepared for
+    fn prebuildify_command(&self) -> Result<Vec<String>, VcpkgFfError> {
+        let package_json = self.addon_src_dir.join("package.json");
+        let content = fs::read_to_string(&package_json)
+            .map_err(|_| VcpkgFfError::NotPrepared { path: package_json.clone(), step: "prepare-addon" })?;
+        let package: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| VcpkgFfError::InvalidFile { path: package_json.clone(), message: e.to_string() })?;
+        let Some(script) = package["scripts"]["prebuildify"].as_str() else {
+            // 只有 napi-rs 绑定风格的 package.json 没有 prebuildify 脚本
+            return Err(VcpkgFfError::Unsupported { by: "prebuildify", kind: "binding style", name: "napi-rs".to_string() });
+        };
+
+        let mut command = Vec::new();
+        let mut tokens = script.split_whitespace();
+        while let Some(token) = tokens.next() {
+            if token == "--target" || token == "-t" {
+                tokens.next();
+            } else if !token.starts_with("--target=") {
+                command.push(token.to_string());
+            }
+        }
+        Ok(command)
+    }
+
+    /// Run npm with retries, echoing its output while capturing it to `<log_dir>/<log_name>.log`
+    fn run_npm_with_retry(&self, args: &[&str], log_name: &str, max_retries: u32) -> Result<(), VcpkgFfError> {
+        let log_path = self.log_dir.join(format!("{}.log", log_name));
+        let mut last_error = None;
+
+        for attempt in 1..=max_retries {
+            if attempt > 1 {
+                let wait_seconds = (attempt * 2) as u64; // 递增等待时间：2秒、4秒、6秒...
+                say!("等待 {} 秒后重试 (尝试 {}/{})...", wait_seconds, attempt, max_retries);
+                thread::sleep(Duration::from_secs(wait_seconds));
+            }
+
+            say!("Running npm {} (attempt {}/{})...", args.join(" "), attempt, max_retries);
+            match self.run_captured(npm_program(), args, &log_path) {
+                Ok(()) => {
+                    say!("✓ npm {} succeeded (log: {})", args.join(" "), log_path.display());
+                    return Ok(());
+                }
+                Err(e) => {
+                    say_error!("✗ npm {} failed: {}", args.join(" "), e);
+                    last_error = Some(e);
+                }
+            }
+        }
+
+        Err(VcpkgFfError::NpmFailed {
+            command: args.join(" "),
+            attempts: max_retries,
+            log_path,
+            message: last_error.unwrap_or_else(|| "unknown error".to_string()),
+        })
+    }
+
+    /// Run a command in addon_src, teeing stdout/stderr to the console and `log_path`.
+    /// Returns the tail of stderr as the error when the command fails.
+    fn run_captured(&self, program: &str, args: &[&str], log_path: &Path) -> Result<(), String> {
+        let command = CommandSpec::new(program)
+            .args(args)
+            .current_dir(&self.addon_src_dir)
+            .log(log_path)
+            .streamed();
+        let output = SystemRunner.run(&command).map_err(|e| format!("could not start {}: {}", program, e))?;
+        if output.success() {
+            return Ok(());
+        }
+
+        let lines: Vec<&str> = output.stderr.lines().collect();
+        let tail = lines[lines.len().saturating_sub(ERROR_TAIL_LINES)..].join("\n");
+        Err(format!("{}\n{}", output, tail))
+    }
+
+    /// Load the built addon through index.js and transcode a short lavfi test pattern with it
+    pub fn smoke_test(&self) -> Result<SmokeReport, VcpkgFfError> {
+        fs::create_dir_all(&self.log_dir)?;
+        say!("Running addon smoke test with {}...", self.runtime.program());
+
+        let load = self.run_script("const addon = require('.'); if (typeof addon.binding.run !== 'function') { throw new Error('binding has no run()'); }")?;
+        if !load.success() {
+            let detail = load.stderr;
+            return Ok(SmokeReport { loaded: false, transcoded: None, diagnosis: diagnose_load_failure(&detail), detail });
+        }
+
+        let output = self.log_dir.join("smoke.mp4");
+        let _ = fs::remove_file(&output);
+        let script = format!(
+            "require('.').run(['-v', 'error', '-f', 'lavfi', '-i', 'testsrc=duration=0.5:size=64x64:rate=10', \
+             '-c:v', 'libx264', '-pix_fmt', 'yuv420p', '-y', {}]).catch((err) => {{ console.error(err.message); process.exit(1); }})",
+            js_string(&output.to_string_lossy()));
+        let transcode = self.run_script(&script)?;
+        let transcoded = transcode.success() && fs::metadata(&output).map(|m| m.len() > 0).unwrap_or(false);
+        let detail = transcode.stderr;
+
+        Ok(SmokeReport {
+            loaded: true,
+            transcoded: Some(transcoded),
+            diagnosis: if transcoded { None } else { diagnose_load_failure(&detail) },
+            detail,
+        })
+    }
+
+    /// Transcode a lavfi test clip into each of `containers` with the built addon, then probe every result for its
+    /// container and codecs (`verify --full`). Each container runs in a process of its own
+    pub fn verify_media(&self, containers: &[String]) -> Result<MediaMatrix, VcpkgFfError> {
+        let cases: Vec<&MediaCase> = containers.iter()
+            .map(|container| MEDIA_CASES.iter().find(|case| case.container == container.as_str())
+                .ok_or_else(|| VcpkgFfError::Unknown {
+                    kind: "container",
+                    name: container.clone(),
+                    expected: VERIFY_CONTAINERS.iter().map(|name| name.to_string()).collect(),
+                }))
+            .collect::<Result<_, _>>()?;
+        let output_dir = self.log_dir.join("verify");
+        fs::create_dir_all(&output_dir)?;
+        say!("Transcoding a test clip into {} with {}...", containers.join(", "), self.runtime.program());
+
+        let mut results = Vec::new();
+        for case in cases {
+            let output = output_dir.join(format!("clip.{}", case.container));
+            let _ = fs::remove_file(&output);
+            let mut args = vec!["-v", "error", "-f", "lavfi", "-i", "testsrc=duration=1:size=320x240:rate=25"];
+            if case.audio.is_some() {
+                args.extend(["-f", "lavfi", "-i", "sine=frequency=440:duration=1"]);
+            }
+            args.extend(["-c:v", case.video.0, "-pix_fmt", "yuv420p"]);
+            match case.audio {
+                Some((encoder, _)) => args.extend(["-c:a", encoder, "-shortest"]),
+                None => args.push("-an"),
+            }
+            let output_arg = output.to_string_lossy();
+            args.extend(["-y", &output_arg]);
+            // 转码成功后先打印一行，区分转码失败和探测失败
+            let script = format!(
+                "const ffmpeg = require('.'); \
+                 ffmpeg.run({}).then(() => {{ console.log('transcoded'); return ffmpeg.probe(['-show_format', '-show_streams', {}]); }}) \
+                 .then((info) => console.log(JSON.stringify({{ format: info.format.format_name, codecs: info.streams.map((stream) => stream.codec_name) }}))) \
+                 .catch((err) => {{ console.error(err.message); process.exit(1); }})",
+                serde_json::to_string(&args)?, js_string(&output_arg));
+            let run = self.run_script(&script)?;
+
+            let transcoded = run.stdout.lines().any(|line| line == "transcoded") && fs::metadata(&output).is_ok_and(|m| m.len() > 0);
+            let probed: Option<serde_json::Value> = run.stdout.lines().last().and_then(|line| serde_json::from_str(line).ok());
+            let format = probed.as_ref().and_then(|probed| probed["format"].as_str()).map(str::to_string);
+            let codecs: Vec<String> = probed.as_ref().and_then(|probed| probed["codecs"].as_array())
+                .map(|codecs| codecs.iter().filter_map(|codec| codec.as_str()).map(str::to_string).collect())
+                .unwrap_or_default();
+            let expected: Vec<String> = std::iter::once(case.video.1).chain(case.audio.map(|(_, codec)| codec)).map(str::to_string).collect();
+            results.push(MediaResult {
+                container: case.container.to_string(),
+                container_ok: format.as_deref().is_some_and(|format| format.split(',').any(|name| name == case.format)),
+                codecs_ok: probed.is_some() && codecs == expected,
+                transcoded,
+                format,
+                codecs,
+                expected,
+                detail: run.stderr,
+            });
+        }
+        Ok(MediaMatrix { results })
+    }
+
+    /// Run `script` with `-e` in the smoke test's runtime, in addon_src
+    fn run_script(&self, script: &str) -> Result<CommandOutput, VcpkgFfError> {
+        let command = CommandSpec::new(self.runtime.program()).args(["-e", script]).current_dir(&self.addon_src_dir);
+        SystemRunner.run(&command).map_err(|e| VcpkgFfError::CommandFailed {
+            command: self.runtime.program().to_string(),
+            message: format!("could not be started: {}", e),
+        })
+    }
+}
+
+/// Outcome of loading the built addon and running a tiny transcode with it
+#[derive(Debug)]
+pub struct SmokeReport {
+    pub loaded: bool,
+    /// None when the load already failed
+    pub transcoded: Option<bool>,
+    /// Likely cause of a failure, from the Node.js error message
+    pub diagnosis: Option<&'static str>,
+    /// stderr of the failing check
+    pub detail: String,
+}
+
+impl SmokeReport {
+    pub fn passed(&self) -> bool {
+        self.loaded && self.transcoded == Some(true)
+    }
+
+    pub fn print(&self) {
+        say!("Smoke test:");
+        if self.loaded {
+            say!("  ✓ addon loads");
+        } else {
+            say!("  ✗ addon failed to load");
+        }
+        match self.transcoded {
+            Some(true) => say!("  ✓ test pattern transcode succeeded"),
+            Some(false) => say!("  ✗ test pattern transcode failed"),
+            None => {}
+        }
+        if let Some(diagnosis) = self.diagnosis {
+            say!("  ⚠ likely cause: {}", diagnosis);
+        }
+        if !self.passed() && !self.detail.is_empty() {
+            for line in self.detail.lines().take(ERROR_TAIL_LINES) {
+                say!("    {}", line);
+            }
+        }
+    }
+}
+
+/// What `verify --full` found for one container
+#[derive(Debug)]
+pub struct MediaResult {
+    pub container: String,
+    pub transcoded: bool,
+    /// ffprobe's format_name, None when the result could not be probed
+    pub format: Option<String>,
+    /// codec_name of every stream in the result
+    pub codecs: Vec<String>,
+    /// Codecs the streams have to be, video first
+    pub expected: Vec<String>,
+    pub container_ok: bool,
+    pub codecs_ok: bool,
+    /// stderr of the run
+    pub detail: String,
+}
+
+impl MediaResult {
+    pub fn passed(&self) -> bool {
+        self.transcoded && self.container_ok && self.codecs_ok
+    }
+}
+
+/// Pass/fail matrix of `verify --full`, one row per container
+#[derive(Debug)]
+pub struct MediaMatrix {
+    pub results: Vec<MediaResult>,
+}
+
+impl MediaMatrix {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(MediaResult::passed)
+    }
+
+    pub fn print(&self) {
+        let mark = |ok: bool| if ok { "✓" } else { "✗" };
+        say!("Verification matrix:");
+        say!("  {:<10} {:<12} {:<10} {:<10} {:<10}", "container", "codecs", "transcode", "container", "codecs");
+        for result in &self.results {
+            let probed = result.format.is_some();
+            say!("  {:<10} {:<12} {:<10} {:<10} {:<10}", result.container, result.expected.join("+"), mark(result.transcoded),
+                if probed { mark(result.container_ok) } else { "-" }, if probed { mark(result.codecs_ok) } else { "-" });
+        }
+        for result in self.results.iter().filter(|result| !result.passed()) {
+            match &result.format {
+                Some(format) => say!("  ✗ {}: probed as {} with {}", result.container, format,
+                    if result.codecs.is_empty() { "no streams".to_string() } else { result.codecs.join("+") }),
+                None => say!("  ✗ {}: {}", result.container, if result.transcoded { "the result could not be probed" } else { "the transcode failed" }),
+            }
+            for line in result.detail.lines().take(ERROR_TAIL_LINES) {
+                say!("    {}", line);
+            }
+        }
+    }
+}
+
+/// Runtime and version to build a prebuilt binary for (`node@18.0.0`, `electron@30.0.0`)
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct PrebuildTarget {
+    pub runtime: String,
+    pub version: String,
+}
+
+impl PrebuildTarget {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        let invalid = || format!("invalid prebuild target `{}`, expected node@<version> or electron@<version>", value);
+        let (runtime, version) = value.split_once('@').ok_or_else(invalid)?;
+        let version = version.strip_prefix('v').unwrap_or(version);
+        let valid_version = version.starts_with(|c: char| c.is_ascii_digit())
+            && version.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+        if !matches!(runtime, "node" | "electron") || !valid_version {
+            return Err(invalid());
+        }
+        Ok(Self { runtime: runtime.to_string(), version: version.to_string() })
+    }
+}
+
+impl std::fmt::Display for PrebuildTarget {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}@{}", self.runtime, self.version)
+    }
+}
+
+/// Outcome of the prebuild matrix: the error of every failed target
+#[derive(Debug)]
+pub struct PrebuildReport {
+    pub results: Vec<(PrebuildTarget, Option<String>)>,
+    pub prebuilds_dir: PathBuf,
+}
+
+impl PrebuildReport {
+    pub fn passed(&self) -> bool {
+        self.results.iter().all(|(_, error)| error.is_none())
+    }
+
+    pub fn print(&self) {
+        say!("Prebuild matrix:");
+        for (target, error) in &self.results {
+            match error {
+                None => say!("  ✓ {}", target),
+                Some(error) => {
+                    say!("  ✗ {}", target);
+                    for line in error.lines().take(ERROR_TAIL_LINES) {
+                        say!("    {}", line);
+                    }
+                }
+            }
+        }
+
+        let mut binaries: Vec<String> = fs::read_dir(&self.prebuilds_dir)
+            .map(|platforms| platforms
+                .flatten()
+                .flat_map(|platform| fs::read_dir(platform.path()).into_iter().flatten().flatten())
+                .filter_map(|binary| binary.path().strip_prefix(&self.prebuilds_dir).ok().map(|p| p.display().to_string()))
+                .collect())
+            .unwrap_or_default();
+        binaries.sort();
+        say!("Binaries in {}:", self.prebuilds_dir.display());
+        for binary in &binaries {
+            say!("  {}", binary);
+        }
+    }
+}
+
+/// Map well-known Node.js / dynamic loader messages to a likely cause
+pub fn diagnose_load_failure(stderr: &str) -> Option<&'static str> {
+    const CAUSES: &[(&str, &str)] = &[
+        ("generated without --bun", "the addon was loaded by Bun but generated for Node.js, regenerate it with --bun"),
+        ("NODE_MODULE_VERSION", "the binary was built for a different Node.js/Electron ABI, rebuild it (check --electron)"),
+        ("is not a valid Win32 application", "architecture mismatch between node.exe and the addon (x86/x64/arm64 triplet)"),
+        ("wrong ELF class", "architecture mismatch between node and the addon (check the vcpkg triplet)"),
+        ("incompatible architecture", "architecture mismatch between node and the addon (check the vcpkg triplet)"),
+        ("VCRUNTIME", "missing MSVC runtime DLL, install the Visual C++ Redistributable or use a -static triplet"),
+        ("MSVCP", "missing MSVC runtime DLL, install the Visual C++ Redistributable or use a -static triplet"),
+        ("ucrtbased", "the Debug build needs the debug CRT, which only exists on machines with Visual Studio"),
+        ("The specified module could not be found", "a dependent DLL is missing, use a -static triplet or copy the vcpkg DLLs next to the .node file"),
+        ("The specified procedure could not be found", "a dependent DLL has the wrong version, another ffmpeg on PATH may be shadowing vcpkg's"),
+        ("cannot open shared object file", "a shared library is missing, add the vcpkg lib directory to LD_LIBRARY_PATH or link statically"),
+        ("Library not loaded", "a dylib is missing, check the install names / rpath of the vcpkg libraries"),
+        ("undefined symbol", "a library was not linked, compare the link libraries with vcpkg's pkg-config files"),
+        ("Symbol not found", "a library was not linked, compare the link libraries with vcpkg's pkg-config files"),
+        ("binary not found", "the build produced no .node file, check the build log"),
+        ("ENCODER_NOT_FOUND", "libx264 is not enabled in the linked ffmpeg, check the installed vcpkg features"),
+    ];
+    CAUSES.iter().find(|(needle, _)| stderr.contains(needle)).map(|(_, cause)| *cause)
+}
+
+/// Map well-known compiler / linker errors of the addon build to a likely cause
+pub fn diagnose_build_failure(output: &str) -> Option<&'static str> {
+    const CAUSES: &[(&str, &str)] = &[
+        ("recompile with -fPIC", "a vcpkg static library was built without -fPIC, rebuild it with a triplet setting VCPKG_C_FLAGS=-fPIC (or use x64-linux-dynamic)"),
+        ("relocation R_X86_64_PC32", "a vcpkg static library was built without -fPIC, rebuild it with a triplet setting VCPKG_C_FLAGS=-fPIC (or use x64-linux-dynamic)"),
+        ("LNK2038", "runtime library mismatch between the addon and the vcpkg libraries, check --addon-config and the triplet's CRT linkage"),
+        ("LNK1112", "architecture mismatch between the addon and the vcpkg libraries, check the vcpkg triplet"),
+        ("undefined reference to", "a library is missing or in the wrong order on the link line, compare with vcpkg's pkg-config files"),
+    ];
+    CAUSES.iter().find(|(needle, _)| output.contains(needle)).map(|(_, cause)| *cause)
+}
+
+/// A failed [`AddonBuilder::run_captured`] of `command`, whose output is in `log_path`
+fn command_failed(command: &str, log_path: &Path, message: String) -> VcpkgFfError {
+    VcpkgFfError::CommandFailed { command: command.to_string(), message: format!("see {}: {}", log_path.display(), message) }
+}
+
+/// Quote `value` as a single-quoted JavaScript string literal
+fn js_string(value: &str) -> String {
+    format!("'{}'", value.replace('\\', "\\\\").replace('\'', "\\'"))
+}
+
+/// npm is a batch script on Windows and can't be started without its extension
+pub fn npm_program() -> &'static str {
+    if cfg!(windows) { "npm.cmd" } else { "npm" }
+}
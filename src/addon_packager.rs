@@ -0,0 +1,230 @@
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::addon_builder;
+use crate::command_runner::{CommandRunner, CommandSpec, SystemRunner};
+use crate::config_h::{TargetArch, TargetOs};
+use crate::error::VcpkgFfError;
+use crate::fs_retry;
+use crate::progress::say;
+use crate::tool_config::ToolConfig;
+use crate::vcpkg_manager;
+
+/// Files copied unchanged from addon_src into the package
+const PACKAGE_FILES: &[&str] = &["index.js", "index.d.ts"];
+
+/// Project license files copied into the package when present
+const LICENSE_FILES: &[&str] = &["LICENSE", "LICENSE.md", "LICENSE.txt", "COPYING"];
+
+/// Notices of the vcpkg ports linked into the addon, written into the package
+const NOTICES_FILE_NAME: &str = "THIRD_PARTY_NOTICES.txt";
+
+/// Build directories searched for the addon binary when there is no prebuilds/ tree, in order of preference
+const BUILD_TYPES: &[&str] = &["Release", "Debug"];
+
+/// Assembles the built addon into an npm-publishable directory under dist/ (`package` command)
+pub struct AddonPackager {
+    base_dir: PathBuf,
+    addon_src_dir: PathBuf,
+    vcpkg_root: PathBuf,
+    triplet: String,
+}
+
+impl AddonPackager {
+    pub fn new(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            addon_src_dir: base_dir.join("addon_src"),
+            vcpkg_root: base_dir.join("vcpkg"),
+            triplet: vcpkg_manager::default_triplet().to_string(),
+        }
+    }
+
+    /// Write dist/<name>-<version>/ with index.js, typings, prebuilt binaries, license notices
+    /// and a package.json without build scripts. Returns the package directory
+    pub fn assemble(&self) -> Result<PathBuf, VcpkgFfError> {
+        let config = ToolConfig::load(&self.base_dir)?;
+        let addon_package_json = self.addon_src_dir.join("package.json");
+        if !addon_package_json.exists() {
+            return Err(VcpkgFfError::NotPrepared { path: addon_package_json, step: "prepare-addon" });
+        }
+        let addon_package: serde_json::Value = serde_json::from_str(&fs::read_to_string(&addon_package_json)?)
+            .map_err(|e| VcpkgFfError::InvalidFile { path: addon_package_json.clone(), message: e.to_string() })?;
+
+        let package = &config.package;
+        let dir_name = format!("{}-{}", package.name.trim_start_matches('@').replace('/', "-"), package.version);
+        let package_dir = self.dist_dir().join(dir_name);
+        say!("Assembling npm package in: {}", package_dir.display());
+
+        // 每次重新组装，避免残留上一次的二进制
+        if package_dir.exists() {
+            fs_retry::remove_dir_all(&package_dir)?;
+        }
+        fs::create_dir_all(&package_dir)?;
+
+        let mut files: Vec<String> = Vec::new();
+        for file_name in PACKAGE_FILES {
+            let source = self.addon_src_dir.join(file_name);
+            if !source.exists() {
+                return Err(VcpkgFfError::NotPrepared { path: source, step: "prepare-addon" });
+            }
+            fs::copy(&source, package_dir.join(file_name))?;
+            files.push(file_name.to_string());
+        }
+
+        let platforms = self.collect_binaries(&package_dir)?;
+        files.push("prebuilds/".to_string());
+
+        for file_name in LICENSE_FILES {
+            let source = self.base_dir.join(file_name);
+            if source.exists() {
+                fs::copy(&source, package_dir.join(file_name))?;
+                files.push(file_name.to_string());
+            }
+        }
+        let ports = self.write_notices(&package_dir)?;
+        if ports > 0 {
+            files.push(NOTICES_FILE_NAME.to_string());
+        }
+
+        let mut content = serde_json::json!({
+            "name": package.name,
+            "version": package.version,
+            "description": addon_package["description"],
+            "main": "index.js",
+            "types": "index.d.ts",
+            "license": package.license,
+            "files": files,
+            "engines": addon_package["engines"],
+            "dependencies": {
+                "node-gyp-build": addon_package["dependencies"]["node-gyp-build"],
+            },
+        });
+        // 只有单一平台的二进制时限制 os/cpu，避免在其他平台安装后才在加载时失败
+        if let [(os, cpu)] = platforms.iter().collect::<Vec<_>>().as_slice() {
+            content["os"] = serde_json::json!([os]);
+            content["cpu"] = cpu.split('+').collect::<Vec<_>>().into();
+        }
+        fs::write(package_dir.join("package.json"), serde_json::to_string_pretty(&content)? + "\n")?;
+
+        say!("✓ Package assembled: {} {} ({} platform(s), {} third-party notice(s))",
+            package.name, package.version, platforms.len(), ports);
+        Ok(package_dir)
+    }
+
+    /// Run `npm pack` on an assembled package, writing the tarball to dist/
+    pub fn npm_pack(&self, package_dir: &Path) -> Result<PathBuf, VcpkgFfError> {
+        let dist_dir = self.dist_dir();
+        let command = CommandSpec::new(addon_builder::npm_program())
+            .args(["pack", "--pack-destination"])
+            .arg(&dist_dir)
+            .current_dir(package_dir);
+        let output = SystemRunner.run(&command).map_err(|e| VcpkgFfError::CommandFailed {
+            command: "npm pack".to_string(),
+            message: format!("could not start npm: {}", e),
+        })?;
+        if !output.success() {
+            return Err(output.failure("npm pack"));
+        }
+
+        // npm pack 最后一行输出是生成的文件名
+        let tarball = output.stdout.lines().rev().find(|line| !line.trim().is_empty())
+            .ok_or_else(|| VcpkgFfError::CommandFailed {
+                command: "npm pack".to_string(),
+                message: "it did not report a tarball name".to_string(),
+            })?;
+        let tarball = dist_dir.join(tarball.trim());
+        say!("✓ npm pack created: {}", tarball.display());
+        Ok(tarball)
+    }
+
+    fn dist_dir(&self) -> PathBuf {
+        self.base_dir.join("dist")
+    }
+
+    /// Copy the prebuilds/ tree written by prebuildify, or else the binary in build/<type>/ as the
+    /// prebuild for the triplet's platform. Returns the (platform, arch) pairs that have a binary
+    fn collect_binaries(&self, package_dir: &Path) -> Result<BTreeSet<(String, String)>, VcpkgFfError> {
+        let target_dir = package_dir.join("prebuilds");
+        let mut platforms = BTreeSet::new();
+
+        let prebuilds_dir = self.base_dir.join("prebuilds");
+        if prebuilds_dir.is_dir() {
+            for entry in fs::read_dir(&prebuilds_dir)?.flatten() {
+                let name = entry.file_name().to_string_lossy().into_owned();
+                let binaries: Vec<PathBuf> = fs::read_dir(entry.path())
+                    .map(|files| files.flatten().map(|file| file.path()).collect())
+                    .unwrap_or_default();
+                let binaries: Vec<&PathBuf> = binaries.iter().filter(|path| path.extension().is_some_and(|ext| ext == "node")).collect();
+                if binaries.is_empty() {
+                    continue;
+                }
+                fs::create_dir_all(target_dir.join(&name))?;
+                for binary in binaries {
+                    if let Some(file_name) = binary.file_name() {
+                        fs::copy(binary, target_dir.join(&name).join(file_name))?;
+                    }
+                }
+                // 目录名形如 linux-x64 或 darwin-x64+arm64
+                if let Some((os, cpu)) = name.split_once('-') {
+                    platforms.insert((os.to_string(), cpu.to_string()));
+                }
+                say!("✓ Added prebuilds/{}", name);
+            }
+        }
+
+        if platforms.is_empty() {
+            let Some((build_type, binary)) = BUILD_TYPES.iter()
+                .map(|build_type| (*build_type, self.base_dir.join("build").join(build_type).join("ffmpeg_node.node")))
+                .find(|(_, binary)| binary.exists())
+            else {
+                // prebuilds/ 也没有
+                return Err(VcpkgFfError::NotPrepared { path: self.base_dir.join("build"), step: "build-addon" });
+            };
+            if build_type == "Debug" {
+                say!("⚠ Only a Debug build was found, packaging it (use --addon-config release for a release package)");
+            }
+
+            let os = TargetOs::from_triplet(&self.triplet).node_name();
+            let cpu = TargetArch::from_triplet(&self.triplet).node_name();
+            let platform_dir = target_dir.join(format!("{}-{}", os, cpu));
+            fs::create_dir_all(&platform_dir)?;
+            // node-gyp-build 按文件名中的 napi 标签选用 N-API 二进制
+            fs::copy(&binary, platform_dir.join("ffmpeg_node.napi.node"))?;
+            platforms.insert((os.to_string(), cpu.to_string()));
+            say!("✓ Added {} as prebuilds/{}-{}/ffmpeg_node.napi.node", binary.display(), os, cpu);
+        }
+        Ok(platforms)
+    }
+
+    /// Concatenate the copyright file of every port vcpkg installed for the triplet into
+    /// THIRD_PARTY_NOTICES.txt. Returns the number of ports included
+    fn write_notices(&self, package_dir: &Path) -> Result<usize, VcpkgFfError> {
+        let share_dir = self.vcpkg_root.join("installed").join(&self.triplet).join("share");
+        let mut ports: Vec<(String, PathBuf)> = fs::read_dir(&share_dir)
+            .map(|entries| entries
+                .flatten()
+                .map(|entry| (entry.file_name().to_string_lossy().into_owned(), entry.path().join("copyright")))
+                .filter(|(_, copyright)| copyright.exists())
+                .collect())
+            .unwrap_or_default();
+        if ports.is_empty() {
+            say!("⚠ No copyright files found in {}, the package has no third-party notices", share_dir.display());
+            return Ok(0);
+        }
+        ports.sort();
+
+        let mut notices = format!("Third-party software linked into this addon (vcpkg triplet {}):\n", self.triplet);
+        for (port, _) in &ports {
+            notices.push_str(&format!("  - {}\n", port));
+        }
+        for (port, copyright) in &ports {
+            notices.push_str(&format!("\n{}\n{}\n{}\n\n", "=".repeat(72), port, "=".repeat(72)));
+            notices.push_str(fs::read_to_string(copyright)?.trim_end());
+            notices.push('\n');
+        }
+        fs::write(package_dir.join(NOTICES_FILE_NAME), notices)?;
+        Ok(ports.len())
+    }
+}
@@ -1,616 +1,2523 @@
-use std::env;
+use std::collections::HashMap;
 use std::fs;
 use std::path::{Path, PathBuf};
 
-#[allow(dead_code)]
+use crate::c_lexer::{self, Token, TokenKind};
+use crate::config_h::{self, ConfigH, TargetArch, TargetOs};
+use crate::diff_patch::{self, FilePatch};
+use crate::error::VcpkgFfError;
+use crate::ffmpeg_version::FfmpegVersion;
+use crate::fftools_sources;
+use crate::generated_headers;
+use crate::journal;
+use crate::marker::{self, Marker, Provenance};
+use crate::napi_version;
+use crate::patch_engine::{PatchEngine, PatchRule};
+use crate::pkg_config::StaticLinkSet;
+use crate::progress::{say, say_error};
+use crate::shims;
+use crate::syntax_check::{self, SyntaxChecker};
+use crate::templates::Templates;
+use crate::tool_config::{self, ToolConfig};
+use crate::user_patches::UserPatches;
+use crate::vcpkg_manager;
+
+/// Patch rules and source layout for one supported ffmpeg release series
+struct PatchSet {
+    /// fftools sources compiled into the addon besides ffmpeg.c and ffprobe.c, relative to fftools/
+    fftools_sources: Vec<String>,
+    /// Rules for ffmpeg.c, given the rendered ffmpeg_run template
+    ffmpeg_c_rules: fn(&str) -> Vec<PatchRule>,
+    /// Rules for ffprobe.c, given the rendered ffprobe_run template
+    ffprobe_c_rules: fn(&str) -> Vec<PatchRule>,
+}
+
+impl PatchSet {
+    /// Select the patch set matching the detected ffmpeg version
+    fn for_version(version: &FfmpegVersion) -> Result<Self, VcpkgFfError> {
+        match version.major {
+            // ffmpeg 7.x: Scheduler-based fftools (transcode(Scheduler *sch), ffmpeg_sched.c)
+            7 => Ok(PatchSet {
+                fftools_sources: FFTOOLS_7_SOURCES.iter().map(|name| name.to_string()).collect(),
+                ffmpeg_c_rules: ffmpeg_7_c_rules,
+                ffprobe_c_rules: ffprobe_7_c_rules,
+            }),
+            // 5.x/6.x 的 transcode(void) 签名和 fftools 文件布局不同，ffmpeg_run 模板无法适用
+            _ => Err(VcpkgFfError::UnsupportedFfmpeg { version: version.to_string() }),
+        }
+    }
+    
+    /// Replace the built-in source list with the one from fftools/Makefile when it can be read
+    fn discover_sources(&mut self, fftools_dir: &Path) {
+        match fftools_sources::discover(fftools_dir) {
+            Some(sources) => {
+                say!("✓ Found {} fftools source(s) in {}", sources.len(), fftools_dir.join("Makefile").display());
+                self.fftools_sources = sources;
+            }
+            None => say!("⚠ Could not read the fftools source list from fftools/Makefile, using the built-in list"),
+        }
+    }
+}
+
+/// A parsed `.patch` file section together with the file it applies to
+struct ExternalPatch {
+    patch_name: String,
+    target: PathBuf,
+    file_patch: FilePatch,
+}
+
+/// User customizations applied on top of the built-in rules
+struct Customizations {
+    /// Unified diffs from patches/
+    external: Vec<ExternalPatch>,
+    /// Replacement rules and extra sources from addon_patches/
+    user: UserPatches,
+    /// Settings from vcpkg_ff.toml
+    config: ToolConfig,
+    /// Templates for generated C files, with overrides from templates/
+    templates: Templates,
+    /// Recorded in the marker of every file written to addon_src
+    provenance: Provenance,
+}
+
+/// Build type of the generated build files
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildType {
+    Debug,
+    Release,
+}
+
+impl BuildType {
+    fn name(&self) -> &'static str {
+        match self {
+            BuildType::Debug => "Debug",
+            BuildType::Release => "Release",
+        }
+    }
+}
+
+/// Which configurations to generate (`--addon-config debug|release|both`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddonConfig {
+    Debug,
+    Release,
+    Both,
+}
+
+impl AddonConfig {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "debug" => Ok(AddonConfig::Debug),
+            "release" => Ok(AddonConfig::Release),
+            "both" => Ok(AddonConfig::Both),
+            other => Err(format!("invalid --addon-config `{}`, expected debug, release or both", other)),
+        }
+    }
+    
+    fn build_types(&self) -> &'static [BuildType] {
+        match self {
+            AddonConfig::Debug => &[BuildType::Debug],
+            AddonConfig::Release => &[BuildType::Release],
+            AddonConfig::Both => &[BuildType::Debug, BuildType::Release],
+        }
+    }
+    
+    fn default_build_type(&self) -> BuildType {
+        match self {
+            AddonConfig::Debug => BuildType::Debug,
+            AddonConfig::Release | AddonConfig::Both => BuildType::Release,
+        }
+    }
+}
+
+/// Build system the generated build files are for (`--build-system gyp|cmake-js`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BuildSystem {
+    Gyp,
+    CmakeJs,
+}
+
+impl BuildSystem {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "gyp" | "node-gyp" => Ok(BuildSystem::Gyp),
+            "cmake-js" => Ok(BuildSystem::CmakeJs),
+            other => Err(format!("invalid --build-system `{}`, expected gyp or cmake-js", other)),
+        }
+    }
+}
+
+/// Language of the generated N-API binding (`--binding-style c|node-addon-api|napi-rs`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum BindingStyle {
+    /// binding.c using the raw C N-API
+    C,
+    /// binding.cc using node-addon-api (Napi::), with an AsyncWorker-based runAsync
+    NodeAddonApi,
+    /// A napi-rs crate (Cargo.toml, build.rs, src/lib.rs) calling the C sources through FFI, built by cargo
+    NapiRs,
+}
+
+impl BindingStyle {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "c" => Ok(BindingStyle::C),
+            "node-addon-api" => Ok(BindingStyle::NodeAddonApi),
+            "napi-rs" => Ok(BindingStyle::NapiRs),
+            other => Err(format!("invalid --binding-style `{}`, expected c, node-addon-api or napi-rs", other)),
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            BindingStyle::C => "c",
+            BindingStyle::NodeAddonApi => "node-addon-api",
+            BindingStyle::NapiRs => "napi-rs",
+        }
+    }
+    
+    /// Generated binding file in addon_src and the template it is rendered from
+    fn binding_file(&self) -> (&'static str, &'static str) {
+        match self {
+            BindingStyle::C => ("binding.c", "binding.c.jinja"),
+            BindingStyle::NodeAddonApi => ("binding.cc", "binding.cc.jinja"),
+            BindingStyle::NapiRs => ("src/lib.rs", "lib.rs.jinja"),
+        }
+    }
+}
+
+/// Runtime the generated sources are for (`--target node|deno|wasm`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum AddonTarget {
+    /// A Node.js native addon using N-API
+    Node,
+    /// A plain shared library with a C ABI (ffmpeg_deno.c) and a Deno.dlopen binding (mod.ts), built with CMake
+    Deno,
+    /// A WebAssembly module built with Emscripten from the wasm32-emscripten libraries, loaded by wasm.mjs
+    Wasm,
+}
+
+impl AddonTarget {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "node" => Ok(AddonTarget::Node),
+            "deno" => Ok(AddonTarget::Deno),
+            "wasm" => Ok(AddonTarget::Wasm),
+            other => Err(format!("invalid --target `{}`, expected node, deno or wasm", other)),
+        }
+    }
+    
+    fn name(&self) -> &'static str {
+        match self {
+            AddonTarget::Node => "node",
+            AddonTarget::Deno => "deno",
+            AddonTarget::Wasm => "wasm",
+        }
+    }
+}
+
+/// vcpkg triplet of the ffmpeg libraries `--target wasm` links
+pub const WASM_TRIPLET: &str = "wasm32-emscripten";
+
+/// Check an `--electron` version ("30", "30.1.2", "31.0.0-beta.3")
+pub fn parse_electron_version(value: &str) -> Result<String, String> {
+    let version = value.strip_prefix('v').unwrap_or(value);
+    let valid = version.starts_with(|c: char| c.is_ascii_digit())
+        && version.chars().all(|c| c.is_ascii_alphanumeric() || c == '.' || c == '-');
+    if valid {
+        Ok(version.to_string())
+    } else {
+        Err(format!("invalid --electron version `{}`, expected e.g. 30.1.2", value))
+    }
+}
+
 pub struct AddonPreparer {
+    base_dir: PathBuf,
     ffmpeg_source_dir: PathBuf,
     addon_src_dir: PathBuf,
+    patches_dir: PathBuf,
+    user_patches_dir: PathBuf,
+    templates_dir: PathBuf,
+    backup_dir: PathBuf,
     vcpkg_root: PathBuf,
+    triplet: String,
+    addon_config: AddonConfig,
+    build_system: BuildSystem,
+    binding_style: BindingStyle,
+    target: AddonTarget,
+    /// Electron version to build against instead of the running Node.js
+    electron: Option<String>,
+    /// N-API version to target, None uses the headers' default
+    napi_version: Option<u32>,
+    /// Generate the addon so that it also loads under Bun's N-API implementation
+    bun: bool,
+    /// Compile the generated sources syntax-only in `validate_generated_sources`
+    syntax_check: bool,
+}
+
+/// Paths of an [`AddonPreparer`], everything not set falls back to the defaults of [`AddonPreparer::new`]
+///
+/// ```no_run
+/// use vcpkg_ff::AddonPreparer;
+///
+/// let preparer = AddonPreparer::builder()
+///     .base_dir("/work/my-addon")
+///     .source_dir("/work/ffmpeg-7.1")
+///     .vcpkg_root("/opt/vcpkg")
+///     .build();
+/// ```
+#[derive(Default)]
+pub struct AddonPreparerBuilder {
+    base_dir: Option<PathBuf>,
+    source_dir: Option<PathBuf>,
+    addon_dir: Option<PathBuf>,
+    templates_dir: Option<PathBuf>,
+    vcpkg_root: Option<PathBuf>,
+    triplet: Option<String>,
+}
+
+impl AddonPreparerBuilder {
+    /// Project directory the other paths default to, also holding patches/, addon_patches/,
+    /// vcpkg_ff.toml and the generated build files
+    pub fn base_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.base_dir = Some(dir.into());
+        self
+    }
+    
+    /// Extracted ffmpeg source tree, ffmpeg/ by default
+    pub fn source_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.source_dir = Some(dir.into());
+        self
+    }
+    
+    /// Directory the addon sources are generated in, addon_src/ by default
+    pub fn addon_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.addon_dir = Some(dir.into());
+        self
+    }
+    
+    /// Directory with template overrides, templates/ by default
+    pub fn templates_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.templates_dir = Some(dir.into());
+        self
+    }
+    
+    /// vcpkg installation with the ffmpeg libraries, vcpkg/ by default
+    pub fn vcpkg_root(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.vcpkg_root = Some(dir.into());
+        self
+    }
+    
+    pub fn triplet(mut self, triplet: impl Into<String>) -> Self {
+        self.triplet = Some(triplet.into());
+        self
+    }
+    
+    pub fn build(self) -> AddonPreparer {
+        let base_dir = self.base_dir.unwrap_or_else(vcpkg_manager::default_base_dir);
+        
+        AddonPreparer {
+            ffmpeg_source_dir: self.source_dir.unwrap_or_else(|| base_dir.join("ffmpeg")),
+            addon_src_dir: self.addon_dir.unwrap_or_else(|| base_dir.join("addon_src")),
+            patches_dir: base_dir.join("patches"),
+            user_patches_dir: base_dir.join("addon_patches"),
+            templates_dir: self.templates_dir.unwrap_or_else(|| base_dir.join("templates")),
+            backup_dir: base_dir.join(".vcpkg_ff").join("backups"),
+            vcpkg_root: self.vcpkg_root.unwrap_or_else(|| base_dir.join("vcpkg")),
+            triplet: self.triplet.unwrap_or_else(|| vcpkg_manager::default_triplet().to_string()),
+            base_dir,
+            addon_config: AddonConfig::Both,
+            build_system: BuildSystem::Gyp,
+            binding_style: BindingStyle::C,
+            target: AddonTarget::Node,
+            electron: None,
+            napi_version: None,
+            bun: false,
+            syntax_check: true,
+        }
+    }
 }
 
 impl AddonPreparer {
+    /// addon_src/ generated from ffmpeg/ in [`vcpkg_manager::default_base_dir`]
     pub fn new() -> Self {
-        let base_dir = if let Ok(manifest_dir) = env::var("CARGO_MANIFEST_DIR") {
-            PathBuf::from(&manifest_dir)
+        Self::builder().build()
+    }
+    
+    pub fn builder() -> AddonPreparerBuilder {
+        AddonPreparerBuilder::default()
+    }
+    
+    /// Select the build system to generate build files for
+    pub fn with_build_system(mut self, build_system: BuildSystem) -> Self {
+        self.build_system = build_system;
+        self
+    }
+    
+    /// Select the language of the generated binding
+    pub fn with_binding_style(mut self, binding_style: BindingStyle) -> Self {
+        self.binding_style = binding_style;
+        self
+    }
+    
+    /// Select the runtime to generate the sources for
+    pub fn with_target(mut self, target: AddonTarget) -> Self {
+        self.target = target;
+        self
+    }
+    
+    /// Build the addon for Electron `version` (headers, runtime flags and host delay-load)
+    pub fn with_electron(mut self, version: Option<String>) -> Self {
+        self.electron = version;
+        self
+    }
+    
+    /// Target N-API `version`: defines NAPI_VERSION and rejects calls newer than it
+    pub fn with_napi_version(mut self, version: Option<u32>) -> Self {
+        self.napi_version = version;
+        self
+    }
+    
+    /// Make the addon loadable under Bun: caps the N-API version at [`napi_version::BUN_NAPI_VERSION`],
+    /// registers the module with NAPI_MODULE and declares Bun in package.json engines
+    pub fn with_bun(mut self, bun: bool) -> Self {
+        self.bun = bun;
+        self
+    }
+    
+    /// Turn the syntax-only compile of the generated sources on or off, for source trees
+    /// the compiler cannot see the ffmpeg headers of
+    pub fn with_syntax_check(mut self, syntax_check: bool) -> Self {
+        self.syntax_check = syntax_check;
+        self
+    }
+    
+    /// Select which build configurations the generated build files contain
+    pub fn with_addon_config(mut self, addon_config: AddonConfig) -> Self {
+        self.addon_config = addon_config;
+        self
+    }
+    
+    /// Prepare addon source code
+    pub fn prepare_addon_source(&self) -> Result<(), VcpkgFfError> {
+        match self.target {
+            AddonTarget::Node => say!("Preparing Node.js addon source code..."),
+            AddonTarget::Deno => say!("Preparing Deno FFI library source code..."),
+            AddonTarget::Wasm => say!("Preparing Emscripten WebAssembly source code..."),
+        }
+        
+        if !self.addon_src_dir.exists() {
+            fs::create_dir_all(&self.addon_src_dir)?;
+            say!("✓ Created addon_src directory");
+        }
+        
+        let version = FfmpegVersion::detect(&self.ffmpeg_source_dir)?;
+        let mut patch_set = PatchSet::for_version(&version)?;
+        say!("✓ Detected ffmpeg {}", version);
+        
+        // 输入和输出都没变时跳过整个准备过程
+        let inputs_hash = self.prepare_inputs_hash();
+        let stamp = self.prepare_stamp_path();
+        if fs::read_to_string(&stamp).ok() == Some(self.prepare_stamp(&inputs_hash)) {
+            say!("✓ addon_src is up to date (ffmpeg sources, templates and configuration unchanged), skipping preparation");
+            return Ok(());
+        }
+        
+        patch_set.discover_sources(&self.ffmpeg_source_dir.join("fftools"));
+        
+        let custom = Customizations {
+            external: self.load_external_patches()?,
+            user: UserPatches::load(&self.user_patches_dir)?,
+            config: ToolConfig::load(&self.base_dir)?,
+            templates: Templates::load(&self.templates_dir)?,
+            provenance: Provenance {
+                ffmpeg_version: version.to_string(),
+                triplet: self.triplet.clone(),
+                features: vcpkg_manager::FFMPEG_FEATURES.join(","),
+            },
+        };
+        
+        self.create_config_h(&version, &custom)?;
+        self.copy_and_modify_program("ffmpeg.c", "ffmpeg_run.c.jinja", patch_set.ffmpeg_c_rules, &version, &custom)?;
+        self.copy_and_modify_program("ffprobe.c", "ffprobe_run.c.jinja", patch_set.ffprobe_c_rules, &version, &custom)?;
+        self.copy_fftools_sources(&patch_set, &custom)?;
+        self.create_binding(&version, &custom)?;
+        self.apply_remaining_external_patches(&patch_set, &custom)?;
+        self.verify_patched_programs()?;
+        self.verify_postproc_guards()?;
+        self.copy_extra_sources(&custom)?;
+        self.create_generated_headers(&version, &custom)?;
+        self.create_shim_headers(&custom)?;
+        if self.bun && self.target == AddonTarget::Node {
+            if let Some(version) = self.napi_version.filter(|version| *version > napi_version::BUN_NAPI_VERSION) {
+                say!("⚠ Bun provides N-API {}, --napi-version {} is capped to it", napi_version::BUN_NAPI_VERSION, version);
+            }
+            if self.electron.is_some() {
+                say!("⚠ --electron and --bun both set, the addon is built against Electron's headers");
+            }
+        }
+        if self.target != AddonTarget::Node && (self.binding_style != BindingStyle::C || self.build_system != BuildSystem::Gyp
+            || self.electron.is_some() || self.napi_version.is_some() || self.bun) {
+            say!("⚠ --binding-style, --build-system, --electron, --napi-version and --bun are ignored with --target {}", self.target.name());
+        }
+        if self.target == AddonTarget::Wasm && self.triplet != WASM_TRIPLET {
+            say!("⚠ --target wasm links the {} libraries, but the triplet is {}", WASM_TRIPLET, self.triplet);
+        }
+        if self.target == AddonTarget::Deno {
+            self.generate_deno_library(&version, &patch_set, &custom)?;
+        } else if self.target == AddonTarget::Wasm {
+            self.generate_wasm_build(&version, &patch_set, &custom)?;
+        } else {
+            self.check_napi_calls()?;
+            match (self.binding_style, self.build_system) {
+                (BindingStyle::NapiRs, _) => self.generate_napi_rs_crate(&version, &patch_set, &custom)?,
+                (_, BuildSystem::Gyp) => self.generate_binding_gyp(&version, &patch_set, &custom)?,
+                (_, BuildSystem::CmakeJs) => self.generate_cmake_lists(&version, &patch_set, &custom)?,
+            }
+            self.update_package_json_scripts()?;
+            self.generate_addon_package_json(&custom)?;
+            self.create_index_js(&version, &custom)?;
+            self.create_index_d_ts(&version, &custom)?;
+            self.create_install_js(&version, &custom)?;
+            self.create_media_tests(&version, &custom)?;
+        }
+        
+        if let Some(parent) = stamp.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        journal::write(&stamp, self.prepare_stamp(&inputs_hash))?;
+        
+        match self.target {
+            AddonTarget::Node => say!("✓ Node.js addon source code preparation completed"),
+            AddonTarget::Deno => say!("✓ Deno FFI library source code preparation completed"),
+            AddonTarget::Wasm => say!("✓ Emscripten WebAssembly source code preparation completed"),
+        }
+        Ok(())
+    }
+    
+    /// Create config.h file (required for ffmpeg compilation).
+    /// Uses the config.h produced by vcpkg's ffmpeg build so the defines match the installed
+    /// libraries; the built-in template is only a fallback when no build tree is available.
+    /// `[config_h]` entries from vcpkg_ff.toml are merged into either one.
+    fn create_config_h(&self, version: &FfmpegVersion, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let config_h_path = self.addon_src_dir.join("config.h");
+        let user_overrides = custom.config.config_h_overrides();
+        // WebAssembly 的 config.h 变体：先应用 Emscripten 的定义，[config_h] 仍可覆盖
+        let mut overrides: Vec<(String, String)> = Vec::new();
+        if self.target == AddonTarget::Wasm {
+            overrides.extend(config_h::EMSCRIPTEN_DEFINES.iter().map(|(name, value)| (name.to_string(), value.to_string())));
+        }
+        overrides.extend(user_overrides.iter().cloned());
+        
+        if let Some(vcpkg_config_h) = self.find_vcpkg_build_file("config.h") {
+            let existing = config_h::define_values(&fs::read_to_string(&vcpkg_config_h)?);
+            for (name, value) in &user_overrides {
+                report_config_h_override(name, existing.get(name).map(String::as_str), value);
+            }
+            let mut defines = existing;
+            defines.extend(overrides.iter().cloned());
+            overrides.extend(self.feature_define_corrections(&defines, &user_overrides)?);
+            
+            let rules = overrides.iter().map(|(name, value)| PatchRule::set_define(name, value)).collect();
+            if self.patch_file(&vcpkg_config_h, &config_h_path, rules, custom)? {
+                say!("✓ config.h copied from vcpkg build tree: {}", vcpkg_config_h.display());
+            } else {
+                say!("✓ config.h is up to date, skipping creation");
+            }
+            return Ok(());
+        }
+        
+        say!("⚠ config.h not found in vcpkg buildtrees, falling back to built-in template (defines may not match the installed libraries)");
+        
+        let mut config_h = ConfigH::for_triplet(&self.triplet);
+        config_h.set("FFMPEG_VERSION", &format!("\"{}\"", version));
+        for (name, value) in &overrides {
+            let previous = config_h.set(name, value);
+            if user_overrides.iter().any(|(user_name, _)| user_name == name) {
+                report_config_h_override(name, previous.as_deref(), value);
+            }
+        }
+        for (name, value) in self.feature_define_corrections(&config_h.values(), &user_overrides)? {
+            config_h.set(&name, &value);
+        }
+        let config_h_content = config_h.render(&custom.templates)?;
+        
+        // 不同 triplet 生成的内容不同，标记中的哈希也随之不同
+        if self.write_generated(&config_h_path, &config_h_content, custom)? {
+            say!("✓ config.h created for {} ({} {}): {}", self.triplet,
+                config_h.target_os().display_name(), config_h.target_arch().display_name(), config_h_path.display());
+        } else {
+            say!("✓ config.h is up to date, skipping creation");
+        }
+        Ok(())
+    }
+    
+    /// Values for the switches of config.h (CONFIG_LIBX264, CONFIG_POSTPROC, ...) that disagree with the ffmpeg
+    /// features vcpkg has installed: the build tree keeps the config.h of an earlier install, which may have had other
+    /// features. A `[config_h]` entry of vcpkg_ff.toml setting one of them against the installed features is an error
+    fn feature_define_corrections(
+        &self,
+        defines: &HashMap<String, String>,
+        user_overrides: &[(String, String)],
+    ) -> Result<Vec<(String, String)>, VcpkgFfError> {
+        let Some(features) = self.installed_ffmpeg_features() else {
+            return Ok(Vec::new());
+        };
+        let mut corrections = Vec::new();
+        let mut conflicts = Vec::new();
+        for mismatch in config_h::feature_mismatches(defines, &features) {
+            let installed = if mismatch.installed { "installed" } else { "not installed" };
+            if user_overrides.iter().any(|(name, _)| name == mismatch.define) {
+                conflicts.push(format!("  {}: {} is set to {}, but ffmpeg's {} feature is {}",
+                    tool_config::CONFIG_FILE_NAME, mismatch.define, mismatch.value, mismatch.feature, installed));
+                continue;
+            }
+            let value = config_h::flag(mismatch.installed);
+            say!("⚠ config.h: {} corrected {} -> {}, ffmpeg's {} feature is {}", mismatch.define, mismatch.value, value,
+                mismatch.feature, installed);
+            corrections.push((mismatch.define.to_string(), value.to_string()));
+        }
+        
+        if conflicts.is_empty() {
+            return Ok(corrections);
+        }
+        for conflict in &conflicts {
+            say_error!("{}", conflict);
+        }
+        Err(VcpkgFfError::ValidationFailed { check: "config-h", count: conflicts.len() })
+    }
+    
+    /// ffmpeg port features vcpkg has installed for the triplet, None when its status database has no ffmpeg
+    fn installed_ffmpeg_features(&self) -> Option<Vec<String>> {
+        vcpkg_manager::installed_features(&self.vcpkg_root, &self.triplet, "ffmpeg")
+    }
+    
+    /// Whether the feature of each switch config.h defines is installed, the part of the installed features that
+    /// changes config.h
+    fn installed_feature_switches(&self) -> Option<Vec<(&'static str, bool)>> {
+        let features = self.installed_ffmpeg_features()?;
+        let defines = match self.find_vcpkg_build_file("config.h") {
+            Some(vcpkg_config_h) => config_h::define_values(&fs::read_to_string(vcpkg_config_h).unwrap_or_default()),
+            None => ConfigH::for_triplet(&self.triplet).values(),
+        };
+        Some(config_h::FEATURE_DEFINES.iter()
+            .filter(|(_, define)| defines.contains_key(*define))
+            .map(|(feature, _)| (*feature, features.iter().any(|installed| installed == feature)))
+            .collect())
+    }
+    
+    /// Provide the configure-generated headers (config_components.h, libavutil/avconfig.h, ...)
+    /// that the copied sources include, copying them from vcpkg's build tree when possible
+    fn create_generated_headers(&self, version: &FfmpegVersion, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let sources = self.addon_source_files()?;
+        let search_dirs = [
+            self.addon_src_dir.clone(),
+            self.ffmpeg_source_dir.clone(),
+            self.ffmpeg_source_dir.join("fftools"),
+        ];
+        let scan = generated_headers::scan(&sources, &search_dirs);
+        
+        for header in &scan.required {
+            let target = self.addon_src_dir.join(header);
+            
+            if let Some(vcpkg_header) = self.find_vcpkg_build_file(header) {
+                if self.patch_file(&vcpkg_header, &target, Vec::new(), custom)? {
+                    say!("✓ {} copied from vcpkg build tree", header);
+                }
+                continue;
+            }
+            
+            let content = match *header {
+                "config_components.h" => {
+                    let config_h = fs::read_to_string(self.addon_src_dir.join("config.h")).unwrap_or_default();
+                    generated_headers::render_config_components(
+                        &scan, &generated_headers::defined_names(&config_h), vcpkg_manager::FFMPEG_FEATURES)
+                }
+                "libavutil/avconfig.h" => generated_headers::render_avconfig(TargetArch::from_triplet(&self.triplet)),
+                "libavutil/ffversion.h" => generated_headers::render_ffversion(version),
+                _ => unreachable!("every entry of GENERATED_HEADERS has a generator"),
+            };
+            if self.write_generated(&target, &content, custom)? {
+                say!("✓ {} generated: {}", header, target.display());
+            }
+        }
+        Ok(())
+    }
+    
+    /// Create compatibility headers for includes that don't resolve from addon_src,
+    /// so the addon compiles with just addon_src, ffmpeg and ffmpeg/fftools on the include path
+    fn create_shim_headers(&self, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let sources: Vec<PathBuf> = self.addon_source_files()?
+            .into_iter()
+            .filter(|path| !shims::is_shim(path))
+            .collect();
+        let plan = shims::plan(&sources, &self.addon_src_dir, &self.ffmpeg_source_dir);
+        
+        for shim in &plan.shims {
+            let target = self.addon_src_dir.join(&shim.path);
+            if self.write_generated(&target, &shim.content, custom)? {
+                say!("✓ Created shim header: {}", target.display());
+            }
+        }
+        for (file, include) in &plan.unresolved {
+            say!("⚠ {}: \"{}\" not found on the addon include path and no shim is available",
+                file.display(), include);
+        }
+        Ok(())
+    }
+    
+    /// All .c/.cc/.h files under addon_src, except the generated headers themselves
+    fn addon_source_files(&self) -> Result<Vec<PathBuf>, VcpkgFfError> {
+        let generated: Vec<PathBuf> = generated_headers::GENERATED_HEADERS
+            .iter()
+            .map(|header| self.addon_src_dir.join(header))
+            .collect();
+        let mut files = Vec::new();
+        let mut pending = vec![self.addon_src_dir.clone()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir)?.flatten() {
+                let path = entry.path();
+                if path.is_dir() {
+                    pending.push(path);
+                } else if path.extension().is_some_and(|ext| ext == "c" || ext == "cc" || ext == "h") && !generated.contains(&path) {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        Ok(files)
+    }
+    
+    /// Locate a file generated by ffmpeg's configure in vcpkg's build tree
+    /// (buildtrees/ffmpeg/<triplet>-rel, then -dbg)
+    fn find_vcpkg_build_file(&self, file_name: &str) -> Option<PathBuf> {
+        let buildtree = self.vcpkg_root.join("buildtrees").join("ffmpeg");
+        ["rel", "dbg"]
+            .iter()
+            .map(|suffix| buildtree.join(format!("{}-{}", self.triplet, suffix)).join(file_name))
+            .find(|path| path.exists())
+    }
+    
+    /// Copy and modify a program's main source (ffmpeg.c, ffprobe.c), appending its rendered run template
+    fn copy_and_modify_program(
+        &self,
+        file_name: &str,
+        run_template: &str,
+        rules: fn(&str) -> Vec<PatchRule>,
+        version: &FfmpegVersion,
+        custom: &Customizations,
+    ) -> Result<(), VcpkgFfError> {
+        let source_file = self.ffmpeg_source_dir.join("fftools").join(file_name);
+        let target_file = self.addon_src_dir.join(file_name);
+        
+        if !source_file.exists() {
+            return Err(VcpkgFfError::FfmpegSourceMissing { path: source_file });
+        }
+        
+        say!("Copying and modifying {}...", file_name);
+        
+        let run_block = custom.templates.render(run_template, self.template_context(version, custom))?;
+        if self.patch_file(&source_file, &target_file, rules(&run_block), custom)? {
+            say!("✓ {} copied and modified to: {}", file_name, target_file.display());
+        } else {
+            say!("✓ {} is up to date, skipping", file_name);
+        }
+        Ok(())
+    }
+    
+    /// Copy the remaining fftools sources the addon compiles into addon_src,
+    /// applying their patch rules to the copies
+    fn copy_fftools_sources(&self, patch_set: &PatchSet, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let fftools_dir = self.ffmpeg_source_dir.join("fftools");
+        let mut copied = 0;
+        
+        for file_name in &patch_set.fftools_sources {
+            let source_file = fftools_dir.join(file_name);
+            if !source_file.exists() {
+                say!("⚠ {} not found, skipping", file_name);
+                continue;
+            }
+            
+            let rules = match file_name.as_str() {
+                "opt_common.c" => Self::opt_common_c_rules(),
+                "ffmpeg_opt.c" => Self::ffmpeg_opt_c_rules(),
+                "ffmpeg_dec.c" => Self::ffmpeg_dec_c_rules(),
+                "ffmpeg_demux.c" => Self::ffmpeg_demux_c_rules(),
+                "ffmpeg_mux_init.c" => Self::ffmpeg_mux_init_c_rules(),
+                "ffmpeg_mux.c" => Self::ffmpeg_mux_c_rules(),
+                "ffmpeg_filter.c" if custom.config.advanced.frame_tap => Self::ffmpeg_filter_c_rules(),
+                _ => Vec::new(),
+            };
+            
+            if self.patch_file(&source_file, &self.addon_src_dir.join(file_name), rules, custom)? {
+                copied += 1;
+            }
+        }
+        
+        if copied > 0 {
+            say!("✓ Copied {} fftools source file(s) to: {}", copied, self.addon_src_dir.display());
         } else {
-            env::current_dir().unwrap_or_else(|_| PathBuf::from("."))
+            say!("✓ fftools sources are up to date, skipping");
+        }
+        Ok(())
+    }
+    
+    /// Generate binding.gyp for the prepared sources, pointing at vcpkg's installed tree for the triplet
+    fn generate_binding_gyp(&self, version: &FfmpegVersion, patch_set: &PatchSet, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let binding_gyp = self.base_dir.join("binding.gyp");
+        
+        let sources = self.addon_c_sources(patch_set, custom);
+        
+        // 静态 triplet 的 vcpkg 库使用静态 CRT (/MT, /MTd)，其余使用 /MD, /MDd
+        let static_crt = self.triplet.ends_with("-static");
+        let installed = "<(vcpkg_installed)";
+        let mut unix_libraries = Vec::new();
+        let configurations: Vec<minijinja::Value> = self.addon_config
+            .build_types()
+            .iter()
+            .map(|build_type| {
+                let debug = *build_type == BuildType::Debug;
+                let (windows_libraries, unix) = self.link_libraries(debug);
+                if !debug || unix_libraries.is_empty() {
+                    unix_libraries = unix;
+                }
+                let msvc_runtime_library = match (static_crt, debug) {
+                    (true, false) => 0,
+                    (true, true) => 1,
+                    (false, false) => 2,
+                    (false, true) => 3,
+                };
+                // 只保留与所选运行库一致的 CRT 导入库，其余通过 IgnoreSpecificDefaultLibraries 排除，
+                // 避免 vcpkg 依赖里混入的 /DEFAULTLIB 指令引起 LNK2005/LNK4098
+                let ignored_crt_libraries: Vec<&str> = MSVC_CRT_LIBRARIES
+                    .iter()
+                    .enumerate()
+                    .filter(|(index, _)| *index != msvc_runtime_library)
+                    .map(|(_, library)| *library)
+                    .collect();
+                minijinja::context! {
+                    name => build_type.name(),
+                    lib_dir => if debug { format!("{}/debug/lib", installed) } else { format!("{}/lib", installed) },
+                    debug,
+                    msvc_runtime_library,
+                    ignored_crt_libraries,
+                    windows_libraries,
+                }
+            })
+            .collect();
+        
+        let content = custom.templates.render("binding.gyp.jinja", minijinja::context! {
+            sources,
+            configurations,
+            default_configuration => self.addon_config.default_build_type().name(),
+            unix_libraries,
+            msvc_linker_options => MSVC_IGNORED_LINKER_WARNINGS.to_vec(),
+            macos => self.macos_context(custom),
+            ..self.template_context(version, custom)
+        })?;
+        
+        if fs::read_to_string(&binding_gyp).ok().as_deref() == Some(content.as_str()) {
+            say!("✓ binding.gyp is up to date, skipping");
+        } else {
+            journal::write(&binding_gyp, &content)?;
+            say!("✓ binding.gyp generated for {}: {}", self.triplet, binding_gyp.display());
+        }
+        Ok(())
+    }
+    
+    /// Generate CMakeLists.txt for cmake-js, resolving ffmpeg through the vcpkg toolchain's find_package
+    fn generate_cmake_lists(&self, version: &FfmpegVersion, patch_set: &PatchSet, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let cmake_lists = self.base_dir.join("CMakeLists.txt");
+        let configuration_types: Vec<&str> = self.addon_config.build_types().iter().map(BuildType::name).collect();
+        
+        let content = custom.templates.render("CMakeLists.txt.jinja", minijinja::context! {
+            sources => self.addon_c_sources(patch_set, custom),
+            configuration_types => configuration_types.join(";"),
+            static_crt => self.triplet.ends_with("-static"),
+            macos => self.macos_context(custom),
+            ..self.template_context(version, custom)
+        })?;
+        
+        if fs::read_to_string(&cmake_lists).ok().as_deref() == Some(content.as_str()) {
+            say!("✓ CMakeLists.txt is up to date, skipping");
+        } else {
+            journal::write(&cmake_lists, &content)?;
+            say!("✓ CMakeLists.txt generated for {}: {}", self.triplet, cmake_lists.display());
+        }
+        Ok(())
+    }
+    
+    /// Generate the napi-rs crate files in addon_src: Cargo.toml, build.rs (compiling the C sources with cc
+    /// and linking vcpkg's libraries) and .cargo/config.toml. src/lib.rs comes from `create_binding`
+    fn generate_napi_rs_crate(&self, version: &FfmpegVersion, patch_set: &PatchSet, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        if self.build_system != BuildSystem::Gyp {
+            say!("⚠ --build-system is ignored with the napi-rs binding style, cargo builds the addon");
+        }
+        
+        let static_crt = self.triplet.ends_with("-static");
+        let configurations: Vec<minijinja::Value> = self.addon_config
+            .build_types()
+            .iter()
+            .map(|build_type| {
+                let debug = *build_type == BuildType::Debug;
+                let (windows_libraries, unix_libraries) = self.link_libraries(debug);
+                minijinja::context! {
+                    profile => if debug { "debug" } else { "release" },
+                    lib_dir => if debug { "debug/lib" } else { "lib" },
+                    windows_libraries,
+                    unix_libraries,
+                }
+            })
+            .collect();
+        let context = minijinja::context! {
+            crate_name => custom.config.package.name.replace(['-', '.'], "_"),
+            package_version => &custom.config.package.version,
+            sources => self.addon_c_sources(patch_set, custom),
+            configurations,
+            static_crt,
+            ..self.template_context(version, custom)
         };
         
-        let ffmpeg_source_dir = base_dir.join("ffmpeg");
-        let addon_src_dir = base_dir.join("addon_src");
-        let vcpkg_root = base_dir.join("vcpkg");
+        for (file_name, template) in [
+            ("Cargo.toml", "Cargo.toml.jinja"),
+            ("build.rs", "build.rs.jinja"),
+            (".cargo/config.toml", "cargo_config.toml.jinja"),
+        ] {
+            let path = self.addon_src_dir.join(file_name);
+            let content = custom.templates.render(template, context.clone())?;
+            if fs::read_to_string(&path).ok().as_deref() == Some(content.as_str()) {
+                say!("✓ addon_src/{} is up to date, skipping", file_name);
+            } else {
+                if let Some(parent) = path.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                journal::write(&path, &content)?;
+                say!("✓ addon_src/{} generated for {}", file_name, self.triplet);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Generate the Deno target's build files: CMakeLists.txt building addon_src into a shared library,
+    /// addon_src/mod.ts binding it with Deno.dlopen and addon_src/deno.json with the build task
+    fn generate_deno_library(&self, version: &FfmpegVersion, patch_set: &PatchSet, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let cmake_lists = self.base_dir.join("CMakeLists.txt");
+        let configuration_types: Vec<&str> = self.addon_config.build_types().iter().map(BuildType::name).collect();
+        let content = custom.templates.render("CMakeLists.deno.txt.jinja", minijinja::context! {
+            sources => self.addon_c_sources(patch_set, custom),
+            configuration_types => configuration_types.join(";"),
+            static_crt => self.triplet.ends_with("-static"),
+            macos => self.macos_context(custom),
+            ..self.template_context(version, custom)
+        })?;
+        if fs::read_to_string(&cmake_lists).ok().as_deref() == Some(content.as_str()) {
+            say!("✓ CMakeLists.txt is up to date, skipping");
+        } else {
+            journal::write(&cmake_lists, &content)?;
+            say!("✓ CMakeLists.txt generated for the {} shared library: {}", self.triplet, cmake_lists.display());
+        }
+        
+        let mod_ts_path = self.addon_src_dir.join("mod.ts");
+        let default_build_type = self.addon_config.default_build_type();
+        let mut build_types = vec![default_build_type.name()];
+        build_types.extend(self.addon_config.build_types().iter().filter(|t| **t != default_build_type).map(BuildType::name));
+        let content = custom.templates.render("mod.ts.jinja", minijinja::context! {
+            build_types => build_types,
+            ..self.template_context(version, custom)
+        })?;
+        if self.write_generated(&mod_ts_path, &content, custom)? {
+            say!("✓ mod.ts created: {}", mod_ts_path.display());
+        } else {
+            say!("✓ mod.ts is up to date, skipping");
+        }
+        
+        // 单配置生成器用 CMAKE_BUILD_TYPE，多配置生成器用 --config，两者产物都在 build/<配置>/
+        let build_type = default_build_type.name();
+        let deno_json = self.addon_src_dir.join("deno.json");
+        let content = serde_json::to_string_pretty(&serde_json::json!({
+            "version": custom.config.package.version,
+            "exports": "./mod.ts",
+            "tasks": {
+                "build": format!("cmake -S .. -B ../build -DCMAKE_BUILD_TYPE={0} && cmake --build ../build --config {0}", build_type),
+            },
+        }))? + "\n";
+        if fs::read_to_string(&deno_json).ok().as_deref() == Some(content.as_str()) {
+            say!("✓ addon_src/deno.json is up to date, skipping");
+        } else {
+            journal::write(&deno_json, &content)?;
+            say!("✓ addon_src/deno.json generated");
+        }
+        Ok(())
+    }
+    
+    /// Generate the WebAssembly target's build files: CMakeLists.txt linking addon_src into an ES module with
+    /// Emscripten, addon_src/exported_functions.json listing what JavaScript may call and the addon_src/wasm.mjs loader
+    fn generate_wasm_build(&self, version: &FfmpegVersion, patch_set: &PatchSet, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let exported_functions: Vec<String> = WASM_EXPORTED_FUNCTIONS.iter().map(|name| format!("_{}", name)).collect();
+        let exported_functions_path = self.addon_src_dir.join("exported_functions.json");
+        let content = serde_json::to_string_pretty(&exported_functions)? + "\n";
+        if fs::read_to_string(&exported_functions_path).ok().as_deref() == Some(content.as_str()) {
+            say!("✓ addon_src/exported_functions.json is up to date, skipping");
+        } else {
+            journal::write(&exported_functions_path, &content)?;
+            say!("✓ addon_src/exported_functions.json generated: {} function(s)", exported_functions.len());
+        }
+        
+        let cmake_lists = self.base_dir.join("CMakeLists.txt");
+        let content = custom.templates.render("CMakeLists.wasm.txt.jinja", minijinja::context! {
+            sources => self.addon_c_sources(patch_set, custom),
+            build_type => self.addon_config.default_build_type().name(),
+            runtime_methods => WASM_RUNTIME_METHODS.join(","),
+            ..self.template_context(version, custom)
+        })?;
+        if fs::read_to_string(&cmake_lists).ok().as_deref() == Some(content.as_str()) {
+            say!("✓ CMakeLists.txt is up to date, skipping");
+        } else {
+            journal::write(&cmake_lists, &content)?;
+            say!("✓ CMakeLists.txt generated for Emscripten: {}", cmake_lists.display());
+        }
+        
+        let loader_path = self.addon_src_dir.join("wasm.mjs");
+        let content = custom.templates.render("wasm.mjs.jinja", self.template_context(version, custom))?;
+        if self.write_generated(&loader_path, &content, custom)? {
+            say!("✓ wasm.mjs created: {}", loader_path.display());
+        } else {
+            say!("✓ wasm.mjs is up to date, skipping");
+        }
+        Ok(())
+    }
+    
+    /// Point the build scripts of the project's package.json at the selected build system
+    fn update_package_json_scripts(&self) -> Result<(), VcpkgFfError> {
+        let package_json = self.base_dir.join("package.json");
+        if !package_json.exists() {
+            return Ok(());
+        }
+        
+        let content = fs::read_to_string(&package_json)?;
+        let mut package: serde_json::Value = serde_json::from_str(&content)
+            .map_err(|e| VcpkgFfError::Config { path: package_json.clone(), message: e.to_string() })?;
+        let Some(object) = package.as_object_mut() else {
+            return Err(VcpkgFfError::Config { path: package_json, message: "expected a JSON object".to_string() });
+        };
+        
+        let napi_build = self.napi_build_command(Some("addon_src"), "build").join(" ");
+        let (tool, build, rebuild, uses_gyp) = match (self.binding_style, self.build_system) {
+            (BindingStyle::NapiRs, _) => ("@napi-rs/cli", napi_build.clone(), napi_build, false),
+            (_, BuildSystem::Gyp) => ("node-gyp", "node-gyp rebuild".to_string(), "node-gyp rebuild".to_string(), true),
+            (_, BuildSystem::CmakeJs) => ("cmake-js", "cmake-js compile".to_string(), "cmake-js rebuild".to_string(), false),
+        };
+        // gypfile 为 true 时 npm install 会自动调用 node-gyp
+        object.insert("gypfile".to_string(), uses_gyp.into());
+        
+        let scripts = object.entry("scripts").or_insert_with(|| serde_json::json!({}));
+        if let Some(scripts) = scripts.as_object_mut() {
+            scripts.insert("build".to_string(), build.into());
+            scripts.insert("rebuild".to_string(), rebuild.into());
+        }
+        let dev_dependencies = object.entry("devDependencies").or_insert_with(|| serde_json::json!({}));
+        if let Some(dev_dependencies) = dev_dependencies.as_object_mut() {
+            dev_dependencies.entry(tool).or_insert_with(|| "latest".into());
+        }
+        
+        let updated = serde_json::to_string_pretty(&package)? + if content.ends_with('\n') { "\n" } else { "" };
+        if updated != content {
+            journal::write(&package_json, updated)?;
+            say!("✓ Updated package.json scripts for {}", tool);
+        }
+        Ok(())
+    }
+    
+    /// Write addon_src/package.json so `npm install` / `npm run build` in addon_src build the addon.
+    /// The build files live in the parent directory, so the scripts point the build tool there
+    fn generate_addon_package_json(&self, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let package_json = self.addon_src_dir.join("package.json");
+        let package = &custom.config.package;
+        
+        let runtime_flags = self.runtime_flags().join(" ");
+        let napi_build = self.napi_build_command(None, "../build").join(" ");
+        let (build, rebuild, mut prebuild, mut dev_dependencies) = match (self.binding_style, self.build_system) {
+            // prebuildify 只会驱动 node-gyp / cmake-js，napi-rs 没有对应脚本
+            (BindingStyle::NapiRs, _) => (napi_build.clone(), napi_build, None,
+                serde_json::json!({ "@napi-rs/cli": "^2.18.0" })),
+            (_, BuildSystem::Gyp) => (format!("node-gyp build --directory=.. {}", runtime_flags),
+                format!("node-gyp rebuild --directory=.. {}", runtime_flags),
+                Some("prebuildify --napi --strip --cwd ..".to_string()),
+                serde_json::json!({ "node-gyp": "^10.0.0" })),
+            (_, BuildSystem::CmakeJs) => (format!("cmake-js compile --directory=.. {}", runtime_flags),
+                format!("cmake-js rebuild --directory=.. {}", runtime_flags),
+                Some("prebuildify --napi --strip --backend cmake-js --cwd ..".to_string()),
+                serde_json::json!({ "cmake-js": "^7.3.0" })),
+        };
+        if let Some(electron) = &self.electron {
+            if let Some(prebuild) = &mut prebuild {
+                prebuild.push_str(&format!(" --target electron@{}", electron));
+            }
+            dev_dependencies["@electron/rebuild"] = "^3.6.0".into();
+        }
+        let mut scripts = serde_json::json!({
+            "install": "node install.js",
+            "build": build.trim_end(),
+            "rebuild": rebuild.trim_end(),
+        });
+        // 脚本名不能叫 prebuild，否则 npm 会把它当成 build 的前置钩子；prebuildify 生成 ../prebuilds/<platform>-<arch>/，安装时由 node-gyp-build 选用
+        if let Some(prebuild) = prebuild {
+            scripts["prebuildify"] = prebuild.into();
+            dev_dependencies["prebuildify"] = "^6.0.0".into();
+        }
+        scripts["test"] = "node --test".into();
+        let build_type = self.addon_config.default_build_type().name();
+        let module_path = format!("../build/{}", build_type);
+        
+        let mut content = serde_json::json!({
+            "name": package.name,
+            "version": package.version,
+            "description": package.description.clone().unwrap_or_else(|| format!(
+                "FFmpeg with {} as a Node.js native addon ({})", vcpkg_manager::FFMPEG_FEATURES.join(", "), self.triplet)),
+            "main": "index.js",
+            "types": "index.d.ts",
+            "license": package.license,
+            "private": true,
+            "gypfile": self.build_system == BuildSystem::Gyp && self.binding_style != BindingStyle::NapiRs,
+            "scripts": scripts,
+            "engines": { "node": self.napi_target().map(napi_version::minimum_node).unwrap_or(">=18") },
+            "binary": {
+                "module_name": "ffmpeg_node",
+                "module_path": module_path,
+                "napi_versions": [self.napi_target().unwrap_or(if self.binding_style == BindingStyle::NapiRs { 4 } else { 1 })],
+                "triplet": self.triplet,
+                "runtime": if self.electron.is_some() { "electron" } else { "node" },
+            },
+            "devDependencies": dev_dependencies,
+        });
+        if let Some(electron) = &self.electron {
+            content["binary"]["target"] = electron.as_str().into();
+        }
+        if self.bun {
+            content["engines"]["bun"] = BUN_MINIMUM_VERSION.into();
+        }
+        if self.binding_style == BindingStyle::NapiRs {
+            // napi build 按 napi.name 命名产物：<目录>/ffmpeg_node.node
+            content["napi"] = serde_json::json!({ "name": "ffmpeg_node" });
+        }
+        content["dependencies"] = serde_json::json!({ "node-gyp-build": "^4.8.0" });
+        if (self.build_system == BuildSystem::Gyp && self.binding_style != BindingStyle::NapiRs)
+            || self.binding_style == BindingStyle::NodeAddonApi {
+            // binding.gyp 和 C++ 绑定通过 require('node-addon-api').include 取头文件目录
+            content["dependencies"]["node-addon-api"] = "^8.0.0".into();
+        }
+        
+        let content = serde_json::to_string_pretty(&content)? + "\n";
+        if fs::read_to_string(&package_json).ok().as_deref() == Some(content.as_str()) {
+            say!("✓ addon_src/package.json is up to date, skipping");
+        } else {
+            journal::write(&package_json, &content)?;
+            say!("✓ addon_src/package.json generated: {} {}", package.name, package.version);
+        }
+        Ok(())
+    }
+    
+    /// Create install.js, the npm install hook that prefers a matching prebuilt binary over building
+    fn create_install_js(&self, version: &FfmpegVersion, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let install_js_path = self.addon_src_dir.join("install.js");
+        let mut build_command = match (self.binding_style, self.build_system) {
+            (BindingStyle::NapiRs, _) => self.napi_build_command(None, "../build"),
+            (_, BuildSystem::Gyp) => vec!["node-gyp".to_string(), "rebuild".to_string(), "--directory=..".to_string()],
+            (_, BuildSystem::CmakeJs) => vec!["cmake-js".to_string(), "rebuild".to_string(), "--directory=..".to_string()],
+        };
+        build_command.extend(self.runtime_flags());
+        
+        let content = custom.templates.render("install.js.jinja", minijinja::context! {
+            build_command => build_command,
+            ..self.template_context(version, custom)
+        })?;
+        
+        if self.write_generated(&install_js_path, &content, custom)? {
+            say!("✓ install.js created: {}", install_js_path.display());
+        } else {
+            say!("✓ install.js is up to date, skipping");
+        }
+        Ok(())
+    }
+    
+    /// Create index.js, the Promise-based JavaScript entry point wrapping the native binary
+    fn create_index_js(&self, version: &FfmpegVersion, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let index_js_path = self.addon_src_dir.join("index.js");
+        // 优先加载默认配置的产物
+        let default_build_type = self.addon_config.default_build_type();
+        let mut build_types = vec![default_build_type.name()];
+        build_types.extend(self.addon_config.build_types().iter().filter(|t| **t != default_build_type).map(BuildType::name));
+        
+        let content = custom.templates.render("index.js.jinja", minijinja::context! {
+            module_name => "ffmpeg_node",
+            build_types => build_types,
+            ..self.template_context(version, custom)
+        })?;
+        
+        if self.write_generated(&index_js_path, &content, custom)? {
+            say!("✓ index.js created: {}", index_js_path.display());
+        } else {
+            say!("✓ index.js is up to date, skipping");
+        }
+        Ok(())
+    }
+    
+    /// Create index.d.ts, the TypeScript declarations of index.js
+    fn create_index_d_ts(&self, version: &FfmpegVersion, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let index_d_ts_path = self.addon_src_dir.join("index.d.ts");
+        let content = custom.templates.render("index.d.ts.jinja", self.template_context(version, custom))?;
+        
+        if self.write_generated(&index_d_ts_path, &content, custom)? {
+            say!("✓ index.d.ts created: {}", index_d_ts_path.display());
+        } else {
+            say!("✓ index.d.ts is up to date, skipping");
+        }
+        Ok(())
+    }
+    
+    /// Create addon_src/test/*.test.js, node:test cases transcoding lavfi-synthesized media
+    /// with each enabled encoder feature and checking the result decodes again
+    fn create_media_tests(&self, version: &FfmpegVersion, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let test_dir = self.addon_src_dir.join("test");
+        
+        for media_test in MEDIA_TESTS.iter().filter(|t| vcpkg_manager::FFMPEG_FEATURES.contains(&t.feature)) {
+            let path = test_dir.join(format!("{}.test.js", media_test.file_stem));
+            let content = custom.templates.render("media.test.js.jinja", minijinja::context! {
+                name => media_test.name,
+                extension => media_test.extension,
+                args => media_test.args.to_vec(),
+                magic_offset => media_test.magic_offset,
+                magic => media_test.magic,
+                ..self.template_context(version, custom)
+            })?;
+            
+            if self.write_generated(&path, &content, custom)? {
+                say!("✓ test/{}.test.js created ({})", media_test.file_stem, media_test.name);
+            }
+        }
+        Ok(())
+    }
+    
+    /// Deployment target and architecture for the macOS settings of the build files
+    fn macos_context(&self, custom: &Customizations) -> minijinja::Value {
+        let arch = TargetArch::from_triplet(&self.triplet);
+        // Apple Silicon 最低就是 macOS 11
+        let default_target = if arch == TargetArch::Aarch64 { "11.0" } else { "10.15" };
+        minijinja::context! {
+            deployment_target => custom.config.macos.deployment_target.as_deref().unwrap_or(default_target),
+            arch => arch.apple_name(),
+        }
+    }
+    
+    /// node-gyp / cmake-js flags selecting the Electron headers, empty when building for Node.js
+    fn runtime_flags(&self) -> Vec<String> {
+        // napi-rs 只用 N-API，与 Node.js / Electron 的 ABI 无关
+        let Some(electron) = self.electron.as_ref().filter(|_| self.binding_style != BindingStyle::NapiRs) else {
+            return Vec::new();
+        };
+        match self.build_system {
+            BuildSystem::Gyp => vec![format!("--target={}", electron), ELECTRON_HEADERS_URL.to_string()],
+            BuildSystem::CmakeJs => vec!["--runtime=electron".to_string(), format!("--runtime-version={}", electron)],
+        }
+    }
+    
+    /// `napi build` for the default configuration, writing `<build_dir>/<type>/ffmpeg_node.node`
+    fn napi_build_command(&self, cargo_cwd: Option<&str>, build_dir: &str) -> Vec<String> {
+        let build_type = self.addon_config.default_build_type();
+        let mut command = vec!["napi".to_string(), "build".to_string()];
+        if let Some(cargo_cwd) = cargo_cwd {
+            command.extend(["--cargo-cwd".to_string(), cargo_cwd.to_string()]);
+        }
+        if build_type == BuildType::Release {
+            command.push("--release".to_string());
+        }
+        command.push(format!("{}/{}", build_dir, build_type.name()));
+        command
+    }
+    
+    /// C sources of the addon relative to addon_src, in build order
+    fn addon_c_sources(&self, patch_set: &PatchSet, custom: &Customizations) -> Vec<String> {
+        let mut sources = Vec::new();
+        // napi-rs 的绑定是 Rust 代码，由 cargo 编译
+        if let Some((binding_file, _)) = self.binding_file().filter(|_| self.target != AddonTarget::Node || self.binding_style != BindingStyle::NapiRs) {
+            sources.push(binding_file.to_string());
+        }
+        sources.push("ffmpeg.c".to_string());
+        sources.push("ffprobe.c".to_string());
+        sources.extend(patch_set.fftools_sources.iter().cloned());
+        sources.extend(custom.user.extra_sources()
+            .iter()
+            .filter(|(_, relative)| relative.extension().is_some_and(|ext| ext == "c"))
+            .map(|(_, relative)| relative.to_string_lossy().replace('\\', "/")));
+        sources
+    }
+    
+    /// Static link libraries for (Windows, macOS/Linux), in link order, from vcpkg's debug/lib or lib tree.
+    /// The target platform's list is
+    /// derived from vcpkg's pkg-config files; the other one (and a missing pkgconfig dir) uses the built-in list.
+    fn link_libraries(&self, debug: bool) -> (Vec<String>, Vec<String>) {
+        let to_strings = |libraries: &[&str]| libraries.iter().map(|lib| lib.to_string()).collect::<Vec<_>>();
+        let mut windows_libraries = to_strings(WINDOWS_FALLBACK_LIBRARIES);
+        windows_libraries.extend(to_strings(WINDOWS_SYSTEM_LIBRARIES));
+        let mut unix_libraries = to_strings(UNIX_FALLBACK_LIBRARIES);
+        
+        let target_os = TargetOs::from_triplet(&self.triplet);
+        let installed = self.vcpkg_root.join("installed").join(&self.triplet);
+        let lib_dir = if debug { installed.join("debug").join("lib") } else { installed.join("lib") };
+        let pkgconfig_dir = lib_dir.join("pkgconfig");
+        if !pkgconfig_dir.exists() {
+            say!("⚠ {} not found, using the built-in link library list", pkgconfig_dir.display());
+        } else {
+            let link_set = StaticLinkSet::resolve(&pkgconfig_dir, FFMPEG_PKG_CONFIG_PACKAGES);
+            for package in &link_set.missing {
+                say!("⚠ {}.pc not found in {}, its libraries are not linked", package, pkgconfig_dir.display());
+            }
+            
+            if target_os == TargetOs::Windows {
+                windows_libraries = link_set.msvc_libraries();
+                for library in WINDOWS_SYSTEM_LIBRARIES {
+                    if !windows_libraries.iter().any(|l| l.eq_ignore_ascii_case(library)) {
+                        windows_libraries.push(library.to_string());
+                    }
+                }
+                say!("✓ Resolved {} link libraries from pkg-config files", windows_libraries.len());
+            } else {
+                unix_libraries = link_set.flags;
+                say!("✓ Resolved {} link libraries from pkg-config files", unix_libraries.len());
+            }
+        }
+        
+        if target_os == TargetOs::Linux {
+            for library in LINUX_SYSTEM_LIBRARIES {
+                if !unix_libraries.iter().any(|l| l == library) {
+                    unix_libraries.push(library.to_string());
+                }
+            }
+        }
+        (windows_libraries, unix_libraries)
+    }
+    
+    /// Stamp file recording the input and output hashes of the last successful preparation
+    fn prepare_stamp_path(&self) -> PathBuf {
+        self.base_dir.join(".vcpkg_ff").join("prepare.stamp")
+    }
+    
+    /// Whether addon_src was prepared from the current inputs and is unchanged since, by the stamp of the last preparation
+    pub(crate) fn is_prepared(&self) -> bool {
+        fs::read_to_string(self.prepare_stamp_path()).ok() == Some(self.prepare_stamp(&self.prepare_inputs_hash()))
+    }
+    
+    fn prepare_stamp(&self, inputs_hash: &str) -> String {
+        format!("inputs={}\noutputs={}\n", inputs_hash, self.prepare_outputs_hash())
+    }
+    
+    /// Hash of everything preparation reads: the fftools sources, version files, configure output
+    /// in vcpkg's build tree, pkg-config files, patches, templates, vcpkg_ff.toml, the triplet and the tool version
+    /// with its built-in templates
+    ///
+    /// The hash doesn't depend on where the project and vcpkg are, so that an addon_src artifact can be checked
+    /// against it on another machine.
+    pub(crate) fn prepare_inputs_hash(&self) -> String {
+        let mut hasher = marker::Hasher::new();
+        hasher.update(format!("{}\0{}\0{}\0{:?}\0{:?}\0", marker::generator_version(), self.triplet,
+            vcpkg_manager::FFMPEG_FEATURES.join(","), self.addon_config, self.build_system).as_bytes());
+        hasher.update(format!("{:?}\0{:?}\0{:?}\0{:?}\0", self.electron, self.napi_version, self.binding_style, self.target).as_bytes());
+        hasher.update(format!("{}\0", self.bun).as_bytes());
+        hasher.update(format!("{:?}\0", self.installed_feature_switches()).as_bytes());
+        
+        let inputs = [
+            self.ffmpeg_source_dir.join("fftools"),
+            self.ffmpeg_source_dir.join("RELEASE"),
+            self.ffmpeg_source_dir.join("libavutil").join("version.h"),
+            self.patches_dir.clone(),
+            self.user_patches_dir.clone(),
+            self.templates_dir.clone(),
+            self.base_dir.join(tool_config::CONFIG_FILE_NAME),
+            self.vcpkg_root.join("installed").join(&self.triplet).join("lib").join("pkgconfig"),
+            self.vcpkg_root.join("installed").join(&self.triplet).join("debug").join("lib").join("pkgconfig"),
+        ];
+        for input in &inputs {
+            marker::hash_tree(&mut hasher, input);
+        }
+        
+        // configure 的输出里有 vcpkg 的绝对路径（FFMPEG_CONFIGURATION），换成占位符再哈希
+        let vcpkg_root = self.vcpkg_root.to_string_lossy().into_owned();
+        for build_file in std::iter::once("config.h")
+            .chain(generated_headers::GENERATED_HEADERS.iter().copied())
+            .filter_map(|name| self.find_vcpkg_build_file(name))
+        {
+            let content = fs::read_to_string(&build_file).unwrap_or_default()
+                .replace(&vcpkg_root, "$VCPKG_ROOT")
+                .replace(&vcpkg_root.replace('\\', "/"), "$VCPKG_ROOT");
+            hasher.update(content.as_bytes());
+            hasher.update(&[0]);
+        }
+        hasher.finish()
+    }
+    
+    /// Hash of what preparation produces (addon_src and the build files), to notice edited or deleted outputs
+    fn prepare_outputs_hash(&self) -> String {
+        let mut hasher = marker::Hasher::new();
+        marker::hash_path(&mut hasher, &self.addon_src_dir);
+        for build_file in BUILD_FILES {
+            marker::hash_path(&mut hasher, &self.base_dir.join(build_file));
+        }
+        hasher.finish()
+    }
+    
+    /// Inputs hash of the last preparation, None when addon_src or the build files changed since
+    pub(crate) fn prepared_inputs_hash(&self) -> Option<String> {
+        let stamp = fs::read_to_string(self.prepare_stamp_path()).ok()?;
+        let inputs_hash = stamp.lines().find_map(|line| line.strip_prefix("inputs="))?.to_string();
+        (stamp == self.prepare_stamp(&inputs_hash)).then_some(inputs_hash)
+    }
+    
+    /// Record addon_src and the build files as prepared from inputs hashing to `inputs_hash`, e.g. after they were
+    /// unpacked from an artifact
+    pub(crate) fn record_prepared(&self, inputs_hash: &str) -> Result<(), VcpkgFfError> {
+        let stamp = self.prepare_stamp_path();
+        if let Some(parent) = stamp.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        journal::write(&stamp, self.prepare_stamp(inputs_hash))?;
+        Ok(())
+    }
+    
+    /// Variables available to every template
+    fn template_context(&self, version: &FfmpegVersion, custom: &Customizations) -> minijinja::Value {
+        minijinja::context! {
+            triplet => &self.triplet,
+            ffmpeg_version => version.to_string(),
+            tool_version => marker::TOOL_VERSION,
+            features => vcpkg_manager::FFMPEG_FEATURES.to_vec(),
+            electron => &self.electron,
+            napi_version => self.napi_target(),
+            bun => self.bun,
+            binding_style => self.binding_style.name(),
+            target => self.target.name(),
+            hwaccels => self.hwdevice_types(),
+            frame_tap => custom.config.advanced.frame_tap,
+        }
+    }
+    
+    /// av_hwdevice types the installed libraries are built with, read from addon_src/config.h (create_config_h runs first);
+    /// the fallback config.h enables none
+    fn hwdevice_types(&self) -> Vec<&'static str> {
+        let content = fs::read_to_string(self.addon_src_dir.join("config.h")).unwrap_or_default();
+        config_h::hwdevice_types(&config_h::define_values(&content))
+    }
+    
+    /// Add conditional compilation for postproc to opt_common.c
+    fn opt_common_c_rules() -> Vec<PatchRule> {
+        vec![
+            PatchRule::wrap_in_conditional(
+                "    PRINT_LIB_INFO(postproc,   POSTPROC,   flags, level);",
+                "CONFIG_POSTPROC",
+            ),
+        ]
+    }
+    
+    /// Give ffmpeg_opt.c ffmpeg_opt_reset(), restoring the global options between runs (some are static)
+    fn ffmpeg_opt_c_rules() -> Vec<PatchRule> {
+        vec![PatchRule::append_block("void ffmpeg_opt_reset(void)", FFMPEG_OPT_RESET)]
+    }
+    
+    /// Make ffmpeg_dec.c use ffmpeg's compat stdbit.h instead of system stdbit.h
+    /// and add MSVC compatibility for _Generic macro (Windows only)
+    fn ffmpeg_dec_c_rules() -> Vec<PatchRule> {
+        // macOS/Linux 使用系统的 stdbit.h（C23 标准库），不需要修改
+        if !cfg!(target_os = "windows") {
+            return Vec::new();
+        }
+        
+        vec![
+            PatchRule::replace_text("#include <stdbit.h>", "#include \"compat/stdbit/stdbit.h\""),
+            PatchRule::insert_after_include("#include \"compat/stdbit/stdbit.h\"", MSVC_STDBIT_COMPAT),
+        ]
+    }
+    
+    /// Let `-i -` open the run's options.input through ffmpeg_input_open() in ffmpeg_demux.c
+    fn ffmpeg_demux_c_rules() -> Vec<PatchRule> {
+        vec![
+            PatchRule::insert_after_include("#include \"ffmpeg.h\"", INPUT_OPEN_DECLARATION),
+            PatchRule::replace_text(
+                "    err = avformat_open_input(&ic, filename, file_iformat, &o->g->format_opts);\n",
+                INPUT_OPEN_CALL,
+            ),
+        ]
+    }
+    
+    /// Let the output `-` write to the run's options.output through ffmpeg_output_open() in ffmpeg_mux_init.c
+    fn ffmpeg_mux_init_c_rules() -> Vec<PatchRule> {
+        vec![
+            PatchRule::insert_after_include("#include \"ffmpeg.h\"", OUTPUT_OPEN_DECLARATION),
+            PatchRule::replace_text(
+                "        if ((err = avio_open2(&oc->pb, filename, AVIO_FLAG_WRITE,\n",
+                OUTPUT_OPEN_CALL,
+            ),
+        ]
+    }
+    
+    /// Close the options.output AVIOContext with ffmpeg_output_closep() in ffmpeg_mux.c, avio_closep() would
+    /// take its opaque for a URLContext
+    fn ffmpeg_mux_c_rules() -> Vec<PatchRule> {
+        vec![
+            PatchRule::insert_after_include("#include \"ffmpeg.h\"", OUTPUT_CLOSE_DECLARATION),
+            PatchRule::replace_text("avio_closep(&", "ffmpeg_output_closep(&"),
+        ]
+    }
+    
+    /// `[advanced] frame_tap`: hand the video frames leaving each filtergraph to ffmpeg_frame_tap() in ffmpeg_filter.c
+    fn ffmpeg_filter_c_rules() -> Vec<PatchRule> {
+        vec![
+            PatchRule::insert_after_include("#include \"ffmpeg.h\"", FRAME_TAP_DECLARATION),
+            PatchRule::replace_text(
+                "    frame->time_base = av_buffersink_get_time_base(filter);\n",
+                FRAME_TAP_CALL,
+            ),
+        ]
+    }
+    
+    /// N-API version the addon targets: `--napi-version`, capped at what Bun provides with `--bun`
+    fn napi_target(&self) -> Option<u32> {
+        if !self.bun {
+            return self.napi_version;
+        }
+        Some(self.napi_version.map_or(napi_version::BUN_NAPI_VERSION, |version| version.min(napi_version::BUN_NAPI_VERSION)))
+    }
+    
+    /// Generated binding file in addon_src and its template: the binding style's, or the C ABI of the Deno target.
+    /// None for the WebAssembly target, whose JavaScript calls the exported functions of ffmpeg.c directly
+    fn binding_file(&self) -> Option<(&'static str, &'static str)> {
+        match self.target {
+            AddonTarget::Node => Some(self.binding_style.binding_file()),
+            AddonTarget::Deno => Some(("ffmpeg_deno.c", "deno_ffi.c.jinja")),
+            AddonTarget::Wasm => None,
+        }
+    }
+    
+    /// Create binding.c, binding.cc, src/lib.rs or ffmpeg_deno.c, depending on the binding style and target
+    fn create_binding(&self, version: &FfmpegVersion, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let binding_file = self.binding_file();
+        let file_name = binding_file.map(|(file_name, _)| file_name);
+        
+        // 切换风格或目标后删除另一种生成的文件，避免被当作额外源码
+        let generated = [BindingStyle::C, BindingStyle::NodeAddonApi, BindingStyle::NapiRs]
+            .map(|style| (style.binding_file().0, format!("the {} binding style", style.name())))
+            .into_iter()
+            .chain(std::iter::once(("ffmpeg_deno.c", "the deno target".to_string())));
+        for (stale_name, generated_for) in generated {
+            let stale = self.addon_src_dir.join(stale_name);
+            if Some(stale_name) != file_name && fs::read_to_string(&stale).is_ok_and(|content| Marker::parse(&content).is_some()) {
+                journal::remove_file(&stale)?;
+                say!("✓ Removed {} generated for {}", stale_name, generated_for);
+            }
+        }
+        
+        let Some((file_name, template)) = binding_file else {
+            return Ok(());
+        };
+        let binding_path = self.addon_src_dir.join(file_name);
+        let binding_content = custom.templates.render(template, self.template_context(version, custom))?;
+        
+        if self.write_generated(&binding_path, &binding_content, custom)? {
+            say!("✓ {} created: {}", file_name, binding_path.display());
+        } else {
+            say!("✓ {} is up to date, skipping", file_name);
+        }
+        Ok(())
+    }
+    
+    /// Apply patch rules, then user rules and matching external patches, to a copy of `source`,
+    /// writing a stamped result to `target` inside addon_src. The ffmpeg source tree itself is never modified.
+    /// Returns false when the existing marker shows the target is already up to date.
+    fn patch_file(
+        &self,
+        source: &Path,
+        target: &Path,
+        mut rules: Vec<PatchRule>,
+        custom: &Customizations,
+    ) -> Result<bool, VcpkgFfError> {
+        let file_name = target.file_name().and_then(|n| n.to_str()).unwrap_or("?").to_string();
+        rules.extend(custom.user.rules_for(&file_name));
+        let external: Vec<&ExternalPatch> = custom.external.iter().filter(|p| p.target == target).collect();
+        
+        // 规则哈希同时覆盖内置规则、用户规则和外部补丁
+        let rule_hash = marker::content_hash(&format!("{:?}{:?}",
+            rules,
+            external.iter().map(|p| &p.file_patch).collect::<Vec<_>>()));
+        
+        let mut source_content = fs::read_to_string(source)?;
+        if let Some(stamp) = Marker::parse(&source_content) {
+            // 旧版本直接修改了 ffmpeg 源码，其中的修改会让规则以为已经应用过；有备份时从原始文件重新生成
+            let backup = source.strip_prefix(&self.base_dir).ok().map(|relative| self.backup_dir.join(relative));
+            match backup.and_then(|backup| fs::read_to_string(backup).ok()).filter(|original| Marker::parse(original).is_none()) {
+                Some(original) => {
+                    say!("⚠ {} was modified in place by vcpkg_ff {}, patching the original from {} instead",
+                        source.display(), stamp.tool_version, self.backup_dir.display());
+                    source_content = original;
+                }
+                None => say!("⚠ {} was modified in place by vcpkg_ff {}, run `vcpkg_ff revert-patches` to restore it",
+                    source.display(), stamp.tool_version),
+            }
+        }
+        
+        let expected = Marker::new(&custom.provenance, rule_hash, marker::content_hash(&source_content));
+        if target.exists() && !self.needs_generating(target, &expected)? {
+            return Ok(false);
+        }
+        
+        let (mut patched, report) = PatchEngine::new(rules).apply(marker::strip(&source_content))?;
+        if !report.results.is_empty() {
+            report.print(&file_name);
+        }
+        if !report.is_success() {
+            say!("⚠ Some {} patch rules were not applied: {}", file_name, report.failed_rules().join(", "));
+        }
+        
+        for patch in external {
+            let (result, hunk_results) = diff_patch::apply_file_patch(&patched, &patch.file_patch)
+                .map_err(|reason| VcpkgFfError::PatchFailed {
+                    file: target.display().to_string(),
+                    rule: patch.patch_name.clone(),
+                    reason,
+                })?;
+            patched = result;
+            
+            say!("✓ {}: patched {}", patch.patch_name, target.display());
+            for (index, result) in hunk_results.iter().enumerate() {
+                if result.offset != 0 || result.fuzz != 0 {
+                    say!("  hunk #{} applied with offset {:+} lines, fuzz {}", index + 1, result.offset, result.fuzz);
+                }
+            }
+        }
+        
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        journal::write(target, expected.stamp(&patched))?;
+        Ok(true)
+    }
+    
+    /// Write generated content (plus any user rules for it) with a marker, unless the existing
+    /// file already carries the same marker. Returns false when the file was up to date.
+    fn write_generated(&self, path: &Path, content: &str, custom: &Customizations) -> Result<bool, VcpkgFfError> {
+        let file_name = path.file_name().and_then(|n| n.to_str()).unwrap_or("?").to_string();
+        let rules = custom.user.rules_for(&file_name);
+        
+        let content_hash = marker::content_hash(content);
+        let rule_hash = if rules.is_empty() {
+            content_hash.clone()
+        } else {
+            marker::content_hash(&format!("{}{:?}", content, rules))
+        };
+        let expected = Marker::new(&custom.provenance, rule_hash, content_hash);
+        
+        if path.exists() && !self.needs_generating(path, &expected)? {
+            return Ok(false);
+        }
+        
+        let (content, report) = PatchEngine::new(rules).apply(content)?;
+        if !report.results.is_empty() {
+            report.print(&file_name);
+        }
+        
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        journal::write(path, expected.stamp(&content))?;
+        Ok(true)
+    }
+    
+    /// Whether the existing file at `path` has to be written again to carry `expected`, saying so when an older
+    /// vcpkg_ff version generated it
+    fn needs_generating(&self, path: &Path, expected: &Marker) -> Result<bool, VcpkgFfError> {
+        let content = fs::read_to_string(path)?;
+        if expected.is_current(&content) {
+            return Ok(false);
+        }
+        if let Some(stamp) = Marker::parse(&content).filter(Marker::is_outdated) {
+            let relative = path.strip_prefix(&self.addon_src_dir).unwrap_or(path);
+            say!("  {} was generated by vcpkg_ff {}, regenerating it with {}", relative.display(), stamp.tool_version, marker::generator_version());
+        }
+        Ok(true)
+    }
+    
+    /// Restore every file backed up under .vcpkg_ff/backups/ and delete files created by the tool.
+    /// Older versions modified the ffmpeg source tree in place; this undoes those modifications.
+    pub fn revert_patches(&self) -> Result<(), VcpkgFfError> {
+        if !self.backup_dir.exists() {
+            say!("✓ No backups found, nothing to revert");
+            return Ok(());
+        }
+        
+        say!("Reverting patched files from: {}", self.backup_dir.display());
+        
+        let created_list = self.backup_dir.join(CREATED_FILES_LIST);
+        if let Ok(created) = fs::read_to_string(&created_list) {
+            for entry in created.lines().filter(|line| !line.is_empty()) {
+                let path = self.base_dir.join(entry);
+                if path.exists() {
+                    journal::remove_file(&path)?;
+                    say!("✓ Removed generated file: {}", path.display());
+                }
+            }
+            journal::remove_file(&created_list)?;
+        }
+        
+        let mut pending = vec![self.backup_dir.clone()];
+        while let Some(dir) = pending.pop() {
+            for entry in fs::read_dir(&dir)?.flatten() {
+                let backup_path = entry.path();
+                if backup_path.is_dir() {
+                    pending.push(backup_path);
+                    continue;
+                }
+                
+                let Ok(relative) = backup_path.strip_prefix(&self.backup_dir) else {
+                    continue;
+                };
+                let original = self.base_dir.join(relative);
+                if let Some(parent) = original.parent() {
+                    fs::create_dir_all(parent)?;
+                }
+                fs::copy(&backup_path, &original)?;
+                say!("✓ Restored: {}", original.display());
+            }
+        }
+        
+        journal::remove_dir_all(&self.backup_dir)?;
+        say!("✓ All original files restored");
+        Ok(())
+    }
+    
+    /// Check the provenance stamps of every file in addon_src: the content must not have been
+    /// edited since it was stamped, and the stamp must match this tool, the ffmpeg tree and the triplet
+    pub fn verify_stamps(&self) -> Result<(), VcpkgFfError> {
+        if !self.addon_src_dir.exists() {
+            return Err(VcpkgFfError::NotPrepared { path: self.addon_src_dir.clone(), step: "prepare-addon" });
+        }
+        
+        let ffmpeg_version = FfmpegVersion::detect(&self.ffmpeg_source_dir).ok().map(|v| v.to_string());
+        let features = vcpkg_manager::FFMPEG_FEATURES.join(",");
+        say!("Verifying stamps in: {}", self.addon_src_dir.display());
+        
+        let mut files = Vec::new();
+        let mut pending = vec![self.addon_src_dir.clone()];
+        while let Some(dir) = pending.pop() {
+            for path in fs::read_dir(&dir)?.flatten().map(|entry| entry.path()) {
+                if path.is_dir() {
+                    pending.push(path);
+                } else {
+                    files.push(path);
+                }
+            }
+        }
+        files.sort();
+        
+        let (mut verified, mut failed) = (0, 0);
+        for file in &files {
+            let relative = file.strip_prefix(&self.addon_src_dir).unwrap_or(file).display();
+            let content = fs::read_to_string(file).unwrap_or_default();
+            let Some(stamp) = Marker::parse(&content) else {
+                say!("  - {}: not stamped (copied verbatim)", relative);
+                continue;
+            };
+            
+            let mut problems = Vec::new();
+            if !stamp.output_matches(&content) {
+                problems.push("content was modified after it was generated".to_string());
+            }
+            if stamp.triplet != self.triplet {
+                problems.push(format!("built for triplet {}, current triplet is {}", stamp.triplet, self.triplet));
+            }
+            if let Some(version) = &ffmpeg_version {
+                if &stamp.ffmpeg_version != version {
+                    problems.push(format!("generated from ffmpeg {}, source tree is ffmpeg {}", stamp.ffmpeg_version, version));
+                }
+            }
+            if stamp.features != features {
+                problems.push(format!("generated for features [{}], expected [{}]", stamp.features, features));
+            }
+            
+            if problems.is_empty() {
+                if stamp.is_outdated() {
+                    say!("  ⚠ {}: produced by vcpkg_ff {} (this is {}), the next preparation regenerates it",
+                        relative, stamp.tool_version, marker::generator_version());
+                } else {
+                    say!("  ✓ {}", relative);
+                }
+                verified += 1;
+            } else {
+                say_error!("  ✗ {}: {}", relative, problems.join("; "));
+                failed += 1;
+            }
+        }
         
-        Self {
-            ffmpeg_source_dir,
-            addon_src_dir,
-            vcpkg_root,
+        if failed > 0 {
+            say_error!("{} of {} stamped file(s) failed verification", failed, verified + failed);
+            return Err(VcpkgFfError::ValidationFailed { check: "stamps", count: failed });
         }
+        say!("✓ {} stamped file(s) verified", verified);
+        Ok(())
     }
     
-    /// Prepare addon source code
-    pub fn prepare_addon_source(&self) -> Result<(), Box<dyn std::error::Error>> {
-        println!("Preparing Node.js addon source code...");
+    /// Load user-supplied unified-diff `.patch` files from the patches/ directory (in file name order).
+    /// They are applied after the built-in modifications of each file.
+    fn load_external_patches(&self) -> Result<Vec<ExternalPatch>, VcpkgFfError> {
+        if !self.patches_dir.exists() {
+            return Ok(Vec::new());
+        }
         
-        if !self.addon_src_dir.exists() {
-            fs::create_dir_all(&self.addon_src_dir)?;
-            println!("✓ Created addon_src directory");
+        let mut patch_files: Vec<PathBuf> = fs::read_dir(&self.patches_dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "patch"))
+            .collect();
+        patch_files.sort();
+        
+        if !patch_files.is_empty() {
+            say!("Found {} external patch file(s) in {}", patch_files.len(), self.patches_dir.display());
         }
         
-        self.create_config_h()?;
-        self.modify_opt_common_c()?;
-        self.modify_ffmpeg_dec_c()?;
-        self.copy_and_modify_ffmpeg_c()?;
-        self.create_binding_c()?;
+        let mut external = Vec::new();
+        for patch_file in &patch_files {
+            let patch_name = patch_file.file_name().and_then(|n| n.to_str()).unwrap_or("?").to_string();
+            let file_patches = diff_patch::parse_unified_diff(&fs::read_to_string(patch_file)?)
+                .map_err(|message| VcpkgFfError::Config { path: patch_file.clone(), message })?;
+            
+            for file_patch in file_patches {
+                external.push(ExternalPatch {
+                    patch_name: patch_name.clone(),
+                    target: self.resolve_patch_target(&file_patch.path),
+                    file_patch,
+                });
+            }
+        }
         
-        println!("✓ Node.js addon source code preparation completed");
-        Ok(())
+        Ok(external)
     }
     
-    /// Create config.h file (required for ffmpeg compilation)
-    fn create_config_h(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let config_h_path = self.ffmpeg_source_dir.join("config.h");
+    /// Copy and patch files targeted by external patches that aren't part of the built-in source set
+    fn apply_remaining_external_patches(&self, patch_set: &PatchSet, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        let mut handled = vec![self.addon_src_dir.join("ffmpeg.c"), self.addon_src_dir.join("ffprobe.c")];
+        handled.extend(patch_set.fftools_sources.iter().map(|name| self.addon_src_dir.join(name)));
         
-        // 检查现有文件是否匹配当前平台
-        if config_h_path.exists() {
-            let existing_content = fs::read_to_string(&config_h_path)?;
-            let is_windows_config = existing_content.contains("Windows build for Node.js addon");
-            let should_be_windows = cfg!(target_os = "windows");
-            
-            // 如果平台匹配，跳过重新生成
-            if (is_windows_config && should_be_windows) || (!is_windows_config && !should_be_windows) {
-                println!("✓ config.h already exists and matches current platform, skipping creation");
-                return Ok(());
-            } else {
-                println!("⚠ config.h exists but is for different platform, regenerating...");
-            }
-        }
-        
-        let config_h_content = if cfg!(target_os = "windows") {
-            // Windows configuration
-            r#"/* config.h - Generated for Windows build */
-#ifndef CONFIG_H
-#define CONFIG_H
-
-/* Windows specific defines */
-#define HAVE_IO_H 1
-#define HAVE_UNISTD_H 0
-#define HAVE_SYS_RESOURCE_H 0
-#define HAVE_GETPROCESSTIMES 1
-#define HAVE_GETPROCESSMEMORYINFO 1
-#define HAVE_SETCONSOLECTRLHANDLER 1
-#define HAVE_SYS_SELECT_H 0
-#define HAVE_TERMIOS_H 0
-#define HAVE_KBHIT 1
-#define HAVE_PEEKNAMEDPIPE 1
-#define HAVE_GETSTDHANDLE 1
-#define HAVE_GETRUSAGE 0
-
-/* FFmpeg components */
-#define CONFIG_AVUTIL 1
-#define CONFIG_AVCODEC 1
-#define CONFIG_AVFORMAT 1
-#define CONFIG_AVDEVICE 1
-#define CONFIG_AVFILTER 1
-#define CONFIG_SWSCALE 1
-#define CONFIG_SWRESAMPLE 1
-#define CONFIG_POSTPROC 0
-
-/* Architecture */
-#define ARCH_X86_32 0
-#define ARCH_X86_64 1
-
-/* Threading */
-#define HAVE_PTHREADS 0
-#define HAVE_W32THREADS 1
-
-/* Endianness */
-#define HAVE_BIGENDIAN 0
-
-/* Math functions - MSVC provides these as intrinsics */
-#define HAVE_LRINT 1
-#define HAVE_LRINTF 1
-
-/* FFmpeg data directory - empty for Node.js addon */
-#define FFMPEG_DATADIR ""
-#define AVCONV_DATADIR ""
-
-/* Build configuration */
-#define CONFIG_THIS_YEAR 2025
-#define FFMPEG_CONFIGURATION "Windows build for Node.js addon"
-#define CC_IDENT "MSVC"
-#define FFMPEG_VERSION "N/A"
-
-#endif /* CONFIG_H */
-"#.to_string()
-        } else {
-            // Unix (macOS/Linux) configuration
-            let arch_defines = if cfg!(target_arch = "aarch64") {
-                "#define ARCH_X86_64 0\n#define ARCH_AARCH64 1\n"
-            } else {
-                "#define ARCH_X86_64 1\n#define ARCH_AARCH64 0\n"
-            };
-            
-            format!(r#"/* config.h - Generated for Unix build (macOS/Linux) */
-#ifndef CONFIG_H
-#define CONFIG_H
-
-/* Unix specific defines */
-#define HAVE_IO_H 0
-#define HAVE_UNISTD_H 1
-#define HAVE_SYS_RESOURCE_H 1
-#define HAVE_GETPROCESSTIMES 0
-#define HAVE_GETPROCESSMEMORYINFO 0
-#define HAVE_SETCONSOLECTRLHANDLER 0
-#define HAVE_SYS_SELECT_H 1
-#define HAVE_TERMIOS_H 1
-#define HAVE_KBHIT 0
-#define HAVE_PEEKNAMEDPIPE 0
-#define HAVE_GETSTDHANDLE 0
-#define HAVE_GETRUSAGE 1
-
-/* FFmpeg components */
-#define CONFIG_AVUTIL 1
-#define CONFIG_AVCODEC 1
-#define CONFIG_AVFORMAT 1
-#define CONFIG_AVDEVICE 1
-#define CONFIG_AVFILTER 1
-#define CONFIG_SWSCALE 1
-#define CONFIG_SWRESAMPLE 1
-#define CONFIG_POSTPROC 0
-
-/* Architecture */
-#define ARCH_X86_32 0
-{}
-
-/* Threading */
-#define HAVE_PTHREADS 1
-#define HAVE_W32THREADS 0
-
-/* Endianness */
-#define HAVE_BIGENDIAN 0
-
-/* Math functions */
-#define HAVE_LRINT 1
-#define HAVE_LRINTF 1
-
-/* System math library functions (macOS/Linux have these) */
-#define HAVE_CBRT 1
-#define HAVE_CBRTF 1
-#define HAVE_COPYSIGN 1
-#define HAVE_ERF 1
-#define HAVE_HYPOT 1
-#define HAVE_RINT 1
-#define HAVE_ROUND 1
-#define HAVE_ROUNDF 1
-#define HAVE_TRUNC 1
-#define HAVE_TRUNCF 1
-#define HAVE_ATANF 1
-#define HAVE_ATAN2F 1
-#define HAVE_POWF 1
-
-/* FFmpeg data directory - empty for Node.js addon */
-#define FFMPEG_DATADIR ""
-#define AVCONV_DATADIR ""
-
-/* Build configuration */
-#define CONFIG_THIS_YEAR 2025
-#define FFMPEG_CONFIGURATION "Unix build for Node.js addon"
-#define CC_IDENT "GCC/Clang"
-#define FFMPEG_VERSION "N/A"
-
-#endif /* CONFIG_H */
-"#, arch_defines)
-        };
+        let mut remaining: Vec<&ExternalPatch> = custom.external
+            .iter()
+            .filter(|p| !handled.contains(&p.target))
+            .collect();
+        remaining.sort_by(|a, b| a.target.cmp(&b.target));
+        remaining.dedup_by(|a, b| a.target == b.target);
+        
+        for patch in remaining {
+            let source = self.ffmpeg_source_dir.join(&patch.file_patch.path);
+            if patch.file_patch.deleted {
+                if patch.target.exists() {
+                    journal::remove_file(&patch.target)?;
+                    say!("✓ {}: removed {}", patch.patch_name, patch.target.display());
+                }
+                continue;
+            }
+            if patch.file_patch.created && !source.exists() {
+                let (content, _) = diff_patch::apply_file_patch("", &patch.file_patch)
+                    .map_err(|reason| VcpkgFfError::PatchFailed {
+                        file: patch.target.display().to_string(),
+                        rule: patch.patch_name.clone(),
+                        reason,
+                    })?;
+                if self.write_generated(&patch.target, &content, custom)? {
+                    say!("✓ {}: created {}", patch.patch_name, patch.target.display());
+                } else {
+                    say!("✓ {} already created, skipping", patch.target.display());
+                }
+                continue;
+            }
+            if !source.exists() {
+                return Err(VcpkgFfError::PatchFailed {
+                    file: source.display().to_string(),
+                    rule: patch.patch_name.clone(),
+                    reason: "the file does not exist".to_string(),
+                });
+            }
+            if !self.patch_file(&source, &patch.target, Vec::new(), custom)? {
+                say!("✓ {} already patched, skipping", patch.target.display());
+            }
+        }
         
-        fs::write(&config_h_path, config_h_content)?;
-        println!("✓ config.h created for {}: {}", 
-            if cfg!(target_os = "windows") { "Windows" } else { "Unix" },
-            config_h_path.display());
         Ok(())
     }
     
-    /// Copy and modify ffmpeg.c
-    fn copy_and_modify_ffmpeg_c(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let source_file = self.ffmpeg_source_dir.join("fftools").join("ffmpeg.c");
-        let target_file = self.addon_src_dir.join("ffmpeg.c");
-        
-        if !source_file.exists() {
-            return Err(format!("Source file does not exist: {}", source_file.display()).into());
+    /// Copy extra sources from addon_patches/sources/ verbatim into addon_src and
+    /// warn about user rules whose target file was not produced
+    fn copy_extra_sources(&self, custom: &Customizations) -> Result<(), VcpkgFfError> {
+        for (source, relative) in custom.user.extra_sources() {
+            let target = self.addon_src_dir.join(relative);
+            let content = fs::read(source)?;
+            if target.exists() && fs::read(&target)? == content {
+                continue;
+            }
+            
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            journal::write(&target, content)?;
+            say!("✓ Copied extra source: {}", target.display());
         }
         
-        println!("Copying and modifying ffmpeg.c...");
-        
-        let content = fs::read_to_string(&source_file)?;
-        let modified_content = self.modify_ffmpeg_c_content(&content)?;
-        fs::write(&target_file, modified_content)?;
+        for file_name in custom.user.target_files() {
+            if !self.addon_src_dir.join(file_name).exists() {
+                say!("⚠ addon_patches rule targets {} which is not part of addon_src, rule ignored", file_name);
+            }
+        }
         
-        println!("✓ ffmpeg.c copied and modified to: {}", target_file.display());
         Ok(())
     }
     
-    /// Modify ffmpeg.c content
-    fn modify_ffmpeg_c_content(&self, content: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let mut modified = content.to_string();
-        
-        modified = modified.replace("static int transcode(Scheduler *sch)", "int transcode(Scheduler *sch)");
-        modified = modified.replace("static void ffmpeg_cleanup(int ret)", "void ffmpeg_cleanup(int ret)");
-        modified = self.remove_main_function(&modified)?;
-        modified = self.add_napi_include(&modified)?;
-        modified = self.add_ffmpeg_run_function(&modified)?;
-        
-        Ok(modified)
+    /// Map a path from a patch header (relative to the ffmpeg tree) to its copy in addon_src:
+    /// fftools files are copied flat, anything else keeps its relative path so the copy
+    /// shadows the original on the include path
+    fn resolve_patch_target(&self, patch_path: &str) -> PathBuf {
+        match patch_path.strip_prefix("fftools/") {
+            Some(file_name) => self.addon_src_dir.join(file_name),
+            None => self.addon_src_dir.join(patch_path),
+        }
     }
     
-    /// Add node_api.h include
-    fn add_napi_include(&self, content: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let include_marker = "#include \"ffmpeg_utils.h\"";
-        if content.contains("#include <node_api.h>") {
-            return Ok(content.to_string());
+    /// Run a syntax-only compile over the generated ffmpeg.c, ffprobe.c and binding.c so broken patches
+    /// are reported now instead of after a long node-gyp build
+    pub fn validate_generated_sources(&self) -> Result<(), VcpkgFfError> {
+        if !self.syntax_check {
+            say!("⚠ Syntax validation disabled, skipping");
+            return Ok(());
         }
         
-        if let Some(pos) = content.find(include_marker) {
-            let insert_pos = pos + include_marker.len();
-            let result = format!("{}\n#include <node_api.h>\n{}", 
-                &content[..insert_pos],
-                &content[insert_pos..]
-            );
-            return Ok(result);
+        let mut include_dirs = vec![
+            self.addon_src_dir.clone(),
+            self.ffmpeg_source_dir.clone(),
+            self.ffmpeg_source_dir.join("fftools"),
+            self.vcpkg_root.join("installed").join(&self.triplet).join("include"),
+        ];
+        if cfg!(target_os = "windows") {
+            include_dirs.push(self.ffmpeg_source_dir.join("compat").join("atomics").join("win32"));
+        }
+        match syntax_check::find_node_include_dir() {
+            Some(node_include) => include_dirs.push(node_include),
+            None if self.target == AddonTarget::Node => say!("⚠ node_api.h not found (is node installed?), binding checks may fail"),
+            None => {}
         }
         
-        Ok(content.to_string())
-    }
-    
-    /// Remove main function
-    fn remove_main_function(&self, content: &str) -> Result<String, Box<dyn std::error::Error>> {
-        let main_start = "int main(int argc, char **argv)";
-        if let Some(start_pos) = content.find(main_start) {
-            let func_start = content[start_pos..].find('{');
-            if let Some(func_start_pos) = func_start {
-                let brace_start = start_pos + func_start_pos;
-                
-                let mut brace_count = 0;
-                let mut in_string = false;
-                let mut escape_next = false;
-                let mut end_pos = None;
-                
-                let bytes = content[brace_start..].as_bytes();
-                for (i, &byte) in bytes.iter().enumerate() {
-                    let ch = byte as char;
-                    
-                    if escape_next {
-                        escape_next = false;
-                        continue;
-                    }
-                    
-                    match ch {
-                        '\\' if in_string => escape_next = true,
-                        '"' => in_string = !in_string,
-                        '{' if !in_string => {
-                            brace_count += 1;
-                        }
-                        '}' if !in_string => {
-                            brace_count -= 1;
-                            if brace_count == 0 {
-                                end_pos = Some(brace_start + i + 1);
-                                break;
-                            }
-                        }
-                        _ => {}
-                    }
-                }
-                
-                if let Some(end) = end_pos {
-                    let before = &content[..start_pos];
-                    let after = &content[end..];
-                    
-                    let result = format!("{}/*\n * Main function removed for Node.js addon\n * Use ffmpeg_run() instead\n */\n{}", 
-                        before.trim_end(), 
-                        after.trim_start()
-                    );
-                    
-                    return Ok(result);
+        let Some(checker) = SyntaxChecker::detect(include_dirs) else {
+            say!("⚠ No C compiler found (cl/clang/gcc), skipping syntax validation");
+            return Ok(());
+        };
+        
+        say!("Validating generated sources with {}...", checker.compiler_name());
+        
+        let mut error_count = 0;
+        for file_name in ["ffmpeg.c", "ffprobe.c", "binding.c", "ffmpeg_deno.c"] {
+            let file = self.addon_src_dir.join(file_name);
+            if !file.exists() {
+                continue;
+            }
+            
+            let diagnostics = checker.check(&file)?;
+            if diagnostics.is_empty() {
+                say!("✓ {}: syntax OK", file_name);
+            } else {
+                say_error!("✗ {}: {} error(s)", file_name, diagnostics.len());
+                for diagnostic in &diagnostics {
+                    diagnostic.print_with_context();
                 }
+                error_count += diagnostics.len();
             }
         }
         
-        Ok(content.to_string())
+        if error_count > 0 {
+            return Err(VcpkgFfError::ValidationFailed { check: "syntax", count: error_count });
+        }
+        Ok(())
     }
     
-    /// Add ffmpeg_run function (N-API implementation for Node.js addon)
-    fn add_ffmpeg_run_function(&self, content: &str) -> Result<String, Box<dyn std::error::Error>> {
-        // 检查是否已经存在 ffmpeg_run 函数
-        if content.contains("napi_value ffmpeg_run") {
-            return Ok(content.to_string());
+    /// Check that the patches of ffmpeg.c and ffprobe.c took: transcode() and ffmpeg_cleanup() no longer static, the
+    /// entry point the binding calls defined once and no main() left. A rule that silently stops matching, e.g.
+    /// after upstream renames a function, fails here instead of as a compile or link error much later
+    fn verify_patched_programs(&self) -> Result<(), VcpkgFfError> {
+        let ffmpeg_c = self.addon_src_dir.join("ffmpeg.c");
+        let ffprobe_c = self.addon_src_dir.join("ffprobe.c");
+        let mut problems = Vec::new();
+        
+        let src = fs::read_to_string(&ffmpeg_c)?;
+        let code = c_lexer::code_tokens(&src);
+        for function in NON_STATIC_FUNCTIONS {
+            let declarations = top_level_declarations(&src, &code, function);
+            if declarations.is_empty() {
+                problems.push(format!("  {}: {}() not found, was it renamed upstream?", ffmpeg_c.display(), function));
+            }
+            for (line, _) in declarations.iter().filter(|(_, is_static)| *is_static) {
+                problems.push(format!("  {}:{}: {}() is still static", ffmpeg_c.display(), line, function));
+            }
+        }
+        // 只有 C 绑定的 Node.js 插件调用 napi_value ffmpeg_run，其余绑定调用 ffmpeg_run_argv
+        let entry_point = if self.target == AddonTarget::Node && self.binding_style == BindingStyle::C {
+            "napi_value ffmpeg_run(napi_env env, napi_callback_info info)"
+        } else {
+            "int ffmpeg_run_argv(int argc, char **argv)"
+        };
+        let definitions: Vec<usize> = c_lexer::find_signature_in(&src, &code, entry_point)
+            .iter()
+            .filter(|found| found.body_end.is_some())
+            .map(|found| line_of(&src, found.start))
+            .collect();
+        match definitions.as_slice() {
+            [_] => {}
+            [] => problems.push(format!("  {}: `{}` is not defined", ffmpeg_c.display(), entry_point)),
+            lines => problems.extend(lines.iter().map(|line| format!("  {}:{}: `{}` is defined {} times",
+                ffmpeg_c.display(), line, entry_point, lines.len()))),
+        }
+        for (file, src) in [(&ffmpeg_c, src.clone()), (&ffprobe_c, fs::read_to_string(&ffprobe_c)?)] {
+            for found in c_lexer::find_signature_in(&src, &c_lexer::code_tokens(&src), "int main(") {
+                problems.push(format!("  {}:{}: main() is still defined", file.display(), line_of(&src, found.start)));
+            }
         }
         
-        let run_function = r#"
-
-/**
- * Run ffmpeg with arguments (N-API function for Node.js addon)
- * This function replaces the main() function for use in Node.js addon
- */
-napi_value ffmpeg_run(napi_env env, napi_callback_info info)
-{
-    napi_status status;
-    size_t argc = 1;
-    napi_value argv[1];
-    napi_value result;
-    Scheduler *sch = NULL;
-    int ret;
-    BenchmarkTimeStamps ti;
-    
-    // 获取参数
-    status = napi_get_cb_info(env, info, &argc, argv, NULL, NULL);
-    if (status != napi_ok) {
-        napi_throw_error(env, NULL, "Failed to get callback info");
-        return NULL;
-    }
-    
-    if (argc < 1) {
-        napi_throw_type_error(env, NULL, "Expected an array of arguments");
-        return NULL;
-    }
-    
-    // 检查第一个参数是否为数组
-    napi_valuetype valuetype;
-    status = napi_typeof(env, argv[0], &valuetype);
-    if (status != napi_ok || valuetype != napi_object) {
-        napi_throw_type_error(env, NULL, "Expected an array of arguments");
-        return NULL;
-    }
-    
-    // 检查是否为数组
-    bool is_array;
-    status = napi_is_array(env, argv[0], &is_array);
-    if (status != napi_ok || !is_array) {
-        napi_throw_type_error(env, NULL, "Expected an array of arguments");
-        return NULL;
-    }
-    
-    // 获取数组长度
-    uint32_t array_length;
-    status = napi_get_array_length(env, argv[0], &array_length);
-    if (status != napi_ok) {
-        napi_throw_error(env, NULL, "Failed to get array length");
-        return NULL;
-    }
-    
-    // 分配内存存储字符串参数
-    // 需要额外一个位置给"ffmpeg"程序名
-    int total_args = (int)array_length + 1;
-    char **argv_ptr = (char **)av_mallocz(sizeof(char *) * total_args);
-    if (!argv_ptr) {
-        napi_throw_error(env, NULL, "Failed to allocate memory");
-        return NULL;
-    }
-    
-    // 存储字符串内容的内存（需要持久化）
-    char **str_storage = (char **)av_mallocz(sizeof(char *) * total_args);
-    if (!str_storage) {
-        av_free(argv_ptr);
-        napi_throw_error(env, NULL, "Failed to allocate memory");
-        return NULL;
+        if problems.is_empty() {
+            say!("✓ Patched ffmpeg.c and ffprobe.c export the functions the addon calls");
+            return Ok(());
+        }
+        for problem in &problems {
+            say_error!("{}", problem);
+        }
+        Err(VcpkgFfError::ValidationFailed { check: "exports", count: problems.len() })
     }
     
-    // 第一个参数是程序名
-    argv_ptr[0] = "ffmpeg";
-    
-    // 从JavaScript数组提取字符串参数
-    for (uint32_t i = 0; i < array_length; i++) {
-        napi_value element;
-        status = napi_get_element(env, argv[0], i, &element);
-        if (status != napi_ok) {
-            // 清理内存
-            for (int j = 0; j < i + 1; j++) {
-                if (str_storage[j]) av_free(str_storage[j]);
-            }
-            av_free(str_storage);
-            av_free(argv_ptr);
-            napi_throw_error(env, NULL, "Failed to get array element");
-            return NULL;
+    /// With CONFIG_POSTPROC 0 the addon doesn't link libpostproc, so opt_common.c and ffprobe.c may only use it below
+    /// `#if CONFIG_POSTPROC`, which their patches add
+    fn verify_postproc_guards(&self) -> Result<(), VcpkgFfError> {
+        let config_h = fs::read_to_string(self.addon_src_dir.join("config.h")).unwrap_or_default();
+        if config_h::define_values(&config_h).get("CONFIG_POSTPROC").is_some_and(|value| value != "0") {
+            return Ok(());
         }
         
-        // 获取字符串值
-        size_t str_len;
-        status = napi_get_value_string_utf8(env, element, NULL, 0, &str_len);
-        if (status != napi_ok) {
-            // 清理内存
-            for (int j = 0; j < i + 1; j++) {
-                if (str_storage[j]) av_free(str_storage[j]);
-            }
-            av_free(str_storage);
-            av_free(argv_ptr);
-            napi_throw_type_error(env, NULL, "Array element must be a string");
-            return NULL;
+        let mut problems = Vec::new();
+        for file_name in ["opt_common.c", "ffprobe.c"] {
+            let file = self.addon_src_dir.join(file_name);
+            let Ok(src) = fs::read_to_string(&file) else {
+                continue;
+            };
+            problems.extend(unguarded_postproc_lines(&src).into_iter()
+                .map(|line| format!("  {}:{}: libpostproc used outside #if CONFIG_POSTPROC", file.display(), line)));
         }
         
-        // 分配内存并复制字符串
-        str_storage[i + 1] = (char *)av_mallocz(str_len + 1);
-        if (!str_storage[i + 1]) {
-            // 清理内存
-            for (int j = 0; j < i + 1; j++) {
-                if (str_storage[j]) av_free(str_storage[j]);
-            }
-            av_free(str_storage);
-            av_free(argv_ptr);
-            napi_throw_error(env, NULL, "Failed to allocate memory for string");
-            return NULL;
+        if problems.is_empty() {
+            return Ok(());
         }
+        for problem in &problems {
+            say_error!("{}", problem);
+        }
+        Err(VcpkgFfError::ValidationFailed { check: "postproc", count: problems.len() })
+    }
+    
+    /// With `--napi-version`, fail if the addon sources call N-API functions newer than the target
+    fn check_napi_calls(&self) -> Result<(), VcpkgFfError> {
+        let Some(target) = self.napi_target() else {
+            return Ok(());
+        };
         
-        size_t copied;
-        status = napi_get_value_string_utf8(env, element, str_storage[i + 1], str_len + 1, &copied);
-        if (status != napi_ok) {
-            // 清理内存
-            for (int j = 0; j < i + 2; j++) {
-                if (str_storage[j]) av_free(str_storage[j]);
+        let mut violations = Vec::new();
+        for file in self.addon_source_files()? {
+            let content = fs::read_to_string(&file)?;
+            for (function, version, line) in napi_version::newer_calls(&content, target) {
+                violations.push(format!("  {}:{}: {} needs N-API {}", file.display(), line, function, version));
             }
-            av_free(str_storage);
-            av_free(argv_ptr);
-            napi_throw_error(env, NULL, "Failed to get string value");
-            return NULL;
         }
         
-        argv_ptr[i + 1] = str_storage[i + 1];
+        if violations.is_empty() {
+            say!("✓ Addon sources only use N-API {} calls", target);
+            return Ok(());
+        }
+        for violation in &violations {
+            say_error!("{}", violation);
+        }
+        Err(VcpkgFfError::ValidationFailed { check: "napi-version", count: violations.len() })
     }
     
-    // 调用ffmpeg核心逻辑
-    init_dynload();
-    
-    setvbuf(stderr, NULL, _IONBF, 0);
-    
-    av_log_set_flags(AV_LOG_SKIP_REPEATED);
-    parse_loglevel(total_args, argv_ptr, options);
-    
-#if CONFIG_AVDEVICE
-    avdevice_register_all();
-#endif
-    avformat_network_init();
-    
-    sch = sch_alloc();
-    if (!sch) {
-        ret = AVERROR(ENOMEM);
-        goto finish;
+    /// Project directory holding vcpkg/, ffmpeg/, addon_src/ and the build files
+    pub fn get_base_dir(&self) -> &Path {
+        &self.base_dir
     }
     
-    ret = ffmpeg_parse_options(total_args, argv_ptr, sch);
-    if (ret < 0)
-        goto finish;
-    
-    if (nb_output_files <= 0 && nb_input_files == 0) {
-        av_log(NULL, AV_LOG_WARNING, "No input or output files specified\n");
-        ret = 1;
-        goto finish;
+    /// Runtime the sources are generated for
+    pub fn get_target(&self) -> AddonTarget {
+        self.target
     }
     
-    if (nb_output_files <= 0) {
-        av_log(NULL, AV_LOG_FATAL, "At least one output file must be specified\n");
-        ret = 1;
-        goto finish;
+    /// Get addon_src directory
+    pub fn get_addon_src_dir(&self) -> &Path {
+        &self.addon_src_dir
     }
     
-    current_time = ti = get_benchmark_time_stamps();
-    ret = transcode(sch);
-    if (ret >= 0 && do_benchmark) {
-        int64_t utime, stime, rtime;
-        current_time = get_benchmark_time_stamps();
-        utime = current_time.user_usec - ti.user_usec;
-        stime = current_time.sys_usec  - ti.sys_usec;
-        rtime = current_time.real_usec - ti.real_usec;
-        av_log(NULL, AV_LOG_INFO,
-               "bench: utime=%0.3fs stime=%0.3fs rtime=%0.3fs\n",
-               utime / 1000000.0, stime / 1000000.0, rtime / 1000000.0);
+    pub fn get_triplet(&self) -> &str {
+        &self.triplet
     }
     
-    ret = received_nb_signals                 ? 255 :
-          (ret == FFMPEG_ERROR_RATE_EXCEEDED) ?  69 : ret;
-    
-finish:
-    if (ret == AVERROR_EXIT)
-        ret = 0;
-    
-    ffmpeg_cleanup(ret);
-    
-    sch_free(&sch);
-    
-    // 清理字符串内存
-    for (int i = 1; i < total_args; i++) {
-        if (str_storage[i]) av_free(str_storage[i]);
+    /// Directory of the ffmpeg sources addon_src is generated from
+    pub(crate) fn get_ffmpeg_source_dir(&self) -> &Path {
+        &self.ffmpeg_source_dir
     }
-    av_free(str_storage);
-    av_free(argv_ptr);
     
-    // 返回结果
-    status = napi_create_int32(env, ret, &result);
-    if (status != napi_ok) {
-        return NULL;
+    /// vcpkg's installed tree of the triplet, which the build files compile and link against
+    pub(crate) fn get_installed_dir(&self) -> PathBuf {
+        self.vcpkg_root.join("installed").join(&self.triplet)
     }
     
-    return result;
+    /// Directory for captured build logs
+    pub fn get_log_dir(&self) -> PathBuf {
+        self.base_dir.join(".vcpkg_ff").join("logs")
+    }
 }
-"#;
-        
-        Ok(format!("{}{}", content, run_function))
+
+impl Default for AddonPreparer {
+    fn default() -> Self {
+        Self::new()
     }
-    
-    /// Modify opt_common.c to add conditional compilation for postproc
-    fn modify_opt_common_c(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let opt_common_c_path = self.ffmpeg_source_dir.join("fftools").join("opt_common.c");
-        
-        if !opt_common_c_path.exists() {
-            println!("⚠ opt_common.c not found, skipping modification");
-            return Ok(());
-        }
-        
-        let content = fs::read_to_string(&opt_common_c_path)?;
-        
-        // 检查是否已经修改过
-        if content.contains("#if CONFIG_POSTPROC") && content.contains("PRINT_LIB_INFO(postproc") {
-            println!("✓ opt_common.c already modified, skipping");
-            return Ok(());
-        }
-        
-        // 查找 print_all_libs_info 函数中的 postproc 行
-        let pattern = "    PRINT_LIB_INFO(postproc,   POSTPROC,   flags, level);";
-        if let Some(pos) = content.find(pattern) {
-            let before = &content[..pos];
-            let after = &content[pos + pattern.len()..];
-            
-            let modified = format!("{}#if CONFIG_POSTPROC\n    PRINT_LIB_INFO(postproc,   POSTPROC,   flags, level);\n#endif{}", 
-                before, after);
-            
-            fs::write(&opt_common_c_path, modified)?;
-            println!("✓ opt_common.c modified: added CONFIG_POSTPROC conditional compilation");
-        } else {
-            println!("⚠ Could not find postproc line in opt_common.c, skipping modification");
+}
+
+/// Lines whose code or `#include` names postproc and that are compiled with CONFIG_POSTPROC 0: outside every branch
+/// that needs it, such as `#if CONFIG_POSTPROC`, `#elif CONFIG_POSTPROC && X` or the `#else` of `#if !CONFIG_POSTPROC`
+fn unguarded_postproc_lines(src: &str) -> Vec<usize> {
+    // config.h 总是定义 CONFIG_POSTPROC，其它宏的值未知
+    let value_of = |name: &str| (name == "CONFIG_POSTPROC").then_some(0);
+    let defined = |name: &str| value_of(name.trim()).map(|_| true);
+    // 每层条件编译：(当前分支是否需要 CONFIG_POSTPROC, 之前的分支是否成立)
+    let mut branches: Vec<(bool, Option<bool>)> = Vec::new();
+    let mut lines = Vec::new();
+    for token in c_lexer::tokenize(src) {
+        let text = &src[token.start..token.end];
+        let names_postproc = match token.kind {
+            TokenKind::Preprocessor => {
+                let directive = text[1..].trim_start();
+                let keyword: String = directive.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+                let condition = &directive[keyword.len()..];
+                match keyword.as_str() {
+                    "if" | "ifdef" | "ifndef" => {
+                        let taken = match keyword.as_str() {
+                            "if" => c_lexer::condition_value(condition, &value_of),
+                            "ifdef" => defined(condition),
+                            _ => defined(condition).map(|taken| !taken),
+                        };
+                        branches.push((taken == Some(false), taken));
+                    }
+                    "elif" => {
+                        if let Some((needs_postproc, earlier)) = branches.last_mut() {
+                            let taken = c_lexer::condition_value(condition, &value_of);
+                            *needs_postproc = *earlier == Some(true) || taken == Some(false);
+                            *earlier = match (*earlier, taken) {
+                                (Some(true), _) | (_, Some(true)) => Some(true),
+                                (Some(false), Some(false)) => Some(false),
+                                _ => None,
+                            };
+                        }
+                    }
+                    "else" => {
+                        if let Some((needs_postproc, earlier)) = branches.last_mut() {
+                            *needs_postproc = *earlier == Some(true);
+                        }
+                    }
+                    "endif" => {
+                        branches.pop();
+                    }
+                    _ => {}
+                }
+                keyword == "include" && condition.contains("postproc")
+            }
+            TokenKind::Identifier => text.contains("postproc"),
+            _ => false,
+        };
+        if names_postproc && !branches.iter().any(|(needs_postproc, _)| *needs_postproc) {
+            let line = src[..token.start].matches('\n').count() + 1;
+            if lines.last() != Some(&line) {
+                lines.push(line);
+            }
         }
-        
-        Ok(())
     }
-    
-    /// Modify ffmpeg_dec.c to use ffmpeg's compat stdbit.h instead of system stdbit.h
-    /// and add MSVC compatibility for _Generic macro (Windows only)
-    fn modify_ffmpeg_dec_c(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let ffmpeg_dec_c_path = self.ffmpeg_source_dir.join("fftools").join("ffmpeg_dec.c");
-        
-        if !ffmpeg_dec_c_path.exists() {
-            println!("⚠ ffmpeg_dec.c not found, skipping modification");
-            return Ok(());
-        }
-        
-        let content = fs::read_to_string(&ffmpeg_dec_c_path)?;
-        
-        // macOS/Linux 不需要修改，直接返回
-        if !cfg!(target_os = "windows") {
-            println!("✓ ffmpeg_dec.c: macOS/Linux uses system stdbit.h, no modification needed");
-            return Ok(());
-        }
-        
-        // 检查是否已经修改过：检查关键函数是否存在
-        // 如果已经存在 stdc_count_ones_ui_compat 函数，说明已经添加过 MSVC 兼容性代码
-        if content.contains("static inline unsigned int stdc_count_ones_ui_compat") {
-            println!("✓ ffmpeg_dec.c already modified, skipping");
-            return Ok(());
-        }
-        
-        let mut modified = content.clone();
-        
-        // 只在 Windows 上替换系统 stdbit.h 为 ffmpeg 的兼容版本
-        // macOS/Linux 可以使用系统的 stdbit.h（C23 标准库）
-        if modified.contains("#include <stdbit.h>") {
-            modified = modified.replace(
-                "#include <stdbit.h>",
-                "#include \"compat/stdbit/stdbit.h\""
-            );
+    lines
+}
+
+/// Report a vcpkg_ff.toml `[config_h]` entry that changes a value config.h already defines
+fn report_config_h_override(name: &str, previous: Option<&str>, value: &str) {
+    match previous {
+        Some(previous) if previous != value => {
+            say!("⚠ config.h: {} overridden by vcpkg_ff.toml ({} -> {})", name, previous, value);
         }
-        
-        // 为 MSVC 添加兼容性宏定义（MSVC 不支持 _Generic，macOS/Linux 不需要）
-        // 在包含 stdbit.h 之后添加 MSVC 特定的兼容性定义
-        // 再次检查是否已经存在（防止在替换 include 后已经存在的情况）
-        if !modified.contains("static inline unsigned int stdc_count_ones_ui_compat") && 
-           modified.contains("#include \"compat/stdbit/stdbit.h\"") {
-            let msvc_compat = r#"
-/* MSVC compatibility for stdbit functions - MSVC doesn't support _Generic */
+        Some(_) => {}
+        None => say!("✓ config.h: {} added from vcpkg_ff.toml", name),
+    }
+}
+
+/// Line and `static`-ness of every top-level declaration or definition of the function `name`
+fn top_level_declarations(src: &str, code: &[(Token, i32)], name: &str) -> Vec<(usize, bool)> {
+    let text = |index: usize| &src[code[index].0.start..code[index].0.end];
+    (0..code.len().saturating_sub(1))
+        .filter(|&i| code[i].1 == 0 && text(i) == name && text(i + 1) == "(")
+        .map(|i| {
+            // 往回看到上一个声明的结尾，static 可能在返回类型之前
+            let is_static = (0..i).rev()
+                .take_while(|&j| code[j].1 == 0 && !matches!(text(j), ";" | "{" | "}"))
+                .any(|j| text(j) == "static");
+            (line_of(src, code[i].0.start), is_static)
+        })
+        .collect()
+}
+
+/// 1-based line of the byte offset `position`
+fn line_of(src: &str, position: usize) -> usize {
+    src[..position].matches('\n').count() + 1
+}
+
+/// Patch rules turning ffmpeg 7.x fftools/ffmpeg.c into a library translation unit with an N-API entry point
+fn ffmpeg_7_c_rules(ffmpeg_run: &str) -> Vec<PatchRule> {
+    vec![
+        PatchRule::make_non_static("int transcode(Scheduler *sch)"),
+        PatchRule::make_non_static("void ffmpeg_cleanup(int ret)"),
+        PatchRule::remove_function("int main(int argc, char **argv)", MAIN_REMOVED_COMMENT),
+        // 网络只由 ffmpeg_init/ffmpeg_shutdown 初始化和释放，每次运行都重复会破坏 TLS 的全局状态
+        PatchRule::replace_text("    avformat_network_deinit();\n", NETWORK_DEINIT_REMOVED),
+        // print_report() 把 -progress 的 key=value 文本交给 ffmpeg_progress_hook
+        PatchRule::insert_after_include("#include \"ffmpeg_utils.h\"", PROGRESS_HOOK_DECLARATION),
+        PatchRule::replace_text(
+            "    if (!print_stats && !is_last_report && !progress_avio)\n",
+            "    if (!print_stats && !is_last_report && !progress_avio && !ffmpeg_progress_hook)\n",
+        ),
+        PatchRule::replace_text(
+            "    if (progress_avio) {\n        av_bprintf(&buf_script, \"progress=%s\\n\",\n",
+            PROGRESS_HOOK_CALL,
+        ),
+        PatchRule::append_block("int ffmpeg_run_argv", &format!("\n\n{}", ffmpeg_run)),
+    ]
+}
+
+/// Patch rules turning ffmpeg 7.x fftools/ffprobe.c into a library translation unit next to ffmpeg.c:
+/// main() becomes ffprobe_main(), the -print_format output can be captured, clashing globals are renamed
+fn ffprobe_7_c_rules(ffprobe_run: &str) -> Vec<PatchRule> {
+    vec![
+        PatchRule::insert_after_include("#include \"opt_common.h\"", FFPROBE_RENAMES),
+        PatchRule::insert_after_include("#include \"opt_common.h\"", FFPROBE_INIT_DECLARATION),
+        PatchRule::replace_text("    avformat_network_init();\n", "    ffmpeg_init();\n"),
+        PatchRule::replace_text("    avformat_network_deinit();\n", NETWORK_DEINIT_REMOVED),
+        PatchRule::replace_text("\nint main(int argc, char **argv)\n", "\nstatic int ffprobe_main(int argc, char **argv)\n"),
+        PatchRule::wrap_in_conditional("    SHOW_LIB_VERSION(postproc,   POSTPROC);", "CONFIG_POSTPROC"),
+        // writer_*_printf 在 ffprobe_output 存在时写入它，而不是 stdout
+        PatchRule::replace_text("    printf(\"%c\", b);\n", FFPROBE_OUTPUT_W8),
+        PatchRule::replace_text("    printf(\"%s\", str);\n", FFPROBE_OUTPUT_PUT_STR),
+        PatchRule::replace_text("    vprintf(fmt, ap);\n", FFPROBE_OUTPUT_PRINTF),
+        PatchRule::append_block("int ffprobe_run_argv", &format!("\n\n{}", ffprobe_run)),
+    ]
+}
+
+/// ffmpeg 7.x fftools sources compiled into the addon besides ffmpeg.c and ffprobe.c, copied into addon_src
+const FFTOOLS_7_SOURCES: &[&str] = &[
+    "cmdutils.c",
+    "ffmpeg_dec.c",
+    "ffmpeg_demux.c",
+    "ffmpeg_enc.c",
+    "ffmpeg_filter.c",
+    "ffmpeg_hw.c",
+    "ffmpeg_mux_init.c",
+    "ffmpeg_mux.c",
+    "ffmpeg_opt.c",
+    "ffmpeg_sched.c",
+    "opt_common.c",
+    "sync_queue.c",
+    "thread_queue.c",
+    "objpool.c",
+];
+
+/// A generated transcoding test case
+struct MediaTest {
+    file_stem: &'static str,
+    name: &'static str,
+    /// vcpkg ffmpeg feature providing the encoder
+    feature: &'static str,
+    extension: &'static str,
+    /// Input and encoder arguments, the output path is appended
+    args: &'static [&'static str],
+    /// Container signature (hex) expected at `magic_offset`
+    magic_offset: usize,
+    magic: &'static str,
+}
+
+const MEDIA_TESTS: &[MediaTest] = &[
+    MediaTest {
+        file_stem: "mp4_h264",
+        name: "mp4 (H.264/AAC)",
+        feature: "x264",
+        extension: "mp4",
+        args: &["-f", "lavfi", "-i", "testsrc=duration=1:size=320x240:rate=25",
+            "-f", "lavfi", "-i", "sine=frequency=440:duration=1",
+            "-c:v", "libx264", "-pix_fmt", "yuv420p", "-c:a", "aac", "-shortest"],
+        magic_offset: 4,
+        magic: "66747970", // "ftyp"
+    },
+    MediaTest {
+        file_stem: "mp4_hevc",
+        name: "mp4 (HEVC)",
+        feature: "x265",
+        extension: "mp4",
+        args: &["-f", "lavfi", "-i", "testsrc=duration=1:size=320x240:rate=25",
+            "-c:v", "libx265", "-pix_fmt", "yuv420p", "-tag:v", "hvc1", "-an"],
+        magic_offset: 4,
+        magic: "66747970",
+    },
+    MediaTest {
+        file_stem: "webm_vp8",
+        name: "webm (VP8)",
+        feature: "vpx",
+        extension: "webm",
+        args: &["-f", "lavfi", "-i", "testsrc=duration=1:size=320x240:rate=25",
+            "-c:v", "libvpx", "-b:v", "500k", "-an"],
+        magic_offset: 0,
+        magic: "1a45dfa3", // EBML
+    },
+];
+
+/// Static and DLL CRT import libraries, indexed by the VCCLCompilerTool RuntimeLibrary value (/MT, /MTd, /MD, /MDd)
+const MSVC_CRT_LIBRARIES: &[&str] = &["libcmt.lib", "libcmtd.lib", "msvcrt.lib", "msvcrtd.lib"];
+
+/// MSVC linker warnings that static ffmpeg links trigger without affecting the result:
+/// 4099 missing PDBs of vcpkg libraries, 4217/4286 symbols imported from the same static library
+const MSVC_IGNORED_LINKER_WARNINGS: &[&str] = &["/IGNORE:4099", "/IGNORE:4217", "/IGNORE:4286"];
+
+/// node-gyp flag downloading Electron's headers instead of Node's
+const ELECTRON_HEADERS_URL: &str = "--dist-url=https://electronjs.org/headers";
+
+/// Functions of ffmpeg.c and ffprobe.c the WebAssembly module exports to wasm.mjs, plus the allocator it builds argv with
+const WASM_EXPORTED_FUNCTIONS: &[&str] = &[
+    "ffmpeg_init",
+    "ffmpeg_shutdown",
+    "ffmpeg_run_argv",
+    "ffmpeg_collect_output",
+    "ffmpeg_take_output",
+    "ffmpeg_capabilities_json",
+    "ffmpeg_build_info_json",
+    "ffmpeg_free_string",
+    "ffprobe_run_argv",
+    "ffprobe_free_output",
+    "malloc",
+    "free",
+];
+
+/// Emscripten runtime helpers wasm.mjs uses
+const WASM_RUNTIME_METHODS: &[&str] = &["FS", "stringToNewUTF8", "UTF8ToString", "getValue", "setValue", "HEAPU8"];
+
+/// Oldest Bun release the `--bun` addon declares in package.json engines, with N-API threadsafe functions and cleanup hooks
+const BUN_MINIMUM_VERSION: &str = ">=1.1.0";
+
+/// pkg-config packages of the ffmpeg libraries linked into the addon
+pub(crate) const FFMPEG_PKG_CONFIG_PACKAGES: &[&str] = &[
+    "libavdevice",
+    "libavfilter",
+    "libavformat",
+    "libavcodec",
+    "libswresample",
+    "libswscale",
+    "libavutil",
+];
+
+/// Files preparation generates in the project directory besides addon_src
+pub(crate) const BUILD_FILES: &[&str] = &["binding.gyp", "CMakeLists.txt", "package.json"];
+
+/// ffmpeg and codec libraries linked on Windows when vcpkg's .pc files are unavailable
+const WINDOWS_FALLBACK_LIBRARIES: &[&str] = &[
+    "avcodec.lib",
+    "avformat.lib",
+    "avutil.lib",
+    "avfilter.lib",
+    "swscale.lib",
+    "swresample.lib",
+    "avdevice.lib",
+    "libx264.lib",
+    "x265-static.lib",
+    "vpx.lib",
+];
+
+/// Windows system libraries needed by a static ffmpeg (networking, Media Foundation, DirectShow, ...)
+const WINDOWS_SYSTEM_LIBRARIES: &[&str] = &[
+    "ws2_32.lib",
+    "secur32.lib",
+    "bcrypt.lib",
+    "strmiids.lib",
+    "ole32.lib",
+    "oleaut32.lib",
+    "vfw32.lib",
+    "mfplat.lib",
+    "mfuuid.lib",
+    "shlwapi.lib",
+    "user32.lib",
+    "gdi32.lib",
+    "winmm.lib",
+    "psapi.lib",
+];
+
+/// Libraries linked on macOS and Linux when vcpkg's .pc files are unavailable
+const UNIX_FALLBACK_LIBRARIES: &[&str] = &[
+    "-lavcodec",
+    "-lavformat",
+    "-lavutil",
+    "-lavfilter",
+    "-lswscale",
+    "-lswresample",
+    "-lavdevice",
+    "-lx264",
+    "-lx265",
+    "-lvpx",
+];
+
+/// System libraries a static ffmpeg needs on Linux
+const LINUX_SYSTEM_LIBRARIES: &[&str] = &["-lm", "-lpthread"];
+
+/// File under the backup directory listing files that did not exist before the tool created them
+const CREATED_FILES_LIST: &str = "created_files.txt";
+
+/// Comment left in place of the removed main()
+/// Functions of ffmpeg.c the appended ffmpeg_run block calls, which the patches make non-static
+const NON_STATIC_FUNCTIONS: &[&str] = &["transcode", "ffmpeg_cleanup"];
+
+const MAIN_REMOVED_COMMENT: &str = "\n\n/*\n * Main function removed for Node.js addon\n * Use ffmpeg_run() instead\n */";
+
+/// Progress hook of ffmpeg.c, set by ffmpeg_set_progress_hook() in the appended ffmpeg_run block
+const PROGRESS_HOOK_DECLARATION: &str = r#"
+/* Receives the -progress key=value report of every print_report() call (vcpkg_ff) */
+static void (*ffmpeg_progress_hook)(const char *report, int is_last, void *opaque) = NULL;
+static void *ffmpeg_progress_opaque = NULL;"#;
+
+/// Call of the progress hook inserted in print_report(), before the -progress output
+const PROGRESS_HOOK_CALL: &str = r#"    if (ffmpeg_progress_hook)
+        ffmpeg_progress_hook(buf_script.str, is_last_report, ffmpeg_progress_opaque);
+
+    if (progress_avio) {
+        av_bprintf(&buf_script, "progress=%s\n",
+"#;
+
+/// Appended to ffmpeg_opt.c: a second run in the same process must not inherit -y, -copyts, -stats, ... of the first
+const FFMPEG_OPT_RESET: &str = r#"
+
+/* Restore the global options to their initial values before a run, they outlive ffmpeg_cleanup() (vcpkg_ff) */
+void ffmpeg_opt_reset(void)
+{
+    dts_delta_threshold      = 10;
+    dts_error_threshold      = 3600 * 30;
+    video_sync_method        = VSYNC_AUTO;
+    frame_drop_threshold     = 0;
+    do_benchmark             = 0;
+    do_benchmark_all         = 0;
+    do_hex_dump              = 0;
+    do_pkt_dump              = 0;
+    copy_ts                  = 0;
+    start_at_zero            = 0;
+    copy_tb                  = -1;
+    debug_ts                 = 0;
+    exit_on_error            = 0;
+    abort_on_flags           = 0;
+    print_stats              = -1;
+    stdin_interaction        = 1;
+    max_error_rate           = 2.0 / 3;
+    av_freep(&filter_nbthreads);
+    filter_complex_nbthreads = 0;
+    vstats_version           = 2;
+    auto_conversion_filters  = 1;
+    stats_period             = 500000;
+    file_overwrite           = 0;
+    no_file_overwrite        = 0;
+    ignore_unknown_streams   = 0;
+    copy_unknown_streams     = 0;
+    recast_media             = 0;
+    filter_hw_device         = NULL;
+}
+"#;
+
+/// Declaration of the options.input hook defined in the ffmpeg_run block of ffmpeg.c, inserted into ffmpeg_demux.c
+const INPUT_OPEN_DECLARATION: &str = r#"
+/* Attaches the run's options.input to `ic` when `filename` names stdin, e.g. "-" (vcpkg_ff) */
+int ffmpeg_input_open(AVFormatContext *ic, const char *filename);"#;
+
+/// avformat_open_input() call of ifile_open() in ffmpeg_demux.c, preceded by the options.input hook
+const INPUT_OPEN_CALL: &str = r#"    err = ffmpeg_input_open(ic, filename);
+    if (err < 0)
+        avformat_free_context(ic);
+    else
+        err = avformat_open_input(&ic, filename, file_iformat, &o->g->format_opts);
+"#;
+
+/// Declaration of the options.output hook defined in the ffmpeg_run block of ffmpeg.c, inserted into ffmpeg_mux_init.c
+const OUTPUT_OPEN_DECLARATION: &str = r#"
+/* Attaches the run's options.output to `oc` when `filename` names stdout, e.g. "-" (vcpkg_ff) */
+int ffmpeg_output_open(AVFormatContext *oc, const char *filename);"#;
+
+/// Start of the avio_open2() call of of_open() in ffmpeg_mux_init.c, skipped when the options.output hook set oc->pb
+const OUTPUT_OPEN_CALL: &str = r#"        err = ffmpeg_output_open(oc, filename);
+        if (err < 0)
+            return err;
+        if (!oc->pb && (err = avio_open2(&oc->pb, filename, AVIO_FLAG_WRITE,
+"#;
+
+/// Declaration of the avio_closep() replacement defined in the ffmpeg_run block of ffmpeg.c, inserted into ffmpeg_mux.c
+const OUTPUT_CLOSE_DECLARATION: &str = r#"
+/* avio_closep() that also closes the AVIOContext of the run's options.output (vcpkg_ff) */
+int ffmpeg_output_closep(AVIOContext **pb);"#;
+
+/// Declaration of the frame tap defined in the ffmpeg_run block of ffmpeg.c, inserted into ffmpeg_filter.c
+const FRAME_TAP_DECLARATION: &str = r#"
+/* Hands a frame leaving output `output` of filtergraph `graph` to the run's onFrame (vcpkg_ff) */
+void ffmpeg_frame_tap(const AVFrame *frame, int graph, int output);"#;
+
+/// Where fg_output_step() in ffmpeg_filter.c has a frame from the buffersink with its time base, followed by the tap
+const FRAME_TAP_CALL: &str = r#"    frame->time_base = av_buffersink_get_time_base(filter);
+    ffmpeg_frame_tap(frame, ofp->ofilter.graph->index, ofp->index);
+"#;
+
+/// Inserted after ffprobe.c's includes: the globals cmdutils expects from every program are already defined
+/// by ffmpeg.c/ffmpeg_opt.c, ffprobe's own copies get other names
+const FFPROBE_RENAMES: &str = r#"
+/* Defined by ffmpeg.c and ffmpeg_opt.c in the same addon (vcpkg_ff) */
+#define program_name ffprobe_program_name
+#define program_birth_year ffprobe_program_birth_year
+#define show_help_default ffprobe_show_help_default
+
+/* Receives the -print_format output instead of stdout while set, see ffprobe_run_argv (vcpkg_ff) */
+static AVBPrint *ffprobe_output = NULL;"#;
+
+/// Replaces the avformat_network_deinit() calls of ffmpeg_cleanup() and ffprobe's main()
+const NETWORK_DEINIT_REMOVED: &str = "    /* avformat_network_deinit() moved to ffmpeg_shutdown() (vcpkg_ff) */\n";
+
+/// ffprobe's main() initializes through the once-per-process ffmpeg_init() of ffmpeg.c
+const FFPROBE_INIT_DECLARATION: &str = r#"
+/* Defined by ffmpeg.c in the same addon (vcpkg_ff) */
+int ffmpeg_init(void);"#;
+
+/// Body of writer_w8_printf() in ffprobe.c
+const FFPROBE_OUTPUT_W8: &str = r#"    if (ffprobe_output)
+        av_bprint_chars(ffprobe_output, b, 1);
+    else
+        printf("%c", b);
+"#;
+
+/// Body of writer_put_str_printf() in ffprobe.c
+const FFPROBE_OUTPUT_PUT_STR: &str = r#"    if (ffprobe_output)
+        av_bprintf(ffprobe_output, "%s", str);
+    else
+        printf("%s", str);
+"#;
+
+/// vprintf() call of writer_printf_printf() in ffprobe.c
+const FFPROBE_OUTPUT_PRINTF: &str = r#"    if (ffprobe_output)
+        av_vbprintf(ffprobe_output, fmt, ap);
+    else
+        vprintf(fmt, ap);
+"#;
+
+/// MSVC compatibility for stdbit functions, inserted after the compat stdbit.h include (Windows only)
+const MSVC_STDBIT_COMPAT: &str = r#"/* MSVC compatibility for stdbit functions - MSVC doesn't support _Generic */
 #ifdef _MSC_VER
 /* Undefine the _Generic-based macros from compat header */
 #undef stdc_count_ones
@@ -638,71 +2545,58 @@ static inline unsigned int stdc_trailing_zeros_ui_compat(unsigned int value) {
 #define stdc_trailing_zeros(value) stdc_trailing_zeros_ui_compat((unsigned int)(value))
 #endif /* _MSC_VER */
 "#;
-            
-            // 在 stdbit.h 包含之后添加 MSVC 兼容性代码
-            if let Some(include_pos) = modified.find("#include \"compat/stdbit/stdbit.h\"") {
-                let after_include = include_pos + "#include \"compat/stdbit/stdbit.h\"".len();
-                let next_line = modified[after_include..].find('\n').unwrap_or(0);
-                let insert_pos = after_include + next_line + 1;
-                
-                modified = format!("{}{}{}", 
-                    &modified[..insert_pos],
-                    msvc_compat,
-                    &modified[insert_pos..]
-                );
-            }
-        }
-        
-        if modified != content {
-            fs::write(&ffmpeg_dec_c_path, modified)?;
-            println!("✓ ffmpeg_dec.c modified: replaced <stdbit.h> with compat version and added MSVC compatibility");
-        } else {
-            println!("⚠ Could not find <stdbit.h> in ffmpeg_dec.c, skipping modification");
-        }
-        
-        Ok(())
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn postproc_below_its_switch_is_guarded() {
+        let src = "#include \"libavutil/avutil.h\"\n#if CONFIG_POSTPROC\n#include \"libpostproc/postprocess.h\"\n#endif\n\
+            void show(void)\n{\n#if CONFIG_POSTPROC && !CONFIG_SMALL\n    SHOW_LIB_VERSION(postproc, POSTPROC);\n#endif\n}\n";
+
+        assert!(unguarded_postproc_lines(src).is_empty());
     }
-    
-    /// Create binding.c
-    fn create_binding_c(&self) -> Result<(), Box<dyn std::error::Error>> {
-        let binding_c_path = self.addon_src_dir.join("binding.c");
-        
-        let binding_c_content = r#"#include <node_api.h>
 
-// 声明ffmpeg.c中的napi函数
-extern napi_value ffmpeg_run(napi_env env, napi_callback_info info);
+    #[test]
+    fn postproc_outside_its_switch_is_reported() {
+        let src = "#include \"libpostproc/postprocess.h\"\n/* postproc in a comment */\nconst char *name = \"postproc\";\n\
+            void show(void)\n{\n    SHOW_LIB_VERSION(postproc, POSTPROC);\n#if CONFIG_SMALL || CONFIG_POSTPROC\n    postproc_version();\n#endif\n}\n";
 
-napi_value Init(napi_env env, napi_value exports)
-{
-    napi_status status;
-    napi_value fn;
-    
-    // 创建run函数
-    status = napi_create_function(env, NULL, 0, ffmpeg_run, NULL, &fn);
-    if (status != napi_ok) {
-        return NULL;
+        assert_eq!(unguarded_postproc_lines(src), [1, 6, 8]);
     }
-    
-    // 将run函数添加到exports对象
-    status = napi_set_named_property(env, exports, "run", fn);
-    if (status != napi_ok) {
-        return NULL;
+
+    #[test]
+    fn ifdef_is_no_guard() {
+        // config.h 总是定义 CONFIG_POSTPROC，为 0 时 #ifdef 也成立
+        let src = "#ifdef CONFIG_POSTPROC\npostproc_version();\n#endif\n#ifndef CONFIG_POSTPROC\npostproc_version();\n#else\npostproc_version();\n#endif\n";
+
+        assert_eq!(unguarded_postproc_lines(src), [2, 7]);
     }
-    
-    return exports;
-}
 
-NAPI_MODULE(NODE_GYP_MODULE_NAME, Init)
-"#;
-        
-        fs::write(&binding_c_path, binding_c_content)?;
-        println!("✓ binding.c created: {}", binding_c_path.display());
-        Ok(())
+    #[test]
+    fn elif_branches() {
+        let src = "#if CONFIG_SMALL\nsmall();\n#elif CONFIG_POSTPROC\npostproc_version();\n#elif HAVE_THREADS\npostproc_version();\n#else\npostproc_version();\n#endif\n\
+            #if CONFIG_POSTPROC\npostproc_version();\n#elif CONFIG_SMALL\npostproc_version();\n#endif\n";
+
+        assert_eq!(unguarded_postproc_lines(src), [6, 8, 13]);
     }
-    
-    /// Get addon_src directory
-    pub fn get_addon_src_dir(&self) -> &Path {
-        &self.addon_src_dir
+
+    #[test]
+    fn negated_conditions() {
+        let src = "#if !CONFIG_POSTPROC\nwithout_pp();\n#else\npostproc_version();\n#endif\n\
+            #if !CONFIG_SMALL && CONFIG_POSTPROC\npostproc_version();\n#endif\n\
+            #if !(CONFIG_SMALL || !CONFIG_POSTPROC)\npostproc_version();\n#endif\n\
+            #if !CONFIG_SMALL\npostproc_version();\n#endif\n";
+
+        assert_eq!(unguarded_postproc_lines(src), [13]);
     }
-}
 
+    #[test]
+    fn nested_conditionals() {
+        let src = "#if CONFIG_POSTPROC\n#if HAVE_THREADS\npostproc_version();\n#else\npostproc_version();\n#endif\npostproc_version();\n#endif\n\
+            #if HAVE_THREADS\n#if CONFIG_POSTPROC\npostproc_version();\n#endif\npostproc_version();\n#endif\n";
+
+        assert_eq!(unguarded_postproc_lines(src), [13]);
+    }
+}
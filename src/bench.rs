@@ -0,0 +1,226 @@
+use std::collections::BTreeMap;
+use std::fs::{self, OpenOptions};
+use std::io::Write;
+use std::path::{Path, PathBuf};
+use std::sync::mpsc::{self, RecvTimeoutError};
+use std::thread::{self, JoinHandle};
+use std::time::{Duration, Instant, SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::VcpkgFfError;
+
+/// How often the disk usage is sampled while a step runs
+const DISK_SAMPLE_INTERVAL: Duration = Duration::from_secs(10);
+
+/// Earlier runs the `bench` report takes the median of
+const BASELINE_RUNS: usize = 10;
+
+/// Clock ticks per second of the CPU times in /proc, USER_HZ is 100 on every Linux architecture
+const CLOCK_TICKS: f64 = 100.0;
+
+/// What one step of a pipeline run took
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct StepSample {
+    pub step: String,
+    /// "completed", "skipped" or "failed"
+    pub status: String,
+    pub wall_secs: f64,
+    /// CPU time of vcpkg_ff and the commands it ran, e.g. the compilers vcpkg started. Linux only
+    pub cpu_secs: Option<f64>,
+    /// Largest size of the project, vcpkg and source directories while the step ran
+    pub peak_disk_bytes: Option<u64>,
+}
+
+/// A pipeline run in the benchmark history
+#[derive(Debug, Clone, PartialEq, Serialize, Deserialize)]
+pub struct BenchRun {
+    /// Seconds since the Unix epoch
+    pub started: u64,
+    /// What the timings depend on: triplet, features, backend, mirror, jobs and binary_sources
+    pub settings: BTreeMap<String, String>,
+    pub steps: Vec<StepSample>,
+}
+
+impl BenchRun {
+    pub fn new(settings: BTreeMap<String, String>) -> Self {
+        let started = SystemTime::now().duration_since(UNIX_EPOCH).map(|elapsed| elapsed.as_secs()).unwrap_or_default();
+        Self { started, settings, steps: Vec::new() }
+    }
+
+    fn step(&self, name: &str) -> Option<&StepSample> {
+        self.steps.iter().find(|sample| sample.step == name)
+    }
+}
+
+/// The step timings of every pipeline run, one JSON object per line in .vcpkg_ff/bench.jsonl
+#[derive(Debug, Default)]
+pub struct BenchHistory {
+    runs: Vec<BenchRun>,
+}
+
+impl BenchHistory {
+    pub fn path_in(base_dir: &Path) -> PathBuf {
+        base_dir.join(".vcpkg_ff").join("bench.jsonl")
+    }
+
+    /// Read the history at `path`, empty when there is none. Lines that don't parse are skipped
+    pub fn load(path: &Path) -> Result<Self, VcpkgFfError> {
+        let Ok(content) = fs::read_to_string(path) else {
+            return Ok(Self::default());
+        };
+        let runs = content.lines().filter_map(|line| serde_json::from_str(line).ok()).collect();
+        Ok(Self { runs })
+    }
+
+    /// Add `run` to the history at `path`
+    pub fn append(path: &Path, run: &BenchRun) -> Result<(), VcpkgFfError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", serde_json::to_string(run)?)?;
+        Ok(())
+    }
+
+    /// Oldest first
+    pub fn runs(&self) -> &[BenchRun] {
+        &self.runs
+    }
+
+    /// The last run step by step next to the run before it and the median of the earlier runs, followed by the
+    /// settings that changed since the run before. None without any run
+    pub fn report(&self) -> Option<String> {
+        let (current, earlier) = self.runs.split_last()?;
+        let previous = earlier.last();
+        let baseline = &earlier[earlier.len().saturating_sub(BASELINE_RUNS)..];
+
+        let mut report = format!("Last run compared to the one before and the median of {} earlier run(s)\n", baseline.len());
+        report += &format!("  {:<18} {:>10} {:>10} {:>10} {:>8} {:>10} {:>11}\n", "step", "wall", "previous", "median", "change", "cpu", "peak disk");
+        for sample in &current.steps {
+            let completed = |run: &BenchRun| run.step(&sample.step).filter(|earlier| earlier.status == "completed").map(|earlier| earlier.wall_secs);
+            let median = median(baseline.iter().filter_map(completed).collect());
+            let wall = match sample.status.as_str() {
+                "completed" => format_secs(sample.wall_secs),
+                status => status.to_string(),
+            };
+            let change = match (sample.status.as_str(), median) {
+                ("completed", Some(median)) if median > 0.0 => format!("{:+.0}%", (sample.wall_secs - median) / median * 100.0),
+                _ => "-".to_string(),
+            };
+            report += &format!("  {:<18} {:>10} {:>10} {:>10} {:>8} {:>10} {:>11}\n",
+                sample.step,
+                wall,
+                previous.and_then(completed).map(format_secs).unwrap_or_else(|| "-".to_string()),
+                median.map(format_secs).unwrap_or_else(|| "-".to_string()),
+                change,
+                sample.cpu_secs.map(format_secs).unwrap_or_else(|| "-".to_string()),
+                sample.peak_disk_bytes.map(format_bytes).unwrap_or_else(|| "-".to_string()));
+        }
+
+        if let Some(previous) = previous {
+            let changed: Vec<String> = current.settings.iter()
+                .filter(|(name, value)| previous.settings.get(*name) != Some(value))
+                .map(|(name, value)| format!("{}: {} -> {}", name, previous.settings.get(name).map(String::as_str).unwrap_or("-"), value))
+                .collect();
+            if !changed.is_empty() {
+                report += &format!("Changed since the previous run: {}\n", changed.join(", "));
+            }
+        }
+        Some(report)
+    }
+}
+
+fn median(mut values: Vec<f64>) -> Option<f64> {
+    if values.is_empty() {
+        return None;
+    }
+    values.sort_by(f64::total_cmp);
+    let middle = values.len() / 2;
+    Some(if values.len().is_multiple_of(2) { (values[middle - 1] + values[middle]) / 2.0 } else { values[middle] })
+}
+
+fn format_secs(secs: f64) -> String {
+    if secs < 60.0 {
+        format!("{:.1}s", secs)
+    } else {
+        let secs = secs.round() as u64;
+        format!("{}m{:02}s", secs / 60, secs % 60)
+    }
+}
+
+fn format_bytes(bytes: u64) -> String {
+    const UNITS: [&str; 4] = ["B", "KiB", "MiB", "GiB"];
+    let mut value = bytes as f64;
+    let mut unit = 0;
+    while value >= 1024.0 && unit < UNITS.len() - 1 {
+        value /= 1024.0;
+        unit += 1;
+    }
+    if unit == 0 { format!("{} B", bytes) } else { format!("{:.1} {}", value, UNITS[unit]) }
+}
+
+/// Measures a running step: wall time, CPU time and, from a background thread, the peak disk usage of `dirs`
+pub(crate) struct StepMeter {
+    started: Instant,
+    cpu_at_start: Option<f64>,
+    stop_sampling: mpsc::Sender<()>,
+    sampler: JoinHandle<u64>,
+}
+
+impl StepMeter {
+    pub(crate) fn start(dirs: Vec<PathBuf>) -> Self {
+        let (stop_sampling, stopped) = mpsc::channel();
+        let sampler = thread::spawn(move || {
+            let mut peak = disk_usage(&dirs);
+            while let Err(RecvTimeoutError::Timeout) = stopped.recv_timeout(DISK_SAMPLE_INTERVAL) {
+                peak = peak.max(disk_usage(&dirs));
+            }
+            peak.max(disk_usage(&dirs))
+        });
+        Self { started: Instant::now(), cpu_at_start: cpu_secs(), stop_sampling, sampler }
+    }
+
+    pub(crate) fn finish(self, step: &str, status: &str) -> StepSample {
+        let wall_secs = self.started.elapsed().as_secs_f64();
+        let cpu_secs = cpu_secs().zip(self.cpu_at_start).map(|(now, start)| now - start);
+        drop(self.stop_sampling);
+        StepSample {
+            step: step.to_string(),
+            status: status.to_string(),
+            wall_secs,
+            cpu_secs,
+            peak_disk_bytes: self.sampler.join().ok(),
+        }
+    }
+}
+
+/// Total size of the files below `dirs`
+fn disk_usage(dirs: &[PathBuf]) -> u64 {
+    fn size(path: &Path) -> u64 {
+        let Ok(metadata) = fs::symlink_metadata(path) else {
+            return 0;
+        };
+        if !metadata.is_dir() {
+            return metadata.len();
+        }
+        fs::read_dir(path).into_iter().flatten().flatten().map(|entry| size(&entry.path())).sum()
+    }
+    dirs.iter().map(|dir| size(dir)).sum()
+}
+
+/// CPU time of this process and its waited-for children so far, from /proc/self/stat
+#[cfg(target_os = "linux")]
+fn cpu_secs() -> Option<f64> {
+    let stat = fs::read_to_string("/proc/self/stat").ok()?;
+    // 进程名可能包含空格，从最后一个 ')' 之后开始数字段
+    let fields: Vec<&str> = stat[stat.rfind(')')? + 1..].split_whitespace().collect();
+    // utime、stime、cutime、cstime 是第 14 到 17 个字段
+    let ticks: Option<Vec<f64>> = fields.get(11..15)?.iter().map(|field| field.parse().ok()).collect();
+    Some(ticks?.iter().sum::<f64>() / CLOCK_TICKS)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn cpu_secs() -> Option<f64> {
+    None
+}
@@ -0,0 +1,497 @@
+/// Kinds of C tokens relevant for locating declarations and function bodies
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TokenKind {
+    Identifier,
+    Number,
+    StringLiteral,
+    CharLiteral,
+    Punct,
+    Comment,
+    /// A whole preprocessor directive, including backslash-continued lines
+    Preprocessor,
+}
+
+/// A token as a byte range into the source
+#[derive(Debug, Clone, Copy)]
+pub struct Token {
+    pub kind: TokenKind,
+    pub start: usize,
+    pub end: usize,
+}
+
+/// A top-level occurrence of a declaration signature
+#[derive(Debug, Clone, Copy)]
+pub struct SignatureMatch {
+    /// Byte offset of the first signature token (or of the `static` qualifier, if any)
+    pub start: usize,
+    /// Byte range of a `static` qualifier directly preceding the signature, including trailing whitespace
+    pub static_range: Option<(usize, usize)>,
+    /// Byte offset just past the closing brace if the signature starts a function definition
+    pub body_end: Option<usize>,
+}
+
+/// Split C source into tokens, skipping whitespace
+pub fn tokenize(src: &str) -> Vec<Token> {
+    let bytes = src.as_bytes();
+    let mut tokens = Vec::new();
+    let mut i = 0;
+    let mut at_line_start = true;
+
+    while i < bytes.len() {
+        let b = bytes[i];
+
+        if b == b'\n' {
+            at_line_start = true;
+            i += 1;
+            continue;
+        }
+        if b.is_ascii_whitespace() {
+            i += 1;
+            continue;
+        }
+
+        let start = i;
+        let kind = if b == b'#' && at_line_start {
+            // 预处理指令一直到行尾，反斜杠续行也算在内
+            while i < bytes.len() && bytes[i] != b'\n' {
+                if bytes[i] == b'\\' && i + 1 < bytes.len() && bytes[i + 1] == b'\n' {
+                    i += 2;
+                } else if bytes[i] == b'/' && bytes.get(i + 1) == Some(&b'*') {
+                    i = skip_block_comment(bytes, i);
+                } else {
+                    i += 1;
+                }
+            }
+            TokenKind::Preprocessor
+        } else if b == b'/' && bytes.get(i + 1) == Some(&b'/') {
+            while i < bytes.len() && bytes[i] != b'\n' {
+                i += 1;
+            }
+            TokenKind::Comment
+        } else if b == b'/' && bytes.get(i + 1) == Some(&b'*') {
+            i = skip_block_comment(bytes, i);
+            TokenKind::Comment
+        } else if b == b'"' || b == b'\'' {
+            i += 1;
+            while i < bytes.len() && bytes[i] != b && bytes[i] != b'\n' {
+                i += if bytes[i] == b'\\' { 2 } else { 1 };
+            }
+            i = (i + 1).min(bytes.len());
+            if b == b'"' { TokenKind::StringLiteral } else { TokenKind::CharLiteral }
+        } else if b.is_ascii_alphabetic() || b == b'_' {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'_') {
+                i += 1;
+            }
+            TokenKind::Identifier
+        } else if b.is_ascii_digit() {
+            while i < bytes.len() && (bytes[i].is_ascii_alphanumeric() || bytes[i] == b'.' || bytes[i] == b'_') {
+                i += 1;
+            }
+            TokenKind::Number
+        } else {
+            // 非 ASCII 字符按完整的 UTF-8 字符前进，保证切片边界合法
+            i += src[i..].chars().next().map(|c| c.len_utf8()).unwrap_or(1);
+            TokenKind::Punct
+        };
+
+        at_line_start = false;
+        tokens.push(Token { kind, start, end: i.min(bytes.len()) });
+    }
+
+    tokens
+}
+
+fn skip_block_comment(bytes: &[u8], start: usize) -> usize {
+    let mut i = start + 2;
+    while i + 1 < bytes.len() && !(bytes[i] == b'*' && bytes[i + 1] == b'/') {
+        i += 1;
+    }
+    (i + 2).min(bytes.len())
+}
+
+/// Path of an `#include "..."` directive (None for `<...>` includes and other directives)
+pub fn quoted_include(directive: &str) -> Option<&str> {
+    let rest = directive.strip_prefix('#')?.trim_start().strip_prefix("include")?.trim_start();
+    let rest = rest.strip_prefix('"')?;
+    rest.find('"').map(|end| &rest[..end])
+}
+
+/// Paths of all `#include "..."` directives in a source file
+pub fn quoted_includes(src: &str) -> Vec<&str> {
+    tokenize(src)
+        .iter()
+        .filter(|token| token.kind == TokenKind::Preprocessor)
+        .filter_map(|token| quoted_include(&src[token.start..token.end]))
+        .collect()
+}
+
+/// Tokens that take part in the C grammar (no comments or preprocessor directives),
+/// paired with the brace depth they appear at.
+///
+/// Braces inside `#if`/`#else` branches are counted once: each alternative branch
+/// restarts from the depth at the `#if`, and `#endif` continues with the depth of the first branch.
+fn code_tokens_with_depth(src: &str, tokens: &[Token]) -> Vec<(Token, i32)> {
+    let mut result = Vec::new();
+    let mut depth = 0;
+    // (depth at #if, depth at end of first branch)
+    let mut conditionals: Vec<(i32, Option<i32>)> = Vec::new();
+
+    for token in tokens {
+        match token.kind {
+            TokenKind::Comment => {}
+            TokenKind::Preprocessor => {
+                let directive = src[token.start + 1..token.end].trim_start();
+                let keyword: String = directive.chars().take_while(|c| c.is_ascii_alphabetic()).collect();
+                match keyword.as_str() {
+                    "if" | "ifdef" | "ifndef" => conditionals.push((depth, None)),
+                    "elif" | "else" => {
+                        if let Some((depth_at_if, first_branch)) = conditionals.last_mut() {
+                            if first_branch.is_none() {
+                                *first_branch = Some(depth);
+                            }
+                            depth = *depth_at_if;
+                        }
+                    }
+                    "endif" => {
+                        if let Some((_, Some(first_branch))) = conditionals.pop() {
+                            depth = first_branch;
+                        }
+                    }
+                    _ => {}
+                }
+            }
+            _ => {
+                let text = &src[token.start..token.end];
+                if token.kind == TokenKind::Punct && text == "}" {
+                    depth -= 1;
+                }
+                result.push((*token, depth));
+                if token.kind == TokenKind::Punct && text == "{" {
+                    depth += 1;
+                }
+            }
+        }
+    }
+
+    result
+}
+
+/// The code tokens of `src` with their brace depth, for [`find_signature_in`]
+pub fn code_tokens(src: &str) -> Vec<(Token, i32)> {
+    code_tokens_with_depth(src, &tokenize(src))
+}
+
+/// Find every top-level occurrence of `signature` (compared token by token, so whitespace,
+/// line breaks and comments between tokens don't matter) in `src`, whose [`code_tokens`] are `code`
+pub fn find_signature_in(src: &str, code: &[(Token, i32)], signature: &str) -> Vec<SignatureMatch> {
+    let signature_tokens: Vec<&str> = tokenize(signature)
+        .iter()
+        .filter(|t| t.kind != TokenKind::Comment)
+        .map(|t| &signature[t.start..t.end])
+        .collect();
+    if signature_tokens.is_empty() {
+        return Vec::new();
+    }
+
+    let text = |index: usize| &src[code[index].0.start..code[index].0.end];
+    let mut matches = Vec::new();
+
+    let mut i = 0;
+    while i + signature_tokens.len() <= code.len() {
+        let is_match = code[i].1 == 0
+            && signature_tokens.iter().enumerate().all(|(k, expected)| text(i + k) == *expected);
+        if !is_match {
+            i += 1;
+            continue;
+        }
+
+        let last = i + signature_tokens.len() - 1;
+        let static_range = if i > 0 && text(i - 1) == "static" {
+            Some((code[i - 1].0.start, code[i].0.start))
+        } else {
+            None
+        };
+
+        // 签名后紧跟 '{' 说明是函数定义，找到与之匹配的 '}'
+        let body_end = if last + 1 < code.len() && text(last + 1) == "{" {
+            code[last + 2..]
+                .iter()
+                .find(|(token, depth)| *depth == 0 && &src[token.start..token.end] == "}")
+                .map(|(token, _)| token.end)
+        } else {
+            None
+        };
+
+        matches.push(SignatureMatch {
+            start: static_range.map(|(start, _)| start).unwrap_or(code[i].0.start),
+            static_range,
+            body_end,
+        });
+        i = last + 1;
+    }
+
+    matches
+}
+
+/// Value of the condition of an `#if` or `#elif`, with `value_of` giving the macros that are known. None when it
+/// depends on other macros or on operators other than `!`, `&&`, `||` and `defined`
+pub fn condition_value(condition: &str, value_of: &dyn Fn(&str) -> Option<i64>) -> Option<bool> {
+    let mut tokens: Vec<Token> = Vec::new();
+    for token in tokenize(condition) {
+        let text = &condition[token.start..token.end];
+        if token.kind == TokenKind::Comment || text == "\\" {
+            continue;
+        }
+        // 分词器按单个字符切分标点，这里把 && || == != <= >= 合起来
+        if let Some(last) = tokens.last_mut().filter(|last| last.kind == TokenKind::Punct && last.end == token.start) {
+            if ["&&", "||", "==", "!=", "<=", ">="].contains(&&condition[last.start..token.end]) {
+                last.end = token.end;
+                continue;
+            }
+        }
+        tokens.push(token);
+    }
+    let texts: Vec<&str> = tokens.iter().map(|token| &condition[token.start..token.end]).collect();
+    let mut rest = &texts[..];
+    let value = Condition { value_of }.or(&mut rest);
+    if rest.is_empty() { value } else { None }
+}
+
+/// Three-valued evaluation of a preprocessor condition, None standing for unknown
+struct Condition<'a> {
+    value_of: &'a dyn Fn(&str) -> Option<i64>,
+}
+
+impl Condition<'_> {
+    fn or(&self, tokens: &mut &[&str]) -> Option<bool> {
+        let mut value = self.and(tokens);
+        while tokens.first() == Some(&"||") {
+            *tokens = &tokens[1..];
+            value = match (value, self.and(tokens)) {
+                (Some(true), _) | (_, Some(true)) => Some(true),
+                (Some(false), Some(false)) => Some(false),
+                _ => None,
+            };
+        }
+        value
+    }
+
+    fn and(&self, tokens: &mut &[&str]) -> Option<bool> {
+        let mut value = self.operand(tokens);
+        while tokens.first() == Some(&"&&") {
+            *tokens = &tokens[1..];
+            value = match (value, self.operand(tokens)) {
+                (Some(false), _) | (_, Some(false)) => Some(false),
+                (Some(true), Some(true)) => Some(true),
+                _ => None,
+            };
+        }
+        value
+    }
+
+    fn operand(&self, tokens: &mut &[&str]) -> Option<bool> {
+        let (&first, rest) = tokens.split_first()?;
+        *tokens = rest;
+        let value = match first {
+            "!" => return self.operand(tokens).map(|value| !value),
+            "(" => {
+                let value = self.or(tokens);
+                if tokens.first() != Some(&")") {
+                    return None;
+                }
+                *tokens = &tokens[1..];
+                value
+            }
+            "defined" => {
+                let current: &[&str] = tokens;
+                let (name, rest) = match current {
+                    ["(", name, ")", rest @ ..] => (*name, rest),
+                    [name, rest @ ..] if *name != "(" => (*name, rest),
+                    _ => return None,
+                };
+                *tokens = rest;
+                (self.value_of)(name).map(|_| true)
+            }
+            number if number.starts_with(|c: char| c.is_ascii_digit()) => {
+                number.trim_end_matches(['u', 'U', 'l', 'L']).parse::<i64>().ok().map(|value| value != 0)
+            }
+            name => (self.value_of)(name).map(|value| value != 0),
+        };
+        // 比较、算术等运算符：整个操作数算作未知
+        if tokens.first().is_some_and(|next| !matches!(*next, "&&" | "||" | ")")) {
+            let mut depth = 0;
+            while let Some(&next) = tokens.first() {
+                match next {
+                    "&&" | "||" | ")" if depth == 0 => break,
+                    "(" => depth += 1,
+                    ")" => depth -= 1,
+                    _ => {}
+                }
+                *tokens = &tokens[1..];
+            }
+            return None;
+        }
+        value
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn kinds(src: &str) -> Vec<(TokenKind, &str)> {
+        tokenize(src).iter().map(|t| (t.kind, &src[t.start..t.end])).collect()
+    }
+
+    /// Depth of each code token, by its text
+    fn depths(src: &str) -> Vec<(&str, i32)> {
+        code_tokens_with_depth(src, &tokenize(src)).iter().map(|(t, depth)| (&src[t.start..t.end], *depth)).collect()
+    }
+
+    #[test]
+    fn token_kinds() {
+        let src = "#include \"a.h\"\nint x = 'a' + 0x1F; /* c */ // d\nchar *s = \"s\\\"t\";";
+
+        assert_eq!(kinds(src), [
+            (TokenKind::Preprocessor, "#include \"a.h\""),
+            (TokenKind::Identifier, "int"),
+            (TokenKind::Identifier, "x"),
+            (TokenKind::Punct, "="),
+            (TokenKind::CharLiteral, "'a'"),
+            (TokenKind::Punct, "+"),
+            (TokenKind::Number, "0x1F"),
+            (TokenKind::Punct, ";"),
+            (TokenKind::Comment, "/* c */"),
+            (TokenKind::Comment, "// d"),
+            (TokenKind::Identifier, "char"),
+            (TokenKind::Punct, "*"),
+            (TokenKind::Identifier, "s"),
+            (TokenKind::Punct, "="),
+            (TokenKind::StringLiteral, "\"s\\\"t\""),
+            (TokenKind::Punct, ";"),
+        ]);
+    }
+
+    #[test]
+    fn directive_runs_over_continued_lines_and_block_comments() {
+        let src = "#define M(x) \\\n    (x) /* spans\nlines */ + 1\nint y; # not a directive";
+
+        let tokens = kinds(src);
+
+        assert_eq!(tokens[0], (TokenKind::Preprocessor, "#define M(x) \\\n    (x) /* spans\nlines */ + 1"));
+        assert_eq!(tokens[1], (TokenKind::Identifier, "int"));
+        assert_eq!(tokens[4], (TokenKind::Punct, "#"));
+    }
+
+    #[test]
+    fn non_ascii_characters_are_single_tokens() {
+        let src = "int π = 1; // — ok\nconst char *s = \"ü\";";
+
+        let tokens = kinds(src);
+
+        assert_eq!(tokens[1], (TokenKind::Punct, "π"));
+        assert_eq!(tokens[5], (TokenKind::Comment, "// — ok"));
+        assert_eq!(tokens[11], (TokenKind::StringLiteral, "\"ü\""));
+    }
+
+    #[test]
+    fn braces_in_literals_and_comments_are_not_counted() {
+        let src = "void f(void) {\n    char c = '{';\n    char q = '\\'';\n    const char *s = \"}{\";\n    /* { */ // }\n}\nint g;";
+
+        let depths = depths(src);
+
+        assert!(!depths.iter().any(|(text, _)| text.starts_with("/*") || text.starts_with("//")));
+        assert!(depths.contains(&("'{'", 1)) && depths.contains(&("'\\''", 1)) && depths.contains(&("\"}{\"", 1)));
+        assert_eq!(&depths[depths.len() - 4..], [("}", 0), ("int", 0), ("g", 0), (";", 0)]);
+    }
+
+    #[test]
+    fn braces_in_conditional_branches_are_counted_once() {
+        let src = "void f(void) {\n#if A\n    if (a) {\n#elif B\n    if (b) {\n#else\n    {\n#endif\n        run();\n    }\n}\nint g;";
+
+        let depths = depths(src);
+
+        assert!(depths.contains(&("run", 2)));
+        assert_eq!(&depths[depths.len() - 4..], [("}", 0), ("int", 0), ("g", 0), (";", 0)]);
+    }
+
+    #[test]
+    fn branch_that_closes_a_brace_restarts_from_the_depth_at_if() {
+        let src = "int f(void) {\n#ifdef A\n    return 1;\n}\n#else\n    return 0;\n}\n#endif\nint g;";
+
+        let depths = depths(src);
+
+        assert_eq!(&depths[depths.len() - 3..], [("int", 0), ("g", 0), (";", 0)]);
+    }
+
+    #[test]
+    fn nested_conditionals() {
+        let src = "void f(void) {\n#if A\n#ifndef B\n    {\n#endif\n    {\n#else\n    {\n#endif\n        run();\n    }\n}\n";
+
+        assert!(depths(src).contains(&("run", 3)));
+    }
+
+    #[test]
+    fn signature_matches_regardless_of_whitespace_and_comments() {
+        let src = "static int\ntranscode( /* sch */ Scheduler *sch)\n{\n    return transcode(sch);\n}\nint transcode(Scheduler *sch);\n";
+
+        let matches = find_signature_in(src, &code_tokens(src), "int transcode(Scheduler *sch)");
+
+        assert_eq!(matches.len(), 2);
+        assert_eq!(matches[0].start, 0);
+        assert_eq!(matches[0].static_range, Some((0, 7)));
+        assert_eq!(matches[0].body_end, Some(src.find("}\n").unwrap() + 1));
+        assert_eq!(matches[1].start, src.rfind("int transcode").unwrap());
+        assert!(matches[1].static_range.is_none() && matches[1].body_end.is_none());
+    }
+
+    #[test]
+    fn signature_inside_a_body_or_a_comment_is_not_matched() {
+        let src = "/* int transcode(void) */\nvoid f(void) {\n#if A\n}\n#else\n    int transcode(void);\n#endif\n}\n";
+
+        assert!(find_signature_in(src, &code_tokens(src), "int transcode(void)").is_empty());
+        assert!(find_signature_in(src, &code_tokens(src), "/* only a comment */").is_empty());
+    }
+
+    #[test]
+    fn function_body_end_skips_braces_in_literals_and_branches() {
+        let src = "int main(void)\n{\n    puts(\"}\");\n#if A\n    if (a) {\n#else\n    if (b) {\n#endif\n    }\n    return '}';\n}\nint after;";
+
+        let matches = find_signature_in(src, &code_tokens(src), "int main(void)");
+
+        assert_eq!(matches[0].body_end, Some(src.find("\nint after").unwrap()));
+    }
+
+    #[test]
+    fn quoted_includes_only() {
+        let src = "#include <stdio.h>\n#  include \"ffmpeg.h\"\n// #include \"commented.h\"\n#define X \"x.h\"\n#include \"cmdutils.h\" /* c */\n";
+
+        assert_eq!(quoted_includes(src), ["ffmpeg.h", "cmdutils.h"]);
+    }
+
+    #[test]
+    fn condition_values() {
+        let value_of = |name: &str| match name {
+            "CONFIG_POSTPROC" => Some(0),
+            "CONFIG_AVDEVICE" => Some(1),
+            _ => None,
+        };
+        let value = |condition: &str| condition_value(condition, &value_of);
+
+        assert_eq!(value(" CONFIG_POSTPROC"), Some(false));
+        assert_eq!(value("!CONFIG_POSTPROC"), Some(true));
+        assert_eq!(value("CONFIG_POSTPROC && !CONFIG_SMALL"), Some(false));
+        assert_eq!(value("CONFIG_SMALL || CONFIG_POSTPROC"), None);
+        assert_eq!(value("CONFIG_AVDEVICE || CONFIG_SMALL"), Some(true));
+        assert_eq!(value("!(CONFIG_POSTPROC || CONFIG_AVDEVICE)"), Some(false));
+        assert_eq!(value("defined(CONFIG_POSTPROC) && defined CONFIG_AVDEVICE"), Some(true));
+        assert_eq!(value("defined(CONFIG_SMALL)"), None);
+        assert_eq!(value("CONFIG_POSTPROC /* libpostproc */ && \\\n    HAVE_THREADS"), Some(false));
+        assert_eq!(value("0 || 1L"), Some(true));
+        // 比较和算术只让所在的操作数未知
+        assert_eq!(value("CONFIG_POSTPROC == 0"), None);
+        assert_eq!(value("LIBAVUTIL_VERSION_MAJOR > 58 && CONFIG_POSTPROC"), Some(false));
+        assert_eq!(value("(CONFIG_POSTPROC"), None);
+        assert_eq!(value(""), None);
+    }
+}
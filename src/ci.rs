@@ -0,0 +1,150 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use minijinja::context;
+
+use crate::error::VcpkgFfError;
+use crate::templates::Templates;
+use crate::tool_config::ToolConfig;
+use crate::vcpkg_manager::FFMPEG_FEATURES;
+
+/// CI service `vcpkg_ff generate ci` writes a workflow for (`--provider github|azure`)
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum CiProvider {
+    /// .github/workflows/prebuild.yml for GitHub Actions
+    GitHub,
+    /// azure-pipelines.yml for Azure Pipelines
+    Azure,
+}
+
+impl CiProvider {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "github" => Ok(CiProvider::GitHub),
+            "azure" => Ok(CiProvider::Azure),
+            other => Err(format!("invalid --provider `{}`, expected github or azure", other)),
+        }
+    }
+
+    /// Where the workflow goes, relative to the project directory
+    pub fn workflow_path(&self) -> PathBuf {
+        match self {
+            CiProvider::GitHub => Path::new(".github").join("workflows").join("prebuild.yml"),
+            CiProvider::Azure => PathBuf::from("azure-pipelines.yml"),
+        }
+    }
+
+    fn template(&self) -> &'static str {
+        match self {
+            CiProvider::GitHub => "github-workflow.yml.jinja",
+            CiProvider::Azure => "azure-pipelines.yml.jinja",
+        }
+    }
+
+    /// Hosted runner images and the triplet vcpkg_ff picks on each of them
+    fn platforms(&self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            CiProvider::GitHub => &[
+                ("ubuntu-latest", "x64-linux"),
+                ("ubuntu-24.04-arm", "arm64-linux"),
+                ("macos-13", "x64-osx"),
+                ("macos-14", "arm64-osx"),
+                ("windows-latest", "x64-windows-static"),
+            ],
+            // Azure 的托管代理没有 arm64 镜像
+            CiProvider::Azure => &[
+                ("ubuntu-latest", "x64-linux"),
+                ("macOS-13", "x64-osx"),
+                ("windows-latest", "x64-windows-static"),
+            ],
+        }
+    }
+}
+
+/// First line of every generated workflow, a file without it is not overwritten
+const GENERATED_HEADER: &str = "# Generated by vcpkg_ff";
+
+/// Node.js major version the workflows run, also the one prebuilt when [prebuild] has no targets
+const DEFAULT_NODE_VERSION: &str = "20";
+
+/// Writes the CI workflow building the addon for every hosted platform and publishing the prebuilt binaries
+///
+/// The workflow caches vcpkg's binary packages under a key naming the ffmpeg features, so that the
+/// ffmpeg build only reruns when the features, vcpkg_ff.toml or Cargo.lock change. The runtimes in
+/// `[prebuild] targets` are prebuilt, node@20 when there are none.
+pub struct CiGenerator {
+    base_dir: PathBuf,
+    features: Vec<String>,
+}
+
+impl CiGenerator {
+    pub fn new(base_dir: &Path) -> Self {
+        Self {
+            base_dir: base_dir.to_path_buf(),
+            features: FFMPEG_FEATURES.iter().map(|feature| feature.to_string()).collect(),
+        }
+    }
+
+    /// ffmpeg port features the cache key names, those of the [`VcpkgManager`](crate::VcpkgManager) used
+    pub fn with_features(mut self, features: &[String]) -> Self {
+        self.features = features.to_vec();
+        self
+    }
+
+    /// Render the workflow for `provider`
+    pub fn render(&self, provider: CiProvider) -> Result<String, VcpkgFfError> {
+        let config = ToolConfig::load(&self.base_dir)?;
+        let templates = Templates::load(&self.base_dir.join("templates"))?;
+
+        let targets = if config.prebuild.targets.is_empty() {
+            vec![format!("node@{}.0.0", DEFAULT_NODE_VERSION)]
+        } else {
+            config.prebuild.targets.clone()
+        };
+        let mut env = Vec::new();
+        if let Some(target) = &config.macos.deployment_target {
+            env.push(("MACOSX_DEPLOYMENT_TARGET", target.clone()));
+        }
+        let cache_files: Vec<&str> = ["vcpkg_ff.toml", "Cargo.lock"]
+            .into_iter()
+            .filter(|file| self.base_dir.join(file).exists())
+            .collect();
+        let platforms: Vec<_> = provider.platforms()
+            .iter()
+            .map(|&(runner, triplet)| context! { runner => runner, triplet => triplet })
+            .collect();
+
+        templates.render(provider.template(), context! {
+            tool_version => env!("CARGO_PKG_VERSION"),
+            platforms => platforms,
+            env => env,
+            node_version => DEFAULT_NODE_VERSION,
+            targets => targets,
+            cache_key => format!("vcpkg-ffmpeg-{}", self.features.join("-")),
+            cache_files => cache_files,
+            vcpkg_ff => "cargo run --release --",
+        })
+    }
+
+    /// Write the workflow for `provider` into the project, returns its path
+    ///
+    /// An existing workflow is only replaced when it was generated, edits are kept in templates/.
+    pub fn generate(&self, provider: CiProvider) -> Result<PathBuf, VcpkgFfError> {
+        let path = self.base_dir.join(provider.workflow_path());
+        if let Ok(existing) = fs::read_to_string(&path) {
+            if !existing.starts_with(GENERATED_HEADER) {
+                return Err(VcpkgFfError::Config {
+                    path,
+                    message: "exists and was not generated by vcpkg_ff, move it away to generate the workflow".to_string(),
+                });
+            }
+        }
+
+        let content = self.render(provider)?;
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(&path, content)?;
+        Ok(path)
+    }
+}
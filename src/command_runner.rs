@@ -0,0 +1,335 @@
+use std::ffi::OsString;
+use std::fmt;
+use std::fs::File;
+use std::io::{self, BufRead, BufReader, Read, Write};
+use std::path::PathBuf;
+use std::process::{Child, Command, ExitStatus, Stdio};
+use std::sync::{Arc, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+use crate::error::VcpkgFfError;
+use crate::journal::{self, Action};
+use crate::progress;
+use crate::trace;
+
+/// An external command for a [`CommandRunner`]
+#[derive(Debug, Clone)]
+pub struct CommandSpec {
+    pub program: PathBuf,
+    pub args: Vec<OsString>,
+    pub current_dir: Option<PathBuf>,
+    /// Set on top of the inherited environment
+    pub env: Vec<(OsString, OsString)>,
+    /// Kill the command when it runs longer
+    pub timeout: Option<Duration>,
+    /// Pass stdout to the progress output and stderr to stderr while the command runs,
+    /// besides capturing them
+    pub stream: bool,
+    /// Written to the command's stdin, which is empty otherwise
+    pub stdin: Option<Vec<u8>>,
+    /// File the stdout and stderr lines are also written to, as they arrive
+    pub log: Option<PathBuf>,
+}
+
+/// Variables set on every command, see [`inject_env`]
+static INJECTED_ENV: Mutex<Vec<(OsString, OsString)>> = Mutex::new(Vec::new());
+
+/// Proxy variables curl (and with it git and vcpkg) reads in lower case only, or in both cases
+const PROXY_VARIABLES: &[&str] = &["http_proxy", "https_proxy", "all_proxy", "no_proxy"];
+
+/// Set `vars` on every command started afterwards, beneath the variables of the command itself,
+/// e.g. a proxy or VCPKG_BINARY_SOURCES from the `[env]` of vcpkg_ff.toml
+///
+/// Proxy variables are set in both cases: curl ignores an upper-case HTTP_PROXY.
+pub fn inject_env<I: IntoIterator<Item = (K, V)>, K: Into<OsString>, V: Into<OsString>>(vars: I) {
+    let mut injected = INJECTED_ENV.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+    for (name, value) in vars {
+        let (name, value) = (name.into(), value.into());
+        let lower = name.to_string_lossy().to_ascii_lowercase();
+        if PROXY_VARIABLES.contains(&lower.as_str()) {
+            injected.push((lower.to_ascii_uppercase().into(), value.clone()));
+            injected.push((lower.into(), value));
+        } else {
+            injected.push((name, value));
+        }
+    }
+}
+
+/// Value of `name` the commands see: the last injected one, or the variable of this process
+pub fn env_var(name: &str) -> Option<OsString> {
+    let injected = INJECTED_ENV.lock().ok()
+        .and_then(|injected| injected.iter().rev().find(|(var, _)| var == name).map(|(_, value)| value.clone()));
+    injected.or_else(|| std::env::var_os(name))
+}
+
+/// The injected variables followed by `env`, later ones win
+fn environment(env: &[(OsString, OsString)]) -> Vec<(OsString, OsString)> {
+    let mut vars = INJECTED_ENV.lock().map(|injected| injected.clone()).unwrap_or_default();
+    vars.extend(env.iter().cloned());
+    vars
+}
+
+impl CommandSpec {
+    pub fn new(program: impl Into<PathBuf>) -> Self {
+        Self {
+            program: program.into(),
+            args: Vec::new(),
+            current_dir: None,
+            env: Vec::new(),
+            timeout: None,
+            stream: false,
+            stdin: None,
+            log: None,
+        }
+    }
+
+    pub fn arg(mut self, arg: impl Into<OsString>) -> Self {
+        self.args.push(arg.into());
+        self
+    }
+
+    pub fn args<I: IntoIterator<Item = S>, S: Into<OsString>>(mut self, args: I) -> Self {
+        self.args.extend(args.into_iter().map(Into::into));
+        self
+    }
+
+    pub fn current_dir(mut self, dir: impl Into<PathBuf>) -> Self {
+        self.current_dir = Some(dir.into());
+        self
+    }
+
+    pub fn env(mut self, name: impl Into<OsString>, value: impl Into<OsString>) -> Self {
+        self.env.push((name.into(), value.into()));
+        self
+    }
+
+    pub fn timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = Some(timeout);
+        self
+    }
+
+    pub fn streamed(mut self) -> Self {
+        self.stream = true;
+        self
+    }
+
+    pub fn stdin(mut self, input: impl Into<Vec<u8>>) -> Self {
+        self.stdin = Some(input.into());
+        self
+    }
+
+    /// Also write the output to `path`, replacing the file
+    pub fn log(mut self, path: impl Into<PathBuf>) -> Self {
+        self.log = Some(path.into());
+        self
+    }
+    
+    /// The same command for tokio, without the timeout, streaming and log, killed when dropped
+    #[cfg(feature = "async")]
+    pub(crate) fn to_tokio(&self) -> tokio::process::Command {
+        let mut command = tokio::process::Command::new(&self.program);
+        command.args(&self.args).envs(environment(&self.env)).kill_on_drop(true);
+        if let Some(dir) = &self.current_dir {
+            command.current_dir(dir);
+        }
+        command
+    }
+
+    /// The program name and arguments, for messages
+    pub fn display(&self) -> String {
+        let mut line = self.program.to_string_lossy().into_owned();
+        for arg in &self.args {
+            line.push(' ');
+            line.push_str(&arg.to_string_lossy());
+        }
+        line
+    }
+}
+
+/// What a finished command produced
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct CommandOutput {
+    /// Exit code, None when the command was killed by a signal or the timeout
+    pub code: Option<i32>,
+    pub timed_out: bool,
+    pub stdout: String,
+    pub stderr: String,
+}
+
+impl CommandOutput {
+    /// Output of a command that exited with `code`, for [`CommandRunner`] mocks
+    pub fn exited(code: i32, stdout: &str, stderr: &str) -> Self {
+        Self { code: Some(code), timed_out: false, stdout: stdout.to_string(), stderr: stderr.to_string() }
+    }
+
+    pub fn success(&self) -> bool {
+        self.code == Some(0)
+    }
+
+    /// [`VcpkgFfError::CommandFailed`] for `command` having ended with this output, quoting its stderr
+    pub fn failure(&self, command: impl Into<String>) -> VcpkgFfError {
+        VcpkgFfError::CommandFailed { command: command.into(), message: format!("{}\n{}", self, self.stderr.trim_end()) }
+    }
+}
+
+impl From<ExitStatus> for CommandOutput {
+    fn from(status: ExitStatus) -> Self {
+        Self { code: status.code(), ..Self::default() }
+    }
+}
+
+impl fmt::Display for CommandOutput {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.code {
+            _ if self.timed_out => write!(f, "timed out"),
+            Some(code) => write!(f, "exit code {}", code),
+            None => write!(f, "terminated by a signal"),
+        }
+    }
+}
+
+/// Runs the git, bootstrap and vcpkg commands of [`VcpkgManager`](crate::VcpkgManager),
+/// replaceable to test it without them
+pub trait CommandRunner: Send + Sync {
+    /// Run `command` to completion. Err only when it could not be started.
+    fn run(&self, command: &CommandSpec) -> io::Result<CommandOutput>;
+}
+
+/// Starts real processes
+pub struct SystemRunner;
+
+impl CommandRunner for SystemRunner {
+    fn run(&self, command: &CommandSpec) -> io::Result<CommandOutput> {
+        let seq = journal::begin(Action::Command { command: command.display() });
+        let name = command.program.file_name().unwrap_or(command.program.as_os_str()).to_string_lossy();
+        let output = trace::span("command", &name, || run_process(command), |output| match output {
+            Ok(output) => serde_json::json!({"command": command.display(), "code": output.code, "timed_out": output.timed_out}),
+            Err(e) => serde_json::json!({"command": command.display(), "error": e.to_string()}),
+        });
+        journal::end(seq, output.as_ref().is_ok_and(CommandOutput::success));
+        output
+    }
+}
+
+/// Kills the child when dropped before it exited, e.g. when waiting for it failed or panicked
+struct KillOnDrop(Child);
+
+impl Drop for KillOnDrop {
+    fn drop(&mut self) {
+        if let Ok(None) = self.0.try_wait() {
+            let _ = self.0.kill();
+            let _ = self.0.wait();
+        }
+    }
+}
+
+/// Spawn `command` and wait for it, with its stdin, timeout, streaming and log
+fn run_process(command: &CommandSpec) -> io::Result<CommandOutput> {
+    let log = match &command.log {
+        Some(path) => Some(Arc::new(Mutex::new(File::create(path)
+            .map_err(|e| io::Error::new(e.kind(), format!("{}: {}", path.display(), e)))?))),
+        None => None,
+    };
+    let mut process = Command::new(&command.program);
+    process
+        .args(&command.args)
+        .envs(environment(&command.env))
+        .stdin(if command.stdin.is_some() { Stdio::piped() } else { Stdio::null() })
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped());
+    if let Some(dir) = &command.current_dir {
+        process.current_dir(dir);
+    }
+    let mut child = KillOnDrop(process.spawn()?);
+    let child = &mut child.0;
+    // 在单独的线程里写入，命令不读 stdin 时也不会阻塞
+    if let (Some(mut stdin), Some(input)) = (child.stdin.take(), command.stdin.clone()) {
+        thread::spawn(move || {
+            let _ = stdin.write_all(&input);
+        });
+    }
+
+    // 读取线程把输出交给启动命令的步骤的观察者
+    let sink = progress::current();
+    let stdout = Arc::new(Mutex::new(String::new()));
+    let stderr = Arc::new(Mutex::new(String::new()));
+    let readers = [
+        child.stdout.take().map(|out| collect(out, Arc::clone(&stdout), log.clone(), command.stream, false, sink.clone())),
+        child.stderr.take().map(|err| collect(err, Arc::clone(&stderr), log.clone(), command.stream, true, sink.clone())),
+    ];
+
+    let status = match command.timeout {
+        None => Some(child.wait()?),
+        Some(timeout) => {
+            let deadline = Instant::now() + timeout;
+            loop {
+                if let Some(status) = child.try_wait()? {
+                    break Some(status);
+                }
+                if Instant::now() >= deadline {
+                    let _ = child.kill();
+                    let _ = child.wait();
+                    break None;
+                }
+                thread::sleep(Duration::from_millis(50));
+            }
+        }
+    };
+    // 超时后子进程派生的进程可能仍占用管道，不等待读取线程
+    if status.is_some() {
+        for reader in readers.into_iter().flatten() {
+            let _ = reader.join();
+        }
+    }
+
+    let take = |buffer: &Arc<Mutex<String>>| buffer.lock().map(|text| text.clone()).unwrap_or_default();
+    Ok(CommandOutput {
+        code: status.and_then(|status| status.code()),
+        timed_out: status.is_none(),
+        stdout: take(&stdout),
+        stderr: take(&stderr),
+    })
+}
+
+/// Read `reader` line by line into `buffer` and the log, echoing the lines when `stream` is set. Lines that
+/// aren't UTF-8 (MSVC prints in the OEM codepage) are decoded lossily, the pipe is read to its end either way
+fn collect<R: Read + Send + 'static>(
+    reader: R,
+    buffer: Arc<Mutex<String>>,
+    log: Option<Arc<Mutex<File>>>,
+    stream: bool,
+    is_stderr: bool,
+    sink: Option<progress::Sink>,
+) -> thread::JoinHandle<()> {
+    thread::spawn(move || progress::with_sink(sink, || {
+        let mut reader = BufReader::new(reader);
+        let mut bytes = Vec::new();
+        loop {
+            bytes.clear();
+            match reader.read_until(b'\n', &mut bytes) {
+                Ok(0) => break,
+                Ok(_) => {}
+                Err(e) if e.kind() == io::ErrorKind::Interrupted => continue,
+                Err(_) => break,
+            }
+            let end = bytes.strip_suffix(b"\n").map_or(bytes.len(), <[u8]>::len);
+            let end = bytes[..end].strip_suffix(b"\r").map_or(end, <[u8]>::len);
+            let line = String::from_utf8_lossy(&bytes[..end]);
+            if stream {
+                if is_stderr {
+                    progress::error_output(&line);
+                } else {
+                    progress::output(&line);
+                }
+            }
+            if let Some(Ok(mut log)) = log.as_ref().map(|log| log.lock()) {
+                let _ = writeln!(log, "{}", line);
+            }
+            if let Ok(mut buffer) = buffer.lock() {
+                buffer.push_str(&line);
+                buffer.push('\n');
+            }
+        }
+    }))
+}
@@ -0,0 +1,380 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
+use std::time::Duration;
+
+use serde::{Deserialize, Serialize};
+
+use crate::command_runner::{CommandRunner, CommandSpec, SystemRunner};
+use crate::error::VcpkgFfError;
+use crate::generated_headers::GENERATED_HEADERS;
+use crate::journal;
+use crate::package_backend::{FfmpegSource, PackageBackend};
+use crate::progress::say;
+use crate::vcpkg_manager::{copy_dir, VcpkgManager};
+
+/// ConanCenter reference installed by default, the ffmpeg release the patch rules are written for
+pub const CONAN_FFMPEG_REFERENCE: &str = "ffmpeg/7.1.1";
+
+/// Options of ConanCenter's ffmpeg recipe enabling the vcpkg port features, by feature
+const FEATURE_OPTIONS: &[(&str, &str)] = &[
+    ("x264", "with_libx264"),
+    ("x265", "with_libx265"),
+    ("vpx", "with_libvpx"),
+    ("aom", "with_libaom"),
+    ("dav1d", "with_libdav1d"),
+    ("fdk-aac", "with_libfdk_aac"),
+    ("mp3lame", "with_libmp3lame"),
+    ("opus", "with_opus"),
+    ("vorbis", "with_vorbis"),
+    ("webp", "with_libwebp"),
+    ("freetype", "with_freetype"),
+    ("zlib", "with_zlib"),
+    ("bzip2", "with_bzip2"),
+    ("lzma", "with_lzma"),
+    ("ffmpeg", "with_programs"),
+    ("ffprobe", "with_programs"),
+];
+
+const CONAN_CHECK_TIMEOUT: Duration = Duration::from_secs(60);
+
+/// What `install_packages` installed, kept in share/vcpkg_ff/conan.json of the installed tree
+#[derive(Debug, Serialize, Deserialize)]
+struct InstallRecord {
+    reference: String,
+    features: Vec<String>,
+}
+
+/// `conan install --format=json` output, only the parts the deployment reads
+#[derive(Debug, Deserialize)]
+struct InstallGraph {
+    graph: Graph,
+}
+
+#[derive(Debug, Deserialize)]
+struct Graph {
+    nodes: serde_json::Map<String, serde_json::Value>,
+}
+
+/// A package of the installed graph
+#[derive(Debug)]
+struct GraphPackage {
+    name: String,
+    package_folder: PathBuf,
+    build_folder: Option<PathBuf>,
+}
+
+/// Installs ffmpeg from ConanCenter instead of vcpkg, for organizations standardized on Conan 2
+///
+/// `conan install` builds or downloads ffmpeg with the options matching the vcpkg features, in Release and Debug,
+/// and the packages are deployed into `<vcpkg root>/installed/<triplet>` the way vcpkg lays them out, with
+/// pkg-config files from `PkgConfigDeps`. The ffmpeg sources come from the source folder of Conan's cache.
+///
+/// ```no_run
+/// use vcpkg_ff::{ConanBackend, Pipeline, VcpkgManager};
+///
+/// let manager = VcpkgManager::new();
+/// let outcome = Pipeline::new()
+///     .with_backend(ConanBackend::for_manager(&manager))
+///     .with_manager(manager)
+///     .run()?;
+/// # Ok::<(), vcpkg_ff::VcpkgFfError>(())
+/// ```
+#[derive(Clone)]
+pub struct ConanBackend {
+    /// ffmpeg reference to install, e.g. `ffmpeg/7.1.1`
+    reference: String,
+    /// vcpkg-style tree the packages are deployed to, `<vcpkg root>/installed/<triplet>`
+    installed_dir: PathBuf,
+    /// vcpkg's build tree of the ffmpeg port, receives config.h of a local build
+    buildtree_dir: PathBuf,
+    triplet: String,
+    features: Vec<String>,
+    runner: Arc<dyn CommandRunner>,
+}
+
+impl ConanBackend {
+    /// Deploy into the installed tree of `manager`'s vcpkg root, with its triplet and features
+    pub fn for_manager(manager: &VcpkgManager) -> Self {
+        Self {
+            reference: CONAN_FFMPEG_REFERENCE.to_string(),
+            installed_dir: manager.get_vcpkg_root().join("installed").join(manager.get_triplet()),
+            buildtree_dir: manager.port_log_dir("ffmpeg"),
+            triplet: manager.get_triplet().to_string(),
+            features: manager.get_features().to_vec(),
+            runner: Arc::new(SystemRunner),
+        }
+    }
+
+    /// Install `reference` (e.g. `ffmpeg/6.1.1`) instead of [`CONAN_FFMPEG_REFERENCE`]
+    pub fn with_reference(mut self, reference: impl Into<String>) -> Self {
+        self.reference = reference.into();
+        self
+    }
+
+    /// Run conan through `runner` instead of starting it directly
+    pub fn with_runner(mut self, runner: Arc<dyn CommandRunner>) -> Self {
+        self.runner = runner;
+        self
+    }
+
+    pub fn get_reference(&self) -> &str {
+        &self.reference
+    }
+
+    /// Directory the packages are deployed to
+    pub fn get_installed_dir(&self) -> &Path {
+        &self.installed_dir
+    }
+
+    fn conan(&self) -> CommandSpec {
+        CommandSpec::new("conan")
+    }
+
+    /// Whether `command` runs and exits successfully
+    fn succeeds(&self, command: CommandSpec) -> bool {
+        self.runner.run(&command.timeout(CONAN_CHECK_TIMEOUT)).is_ok_and(|output| output.success())
+    }
+
+    fn record_path(&self) -> PathBuf {
+        self.installed_dir.join("share").join("vcpkg_ff").join("conan.json")
+    }
+
+    fn package_name(&self) -> &str {
+        self.reference.split('/').next().unwrap_or(&self.reference)
+    }
+
+    /// `-s` settings of the host for the triplet: os, arch and, for static Windows triplets, the static CRT
+    fn settings(&self) -> Result<Vec<String>, VcpkgFfError> {
+        let unsupported = || VcpkgFfError::Unsupported { by: "the conan backend", kind: "triplet", name: self.triplet.clone() };
+        let arch = match self.triplet.split('-').next() {
+            Some("x64") => "x86_64",
+            Some("x86") => "x86",
+            Some("arm64") => "armv8",
+            Some("arm") => "armv7",
+            _ => return Err(unsupported()),
+        };
+        let os = if self.triplet.contains("windows") {
+            "Windows"
+        } else if self.triplet.contains("osx") {
+            "Macos"
+        } else if self.triplet.contains("linux") {
+            "Linux"
+        } else {
+            return Err(unsupported());
+        };
+        let mut settings = vec![format!("os={}", os), format!("arch={}", arch)];
+        if os == "Windows" && self.triplet.ends_with("-static") {
+            settings.push("compiler.runtime=static".to_string());
+        }
+        Ok(settings)
+    }
+
+    /// Whether the triplet links dynamically: vcpkg's Windows triplets unless static, the others with -dynamic
+    fn shared(&self) -> bool {
+        (self.triplet.contains("windows") && !self.triplet.contains("-static")) || self.triplet.ends_with("-dynamic")
+    }
+
+    /// `-o` options of the ffmpeg recipe for the features
+    fn options(&self) -> Result<Vec<String>, VcpkgFfError> {
+        let package = self.package_name();
+        let mut options = vec![format!("{}/*:shared={}", package, if self.shared() { "True" } else { "False" })];
+        for feature in &self.features {
+            let Some((_, option)) = FEATURE_OPTIONS.iter().find(|(name, _)| name == feature) else {
+                return Err(VcpkgFfError::Unknown {
+                    kind: "ffmpeg feature of Conan's recipe",
+                    name: feature.clone(),
+                    expected: FEATURE_OPTIONS.iter().map(|(name, _)| name.to_string()).collect(),
+                });
+            };
+            let option = format!("{}/*:{}=True", package, option);
+            if !options.contains(&option) {
+                options.push(option);
+            }
+        }
+        Ok(options)
+    }
+
+    /// `conan install` of the reference for `build_type`, with the pkg-config files written to `pkgconfig_dir`
+    fn install_command(&self, build_type: &str, pkgconfig_dir: &Path) -> Result<CommandSpec, VcpkgFfError> {
+        let mut command = self.conan()
+            .args(["install", &format!("--requires={}", self.reference), "--build=missing", "--format=json", "-g", "PkgConfigDeps"])
+            .arg("--output-folder")
+            .arg(pkgconfig_dir)
+            .args(["-s", &format!("build_type={}", build_type)])
+            // 预编译的二进制包也需要源码，供 extract-ffmpeg 使用
+            .args(["-c", "tools.build:download_source=True"]);
+        for setting in self.settings()? {
+            command = command.args(["-s", &setting]);
+        }
+        for option in self.options()? {
+            command = command.args(["-o", &option]);
+        }
+        Ok(command.log(self.buildtree_dir.join(format!("conan-install-{}.log", build_type.to_lowercase()))))
+    }
+
+    /// Install one build type and deploy it to `target_dir` (the installed tree, or debug/ below it)
+    fn install_build_type(&self, build_type: &str, target_dir: &Path, with_headers: bool) -> Result<(), VcpkgFfError> {
+        let pkgconfig_dir = target_dir.join("lib").join("pkgconfig");
+        fs::create_dir_all(&pkgconfig_dir)?;
+        fs::create_dir_all(&self.buildtree_dir)?;
+        say!("Running conan install {} ({})...", self.reference, build_type);
+        // stdout 是 JSON 格式的依赖图，conan 的进度输出在 stderr
+        let output = self.runner.run(&self.install_command(build_type, &pkgconfig_dir)?)?;
+        if !output.success() {
+            return Err(VcpkgFfError::PackageInstallFailed {
+                package: format!("{} ({}, conan)", self.reference, build_type),
+                log_path: self.buildtree_dir.clone(),
+            });
+        }
+        let graph: InstallGraph = serde_json::from_str(&output.stdout)
+            .map_err(|e| VcpkgFfError::CommandFailed {
+                command: "conan install".to_string(),
+                message: format!("it printed no dependency graph: {}", e),
+            })?;
+
+        let packages = graph_packages(&graph);
+        if !packages.iter().any(|package| package.name == self.package_name()) {
+            return Err(VcpkgFfError::CommandFailed {
+                command: "conan install".to_string(),
+                message: format!("{} is not in the installed packages", self.reference),
+            });
+        }
+        for package in &packages {
+            let mut dirs = vec!["lib", "bin"];
+            if with_headers {
+                dirs.push("include");
+            }
+            for dir in dirs {
+                let source = package.package_folder.join(dir);
+                if source.is_dir() {
+                    copy_dir(&source, &target_dir.join(dir))?;
+                }
+            }
+            if package.name != self.package_name() {
+                continue;
+            }
+            // 与 vcpkg 相同：ffmpeg 和 ffprobe 程序在 tools/ffmpeg，配置生成的头文件在 buildtrees/ffmpeg
+            let bin_dir = package.package_folder.join("bin");
+            if with_headers && bin_dir.is_dir() {
+                copy_dir(&bin_dir, &self.installed_dir.join("tools").join("ffmpeg"))?;
+            }
+            if let Some(build_folder) = &package.build_folder {
+                let suffix = if with_headers { "rel" } else { "dbg" };
+                self.copy_build_headers(build_folder, &self.buildtree_dir.join(format!("{}-{}", self.triplet, suffix)))?;
+            }
+        }
+        say!("✓ {} ({}) deployed to {}", self.reference, build_type, target_dir.display());
+        Ok(())
+    }
+
+    /// Copy config.h and the other configure-generated headers of a local ffmpeg build to `target_dir`
+    fn copy_build_headers(&self, build_folder: &Path, target_dir: &Path) -> Result<(), VcpkgFfError> {
+        for header in std::iter::once("config.h").chain(GENERATED_HEADERS.iter().copied()) {
+            let source = build_folder.join(header);
+            if source.is_file() {
+                let target = target_dir.join(header);
+                fs::create_dir_all(target.parent().unwrap_or(target_dir))?;
+                fs::copy(&source, &target)?;
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Host packages of the graph that have a package folder, leaving out the consumer and build tools
+fn graph_packages(graph: &InstallGraph) -> Vec<GraphPackage> {
+    graph.graph.nodes.values()
+        .filter(|node| node["context"].as_str() != Some("build"))
+        .filter_map(|node| {
+            let reference = node["ref"].as_str()?;
+            let package_folder = node["package_folder"].as_str()?;
+            Some(GraphPackage {
+                name: reference.split('/').next().unwrap_or(reference).to_string(),
+                package_folder: PathBuf::from(package_folder),
+                build_folder: node["build_folder"].as_str().map(PathBuf::from),
+            })
+        })
+        .collect()
+}
+
+impl PackageBackend for ConanBackend {
+    fn name(&self) -> &'static str {
+        "conan"
+    }
+
+    fn is_tool_installed(&self) -> bool {
+        self.succeeds(self.conan().arg("--version")) && self.succeeds(self.conan().args(["profile", "path", "default"]))
+    }
+
+    /// Check that conan is on PATH and detect the default profile when there is none
+    fn install_tool(&self) -> Result<(), VcpkgFfError> {
+        if !self.succeeds(self.conan().arg("--version")) {
+            return Err(VcpkgFfError::ToolNotFound { tool: "conan", hint: "install Conan 2 with `pip install conan`" });
+        }
+        if self.succeeds(self.conan().args(["profile", "path", "default"])) {
+            say!("✓ conan is installed with a default profile");
+            return Ok(());
+        }
+        say!("Detecting the default conan profile...");
+        let output = self.runner.run(&self.conan().args(["profile", "detect"]).timeout(CONAN_CHECK_TIMEOUT).streamed())?;
+        if !output.success() {
+            return Err(output.failure("conan profile detect"));
+        }
+        say!("✓ Default conan profile created");
+        Ok(())
+    }
+
+    fn tool_inputs(&self) -> String {
+        "conan".to_string()
+    }
+
+    fn is_ffmpeg_installed(&self) -> bool {
+        let Ok(record) = fs::read_to_string(self.record_path()) else {
+            return false;
+        };
+        let Ok(record) = serde_json::from_str::<InstallRecord>(&record) else {
+            return false;
+        };
+        record.reference == self.reference
+            && self.features.iter().all(|feature| record.features.contains(feature))
+            && self.installed_dir.join("include").join("libavcodec").join("avcodec.h").is_file()
+    }
+
+    /// `conan install` in Release and Debug, deployed like vcpkg's release and debug/ trees
+    fn install_packages(&self) -> Result<(), VcpkgFfError> {
+        if self.is_ffmpeg_installed() {
+            say!("✓ {} already installed with conan", self.reference);
+            say!("  Features: {}", self.features.join(", "));
+            return Ok(());
+        }
+        say!("Installing {} with conan...", self.reference);
+        say!("  Platform: {}", self.triplet);
+        say!("  Features: {}", self.features.join(", "));
+        self.install_build_type("Release", &self.installed_dir, true)?;
+        self.install_build_type("Debug", &self.installed_dir.join("debug"), false)?;
+
+        let record = InstallRecord { reference: self.reference.clone(), features: self.features.clone() };
+        fs::create_dir_all(self.record_path().parent().unwrap_or(&self.installed_dir))?;
+        journal::write(&self.record_path(), &serde_json::to_string_pretty(&record)?)?;
+        say!("✓ ffmpeg installation completed with features: {}", self.features.join(", "));
+        Ok(())
+    }
+
+    fn package_inputs(&self) -> String {
+        format!("{}[{}]:{} in {}", self.reference, self.features.join(","), self.triplet, self.installed_dir.display())
+    }
+
+    fn ffmpeg_source(&self) -> Result<FfmpegSource, VcpkgFfError> {
+        let command = self.conan().args(["cache", "path", &self.reference, "--folder", "source"]).timeout(CONAN_CHECK_TIMEOUT);
+        let output = self.runner.run(&command)?;
+        let source_dir = PathBuf::from(output.stdout.trim());
+        if !output.success() || !source_dir.is_dir() {
+            return Err(VcpkgFfError::CommandFailed {
+                command: command.display(),
+                message: format!("the {} sources are not in conan's cache, run the install-packages step first", self.reference),
+            });
+        }
+        Ok(FfmpegSource::Directory(source_dir))
+    }
+}
@@ -0,0 +1,377 @@
+use std::collections::HashMap;
+
+use minijinja::{context, Value};
+
+use crate::error::VcpkgFfError;
+use crate::templates::Templates;
+
+/// Operating system a vcpkg triplet targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetOs {
+    Windows,
+    MacOs,
+    Linux,
+}
+
+impl TargetOs {
+    pub fn from_triplet(triplet: &str) -> Self {
+        if triplet.contains("windows") || triplet.contains("mingw") {
+            TargetOs::Windows
+        } else if triplet.contains("osx") {
+            TargetOs::MacOs
+        } else {
+            TargetOs::Linux
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            TargetOs::Windows => "Windows",
+            TargetOs::MacOs => "macOS",
+            TargetOs::Linux => "Linux",
+        }
+    }
+
+    /// Platform name used by Node.js (process.platform)
+    pub fn node_name(&self) -> &'static str {
+        match self {
+            TargetOs::Windows => "win32",
+            TargetOs::MacOs => "darwin",
+            TargetOs::Linux => "linux",
+        }
+    }
+}
+
+/// CPU architecture a vcpkg triplet targets
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum TargetArch {
+    X86_64,
+    X86,
+    Aarch64,
+    Arm,
+}
+
+impl TargetArch {
+    /// Parse the architecture prefix of a triplet ("x64-linux", "arm64-osx", "x86-windows-static", ...)
+    pub fn from_triplet(triplet: &str) -> Self {
+        match triplet.split('-').next().unwrap_or("") {
+            "x86" => TargetArch::X86,
+            "arm64" => TargetArch::Aarch64,
+            "arm" => TargetArch::Arm,
+            _ => TargetArch::X86_64,
+        }
+    }
+
+    pub fn display_name(&self) -> &'static str {
+        match self {
+            TargetArch::X86_64 => "x86_64",
+            TargetArch::X86 => "x86",
+            TargetArch::Aarch64 => "aarch64",
+            TargetArch::Arm => "arm",
+        }
+    }
+
+    /// Architecture name used by Node.js (process.arch)
+    pub fn node_name(&self) -> &'static str {
+        match self {
+            TargetArch::X86_64 => "x64",
+            TargetArch::X86 => "ia32",
+            TargetArch::Aarch64 => "arm64",
+            TargetArch::Arm => "arm",
+        }
+    }
+
+    /// Architecture name used by Apple toolchains (ARCHS, CMAKE_OSX_ARCHITECTURES)
+    pub fn apple_name(&self) -> &'static str {
+        match self {
+            TargetArch::X86_64 => "x86_64",
+            TargetArch::X86 => "i386",
+            TargetArch::Aarch64 => "arm64",
+            TargetArch::Arm => "armv7",
+        }
+    }
+}
+
+/// Defines of the `--target wasm` config.h variant, set over vcpkg's or the built-in config.h:
+/// Emscripten has no native architecture code, terminal or capture devices
+pub const EMSCRIPTEN_DEFINES: &[(&str, &str)] = &[
+    ("ARCH_X86", "0"),
+    ("ARCH_X86_32", "0"),
+    ("ARCH_X86_64", "0"),
+    ("ARCH_AARCH64", "0"),
+    ("ARCH_ARM", "0"),
+    ("HAVE_NEON", "0"),
+    ("HAVE_INLINE_ASM", "0"),
+    ("HAVE_X86ASM", "0"),
+    ("HAVE_TERMIOS_H", "0"),
+    ("HAVE_KBHIT", "0"),
+    ("HAVE_PRCTL", "0"),
+    ("HAVE_SYSCTL", "0"),
+    ("HAVE_MACH_ABSOLUTE_TIME", "0"),
+    ("HAVE_PTHREADS", "1"),
+    ("HAVE_W32THREADS", "0"),
+    ("CONFIG_AVDEVICE", "0"),
+    ("CC_IDENT", "\"Emscripten\""),
+];
+
+/// ffmpeg port features and the config.h switch each of them turns on
+pub const FEATURE_DEFINES: &[(&str, &str)] = &[
+    ("x264", "CONFIG_LIBX264"),
+    ("x265", "CONFIG_LIBX265"),
+    ("vpx", "CONFIG_LIBVPX"),
+    ("aom", "CONFIG_LIBAOM"),
+    ("dav1d", "CONFIG_LIBDAV1D"),
+    ("mp3lame", "CONFIG_LIBMP3LAME"),
+    ("opus", "CONFIG_LIBOPUS"),
+    ("vorbis", "CONFIG_LIBVORBIS"),
+    ("fdk-aac", "CONFIG_LIBFDK_AAC"),
+    ("openh264", "CONFIG_LIBOPENH264"),
+    ("postproc", "CONFIG_POSTPROC"),
+];
+
+/// A switch of config.h that disagrees with the installed ffmpeg port features
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct FeatureMismatch {
+    pub feature: &'static str,
+    pub define: &'static str,
+    /// Value config.h has
+    pub value: String,
+    /// Whether the feature is installed, the switch has to be 1 then and 0 otherwise
+    pub installed: bool,
+}
+
+/// The switches of [`FEATURE_DEFINES`] in `defines` that disagree with the installed `features`. Switches the header
+/// doesn't define are left alone
+pub fn feature_mismatches(defines: &HashMap<String, String>, features: &[String]) -> Vec<FeatureMismatch> {
+    FEATURE_DEFINES
+        .iter()
+        .filter_map(|(feature, define)| {
+            let value = defines.get(*define)?;
+            let installed = features.iter().any(|installed| installed == feature);
+            ((value != "0") != installed).then(|| FeatureMismatch { feature, define, value: value.clone(), installed })
+        })
+        .collect()
+}
+
+/// A titled group of `#define`s
+struct Section {
+    title: &'static str,
+    defines: Vec<(String, String)>,
+}
+
+/// Fallback config.h, used when vcpkg's build tree doesn't provide the real one
+pub struct ConfigH {
+    target_os: TargetOs,
+    target_arch: TargetArch,
+    sections: Vec<Section>,
+}
+
+impl ConfigH {
+    /// Build the template for the given triplet
+    pub fn for_triplet(triplet: &str) -> Self {
+        let target_os = TargetOs::from_triplet(triplet);
+        let target_arch = TargetArch::from_triplet(triplet);
+        let mut config = ConfigH { target_os, target_arch, sections: Vec::new() };
+
+        let platform_title = match target_os {
+            TargetOs::Windows => "Windows specific defines",
+            TargetOs::MacOs => "macOS specific defines",
+            TargetOs::Linux => "Linux specific defines",
+        };
+        let windows = target_os == TargetOs::Windows;
+        let unix = !windows;
+        config.add_section(platform_title, &[
+            ("HAVE_IO_H", flag(windows)),
+            ("HAVE_UNISTD_H", flag(unix)),
+            ("HAVE_SYS_RESOURCE_H", flag(unix)),
+            ("HAVE_GETPROCESSTIMES", flag(windows)),
+            ("HAVE_GETPROCESSMEMORYINFO", flag(windows)),
+            ("HAVE_SETCONSOLECTRLHANDLER", flag(windows)),
+            ("HAVE_SYS_SELECT_H", flag(unix)),
+            ("HAVE_TERMIOS_H", flag(unix)),
+            ("HAVE_KBHIT", flag(windows)),
+            ("HAVE_PEEKNAMEDPIPE", flag(windows)),
+            ("HAVE_GETSTDHANDLE", flag(windows)),
+            ("HAVE_GETRUSAGE", flag(unix)),
+            ("HAVE_CLOCK_GETTIME", flag(unix)),
+            ("HAVE_MACH_ABSOLUTE_TIME", flag(target_os == TargetOs::MacOs)),
+            ("HAVE_SYSCTL", flag(target_os == TargetOs::MacOs)),
+            ("HAVE_PRCTL", flag(target_os == TargetOs::Linux)),
+        ]);
+
+        config.add_section("FFmpeg components", &[
+            ("CONFIG_AVUTIL", "1"),
+            ("CONFIG_AVCODEC", "1"),
+            ("CONFIG_AVFORMAT", "1"),
+            ("CONFIG_AVDEVICE", "1"),
+            ("CONFIG_AVFILTER", "1"),
+            ("CONFIG_SWSCALE", "1"),
+            ("CONFIG_SWRESAMPLE", "1"),
+            ("CONFIG_POSTPROC", "0"),
+        ]);
+
+        let x86 = matches!(target_arch, TargetArch::X86 | TargetArch::X86_64);
+        config.add_section("Architecture", &[
+            ("ARCH_X86", flag(x86)),
+            ("ARCH_X86_32", flag(target_arch == TargetArch::X86)),
+            ("ARCH_X86_64", flag(target_arch == TargetArch::X86_64)),
+            ("ARCH_AARCH64", flag(target_arch == TargetArch::Aarch64)),
+            ("ARCH_ARM", flag(target_arch == TargetArch::Arm)),
+            ("HAVE_FAST_64BIT", flag(matches!(target_arch, TargetArch::X86_64 | TargetArch::Aarch64))),
+            ("HAVE_NEON", flag(target_arch == TargetArch::Aarch64)),
+        ]);
+
+        config.add_section("Threading", &[
+            ("HAVE_PTHREADS", flag(unix)),
+            ("HAVE_W32THREADS", flag(windows)),
+        ]);
+
+        // vcpkg 支持的架构都是小端序
+        config.add_section("Endianness", &[
+            ("HAVE_BIGENDIAN", "0"),
+            ("AV_HAVE_BIGENDIAN", "0"),
+        ]);
+
+        config.add_section("Math functions", &[
+            ("HAVE_LRINT", "1"),
+            ("HAVE_LRINTF", "1"),
+        ]);
+
+        // MSVC 以内建函数提供这些数学函数，不需要声明
+        if unix {
+            config.add_section("System math library functions", &[
+                ("HAVE_CBRT", "1"),
+                ("HAVE_CBRTF", "1"),
+                ("HAVE_COPYSIGN", "1"),
+                ("HAVE_ERF", "1"),
+                ("HAVE_HYPOT", "1"),
+                ("HAVE_RINT", "1"),
+                ("HAVE_ROUND", "1"),
+                ("HAVE_ROUNDF", "1"),
+                ("HAVE_TRUNC", "1"),
+                ("HAVE_TRUNCF", "1"),
+                ("HAVE_ATANF", "1"),
+                ("HAVE_ATAN2F", "1"),
+                ("HAVE_POWF", "1"),
+            ]);
+        }
+
+        config.add_section("FFmpeg data directory - empty for Node.js addon", &[
+            ("FFMPEG_DATADIR", "\"\""),
+            ("AVCONV_DATADIR", "\"\""),
+        ]);
+
+        let configuration = format!("\"{} build for Node.js addon\"", target_os.display_name());
+        let cc_ident = match target_os {
+            TargetOs::Windows => "\"MSVC\"",
+            TargetOs::MacOs => "\"Clang\"",
+            TargetOs::Linux => "\"GCC\"",
+        };
+        config.add_section("Build configuration", &[
+            ("CONFIG_THIS_YEAR", "2025"),
+            ("FFMPEG_CONFIGURATION", &configuration),
+            ("CC_IDENT", cc_ident),
+            ("FFMPEG_VERSION", "\"N/A\""),
+        ]);
+
+        config
+    }
+
+    pub fn target_os(&self) -> TargetOs {
+        self.target_os
+    }
+
+    pub fn target_arch(&self) -> TargetArch {
+        self.target_arch
+    }
+
+    /// Values of the defines
+    pub fn values(&self) -> HashMap<String, String> {
+        self.sections.iter().flat_map(|section| section.defines.iter().cloned()).collect()
+    }
+
+    /// Replace the value of an existing define, or append it to the last section.
+    /// Returns the previous value.
+    pub fn set(&mut self, name: &str, value: &str) -> Option<String> {
+        for section in &mut self.sections {
+            if let Some(define) = section.defines.iter_mut().find(|(n, _)| n == name) {
+                return Some(std::mem::replace(&mut define.1, value.to_string()));
+            }
+        }
+        if let Some(section) = self.sections.last_mut() {
+            section.defines.push((name.to_string(), value.to_string()));
+        }
+        None
+    }
+
+    fn add_section(&mut self, title: &'static str, defines: &[(&str, &str)]) {
+        self.sections.push(Section {
+            title,
+            defines: defines.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect(),
+        });
+    }
+
+    /// Render the header through the config.h template
+    pub fn render(&self, templates: &Templates) -> Result<String, VcpkgFfError> {
+        let sections: Vec<Value> = self.sections
+            .iter()
+            .map(|section| {
+                let defines: Vec<Value> = section.defines
+                    .iter()
+                    .map(|(name, value)| context! { name, value })
+                    .collect();
+                context! { title => section.title, defines }
+            })
+            .collect();
+
+        templates.render("config.h.jinja", context! {
+            os => self.target_os.display_name(),
+            arch => self.target_arch.display_name(),
+            sections,
+        })
+    }
+}
+
+/// config.h switches compiling an av_hwdevice type into libavutil, and the type's av_hwdevice_get_type_name
+const HWDEVICE_TYPES: &[(&str, &str)] = &[
+    ("CONFIG_CUDA", "cuda"),
+    ("CONFIG_VAAPI", "vaapi"),
+    ("CONFIG_VDPAU", "vdpau"),
+    ("CONFIG_QSV", "qsv"),
+    ("CONFIG_D3D11VA", "d3d11va"),
+    ("CONFIG_D3D12VA", "d3d12va"),
+    ("CONFIG_DXVA2", "dxva2"),
+    ("CONFIG_VIDEOTOOLBOX", "videotoolbox"),
+    ("CONFIG_LIBDRM", "drm"),
+    ("CONFIG_OPENCL", "opencl"),
+    ("CONFIG_VULKAN", "vulkan"),
+    ("CONFIG_MEDIACODEC", "mediacodec"),
+];
+
+/// The av_hwdevice types the `#define`s of a config.h enable
+pub fn hwdevice_types(defines: &HashMap<String, String>) -> Vec<&'static str> {
+    HWDEVICE_TYPES
+        .iter()
+        .filter(|(name, _)| defines.get(*name).is_some_and(|value| value != "0"))
+        .map(|(_, device)| *device)
+        .collect()
+}
+
+/// Values of the `#define NAME value` lines in an existing header
+pub fn define_values(content: &str) -> HashMap<String, String> {
+    content
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix('#'))
+        .filter_map(|line| line.trim_start().strip_prefix("define"))
+        .filter_map(|rest| {
+            let rest = rest.trim_start();
+            let name_end = rest.find(char::is_whitespace).unwrap_or(rest.len());
+            let name = &rest[..name_end];
+            (!name.is_empty() && !name.contains('(')).then(|| (name.to_string(), rest[name_end..].trim().to_string()))
+        })
+        .collect()
+}
+
+pub(crate) fn flag(enabled: bool) -> &'static str {
+    if enabled { "1" } else { "0" }
+}
@@ -0,0 +1,339 @@
+/// Maximum number of context lines that may be ignored at each end of a hunk
+pub const MAX_FUZZ: usize = 2;
+
+#[derive(Debug, Clone)]
+enum HunkLine {
+    Context(String),
+    Remove(String),
+    Add(String),
+}
+
+/// A single `@@ -a,b +c,d @@` hunk
+#[derive(Debug, Clone)]
+pub struct Hunk {
+    old_start: usize,
+    old_len: usize,
+    lines: Vec<HunkLine>,
+}
+
+/// All hunks targeting one file in a unified diff
+#[derive(Debug, Clone)]
+pub struct FilePatch {
+    /// Target path with the leading `a/` / `b/` component stripped
+    pub path: String,
+    /// The old side is `/dev/null`: the patch creates the file
+    pub created: bool,
+    /// The new side is `/dev/null`: the patch deletes the file, `path` is the old one
+    pub deleted: bool,
+    pub hunks: Vec<Hunk>,
+}
+
+/// How a hunk ended up being applied
+#[derive(Debug, Clone, Copy)]
+pub struct HunkResult {
+    pub offset: isize,
+    pub fuzz: usize,
+}
+
+/// Parse a unified diff (as produced by `git diff` or `diff -u`)
+pub fn parse_unified_diff(text: &str) -> Result<Vec<FilePatch>, String> {
+    let mut patches: Vec<FilePatch> = Vec::new();
+    let mut lines = text.lines().peekable();
+    let mut old_path = None;
+
+    while let Some(line) = lines.next() {
+        if let Some(path) = line.strip_prefix("--- ") {
+            old_path = strip_path_prefix(path);
+            continue;
+        }
+        if let Some(new_path) = line.strip_prefix("+++ ") {
+            let created = old_path.is_none();
+            let (path, deleted) = match strip_path_prefix(new_path) {
+                Some(path) => (path, false),
+                None => (old_path.take().ok_or("both sides of a file header are /dev/null")?, true),
+            };
+            patches.push(FilePatch { path, created, deleted, hunks: Vec::new() });
+            old_path = None;
+            continue;
+        }
+
+        if !line.starts_with("@@") {
+            continue;
+        }
+
+        let Some(current) = patches.last_mut() else {
+            return Err("hunk found before any +++ file header".to_string());
+        };
+
+        let (old_start, old_len, new_len) = parse_hunk_header(line)
+            .ok_or_else(|| format!("invalid hunk header: {}", line))?;
+        let mut hunk = Hunk { old_start, old_len, lines: Vec::new() };
+        let (mut old_seen, mut new_seen) = (0, 0);
+
+        while old_seen < old_len || new_seen < new_len {
+            let Some(body_line) = lines.next() else {
+                return Err(format!("truncated hunk in {}", current.path));
+            };
+            if body_line.starts_with('\\') {
+                continue;
+            }
+            let mut chars = body_line.chars();
+            let tag = chars.next();
+            let text = chars.as_str();
+            match tag {
+                Some('+') => {
+                    hunk.lines.push(HunkLine::Add(text.to_string()));
+                    new_seen += 1;
+                }
+                Some('-') => {
+                    hunk.lines.push(HunkLine::Remove(text.to_string()));
+                    old_seen += 1;
+                }
+                // 某些编辑器会去掉空上下文行前的空格
+                Some(' ') | None => {
+                    hunk.lines.push(HunkLine::Context(text.to_string()));
+                    old_seen += 1;
+                    new_seen += 1;
+                }
+                _ => return Err(format!("unexpected line in hunk for {}: {}", current.path, body_line)),
+            }
+        }
+
+        // 跳过 "\ No newline at end of file"
+        while lines.peek().is_some_and(|l| l.starts_with('\\')) {
+            lines.next();
+        }
+
+        current.hunks.push(hunk);
+    }
+
+    patches.retain(|p| !p.hunks.is_empty());
+    Ok(patches)
+}
+
+/// Apply all hunks of a FilePatch to `content`, searching nearby lines (offset) and
+/// dropping up to MAX_FUZZ outer context lines (fuzz) when the exact position doesn't match
+pub fn apply_file_patch(content: &str, patch: &FilePatch) -> Result<(String, Vec<HunkResult>), String> {
+    let had_trailing_newline = content.ends_with('\n');
+    let mut lines: Vec<String> = content.lines().map(|l| l.trim_end_matches('\r').to_string()).collect();
+    let mut delta: isize = 0;
+    let mut results = Vec::new();
+
+    for (index, hunk) in patch.hunks.iter().enumerate() {
+        let mut applied = None;
+
+        for fuzz in 0..=MAX_FUZZ {
+            let (leading, trailing) = trimmable_context(hunk, fuzz);
+            let body = &hunk.lines[leading..hunk.lines.len() - trailing];
+
+            let old_block: Vec<&str> = body
+                .iter()
+                .filter_map(|l| match l {
+                    HunkLine::Context(s) | HunkLine::Remove(s) => Some(s.as_str()),
+                    HunkLine::Add(_) => None,
+                })
+                .collect();
+            let new_block: Vec<String> = body
+                .iter()
+                .filter_map(|l| match l {
+                    HunkLine::Context(s) | HunkLine::Add(s) => Some(s.clone()),
+                    HunkLine::Remove(_) => None,
+                })
+                .collect();
+
+            // 纯插入的 hunk (`@@ -N,0 ...`) 插在第 N 行之后
+            let start = if hunk.old_len == 0 { hunk.old_start } else { hunk.old_start.saturating_sub(1) };
+            let expected = start as isize + delta + leading as isize;
+            if let Some(pos) = find_block(&lines, &old_block, expected) {
+                let offset = pos as isize - expected;
+                lines.splice(pos..pos + old_block.len(), new_block.iter().cloned());
+                delta += offset + new_block.len() as isize - old_block.len() as isize;
+                applied = Some(HunkResult { offset, fuzz });
+                break;
+            }
+        }
+
+        match applied {
+            Some(result) => results.push(result),
+            None => return Err(format!("hunk #{} failed to apply to {}", index + 1, patch.path)),
+        }
+    }
+
+    let mut output = lines.join("\n");
+    if had_trailing_newline && !lines.is_empty() {
+        output.push('\n');
+    }
+    Ok((output, results))
+}
+
+/// Number of leading/trailing context lines that may be dropped at the given fuzz level
+fn trimmable_context(hunk: &Hunk, fuzz: usize) -> (usize, usize) {
+    let leading = hunk
+        .lines
+        .iter()
+        .take_while(|l| matches!(l, HunkLine::Context(_)))
+        .count()
+        .min(fuzz);
+    let trailing = hunk
+        .lines
+        .iter()
+        .rev()
+        .take_while(|l| matches!(l, HunkLine::Context(_)))
+        .count()
+        .min(fuzz);
+    if leading + trailing >= hunk.lines.len() {
+        return (0, 0);
+    }
+    (leading, trailing)
+}
+
+/// Find `block` in `lines`, preferring the position closest to `expected`
+fn find_block(lines: &[String], block: &[&str], expected: isize) -> Option<usize> {
+    if block.len() > lines.len() {
+        return None;
+    }
+    let last_start = (lines.len() - block.len()) as isize;
+    let matches_at = |pos: isize| -> bool {
+        pos >= 0
+            && pos <= last_start
+            && block.iter().enumerate().all(|(i, l)| lines[pos as usize + i] == *l)
+    };
+
+    let expected = expected.clamp(0, last_start);
+    for distance in 0..=last_start {
+        if matches_at(expected - distance) {
+            return Some((expected - distance) as usize);
+        }
+        if matches_at(expected + distance) {
+            return Some((expected + distance) as usize);
+        }
+    }
+    None
+}
+
+fn parse_hunk_header(line: &str) -> Option<(usize, usize, usize)> {
+    // @@ -old_start[,old_len] +new_start[,new_len] @@
+    let mut parts = line.split_whitespace().skip(1);
+    let old = parts.next()?.strip_prefix('-')?;
+    let new = parts.next()?.strip_prefix('+')?;
+
+    let parse_range = |range: &str| -> Option<(usize, usize)> {
+        match range.split_once(',') {
+            Some((start, len)) => Some((start.parse().ok()?, len.parse().ok()?)),
+            None => Some((range.parse().ok()?, 1)),
+        }
+    };
+
+    let (old_start, old_len) = parse_range(old)?;
+    let (_, new_len) = parse_range(new)?;
+    Some((old_start, old_len, new_len))
+}
+
+/// Path from a `---`/`+++` header, `None` for `/dev/null`
+fn strip_path_prefix(header: &str) -> Option<String> {
+    let path = header.split('\t').next().unwrap_or(header).trim();
+    if path == "/dev/null" {
+        return None;
+    }
+    match path.split_once('/') {
+        Some((prefix, rest)) if prefix == "a" || prefix == "b" => Some(rest.to_string()),
+        _ => Some(path.to_string()),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "one\ntwo\nthree\nfour\nfive\nsix\nseven\neight\n";
+
+    fn patch(diff: &str) -> FilePatch {
+        let mut patches = parse_unified_diff(diff).unwrap();
+        assert_eq!(patches.len(), 1);
+        patches.remove(0)
+    }
+
+    #[test]
+    fn hunk_at_its_position() {
+        let patch = patch("--- a/f.c\n+++ b/f.c\n@@ -3,3 +3,3 @@\n three\n-four\n+FOUR\n five\n");
+
+        let (patched, results) = apply_file_patch(SOURCE, &patch).unwrap();
+
+        assert_eq!(patched, SOURCE.replace("four", "FOUR"));
+        assert_eq!((results[0].offset, results[0].fuzz), (0, 0));
+    }
+
+    #[test]
+    fn hunk_found_at_an_offset() {
+        let patch = patch("--- a/f.c\n+++ b/f.c\n@@ -1,3 +1,3 @@\n five\n-six\n+SIX\n seven\n");
+
+        let (patched, results) = apply_file_patch(SOURCE, &patch).unwrap();
+
+        assert_eq!(patched, SOURCE.replace("six", "SIX"));
+        assert_eq!((results[0].offset, results[0].fuzz), (4, 0));
+    }
+
+    #[test]
+    fn offset_of_one_hunk_carries_over_to_the_next() {
+        let source = format!("zero\n{}", SOURCE);
+        let patch = patch("--- a/f.c\n+++ b/f.c\n@@ -1,2 +1,3 @@\n one\n+one and a half\n two\n\
+            @@ -6,2 +7,2 @@\n six\n-seven\n+SEVEN\n");
+
+        let (patched, results) = apply_file_patch(&source, &patch).unwrap();
+
+        assert!(patched.contains("one\none and a half\ntwo\n") && patched.contains("six\nSEVEN\n"), "{}", patched);
+        assert_eq!(results.iter().map(|r| r.offset).collect::<Vec<_>>(), [1, 0]);
+    }
+
+    #[test]
+    fn outer_context_is_dropped_with_fuzz() {
+        let patch = patch("--- a/f.c\n+++ b/f.c\n@@ -2,5 +2,5 @@\n two\n changed\n-four\n+FOUR\n five\n six\n");
+
+        let (patched, results) = apply_file_patch(SOURCE, &patch).unwrap();
+
+        assert_eq!(patched, SOURCE.replace("four", "FOUR"));
+        assert_eq!((results[0].offset, results[0].fuzz), (0, 2));
+    }
+
+    #[test]
+    fn hunk_beyond_the_fuzz_fails() {
+        let patch = patch("--- a/f.c\n+++ b/f.c\n@@ -1,7 +1,7 @@\n one\n 2\n 3\n-four\n+FOUR\n five\n six\n seven\n");
+
+        assert!(apply_file_patch(SOURCE, &patch).unwrap_err().contains("hunk #1 failed"));
+    }
+
+    #[test]
+    fn pure_insertion_goes_after_its_line() {
+        let patch = patch("--- a/f.c\n+++ b/f.c\n@@ -3,0 +4,2 @@\n+three and a half\n+three and three quarters\n");
+
+        let (patched, results) = apply_file_patch(SOURCE, &patch).unwrap();
+
+        assert_eq!(patched, SOURCE.replace("three\n", "three\nthree and a half\nthree and three quarters\n"));
+        assert_eq!(results[0].offset, 0);
+    }
+
+    #[test]
+    fn new_and_deleted_files() {
+        let created = patch("--- /dev/null\n+++ b/fftools/extra.c\n@@ -0,0 +1,2 @@\n+int a;\n+int b;\n");
+        assert_eq!((created.path.as_str(), created.created, created.deleted), ("fftools/extra.c", true, false));
+        assert_eq!(apply_file_patch("", &created).unwrap().0, "int a;\nint b;");
+
+        let deleted = patch("--- a/fftools/extra.c\n+++ /dev/null\n@@ -1,2 +0,0 @@\n-int a;\n-int b;\n");
+        assert_eq!((deleted.path.as_str(), deleted.created, deleted.deleted), ("fftools/extra.c", false, true));
+        assert_eq!(apply_file_patch("int a;\nint b;\n", &deleted).unwrap().0, "");
+    }
+
+    #[test]
+    fn malformed_line_starting_with_a_multibyte_character_is_an_error() {
+        let error = parse_unified_diff("--- a/f.c\n+++ b/f.c\n@@ -1,2 +1,2 @@\n one\n—two\n").unwrap_err();
+
+        assert!(error.to_string().contains("unexpected line in hunk for f.c: —two"), "{}", error);
+    }
+
+    #[test]
+    fn stripped_empty_context_lines_and_no_newline_markers() {
+        let patch = patch("--- a/f.c\n+++ b/f.c\n@@ -1,3 +1,3 @@\n a\n\n-b\n\\ No newline at end of file\n+B\n\\ No newline at end of file\n");
+
+        assert_eq!(apply_file_patch("a\n\nb", &patch).unwrap().0, "a\n\nB");
+    }
+}
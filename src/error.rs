@@ -0,0 +1,123 @@
+use std::path::PathBuf;
+
+/// Everything that can go wrong installing ffmpeg and generating the addon
+#[derive(Debug, thiserror::Error)]
+pub enum VcpkgFfError {
+    #[error("git is not installed or not in PATH, please install git first")]
+    GitUnavailable,
+
+    #[error("cloning vcpkg from {mirror} failed after {attempts} attempt(s): {message}")]
+    CloneFailed { mirror: String, attempts: u32, message: String },
+
+    /// The bootstrap script exited with an error or didn't produce the vcpkg executable; `cause` is the likely cause
+    /// recognized in its output
+    #[error("vcpkg bootstrap failed: {message}{}", cause.map(|cause| format!("\n⚠ likely cause: {}", cause)).unwrap_or_default())]
+    BootstrapFailed { message: String, cause: Option<&'static str> },
+
+    #[error("vcpkg is not installed, please call install_vcpkg() first")]
+    VcpkgNotInstalled,
+
+    /// `vcpkg install` or `vcpkg remove` exited with an error; `log_path` holds the port's build logs
+    #[error("{package} installation failed, see the build logs in {}", log_path.display())]
+    PackageInstallFailed { package: String, log_path: PathBuf },
+
+    /// `vcpkg install --only-downloads` kept failing, nothing was built yet
+    #[error("downloading the sources of {package} failed after {attempts} attempt(s): {message}")]
+    DownloadFailed { package: String, attempts: u32, message: String },
+
+    /// [`probe`](crate::probe()) found no pkg-config file for `package`
+    #[error("{package} is not installed by vcpkg for {triplet}, run vcpkg_ff to install it")]
+    PackageNotFound { package: String, triplet: String },
+
+    #[error("ffmpeg tar.gz file not found in {}, please install ffmpeg package first", .0.display())]
+    ArchiveNotFound(PathBuf),
+
+    /// The source archive or source tree at `path` can't be extracted
+    #[error("ffmpeg extraction failed: {}: {message}", path.display())]
+    ExtractionFailed { path: PathBuf, message: String },
+
+    /// The ffmpeg sources are a version the patch rules don't support
+    #[error("unsupported ffmpeg version {version}: only ffmpeg 7.x (Scheduler-based fftools) is supported")]
+    UnsupportedFfmpeg { version: String },
+
+    /// A file of the ffmpeg source tree is missing or unusable, e.g. libavutil/version.h for detecting the version
+    #[error("{} is missing from the ffmpeg sources or unusable", path.display())]
+    FfmpegSourceMissing { path: PathBuf },
+
+    /// A unified diff from patches/ did not apply to `file`
+    #[error("{rule} did not apply to {file}: {reason}")]
+    PatchFailed { file: String, rule: String, reason: String },
+
+    /// A patch rule that can't be used, such as a regex that doesn't compile
+    #[error("invalid patch rule {rule}: {message}")]
+    InvalidPatchRule { rule: String, message: String },
+
+    /// vcpkg_ff.toml, addon_patches/, patches/ or templates/ could not be read
+    #[error("{}: {message}", path.display())]
+    Config { path: PathBuf, message: String },
+
+    /// A file vcpkg_ff wrote or downloaded earlier, such as the journal, an addon_src artifact or a prebuilt tree, is
+    /// damaged, of another format or made for another triplet
+    #[error("{}: {message}", path.display())]
+    InvalidFile { path: PathBuf, message: String },
+
+    /// A step, workspace target or verify container that doesn't exist
+    #[error("unknown {kind} `{name}`, expected one of: {}", expected.join(", "))]
+    Unknown { kind: &'static str, name: String, expected: Vec<String> },
+
+    /// What a step works on is missing, e.g. addon_src/package.json before building the addon; `step` produces it
+    #[error("{} is missing or out of date, run the {step} step first", path.display())]
+    NotPrepared { path: PathBuf, step: &'static str },
+
+    /// A program vcpkg_ff needs is not installed or not set up, such as conan, emsdk or the Visual C++ tools
+    #[error("{tool} not found, {hint}")]
+    ToolNotFound { tool: &'static str, hint: &'static str },
+
+    /// `by`, e.g. the conan backend, can't build the `kind` (triplet, binding style) `name`
+    #[error("{by} doesn't support the {kind} {name}")]
+    Unsupported { by: &'static str, kind: &'static str, name: String },
+
+    /// A command (vcpkg, curl, cmake, conan, npm pack, ...) could not be started, exited with an error or printed
+    /// something unexpected
+    #[error("{command} failed: {message}")]
+    CommandFailed { command: String, message: String },
+
+    /// The `check` (config-h, stamps, syntax, exports, postproc, napi-version) found `count` problems in the generated
+    /// sources, each printed before
+    #[error("the {check} check found {count} problem(s)")]
+    ValidationFailed { check: &'static str, count: usize },
+
+    /// An npm command failed, its output is in `log_path`
+    #[error("npm {command} failed after {attempts} attempt(s), see {}: {message}", log_path.display())]
+    NpmFailed { command: String, attempts: u32, log_path: PathBuf, message: String },
+
+    /// A `[[plugins]]` executable could not be started or exited with an error
+    #[error("plugin {plugin} failed: {message}")]
+    PluginFailed { plugin: String, message: String },
+
+    /// A `[hooks]` command of a step exited with an error
+    #[error("{phase} hook of {step} failed: {message}")]
+    HookFailed { step: String, phase: &'static str, message: String },
+
+    /// A pipeline step failed
+    #[error("{step} failed: {source}")]
+    StepFailed { step: String, source: Box<VcpkgFfError> },
+
+    #[error(transparent)]
+    Io(#[from] std::io::Error),
+
+    #[error(transparent)]
+    Json(#[from] serde_json::Error),
+
+    #[error("template error: {0:#}")]
+    Template(#[from] minijinja::Error),
+
+    /// Rendering the template `name` failed
+    #[error("rendering {name}: {source:#}")]
+    RenderFailed { name: String, source: minijinja::Error },
+
+    /// A blocking task of the async API panicked or was cancelled
+    #[cfg(feature = "async")]
+    #[error("background task failed: {0}")]
+    Task(#[from] tokio::task::JoinError),
+}
@@ -0,0 +1,148 @@
+use std::collections::{BTreeMap, BTreeSet};
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::time::UNIX_EPOCH;
+
+use crate::fs_retry;
+use crate::journal;
+use crate::marker;
+
+/// A file the extraction wrote: the hash and size of its content and the mtime it got on disk
+#[derive(Debug, Clone, PartialEq, Eq)]
+struct IndexEntry {
+    hash: String,
+    size: u64,
+    mtime: u128,
+}
+
+/// Index of the files extracted into ffmpeg/, so that extracting again, after an interrupted run or from a new
+/// archive, only writes the files that are missing, were changed on disk or differ in the source
+///
+/// Every written file is appended to the index right away, one `hash size mtime path` line, so an interrupted run
+/// keeps what it got done. `finish` removes the files the source no longer has and compacts the index.
+pub(crate) struct ExtractIndex {
+    path: PathBuf,
+    entries: BTreeMap<String, IndexEntry>,
+    /// Paths of the current source, relative to the extraction directory
+    seen: BTreeSet<String>,
+    log: File,
+    written: usize,
+}
+
+impl ExtractIndex {
+    /// Load the index at `path`, malformed lines are ignored and later lines win
+    pub(crate) fn open(path: &Path) -> io::Result<Self> {
+        let mut entries = BTreeMap::new();
+        for line in fs::read_to_string(path).unwrap_or_default().lines() {
+            let mut fields = line.splitn(4, ' ');
+            let (Some(hash), Some(size), Some(mtime), Some(relative)) = (fields.next(), fields.next(), fields.next(), fields.next()) else {
+                continue;
+            };
+            if let (Ok(size), Ok(mtime)) = (size.parse(), mtime.parse()) {
+                entries.insert(relative.to_string(), IndexEntry { hash: hash.to_string(), size, mtime });
+            }
+        }
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let log = OpenOptions::new().create(true).append(true).open(path)?;
+        Ok(Self { path: path.to_path_buf(), entries, seen: BTreeSet::new(), log, written: 0 })
+    }
+
+    /// Write `content` to `relative` below `dir` unless the file there is what the index recorded for the same content.
+    /// Returns whether the file was written
+    pub(crate) fn sync_file(&mut self, dir: &Path, relative: &str, content: &[u8], mode: Option<u32>) -> io::Result<bool> {
+        self.seen.insert(relative.to_string());
+        let target = dir.join(relative);
+        let mut hasher = marker::Hasher::new();
+        hasher.update(content);
+        let hash = hasher.finish();
+        let current = self.entries.get(relative).is_some_and(|entry| {
+            entry.hash == hash && entry.size == content.len() as u64 && disk_state(&target) == Some((entry.size, entry.mtime))
+        });
+        if current {
+            return Ok(false);
+        }
+
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        // 旧文件可能是共享缓存的硬链接，直接写入会改掉其他项目的文件
+        if fs::symlink_metadata(&target).is_ok() {
+            fs_retry::remove_file(&target)?;
+        }
+        // 杀毒软件可能正在扫描上次写入的文件
+        fs_retry::write(&target, content)?;
+        set_mode(&target, mode)?;
+        let Some((size, mtime)) = disk_state(&target) else {
+            return Err(io::Error::other(format!("{} vanished after it was written", target.display())));
+        };
+        writeln!(self.log, "{} {} {} {}", hash, size, mtime, relative)?;
+        self.entries.insert(relative.to_string(), IndexEntry { hash, size, mtime });
+        self.written += 1;
+        Ok(true)
+    }
+
+    /// Record the size and mtime `relative` below `dir` has now, after it was replaced by a file with the same content,
+    /// e.g. a link into the shared cache
+    pub(crate) fn refresh(&mut self, dir: &Path, relative: &str) -> io::Result<()> {
+        let (Some(entry), Some((size, mtime))) = (self.entries.get_mut(relative), disk_state(&dir.join(relative))) else {
+            return Ok(());
+        };
+        (entry.size, entry.mtime) = (size, mtime);
+        writeln!(self.log, "{} {} {} {}", entry.hash, size, mtime, relative)
+    }
+
+    /// Number of files `sync_file` wrote
+    pub(crate) fn written(&self) -> usize {
+        self.written
+    }
+
+    /// Number of files of the current source
+    pub(crate) fn total(&self) -> usize {
+        self.seen.len()
+    }
+
+    /// Remove the indexed files below `dir` the current source doesn't have and rewrite the index with its files.
+    /// Returns the number of removed files
+    pub(crate) fn finish(mut self, dir: &Path) -> io::Result<usize> {
+        let stale: Vec<String> = self.entries.keys().filter(|relative| !self.seen.contains(*relative)).cloned().collect();
+        for relative in &stale {
+            let target = dir.join(relative);
+            if target.is_file() {
+                journal::remove_file(&target)?;
+            }
+            self.entries.remove(relative);
+        }
+        let content: String = self.entries.iter()
+            .map(|(relative, entry)| format!("{} {} {} {}\n", entry.hash, entry.size, entry.mtime, relative))
+            .collect();
+        drop(self.log);
+        journal::write(&self.path, content)?;
+        Ok(stale.len())
+    }
+}
+
+/// Size and mtime (ns since the epoch) of the file at `path`
+fn disk_state(path: &Path) -> Option<(u64, u128)> {
+    let metadata = fs::metadata(path).ok()?;
+    let mtime = metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok()?.as_nanos();
+    Some((metadata.len(), mtime))
+}
+
+/// Keep the executable bit of scripts like configure
+#[cfg(unix)]
+fn set_mode(path: &Path, mode: Option<u32>) -> io::Result<()> {
+    use std::os::unix::fs::PermissionsExt;
+
+    match mode {
+        Some(mode) => fs::set_permissions(path, fs::Permissions::from_mode(mode & 0o777)),
+        None => Ok(()),
+    }
+}
+
+#[cfg(not(unix))]
+fn set_mode(_path: &Path, _mode: Option<u32>) -> io::Result<()> {
+    Ok(())
+}
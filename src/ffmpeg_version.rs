@@ -0,0 +1,71 @@
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use crate::error::VcpkgFfError;
+
+/// Version of an extracted ffmpeg source tree
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct FfmpegVersion {
+    pub major: u32,
+    pub minor: u32,
+}
+
+impl FfmpegVersion {
+    /// Detect the version from the RELEASE file, falling back to libavutil/version.h
+    /// (git snapshots have a RELEASE like "N-113000-g..." without a usable version)
+    pub fn detect(ffmpeg_dir: &Path) -> Result<Self, VcpkgFfError> {
+        if let Ok(release) = fs::read_to_string(ffmpeg_dir.join("RELEASE")) {
+            if let Some(version) = Self::parse_release(release.trim()) {
+                return Ok(version);
+            }
+        }
+
+        let version_h = ffmpeg_dir.join("libavutil").join("version.h");
+        if let Ok(content) = fs::read_to_string(&version_h) {
+            if let Some(version) = Self::from_libavutil_version_h(&content) {
+                return Ok(version);
+            }
+        }
+
+        // RELEASE 也没有可用的版本号
+        Err(VcpkgFfError::FfmpegSourceMissing { path: version_h })
+    }
+
+    /// Parse "7.1", "7.0.2" or "7.1.git"
+    fn parse_release(release: &str) -> Option<Self> {
+        let mut parts = release.split('.');
+        let major = parts.next()?.parse().ok()?;
+        let minor = parts.next().and_then(|m| m.parse().ok()).unwrap_or(0);
+        Some(Self { major, minor })
+    }
+
+    /// Map LIBAVUTIL_VERSION_MAJOR/MINOR to the ffmpeg release series
+    fn from_libavutil_version_h(content: &str) -> Option<Self> {
+        let define_value = |name: &str| -> Option<u32> {
+            content.lines().find_map(|line| {
+                let rest = line.trim().strip_prefix("#define")?.trim_start();
+                rest.strip_prefix(name)?.trim().parse().ok()
+            })
+        };
+
+        let avutil_major = define_value("LIBAVUTIL_VERSION_MAJOR")?;
+
+        let major = match avutil_major {
+            56 => 4,
+            57 => 5,
+            58 => 6,
+            59 => 7,
+            60 => 8,
+            _ => return None,
+        };
+        // 次版本号无法从 libavutil 版本可靠推断，只用主版本选择补丁集
+        Some(Self { major, minor: 0 })
+    }
+}
+
+impl fmt::Display for FfmpegVersion {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{}.{}", self.major, self.minor)
+    }
+}
@@ -0,0 +1,62 @@
+use std::fs;
+use std::path::Path;
+
+/// Read the ffmpeg program's object list from fftools/Makefile and return the matching
+/// sources (relative to fftools/, ffmpeg.c excluded), or None if the Makefile is missing
+/// or lists nothing usable
+pub fn discover(fftools_dir: &Path) -> Option<Vec<String>> {
+    let makefile = fs::read_to_string(fftools_dir.join("Makefile")).ok()?;
+    let mut sources: Vec<String> = Vec::new();
+
+    for statement in logical_lines(&makefile) {
+        let Some((variable, value)) = statement.split_once("+=").or_else(|| statement.split_once('=')) else {
+            continue;
+        };
+        // OBJS-ffmpeg 是 ffmpeg 专用的目标文件，OBJS-$(1) 是 DOFFTOOL 给每个工具都加上的公共部分
+        let variable = variable.trim();
+        if variable != "OBJS-ffmpeg" && variable != "OBJS-$(1)" {
+            continue;
+        }
+
+        for object in value.split_whitespace() {
+            let Some(relative) = object.strip_prefix("fftools/").and_then(|o| o.strip_suffix(".o")) else {
+                continue;
+            };
+            if relative.contains('$') || relative == "ffmpeg" {
+                continue;
+            }
+            let source = format!("{}.c", relative);
+            if fftools_dir.join(&source).exists() && !sources.contains(&source) {
+                sources.push(source);
+            }
+        }
+    }
+
+    if sources.is_empty() {
+        None
+    } else {
+        Some(sources)
+    }
+}
+
+/// Join backslash-continued lines and drop comments
+fn logical_lines(makefile: &str) -> Vec<String> {
+    let mut lines = Vec::new();
+    let mut current = String::new();
+
+    for line in makefile.lines() {
+        let line = line.split('#').next().unwrap_or("");
+        if let Some(continued) = line.strip_suffix('\\') {
+            current.push_str(continued);
+            current.push(' ');
+        } else {
+            current.push_str(line);
+            lines.push(std::mem::take(&mut current));
+        }
+    }
+    if !current.is_empty() {
+        lines.push(current);
+    }
+
+    lines
+}
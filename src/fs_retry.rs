@@ -0,0 +1,128 @@
+use std::error::Error;
+use std::fmt;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::thread;
+use std::time::Duration;
+
+use crate::progress::say;
+
+/// Attempts of a locked operation, waiting 100 ms, 200 ms, ... 1.6 s in between, about 3 s in total
+const ATTEMPTS: u32 = 6;
+const FIRST_DELAY: Duration = Duration::from_millis(100);
+
+/// A file operation that kept failing because another process held the file open, typically an antivirus
+/// scanner (Windows Defender) reading the files vcpkg or the extraction just wrote
+#[derive(Debug)]
+pub struct FileLocked {
+    pub operation: &'static str,
+    pub path: PathBuf,
+    pub source: io::Error,
+}
+
+impl fmt::Display for FileLocked {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "{} {} failed after {} attempts, it is locked by another process, most likely an antivirus scan: {}",
+            self.operation, self.path.display(), ATTEMPTS, self.source)
+    }
+}
+
+impl Error for FileLocked {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        Some(&self.source)
+    }
+}
+
+/// Messages of Windows tools that ran into a file another process holds open
+const LOCK_MESSAGES: &[&str] = &["Access is denied", "being used by another process", "sharing violation"];
+
+/// Whether command output shows a file lock, see [`antivirus_guidance`]
+pub fn mentions_file_lock(output: &str) -> bool {
+    LOCK_MESSAGES.iter().any(|message| output.contains(message))
+}
+
+/// How to keep the antivirus scanner away from `dirs`
+pub fn antivirus_guidance(dirs: &[&Path]) -> String {
+    let dirs: Vec<String> = dirs.iter().map(|dir| format!("'{}'", dir.display())).collect();
+    format!("exclude {} from antivirus scanning, for Windows Defender with `Add-MpPreference -ExclusionPath {}` \
+        in an elevated PowerShell, then rerun with --resume", dirs.join(" and "), dirs.join(","))
+}
+
+/// The [`FileLocked`] inside an io::Error returned by this module
+pub fn file_locked(error: &io::Error) -> Option<&FileLocked> {
+    error.get_ref().and_then(|inner| inner.downcast_ref::<FileLocked>())
+}
+
+/// Whether `error` is what Windows reports for a file another process holds open: access denied (5), sharing
+/// and lock violations (32, 33) and, when removing a tree, a directory not yet empty (145)
+fn is_lock_error(error: &io::Error) -> bool {
+    cfg!(windows) && matches!(error.raw_os_error(), Some(5 | 32 | 33 | 145))
+}
+
+/// Run `f` until it succeeds or fails with something else than a lock, waiting longer after each lock
+fn retry<T>(operation: &'static str, path: &Path, mut f: impl FnMut(u32) -> io::Result<T>) -> io::Result<T> {
+    let mut delay = FIRST_DELAY;
+    let mut attempt = 1;
+    loop {
+        match f(attempt) {
+            Err(e) if is_lock_error(&e) && attempt < ATTEMPTS => {
+                if attempt == 1 {
+                    say!("⚠ {} is locked by another process, retrying...", path.display());
+                }
+                thread::sleep(delay);
+                delay *= 2;
+                attempt += 1;
+            }
+            Err(e) if is_lock_error(&e) => {
+                return Err(io::Error::new(e.kind(), FileLocked { operation, path: path.to_path_buf(), source: e }));
+            }
+            result => return result,
+        }
+    }
+}
+
+/// `fs::remove_dir_all`, retried while the tree is locked. Read-only files (git objects) are made writable
+/// after the first failure, Windows refuses to delete them otherwise
+pub(crate) fn remove_dir_all(path: &Path) -> io::Result<()> {
+    retry("removing", path, |attempt| {
+        if attempt > 1 {
+            clear_readonly(path);
+        }
+        fs::remove_dir_all(path)
+    })
+}
+
+/// `fs::remove_file`, retried while the file is locked
+pub(crate) fn remove_file(path: &Path) -> io::Result<()> {
+    retry("removing", path, |_| fs::remove_file(path))
+}
+
+/// `fs::rename`, retried while `from` or `to` is locked
+pub(crate) fn rename(from: &Path, to: &Path) -> io::Result<()> {
+    retry("moving", from, |_| fs::rename(from, to))
+}
+
+/// `fs::write`, retried while the file is locked
+pub(crate) fn write(path: &Path, contents: &[u8]) -> io::Result<()> {
+    retry("writing", path, |_| fs::write(path, contents))
+}
+
+#[cfg(windows)]
+fn clear_readonly(path: &Path) {
+    let Ok(metadata) = fs::symlink_metadata(path) else {
+        return;
+    };
+    if metadata.is_dir() {
+        for entry in fs::read_dir(path).into_iter().flatten().flatten() {
+            clear_readonly(&entry.path());
+        }
+    } else if metadata.permissions().readonly() {
+        let mut permissions = metadata.permissions();
+        permissions.set_readonly(false);
+        let _ = fs::set_permissions(path, permissions);
+    }
+}
+
+#[cfg(not(windows))]
+fn clear_readonly(_path: &Path) {}
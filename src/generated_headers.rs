@@ -0,0 +1,153 @@
+use std::collections::{BTreeSet, HashSet};
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::c_lexer::{self, TokenKind};
+use crate::config_h::TargetArch;
+use crate::ffmpeg_version::FfmpegVersion;
+
+/// Headers that ffmpeg's configure writes into the build tree; they are missing from the source tarball
+pub const GENERATED_HEADERS: &[&str] = &[
+    "config_components.h",
+    "libavutil/avconfig.h",
+    "libavutil/ffversion.h",
+];
+
+/// Component switches enabled by each vcpkg ffmpeg feature
+const FEATURE_COMPONENTS: &[(&str, &[&str])] = &[
+    ("x264", &["CONFIG_LIBX264_ENCODER", "CONFIG_LIBX264RGB_ENCODER"]),
+    ("x265", &["CONFIG_LIBX265_ENCODER"]),
+    ("vpx", &[
+        "CONFIG_LIBVPX_VP8_ENCODER",
+        "CONFIG_LIBVPX_VP8_DECODER",
+        "CONFIG_LIBVPX_VP9_ENCODER",
+        "CONFIG_LIBVPX_VP9_DECODER",
+    ]),
+];
+
+/// What the copied sources need from the generated headers
+#[derive(Debug, Default)]
+pub struct HeaderScan {
+    /// Generated headers reachable through `#include "..."`, in GENERATED_HEADERS order
+    pub required: Vec<&'static str>,
+    /// `CONFIG_*` identifiers referenced by the scanned files
+    pub config_identifiers: BTreeSet<String>,
+}
+
+/// Follow quoted includes from `sources` through `search_dirs` and collect the generated
+/// headers and `CONFIG_*` switches they depend on
+pub fn scan(sources: &[PathBuf], search_dirs: &[PathBuf]) -> HeaderScan {
+    let mut result = HeaderScan::default();
+    let mut required = HashSet::new();
+    let mut visited = HashSet::new();
+    let mut defined_in_sources = HashSet::new();
+    let mut pending: Vec<PathBuf> = sources.to_vec();
+
+    while let Some(file) = pending.pop() {
+        if !visited.insert(file.clone()) {
+            continue;
+        }
+        let Ok(content) = fs::read_to_string(&file) else {
+            continue;
+        };
+        defined_in_sources.extend(defined_names(&content));
+
+        for token in c_lexer::tokenize(&content) {
+            let text = &content[token.start..token.end];
+            match token.kind {
+                TokenKind::Preprocessor => {
+                    collect_config_identifiers(text, &mut result.config_identifiers);
+                    let Some(include) = c_lexer::quoted_include(text) else {
+                        continue;
+                    };
+                    // libavutil 内部用 "avconfig.h" 这样的相对路径包含
+                    let base = file.parent().map(Path::to_path_buf).unwrap_or_default();
+                    if let Some(header) = GENERATED_HEADERS
+                        .iter()
+                        .find(|h| **h == include || base.join(include).ends_with(h))
+                    {
+                        required.insert(*header);
+                        continue;
+                    }
+                    // 先按包含文件所在目录查找，再按 include 路径查找
+                    if let Some(found) = std::iter::once(&base)
+                        .chain(search_dirs)
+                        .map(|dir| dir.join(include))
+                        .find(|path| path.is_file())
+                    {
+                        pending.push(found);
+                    }
+                }
+                TokenKind::Identifier if text.starts_with("CONFIG_") => {
+                    result.config_identifiers.insert(text.to_string());
+                }
+                _ => {}
+            }
+        }
+    }
+
+    // 源码自己定义的开关不需要再写进 config_components.h
+    result.config_identifiers.retain(|name| !defined_in_sources.contains(name));
+    result.required = GENERATED_HEADERS.iter().copied().filter(|h| required.contains(h)).collect();
+    result
+}
+
+fn collect_config_identifiers(directive: &str, identifiers: &mut BTreeSet<String>) {
+    for token in c_lexer::tokenize(&directive[1..]) {
+        let text = &directive[1 + token.start..1 + token.end];
+        if token.kind == TokenKind::Identifier && text.starts_with("CONFIG_") {
+            identifiers.insert(text.to_string());
+        }
+    }
+}
+
+/// Names `#define`d in a header
+pub fn defined_names(content: &str) -> HashSet<String> {
+    content
+        .lines()
+        .filter_map(|line| line.trim_start().strip_prefix('#'))
+        .filter_map(|line| line.trim_start().strip_prefix("define"))
+        .filter_map(|rest| rest.split_whitespace().next())
+        .map(|name| name.split('(').next().unwrap_or(name).to_string())
+        .collect()
+}
+
+/// config_components.h: every `CONFIG_*` switch the sources use that config.h doesn't define,
+/// enabled only for the components the installed vcpkg features provide
+pub fn render_config_components(scan: &HeaderScan, config_h_defines: &HashSet<String>, features: &[&str]) -> String {
+    let enabled: HashSet<&str> = FEATURE_COMPONENTS
+        .iter()
+        .filter(|(feature, _)| features.contains(feature))
+        .flat_map(|(_, components)| components.iter().copied())
+        .collect();
+
+    let mut out = format!("/* config_components.h - Generated for features: {} */\n", features.join(", "));
+    out.push_str("#ifndef FFMPEG_CONFIG_COMPONENTS_H\n#define FFMPEG_CONFIG_COMPONENTS_H\n\n");
+    for name in scan.config_identifiers.iter().filter(|name| !config_h_defines.contains(*name)) {
+        let value = if enabled.contains(name.as_str()) { 1 } else { 0 };
+        out.push_str(&format!("#define {} {}\n", name, value));
+    }
+    out.push_str("\n#endif /* FFMPEG_CONFIG_COMPONENTS_H */\n");
+    out
+}
+
+/// libavutil/avconfig.h for the target architecture
+pub fn render_avconfig(arch: TargetArch) -> String {
+    let fast_unaligned = matches!(arch, TargetArch::X86_64 | TargetArch::X86 | TargetArch::Aarch64);
+    format!(
+        "/* Generated by vcpkg_ff */\n#ifndef AVUTIL_AVCONFIG_H\n#define AVUTIL_AVCONFIG_H\n\
+         #define AV_HAVE_BIGENDIAN 0\n#define AV_HAVE_FAST_UNALIGNED {}\n#endif /* AVUTIL_AVCONFIG_H */\n",
+        if fast_unaligned { 1 } else { 0 }
+    )
+}
+
+/// libavutil/ffversion.h for the detected source version
+/// (the fallback config.h also defines FFMPEG_VERSION, so don't redefine it)
+pub fn render_ffversion(version: &FfmpegVersion) -> String {
+    format!(
+        "/* Automatically generated by vcpkg_ff, do not modify! */\n#ifndef AVUTIL_FFVERSION_H\n\
+         #define AVUTIL_FFVERSION_H\n#ifndef FFMPEG_VERSION\n#define FFMPEG_VERSION \"{}\"\n#endif\n\
+         #endif /* AVUTIL_FFVERSION_H */\n",
+        version
+    )
+}
@@ -0,0 +1,32 @@
+use std::path::Path;
+
+use crate::command_runner::{CommandRunner, CommandSpec, SystemRunner};
+use crate::error::VcpkgFfError;
+
+/// Run a `[hooks]` command of `step` with the shell
+///
+/// `exit_status` is the step's outcome for post hooks, None for pre hooks.
+pub(crate) fn run_hook(command: &str, step: &str, phase: &'static str, base_dir: &Path, exit_status: Option<i32>)
+    -> Result<(), VcpkgFfError>
+{
+    let shell = if cfg!(windows) {
+        CommandSpec::new("cmd").args(["/C", command])
+    } else {
+        CommandSpec::new("sh").args(["-c", command])
+    };
+    let mut spec = shell
+        .current_dir(base_dir)
+        .env("VCPKG_FF_STEP", step)
+        .env("VCPKG_FF_ROOT", base_dir)
+        .streamed();
+    if let Some(status) = exit_status {
+        spec = spec.env("VCPKG_FF_EXIT_STATUS", status.to_string());
+    }
+
+    let failed = |message: String| VcpkgFfError::HookFailed { step: step.to_string(), phase, message };
+    let output = SystemRunner.run(&spec).map_err(|e| failed(format!("could not start `{}`: {}", command, e)))?;
+    if !output.success() {
+        return Err(failed(format!("`{}` {}", command, output)));
+    }
+    Ok(())
+}
@@ -0,0 +1,200 @@
+use std::fs::{self, File, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+use serde::{Deserialize, Serialize};
+
+use crate::error::VcpkgFfError;
+use crate::fs_retry;
+
+/// Something a pipeline run does to the machine, recorded before it starts
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "kind", rename_all = "snake_case")]
+pub enum Action {
+    /// A pipeline step, around the actions it performs
+    Step { name: String },
+    RemoveDir { path: PathBuf },
+    RemoveFile { path: PathBuf },
+    WriteFile { path: PathBuf },
+    /// An external command (git, vcpkg, a plugin or hook)
+    Command { command: String },
+}
+
+impl Action {
+    /// One line for the `journal` and `repair` commands
+    pub fn describe(&self) -> String {
+        match self {
+            Action::Step { name } => format!("step {}", name),
+            Action::RemoveDir { path } => format!("removing {}", path.display()),
+            Action::RemoveFile { path } => format!("removing {}", path.display()),
+            Action::WriteFile { path } => format!("writing {}", path.display()),
+            Action::Command { command } => format!("running {}", command),
+        }
+    }
+}
+
+/// A line of the journal: an action starting, or the action with the same `seq` ending
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+#[serde(tag = "event", rename_all = "snake_case")]
+pub enum JournalEntry {
+    Begin { seq: u64, time: u64, action: Action },
+    End { seq: u64, time: u64, ok: bool },
+}
+
+/// The append-only record of what pipeline runs did to the machine, .vcpkg_ff/journal.ndjson
+///
+/// Every action is written and synced to disk before it starts and again when it ends, so an action
+/// without an end was cut short by a crash or a kill. A run that completes removes the journal.
+pub struct Journal {
+    path: PathBuf,
+    entries: Vec<JournalEntry>,
+}
+
+impl Journal {
+    pub fn path_in(base_dir: &Path) -> PathBuf {
+        base_dir.join(".vcpkg_ff").join("journal.ndjson")
+    }
+
+    /// Read the journal at `path`, empty when there is none
+    ///
+    /// A last line cut off by the crash is ignored, any other unreadable line is an error.
+    pub fn load(path: &Path) -> Result<Self, VcpkgFfError> {
+        let content = match fs::read_to_string(path) {
+            Ok(content) => content,
+            Err(e) if e.kind() == io::ErrorKind::NotFound => String::new(),
+            Err(e) => return Err(e.into()),
+        };
+        let lines: Vec<&str> = content.lines().filter(|line| !line.trim().is_empty()).collect();
+        let mut entries = Vec::new();
+        for (index, line) in lines.iter().enumerate() {
+            match serde_json::from_str(line) {
+                Ok(entry) => entries.push(entry),
+                Err(_) if index + 1 == lines.len() && !content.ends_with('\n') => {}
+                Err(e) => return Err(VcpkgFfError::InvalidFile { path: path.to_path_buf(), message: format!("line {}: {}", index + 1, e) }),
+            }
+        }
+        Ok(Self { path: path.to_path_buf(), entries })
+    }
+
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+
+    pub fn entries(&self) -> &[JournalEntry] {
+        &self.entries
+    }
+
+    /// Every action in the order it began, with whether it succeeded; None when it never ended
+    pub fn outcomes(&self) -> Vec<(&Action, Option<bool>)> {
+        self.entries
+            .iter()
+            .filter_map(|entry| match entry {
+                JournalEntry::Begin { seq, action, .. } => {
+                    let ok = self.entries.iter().find_map(|other| match other {
+                        JournalEntry::End { seq: end, ok, .. } if end == seq => Some(*ok),
+                        _ => None,
+                    });
+                    Some((action, ok))
+                }
+                JournalEntry::End { .. } => None,
+            })
+            .collect()
+    }
+
+    /// Actions that began and never ended, cut short by a crash
+    pub fn interrupted(&self) -> Vec<&Action> {
+        self.outcomes().into_iter().filter(|(_, ok)| ok.is_none()).map(|(action, _)| action).collect()
+    }
+
+    /// Delete the journal, after a completed run or a repair
+    pub fn clear(self) -> Result<(), VcpkgFfError> {
+        if self.path.exists() {
+            fs::remove_file(&self.path)?;
+        }
+        Ok(())
+    }
+}
+
+/// The journal the running pipeline appends to.
+/// Process-wide, the actions happen deep inside the manager and preparer.
+static CURRENT: Mutex<Option<Writer>> = Mutex::new(None);
+
+struct Writer {
+    file: File,
+    next_seq: u64,
+}
+
+impl Writer {
+    fn append(&mut self, entry: &JournalEntry) {
+        let line = serde_json::to_string(entry).expect("journal entries serialize") + "\n";
+        // 每条记录都落盘，崩溃后日志仍然完整
+        let _ = self.file.write_all(line.as_bytes()).and_then(|_| self.file.sync_data());
+    }
+}
+
+/// Run `f` with the actions it records appended to the journal at `path`
+pub(crate) fn with_journal<T>(path: &Path, f: impl FnOnce() -> T) -> Result<T, VcpkgFfError> {
+    let existing = Journal::load(path)?;
+    let next_seq = existing.entries.iter().map(|entry| match entry {
+        JournalEntry::Begin { seq, .. } | JournalEntry::End { seq, .. } => seq + 1,
+    }).max().unwrap_or(1);
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let file = OpenOptions::new().create(true).append(true).open(path)?;
+
+    let previous = CURRENT.lock().ok().and_then(|mut current| current.replace(Writer { file, next_seq }));
+    let result = f();
+    if let Ok(mut current) = CURRENT.lock() {
+        *current = previous;
+    }
+    Ok(result)
+}
+
+fn now() -> u64 {
+    SystemTime::now().duration_since(UNIX_EPOCH).map(|d| d.as_secs()).unwrap_or(0)
+}
+
+/// Record that `action` starts, returns its sequence number for [`end`], None outside a journaled run
+pub(crate) fn begin(action: Action) -> Option<u64> {
+    let mut current = CURRENT.lock().ok()?;
+    let writer = current.as_mut()?;
+    let seq = writer.next_seq;
+    writer.next_seq += 1;
+    writer.append(&JournalEntry::Begin { seq, time: now(), action });
+    Some(seq)
+}
+
+pub(crate) fn end(seq: Option<u64>, ok: bool) {
+    let (Some(seq), Ok(mut current)) = (seq, CURRENT.lock()) else {
+        return;
+    };
+    if let Some(writer) = current.as_mut() {
+        writer.append(&JournalEntry::End { seq, time: now(), ok });
+    }
+}
+
+/// Run `f` as `action`
+pub(crate) fn record<T, E>(action: Action, f: impl FnOnce() -> Result<T, E>) -> Result<T, E> {
+    let seq = begin(action);
+    let result = f();
+    end(seq, result.is_ok());
+    result
+}
+
+/// Journaled `fs::remove_dir_all`, retried while antivirus scanners hold files of the tree open
+pub(crate) fn remove_dir_all(path: &Path) -> io::Result<()> {
+    record(Action::RemoveDir { path: path.to_path_buf() }, || fs_retry::remove_dir_all(path))
+}
+
+/// Journaled `fs::remove_file`, retried while the file is locked
+pub(crate) fn remove_file(path: &Path) -> io::Result<()> {
+    record(Action::RemoveFile { path: path.to_path_buf() }, || fs_retry::remove_file(path))
+}
+
+/// Journaled `fs::write`
+pub(crate) fn write(path: &Path, contents: impl AsRef<[u8]>) -> io::Result<()> {
+    record(Action::WriteFile { path: path.to_path_buf() }, || fs::write(path, contents))
+}
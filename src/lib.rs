@@ -0,0 +1,74 @@
+//! Installs ffmpeg through vcpkg and turns its fftools sources into a Node.js addon
+//!
+//! [`Pipeline`] runs the whole install the way the `vcpkg_ff` binary does, as a sequence of
+//! [`Step`]s that can be skipped or extended. The parts are also usable on their own: [`VcpkgManager`] installs vcpkg, the ffmpeg packages and the
+//! ffmpeg source ([`ConanBackend`] installs them with Conan instead), [`AddonPreparer`] generates addon_src from it, [`AddonBuilder`] compiles
+//! and smoke tests the addon and [`AddonPackager`] assembles the npm package. [`probe()`] finds the
+//! installed libraries from the build script of a crate linking them.
+//!
+//! With the `async` feature, `VcpkgManager` also has tokio versions of its long operations.
+
+pub mod vcpkg_manager;
+pub mod addon_artifact;
+pub mod addon_builder;
+pub mod addon_packager;
+pub mod addon_preparer;
+pub mod bench;
+mod c_lexer;
+pub mod ci;
+pub mod command_runner;
+pub mod conan_backend;
+mod config_h;
+mod diff_patch;
+pub mod error;
+mod extract_index;
+mod ffmpeg_version;
+mod fftools_sources;
+pub mod fs_retry;
+mod generated_headers;
+mod hooks;
+pub mod journal;
+mod marker;
+pub mod msvc_env;
+pub mod napi_version;
+pub mod package_backend;
+mod patch_engine;
+pub mod pipeline;
+mod pkg_config;
+pub mod plugins;
+pub mod prebuilt_cache;
+pub mod probe;
+pub mod progress;
+pub mod shared_cache;
+pub mod shell_env;
+mod shims;
+pub mod steps;
+mod syntax_check;
+mod tarball;
+mod templates;
+mod trace;
+pub mod tool_config;
+mod user_patches;
+pub mod workspace;
+
+pub use vcpkg_manager::{RemovalReport, VcpkgManager, VcpkgManagerBuilder};
+#[cfg(feature = "async")]
+pub use vcpkg_manager::{probe_mirrors, MirrorProbe};
+pub use addon_artifact::{AddonArtifact, ArtifactManifest};
+pub use addon_builder::{AddonBuilder, MediaMatrix, MediaResult, PrebuildTarget, SmokeReport, SmokeRuntime};
+pub use addon_packager::AddonPackager;
+pub use addon_preparer::{AddonConfig, AddonPreparer, AddonPreparerBuilder, AddonTarget, BindingStyle, BuildSystem, WASM_TRIPLET};
+pub use bench::{BenchHistory, BenchRun, StepSample};
+pub use ci::{CiGenerator, CiProvider};
+pub use command_runner::{CommandOutput, CommandRunner, CommandSpec, SystemRunner};
+pub use conan_backend::ConanBackend;
+pub use error::VcpkgFfError;
+pub use journal::Journal;
+pub use package_backend::{FfmpegSource, PackageBackend};
+pub use pipeline::{Pipeline, PipelineContext, PipelineOutcome, Step};
+pub use prebuilt_cache::{PrebuiltCache, PrebuiltKey};
+pub use probe::{probe, probe_root, LinkMetadata, ProbedLibraries};
+pub use shared_cache::{DedupeReport, SharedCache};
+pub use shell_env::{Shell, ShellEnv};
+pub use progress::{ConsoleObserver, NdjsonObserver, ProgressObserver, SkipReason, StepStatus};
+pub use workspace::Workspace;
@@ -1,55 +1,663 @@
-mod vcpkg_manager;
-mod addon_preparer;
+use vcpkg_ff::addon_builder;
+use vcpkg_ff::addon_preparer;
+use vcpkg_ff::command_runner;
+use vcpkg_ff::fs_retry;
+use vcpkg_ff::msvc_env;
+use vcpkg_ff::napi_version;
+use vcpkg_ff::shared_cache;
+use vcpkg_ff::tool_config;
+use vcpkg_ff::vcpkg_manager;
+use std::path::{Path, PathBuf};
+use std::sync::Arc;
 
-use vcpkg_manager::VcpkgManager;
-use addon_preparer::AddonPreparer;
+use vcpkg_ff::{AddonArtifact, AddonBuilder, AddonConfig, AddonPackager, AddonPreparer, AddonTarget, BenchHistory, BindingStyle, BuildSystem, CiGenerator, CiProvider, ConanBackend, Journal, LinkMetadata, NdjsonObserver, Pipeline, PrebuildTarget, PrebuiltCache, SharedCache, Shell, ShellEnv, SmokeRuntime, SystemRunner, VcpkgFfError, VcpkgManager, Workspace, WASM_TRIPLET};
 
 fn main() {
-    println!("=== vcpkg FFmpeg/x264/x265/vpx Installer ===\n");
+    let mut args: Vec<String> = std::env::args().skip(1).collect();
+    let addon_config = match take_option(&mut args, "--addon-config")
+        .and_then(|value| value.map(|v| AddonConfig::parse(&v)).transpose())
+    {
+        Ok(config) => config.unwrap_or(AddonConfig::Both),
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
     
-    let manager = VcpkgManager::new();
+    let build_system = match take_option(&mut args, "--build-system")
+        .and_then(|value| value.map(|v| BuildSystem::parse(&v)).transpose())
+    {
+        Ok(build_system) => build_system,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
     
-    match manager.install_vcpkg() {
-        Ok(_) => {},
+    let binding_style = match take_option(&mut args, "--binding-style")
+        .and_then(|value| value.map(|v| BindingStyle::parse(&v)).transpose())
+    {
+        Ok(binding_style) => binding_style,
         Err(e) => {
-            eprintln!("✗ vcpkg installation failed: {}", e);
+            eprintln!("✗ {}", e);
             std::process::exit(1);
         }
-    }
+    };
     
-    match manager.install_packages() {
-        Ok(_) => {},
+    let target = match take_option(&mut args, "--target")
+        .and_then(|value| value.map(|v| AddonTarget::parse(&v)).transpose())
+    {
+        Ok(target) => target.unwrap_or(AddonTarget::Node),
         Err(e) => {
-            eprintln!("✗ Package installation failed: {}", e);
+            eprintln!("✗ {}", e);
             std::process::exit(1);
         }
-    }
+    };
     
-    match manager.extract_ffmpeg() {
-        Ok(_) => {},
+    let smoke_runtime = match take_option(&mut args, "--smoke-runtime")
+        .and_then(|value| value.map(|v| SmokeRuntime::parse(&v)).transpose())
+    {
+        Ok(runtime) => runtime.unwrap_or_default(),
         Err(e) => {
-            eprintln!("✗ ffmpeg extraction failed: {}", e);
+            eprintln!("✗ {}", e);
             std::process::exit(1);
         }
-    }
+    };
     
-    println!("\n=== Installation Complete ===");
-    println!("vcpkg root: {}", manager.get_vcpkg_root().display());
-    println!("vcpkg executable: {}", manager.get_vcpkg_exe().display());
+    let electron = match take_option(&mut args, "--electron")
+        .and_then(|value| value.map(|v| addon_preparer::parse_electron_version(&v)).transpose())
+    {
+        Ok(electron) => electron,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
     
-    if let Some(ffmpeg_dir) = manager.is_ffmpeg_extracted() {
-        println!("ffmpeg project directory: {}", ffmpeg_dir.display());
+    let napi_version = match take_option(&mut args, "--napi-version")
+        .and_then(|value| value.map(|v| napi_version::parse(&v)).transpose())
+    {
+        Ok(napi_version) => napi_version,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let prebuild_targets = match take_option(&mut args, "--targets").and_then(|value| {
+        value.map(|v| v.split(',').map(PrebuildTarget::parse).collect::<Result<Vec<_>, _>>()).transpose()
+    }) {
+        Ok(targets) => targets,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let skip = match take_option(&mut args, "--skip") {
+        Ok(skip) => skip.map(|v| v.split(',').map(str::to_string).collect()).unwrap_or_default(),
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let containers = match take_option(&mut args, "--containers") {
+        Ok(containers) => containers.map(|v| v.split(',').map(str::to_string).collect::<Vec<_>>()),
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let output = match take_option(&mut args, "--output") {
+        Ok(output) => output,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let provider = match take_option(&mut args, "--provider")
+        .and_then(|value| value.map(|v| CiProvider::parse(&v)).transpose())
+    {
+        Ok(provider) => provider.unwrap_or(CiProvider::GitHub),
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let trace = match take_option(&mut args, "--trace") {
+        Ok(trace) => trace,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let backend = match take_option(&mut args, "--backend") {
+        Ok(None) => None,
+        Ok(Some(backend)) if backend == "vcpkg" || backend == "conan" => Some(backend),
+        Ok(Some(other)) => {
+            eprintln!("✗ Unknown backend: {}, expected vcpkg or conan", other);
+            std::process::exit(1);
+        }
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let conan_reference = match take_option(&mut args, "--conan-ref") {
+        Ok(reference) => reference,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let shell = match take_option(&mut args, "--shell")
+        .and_then(|value| value.map(|v| Shell::parse(&v)).transpose())
+    {
+        Ok(shell) => shell.unwrap_or_else(Shell::platform_default),
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    
+    let build_addon = take_flag(&mut args, "--build-addon");
+    let full = take_flag(&mut args, "--full");
+    let bun = take_flag(&mut args, "--bun");
+    let cli_tools = take_flag(&mut args, "--cli-tools");
+    let fsck = take_flag(&mut args, "--fsck");
+    let integrate = take_flag(&mut args, "--integrate");
+    let no_vcvars = take_flag(&mut args, "--no-vcvars");
+    let recurse = take_flag(&mut args, "--recurse");
+    let outdated = take_flag(&mut args, "--outdated");
+    let resume = take_flag(&mut args, "--resume");
+    let ndjson = take_flag(&mut args, "--ndjson");
+    let npm_pack = take_flag(&mut args, "--npm-pack");
+    
+    match args.first().map(String::as_str) {
+        None | Some("build") | Some("pack-addon") | Some("unpack-addon") => {}
+        Some("revert-patches") => {
+            if let Err(e) = AddonPreparer::new().revert_patches() {
+                eprintln!("✗ Reverting patches failed: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("clean") => {
+            if let Err(e) = VcpkgManager::new().integrate_remove() {
+                eprintln!("✗ {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("remove") => {
+            let manager = if cli_tools { VcpkgManager::new().with_cli_tools() } else { VcpkgManager::new() };
+            let report = match manager.remove_packages(recurse, outdated) {
+                Ok(report) => report,
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    if !recurse {
+                        eprintln!("  hint: rerun with --recurse to also remove the packages depending on ffmpeg");
+                    }
+                    std::process::exit(1);
+                }
+            };
+            report.print();
+            // 检查点里的 fetch-packages 及之后的步骤（包括可选步骤）已失效，--resume 时重新运行
+            match Pipeline::new().with_cli_tools(true).with_integrate(true).with_build_addon(true).forget_checkpoint_from("fetch-packages") {
+                Ok(true) => println!("✓ Checkpoint updated, the next run installs ffmpeg again"),
+                Ok(false) => {}
+                Err(e) => {
+                    eprintln!("✗ Updating the checkpoint failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("verify") => {
+            let preparer = AddonPreparer::new();
+            if let Err(e) = preparer.verify_stamps() {
+                eprintln!("✗ Verification failed: {}", e);
+                std::process::exit(1);
+            }
+            if !full {
+                return;
+            }
+            
+            // 编译 addon 后用它转码测试片段，检查每种容器的结果
+            if !no_vcvars {
+                load_msvc_environment(VcpkgManager::new().get_triplet(), false);
+            }
+            let containers = containers
+                .unwrap_or_else(|| addon_builder::VERIFY_CONTAINERS.iter().map(|c| c.to_string()).collect());
+            let builder = AddonBuilder::new(preparer.get_addon_src_dir(), &preparer.get_log_dir()).with_runtime(smoke_runtime);
+            match builder.build().and_then(|()| builder.verify_media(&containers)) {
+                Ok(matrix) => {
+                    matrix.print();
+                    if !matrix.passed() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("✗ Verification failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("package") => {
+            let packager = AddonPackager::new(AddonPreparer::new().get_base_dir());
+            let package_dir = match packager.assemble() {
+                Ok(package_dir) => package_dir,
+                Err(e) => {
+                    eprintln!("✗ Packaging failed: {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if npm_pack {
+                if let Err(e) = packager.npm_pack(&package_dir) {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            } else {
+                println!("Publish with: npm publish {}", package_dir.display());
+            }
+            return;
+        }
+        Some("journal") => {
+            let path = Journal::path_in(AddonPreparer::new().get_base_dir());
+            let journal = match Journal::load(&path) {
+                Ok(journal) => journal,
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            };
+            if journal.entries().is_empty() {
+                println!("✓ No journal, the last run completed");
+                return;
+            }
+            println!("Journal: {}", path.display());
+            for (action, ok) in journal.outcomes() {
+                match ok {
+                    Some(true) => println!("  ✓ {}", action.describe()),
+                    Some(false) => println!("  ✗ {}", action.describe()),
+                    None => println!("  ⚠ {} (interrupted)", action.describe()),
+                }
+            }
+            if !journal.interrupted().is_empty() {
+                println!("Run `vcpkg_ff repair` to clean up the interrupted actions");
+            }
+            return;
+        }
+        Some("bench") => {
+            let path = BenchHistory::path_in(AddonPreparer::new().get_base_dir());
+            match BenchHistory::load(&path).map(|history| history.report()) {
+                Ok(Some(report)) => print!("{}", report),
+                Ok(None) => println!("⚠ No runs recorded in {} yet", path.display()),
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("dedupe") => {
+            let config = match tool_config::ToolConfig::load(AddonPreparer::new().get_base_dir()) {
+                Ok(config) => config,
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            };
+            command_runner::inject_env(&config.env);
+            let Some(cache) = SharedCache::from_env() else {
+                eprintln!("✗ No shared cache, set {} or add it to [env] in vcpkg_ff.toml", shared_cache::SHARED_CACHE_VAR);
+                std::process::exit(1);
+            };
+            let manager = if cli_tools { VcpkgManager::new().with_cli_tools() } else { VcpkgManager::new() };
+            if let Err(e) = manager.dedupe(&cache) {
+                eprintln!("✗ Deduplication failed: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        Some("repair") => {
+            match Pipeline::new().with_build_addon(true).repair() {
+                Ok(repaired) if repaired.is_empty() => println!("✓ Nothing was interrupted, nothing to repair"),
+                Ok(repaired) => {
+                    for line in repaired {
+                        println!("✓ {}", line);
+                    }
+                    println!("Rerun vcpkg_ff with --resume to continue the install");
+                }
+                Err(e) => {
+                    eprintln!("✗ Repair failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("generate") => {
+            if args.get(1).map(String::as_str) != Some("ci") {
+                eprintln!("✗ Usage: vcpkg_ff generate ci [--provider github|azure]");
+                std::process::exit(1);
+            }
+            let manager = VcpkgManager::new();
+            let generator = CiGenerator::new(AddonPreparer::new().get_base_dir()).with_features(manager.get_features());
+            match generator.generate(provider) {
+                Ok(path) => println!("✓ Workflow written to {}", path.display()),
+                Err(e) => {
+                    eprintln!("✗ Generating the workflow failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some("env") => {
+            let (manager, preparer) = if target == AddonTarget::Wasm {
+                (VcpkgManager::builder().triplet(WASM_TRIPLET).build(), AddonPreparer::builder().triplet(WASM_TRIPLET).build())
+            } else {
+                (VcpkgManager::new(), AddonPreparer::new())
+            };
+            print!("{}", ShellEnv::new(&manager, &preparer).render(shell));
+            return;
+        }
+        Some("link-metadata") => {
+            let manager = VcpkgManager::new();
+            let metadata = match LinkMetadata::probe(manager.get_vcpkg_root(), manager.get_triplet(), &["ffmpeg"]) {
+                Ok(metadata) => metadata,
+                Err(e) => {
+                    eprintln!("✗ {}", e);
+                    std::process::exit(1);
+                }
+            };
+            let path = output.map(PathBuf::from).unwrap_or_else(|| manager.get_output_dir().join("ffmpeg-sys.toml"));
+            if let Err(e) = metadata.write(&path) {
+                eprintln!("✗ Writing {} failed: {}", path.display(), e);
+                std::process::exit(1);
+            }
+            println!("✓ Link metadata for {} written to {}", manager.get_triplet(), path.display());
+            println!("  {} static and {} system libraries", metadata.static_libs.len(), metadata.system_libs.len());
+            return;
+        }
+        Some("prebuild") => {
+            let preparer = AddonPreparer::new();
+            let targets = match prebuild_targets {
+                Some(targets) => targets,
+                None => match tool_config::ToolConfig::load(preparer.get_base_dir()) {
+                    Ok(config) => config.prebuild.targets.iter().filter_map(|t| PrebuildTarget::parse(t).ok()).collect(),
+                    Err(e) => {
+                        eprintln!("✗ {}", e);
+                        std::process::exit(1);
+                    }
+                },
+            };
+            if targets.is_empty() {
+                eprintln!("✗ No prebuild targets, pass --targets node@<version>,electron@<version> or set [prebuild] targets in vcpkg_ff.toml");
+                std::process::exit(1);
+            }
+            
+            if !no_vcvars {
+                load_msvc_environment(VcpkgManager::new().get_triplet(), false);
+            }
+            let builder = AddonBuilder::new(preparer.get_addon_src_dir(), &preparer.get_log_dir());
+            match builder.prebuild_matrix(&targets) {
+                Ok(report) => {
+                    report.print();
+                    if !report.passed() {
+                        std::process::exit(1);
+                    }
+                }
+                Err(e) => {
+                    eprintln!("✗ Prebuild failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            return;
+        }
+        Some(other) => {
+            eprintln!("✗ Unknown command: {}", other);
+            eprintln!("Usage: vcpkg_ff [build <target>|revert-patches|verify [--full] [--containers mp4,webm,mkv]|clean|remove [--recurse] [--outdated]|journal|bench|dedupe|repair|package [--npm-pack]|pack-addon [--output <file>]|unpack-addon <file>|prebuild [--targets node@V,electron@V]|env [--shell bash|powershell|cmd]|link-metadata [--output <file>]|generate ci [--provider github|azure]] [--addon-config debug|release|both] [--build-system gyp|cmake-js] [--binding-style c|node-addon-api|napi-rs] [--target node|deno|wasm] [--backend vcpkg|conan] [--conan-ref ffmpeg/<version>] [--electron <version>] [--napi-version N] [--bun] [--cli-tools] [--fsck] [--integrate] [--no-vcvars] [--build-addon] [--smoke-runtime node|bun] [--skip step,step] [--resume] [--ndjson] [--trace <file>]");
+            std::process::exit(1);
+        }
+    }
+    
+    let artifact_command = matches!(args.first().map(String::as_str), Some("pack-addon" | "unpack-addon"));
+    if !ndjson && !artifact_command {
+        println!("=== vcpkg FFmpeg/x264/x265/vpx Installer ===\n");
     }
     
-    let addon_preparer = AddonPreparer::new();
-    match addon_preparer.prepare_addon_source() {
-        Ok(_) => {},
+    let (manager, mut addon_preparer) = if args.first().map(String::as_str) == Some("build") {
+        let workspace = match Workspace::load(&vcpkg_manager::default_base_dir()) {
+            Ok(workspace) => workspace,
+            Err(e) => {
+                eprintln!("✗ {}", e);
+                std::process::exit(1);
+            }
+        };
+        let Some(target) = args.get(1) else {
+            eprintln!("✗ Usage: vcpkg_ff build <target>, targets: {}", workspace.target_names().join(", "));
+            std::process::exit(1);
+        };
+        match workspace.preparer(target) {
+            Ok(preparer) => (workspace.manager(), preparer),
+            Err(e) => {
+                eprintln!("✗ {}", e);
+                std::process::exit(1);
+            }
+        }
+    } else if target == AddonTarget::Wasm {
+        // wasm 目标的 ffmpeg 由 vcpkg 用 Emscripten 交叉编译
+        (VcpkgManager::builder().triplet(WASM_TRIPLET).build(), AddonPreparer::builder().triplet(WASM_TRIPLET).build())
+    } else {
+        (VcpkgManager::new(), AddonPreparer::new())
+    };
+    let manager = if cli_tools { manager.with_cli_tools() } else { manager }.with_fsck(fsck);
+    // 命令行参数优先于工作区目标的设置
+    if let Some(build_system) = build_system {
+        addon_preparer = addon_preparer.with_build_system(build_system);
+    }
+    if let Some(binding_style) = binding_style {
+        addon_preparer = addon_preparer.with_binding_style(binding_style);
+    }
+    let addon_preparer = addon_preparer
+        .with_addon_config(addon_config)
+        .with_target(target)
+        .with_electron(electron)
+        .with_bun(bun)
+        .with_napi_version(napi_version);
+    // 打包和解包用与流水线相同的选项配置的 preparer，输入哈希才对得上
+    if artifact_command {
+        let artifact = AddonArtifact::new(&addon_preparer);
+        if args[0] == "pack-addon" {
+            if let Err(e) = artifact.pack(output.as_deref().map(Path::new)) {
+                eprintln!("✗ Packing addon_src failed: {}", e);
+                std::process::exit(1);
+            }
+            return;
+        }
+        let Some(archive) = args.get(1) else {
+            eprintln!("✗ Usage: vcpkg_ff unpack-addon <artifact.tar.gz>");
+            std::process::exit(1);
+        };
+        match artifact.unpack(Path::new(archive)) {
+            Ok(true) => {
+                let pipeline = Pipeline::new().with_manager(manager).with_preparer(addon_preparer);
+                if let Err(e) = pipeline.adopt("prepare-addon") {
+                    eprintln!("✗ Recording the preparation failed: {}", e);
+                    std::process::exit(1);
+                }
+            }
+            Ok(false) => {}
+            Err(e) => {
+                eprintln!("✗ Unpacking addon_src failed: {}", e);
+                std::process::exit(1);
+            }
+        }
+        return;
+    }
+    let config = match tool_config::ToolConfig::load(addon_preparer.get_base_dir()) {
+        Ok(config) => config,
         Err(e) => {
-            eprintln!("✗ Addon preparation failed: {}", e);
+            eprintln!("✗ {}", e);
             std::process::exit(1);
         }
+    };
+    // [env] 在开发者环境之后注入，同名变量以 vcpkg_ff.toml 为准
+    if !no_vcvars {
+        load_msvc_environment(manager.get_triplet(), ndjson);
+    }
+    command_runner::inject_env(&config.env);
+    let manager = manager.with_prebuilt_cache(PrebuiltCache::from_env());
+    let conan = backend.as_deref() == Some("conan");
+    if conan && integrate {
+        eprintln!("✗ --integrate needs the vcpkg backend");
+        std::process::exit(1);
+    }
+    let mut pipeline = Pipeline::new();
+    if conan {
+        let conan_backend = ConanBackend::for_manager(&manager);
+        pipeline = pipeline.with_backend(match conan_reference {
+            Some(reference) => conan_backend.with_reference(reference),
+            None => conan_backend,
+        });
+    }
+    let pipeline = pipeline
+        .with_manager(manager)
+        .with_preparer(addon_preparer)
+        .with_integrate(integrate)
+        .with_cli_tools(cli_tools)
+        .with_build_addon(build_addon)
+        .with_smoke_runtime(smoke_runtime)
+        .with_shared_cache(SharedCache::from_env())
+        .with_plugins(&config.plugins)
+        .and_then(|pipeline| pipeline.with_hooks(config.hooks));
+    let mut pipeline = match pipeline {
+        Ok(pipeline) => pipeline.with_skip(skip).with_resume(resume),
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            std::process::exit(1);
+        }
+    };
+    if let Some(trace) = trace {
+        pipeline = pipeline.with_trace(trace);
+    }
+    if ndjson {
+        // 每行一个 JSON 事件，步骤输出也作为事件发出，stdout 上没有其他内容
+        pipeline = pipeline.with_observer(Arc::new(NdjsonObserver::new(std::io::stdout())));
+    }
+    let outcome = match pipeline.run() {
+        Ok(outcome) => outcome,
+        Err(e) => {
+            eprintln!("✗ {}", e);
+            if let Some(hint) = guidance(&e) {
+                eprintln!("  hint: {}", hint);
+            }
+            std::process::exit(1);
+        }
+    };
+    
+    let smoke_failed = outcome.smoke_report.as_ref().is_some_and(|report| !report.passed());
+    if ndjson {
+        if smoke_failed {
+            std::process::exit(1);
+        }
+        return;
     }
     
     println!("\n=== All Steps Completed ===");
-    println!("addon source directory: {}", addon_preparer.get_addon_src_dir().display());
+    let manager = &pipeline.context().manager;
+    match pipeline.context().packages().name() {
+        "vcpkg" => {
+            println!("vcpkg root: {}", manager.get_vcpkg_root().display());
+            println!("vcpkg executable: {}", manager.get_vcpkg_exe().display());
+        }
+        backend => println!("ffmpeg packages: installed with {} in {}", backend,
+            manager.get_vcpkg_root().join("installed").join(manager.get_triplet()).display()),
+    }
+    if let Some(ffmpeg_dir) = manager.is_ffmpeg_extracted() {
+        println!("ffmpeg project directory: {}", ffmpeg_dir.display());
+    }
+    println!("addon source directory: {}", outcome.addon_src_dir.display());
+    for program in &outcome.cli_tools {
+        println!("ffmpeg program: {}", program.display());
+    }
+    if outcome.smoke_report.is_some() {
+        if smoke_failed {
+            std::process::exit(1);
+        }
+        return;
+    }
+    println!("\nNext steps:");
+    println!("  cd {}", outcome.addon_src_dir.display());
+    println!("  npm install    # builds the addon");
+    println!("  npm test       # loads the built binary");
+}
+
+/// What to try next for a failed install
+fn guidance(error: &VcpkgFfError) -> Option<&'static str> {
+    match error {
+        VcpkgFfError::StepFailed { source, .. } => guidance(source),
+        VcpkgFfError::Io(e) if fs_retry::file_locked(e).is_some() => Some("exclude the vcpkg folder, its buildtrees and the project folder from antivirus scanning (Windows Security > Virus & threat protection > Exclusions, or `Add-MpPreference -ExclusionPath <folder>` in an elevated PowerShell), then rerun with --resume"),
+        VcpkgFfError::GitUnavailable => Some("install git and make sure it is on PATH"),
+        VcpkgFfError::CloneFailed { .. } => Some("check the network connection and proxy settings (HTTPS_PROXY), then rerun with --resume"),
+        VcpkgFfError::BootstrapFailed { .. } => Some("vcpkg's bootstrap needs a C++ compiler, curl, zip, unzip and tar; rerun after installing them, the existing clone is reused"),
+        VcpkgFfError::DownloadFailed { .. } => Some("check the network connection and proxy settings (HTTPS_PROXY) or set up an asset cache with X_VCPKG_ASSET_SOURCES, then rerun with --resume"),
+        VcpkgFfError::PackageInstallFailed { .. } => Some("fix the failure shown in the port's build logs, then rerun with --resume to skip the completed steps"),
+        VcpkgFfError::ArchiveNotFound(_) => Some("run the install-packages step so that vcpkg downloads the ffmpeg sources"),
+        VcpkgFfError::UnsupportedFfmpeg { .. } | VcpkgFfError::FfmpegSourceMissing { .. } => Some("delete ffmpeg/ and rerun to extract the ffmpeg sources vcpkg installed"),
+        VcpkgFfError::PatchFailed { .. } => Some("update the patch in patches/ for this ffmpeg version or remove it"),
+        VcpkgFfError::Config { .. } => Some("fix the file, or remove it to use the defaults"),
+        VcpkgFfError::InvalidFile { .. } => Some("remove the file and rerun, or fetch it again"),
+        VcpkgFfError::ValidationFailed { .. } => Some("check the template overrides in templates/ and the rules in addon_patches/"),
+        VcpkgFfError::NpmFailed { .. } => Some("the npm log has the full compiler output"),
+        VcpkgFfError::PluginFailed { .. } => Some("the plugin is declared in [[plugins]] of vcpkg_ff.toml, skip it with --skip <name>"),
+        VcpkgFfError::HookFailed { .. } => Some("the hook is declared in [hooks] of vcpkg_ff.toml, fix the command or remove it"),
+        _ => None,
+    }
+}
+
+/// Inject the MSVC developer environment into the child processes when not started from a Developer Command Prompt,
+/// quietly when stdout carries NDJSON events
+fn load_msvc_environment(triplet: &str, quiet: bool) {
+    match msvc_env::developer_environment(&SystemRunner, triplet) {
+        Ok(Some(environment)) => {
+            if !quiet {
+                println!("✓ MSVC developer environment ({}) loaded from {}", environment.arch, environment.vcvarsall.display());
+            }
+            command_runner::inject_env(environment.vars);
+        }
+        Ok(None) => {}
+        Err(e) => eprintln!("⚠ MSVC developer environment not loaded: {}", e),
+    }
+}
+
+/// Remove a boolean `--name` flag from `args`, returning whether it was present
+fn take_flag(args: &mut Vec<String>, name: &str) -> bool {
+    let before = args.len();
+    args.retain(|arg| arg != name);
+    args.len() != before
+}
+
+/// Remove `--name value` or `--name=value` from `args`, returning the value
+fn take_option(args: &mut Vec<String>, name: &str) -> Result<Option<String>, String> {
+    let Some(index) = args.iter().position(|arg| arg == name || arg.starts_with(&format!("{}=", name))) else {
+        return Ok(None);
+    };
+    let arg = args.remove(index);
+    if let Some(value) = arg.strip_prefix(&format!("{}=", name)) {
+        return Ok(Some(value.to_string()));
+    }
+    if index < args.len() {
+        Ok(Some(args.remove(index)))
+    } else {
+        Err(format!("{} requires a value", name))
+    }
 }
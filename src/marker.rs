@@ -0,0 +1,210 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::OnceLock;
+
+use crate::templates;
+
+/// Version of this tool
+pub const TOOL_VERSION: &str = env!("CARGO_PKG_VERSION");
+
+/// [`TOOL_VERSION`] and a hash of the built-in templates, recorded in every marker: a fixed template changes it
+/// even when the version stays, so that files generated before the fix are generated again
+pub fn generator_version() -> &'static str {
+    static GENERATOR_VERSION: OnceLock<String> = OnceLock::new();
+    GENERATOR_VERSION.get_or_init(|| format!("{}+{}", TOOL_VERSION, &templates::builtin_hash()[..8]))
+}
+
+const MARKER_PREFIX: &str = "/* vcpkg_ff marker:";
+
+/// Where a generated file came from: recorded in its marker so an addon_src found in the field
+/// can be traced back to the installer run that produced it
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Provenance {
+    pub ffmpeg_version: String,
+    pub triplet: String,
+    /// vcpkg ffmpeg features, comma separated
+    pub features: String,
+}
+
+/// Idempotency and provenance marker written as the first line of every generated or modified file
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Marker {
+    pub tool_version: String,
+    pub ffmpeg_version: String,
+    pub triplet: String,
+    pub features: String,
+    /// Hash of the rules (or template) that produced the file
+    pub rule_hash: String,
+    /// Hash of the source content the rules were applied to
+    pub source_hash: String,
+    /// Hash of the file content below the marker, filled in by `stamp`
+    pub output_hash: String,
+}
+
+impl Marker {
+    pub fn new(provenance: &Provenance, rule_hash: String, source_hash: String) -> Self {
+        Self {
+            tool_version: generator_version().to_string(),
+            ffmpeg_version: provenance.ffmpeg_version.clone(),
+            triplet: provenance.triplet.clone(),
+            features: provenance.features.clone(),
+            rule_hash,
+            source_hash,
+            output_hash: String::new(),
+        }
+    }
+
+    /// Render the marker as a C comment line
+    pub fn render(&self) -> String {
+        format!("{} tool={} ffmpeg={} triplet={} features={} rules={} source={} output={} */",
+            MARKER_PREFIX, self.tool_version, self.ffmpeg_version, self.triplet, self.features,
+            self.rule_hash, self.source_hash, self.output_hash)
+    }
+
+    /// Read the marker from the first line of `content`, if present
+    pub fn parse(content: &str) -> Option<Self> {
+        let first_line = content.lines().next()?;
+        let fields = first_line.strip_prefix(MARKER_PREFIX)?.strip_suffix("*/")?;
+
+        let mut marker = Marker {
+            tool_version: String::new(),
+            ffmpeg_version: String::new(),
+            triplet: String::new(),
+            features: String::new(),
+            rule_hash: String::new(),
+            source_hash: String::new(),
+            output_hash: String::new(),
+        };
+        for field in fields.split_whitespace() {
+            match field.split_once('=') {
+                Some(("tool", value)) => marker.tool_version = value.to_string(),
+                Some(("ffmpeg", value)) => marker.ffmpeg_version = value.to_string(),
+                Some(("triplet", value)) => marker.triplet = value.to_string(),
+                Some(("features", value)) => marker.features = value.to_string(),
+                Some(("rules", value)) => marker.rule_hash = value.to_string(),
+                Some(("source", value)) => marker.source_hash = value.to_string(),
+                Some(("output", value)) => marker.output_hash = value.to_string(),
+                _ => {}
+            }
+        }
+        Some(marker)
+    }
+
+    /// True if `content` carries a marker produced from the same inputs as `self`
+    /// and hasn't been edited since
+    pub fn is_current(&self, content: &str) -> bool {
+        match Marker::parse(content) {
+            Some(existing) => {
+                existing.tool_version == self.tool_version
+                    && existing.ffmpeg_version == self.ffmpeg_version
+                    && existing.triplet == self.triplet
+                    && existing.features == self.features
+                    && existing.rule_hash == self.rule_hash
+                    && existing.source_hash == self.source_hash
+                    && existing.output_matches(content)
+            }
+            None => false,
+        }
+    }
+
+    /// Whether the marker was written by another vcpkg_ff version or with other built-in templates
+    pub fn is_outdated(&self) -> bool {
+        self.tool_version != generator_version()
+    }
+
+    /// True if the content below the marker still hashes to `output_hash`
+    pub fn output_matches(&self, content: &str) -> bool {
+        self.output_hash == content_hash(strip(content))
+    }
+
+    /// Prepend the marker line to `content`, recording the hash of `content`
+    pub fn stamp(&self, content: &str) -> String {
+        let body = strip(content);
+        let marker = Marker { output_hash: content_hash(body), ..self.clone() };
+        format!("{}\n{}", marker.render(), body)
+    }
+}
+
+/// Return `content` without a leading marker line
+pub fn strip(content: &str) -> &str {
+    if content.starts_with(MARKER_PREFIX) {
+        match content.find('\n') {
+            Some(pos) => &content[pos + 1..],
+            None => "",
+        }
+    } else {
+        content
+    }
+}
+
+/// Stable 64-bit FNV-1a hash of `data`, as hex
+pub fn content_hash(data: &str) -> String {
+    let mut hasher = Hasher::new();
+    hasher.update(data.as_bytes());
+    hasher.finish()
+}
+
+/// Incremental 64-bit FNV-1a hasher, for hashing many inputs into one value
+pub struct Hasher {
+    hash: u64,
+}
+
+impl Hasher {
+    pub fn new() -> Self {
+        Self { hash: 0xcbf29ce484222325 }
+    }
+
+    pub fn update(&mut self, data: &[u8]) {
+        for byte in data {
+            self.hash ^= *byte as u64;
+            self.hash = self.hash.wrapping_mul(0x100000001b3);
+        }
+    }
+
+    /// The hash as hex
+    pub fn finish(&self) -> String {
+        format!("{:016x}", self.hash)
+    }
+}
+
+/// Feed a file, or every file below a directory in sorted order, into `hasher`
+pub(crate) fn hash_path(hasher: &mut Hasher, path: &Path) {
+    hasher.update(path.to_string_lossy().as_bytes());
+    hasher.update(&[0]);
+    
+    if path.is_dir() {
+        let mut entries: Vec<PathBuf> = fs::read_dir(path)
+            .map(|entries| entries.flatten().map(|entry| entry.path()).collect())
+            .unwrap_or_default();
+        entries.sort();
+        for entry in &entries {
+            hash_path(hasher, entry);
+        }
+    } else if let Ok(content) = fs::read(path) {
+        hasher.update(&content);
+    }
+    hasher.update(&[0]);
+}
+
+/// Like [`hash_path`] but with the paths below `path` relative to it, so that the same tree in another directory,
+/// e.g. on another machine, hashes the same
+pub(crate) fn hash_tree(hasher: &mut Hasher, path: &Path) {
+    fn hash_entry(hasher: &mut Hasher, path: &Path, relative: &str) {
+        hasher.update(relative.as_bytes());
+        hasher.update(&[0]);
+        if path.is_dir() {
+            let mut names: Vec<String> = fs::read_dir(path)
+                .map(|entries| entries.flatten().map(|entry| entry.file_name().to_string_lossy().into_owned()).collect())
+                .unwrap_or_default();
+            names.sort();
+            for name in &names {
+                let entry_relative = if relative.is_empty() { name.clone() } else { format!("{}/{}", relative, name) };
+                hash_entry(hasher, &path.join(name), &entry_relative);
+            }
+        } else if let Ok(content) = fs::read(path) {
+            hasher.update(&content);
+        }
+        hasher.update(&[0]);
+    }
+    hash_entry(hasher, path, "");
+}
@@ -0,0 +1,105 @@
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use crate::command_runner::{CommandRunner, CommandSpec};
+use crate::error::VcpkgFfError;
+
+/// vcvarsall.bat of a large install runs for several seconds
+const VCVARS_TIMEOUT: Duration = Duration::from_secs(2 * 60);
+
+/// Set by vcvarsall.bat, present when the tool runs in a Developer Command Prompt
+const DEVELOPER_PROMPT_VARIABLE: &str = "VCINSTALLDIR";
+
+/// Variables vcvarsall.bat set for a target architecture
+#[derive(Debug, Clone)]
+pub struct MsvcEnvironment {
+    /// The vcvarsall.bat that was run
+    pub vcvarsall: PathBuf,
+    /// Argument it was run with, e.g. `x64` or `x64_arm64`
+    pub arch: String,
+    /// Variables that are new or differ from the current environment: PATH, INCLUDE, LIB, ...
+    pub vars: Vec<(String, String)>,
+}
+
+/// The Visual C++ developer environment for `triplet`, to inject into child processes when the tool was not started
+/// from a Developer Command Prompt
+///
+/// Returns None off Windows, for triplets not built with MSVC and when the environment is already set up. Visual
+/// Studio is located with vswhere, which the installer of every edition since 2017 puts in a fixed place.
+pub fn developer_environment(runner: &dyn CommandRunner, triplet: &str) -> Result<Option<MsvcEnvironment>, VcpkgFfError> {
+    if !cfg!(windows) || !triplet.contains("windows") || env::var_os(DEVELOPER_PROMPT_VARIABLE).is_some() {
+        return Ok(None);
+    }
+    let vcvarsall = find_vcvarsall(runner)?;
+    let arch = vcvars_arch(triplet)?;
+
+    // 通过批处理文件运行，避免 cmd /c 对引号的特殊处理
+    let script = env::temp_dir().join(format!("vcpkg_ff-vcvars-{}.bat", std::process::id()));
+    fs::write(&script, format!("@call \"{}\" {} >nul\r\n@if errorlevel 1 exit /b 1\r\n@set\r\n", vcvarsall.display(), arch))?;
+    let output = runner.run(&CommandSpec::new("cmd").args(["/d", "/c"]).arg(&script).timeout(VCVARS_TIMEOUT));
+    let _ = fs::remove_file(&script);
+    let output = output?;
+    if !output.success() {
+        return Err(VcpkgFfError::CommandFailed { command: format!("{} {}", vcvarsall.display(), arch), message: output.to_string() });
+    }
+
+    let vars: Vec<(String, String)> = output.stdout
+        .lines()
+        .filter_map(|line| line.split_once('='))
+        .filter(|(name, value)| !name.is_empty() && env::var(name).ok().as_deref() != Some(*value))
+        .map(|(name, value)| (name.to_string(), value.to_string()))
+        .collect();
+    if !vars.iter().any(|(name, _)| name.eq_ignore_ascii_case(DEVELOPER_PROMPT_VARIABLE)) {
+        return Err(VcpkgFfError::CommandFailed {
+            command: format!("{} {}", vcvarsall.display(), arch),
+            message: "it did not set up the developer environment".to_string(),
+        });
+    }
+    Ok(Some(MsvcEnvironment { vcvarsall, arch, vars }))
+}
+
+/// Where the Visual Studio installer puts vswhere.exe
+fn vswhere_path() -> PathBuf {
+    let program_files = env::var_os("ProgramFiles(x86)").map(PathBuf::from).unwrap_or_else(|| PathBuf::from(r"C:\Program Files (x86)"));
+    program_files.join("Microsoft Visual Studio").join("Installer").join("vswhere.exe")
+}
+
+/// vcvarsall.bat of the newest Visual Studio with the C++ tools, located with vswhere
+fn find_vcvarsall(runner: &dyn CommandRunner) -> Result<PathBuf, VcpkgFfError> {
+    let vswhere = vswhere_path();
+    if !vswhere.is_file() {
+        return Err(VcpkgFfError::ToolNotFound { tool: "vswhere.exe", hint: "install Visual Studio or the Build Tools with the C++ workload" });
+    }
+    let command = CommandSpec::new(&vswhere)
+        .args(["-latest", "-products", "*", "-requires", "Microsoft.VisualStudio.Component.VC.Tools.x86.x64", "-property", "installationPath"])
+        .timeout(VCVARS_TIMEOUT);
+    let output = runner.run(&command)?;
+    let installation = output.stdout.lines().next().map(str::trim).unwrap_or_default();
+    if !output.success() || installation.is_empty() {
+        return Err(VcpkgFfError::ToolNotFound {
+            tool: "Visual Studio with the C++ tools",
+            hint: "add the \"Desktop development with C++\" workload",
+        });
+    }
+    let vcvarsall = Path::new(installation).join("VC").join("Auxiliary").join("Build").join("vcvarsall.bat");
+    if !vcvarsall.is_file() {
+        return Err(VcpkgFfError::ToolNotFound { tool: "vcvarsall.bat", hint: "repair the Visual Studio installation" });
+    }
+    Ok(vcvarsall)
+}
+
+/// vcvarsall.bat argument for building `triplet` on this machine: the target, prefixed by the host when they differ
+fn vcvars_arch(triplet: &str) -> Result<String, VcpkgFfError> {
+    let target = match triplet.split('-').next() {
+        Some(arch @ ("x64" | "x86" | "arm64" | "arm")) => arch,
+        _ => return Err(VcpkgFfError::Unsupported { by: "Visual C++", kind: "triplet", name: triplet.to_string() }),
+    };
+    let host = match env::consts::ARCH {
+        "aarch64" => "arm64",
+        "x86" => "x86",
+        _ => "x64",
+    };
+    Ok(if host == target { host.to_string() } else { format!("{}_{}", host, target) })
+}
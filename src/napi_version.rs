@@ -0,0 +1,97 @@
+use crate::c_lexer::{self, TokenKind};
+
+/// Newest N-API version the `--napi-version` option accepts
+pub const LATEST_NAPI_VERSION: u32 = 10;
+
+/// N-API version addons generated with `--bun` target at most, the newest one Bun's implementation provides
+pub const BUN_NAPI_VERSION: u32 = 8;
+
+/// N-API functions newer than version 1, with the version that introduced them
+const NAPI_FUNCTION_VERSIONS: &[(&str, u32)] = &[
+    ("napi_get_uv_event_loop", 2),
+    ("napi_fatal_exception", 3),
+    ("napi_add_env_cleanup_hook", 3),
+    ("napi_remove_env_cleanup_hook", 3),
+    ("napi_open_callback_scope", 3),
+    ("napi_close_callback_scope", 3),
+    ("napi_create_threadsafe_function", 4),
+    ("napi_get_threadsafe_function_context", 4),
+    ("napi_call_threadsafe_function", 4),
+    ("napi_acquire_threadsafe_function", 4),
+    ("napi_release_threadsafe_function", 4),
+    ("napi_ref_threadsafe_function", 4),
+    ("napi_unref_threadsafe_function", 4),
+    ("napi_create_date", 5),
+    ("napi_is_date", 5),
+    ("napi_get_date_value", 5),
+    ("napi_add_finalizer", 5),
+    ("napi_create_bigint_int64", 6),
+    ("napi_create_bigint_uint64", 6),
+    ("napi_create_bigint_words", 6),
+    ("napi_get_value_bigint_int64", 6),
+    ("napi_get_value_bigint_uint64", 6),
+    ("napi_get_value_bigint_words", 6),
+    ("napi_get_all_property_names", 6),
+    ("napi_set_instance_data", 6),
+    ("napi_get_instance_data", 6),
+    ("napi_detach_arraybuffer", 7),
+    ("napi_is_detached_arraybuffer", 7),
+    ("napi_add_async_cleanup_hook", 8),
+    ("napi_remove_async_cleanup_hook", 8),
+    ("napi_object_freeze", 8),
+    ("napi_object_seal", 8),
+    ("napi_type_tag_object", 8),
+    ("napi_check_object_type_tag", 8),
+    ("node_api_symbol_for", 9),
+    ("node_api_get_module_file_name", 9),
+    ("node_api_create_syntax_error", 9),
+    ("node_api_throw_syntax_error", 9),
+    ("node_api_create_external_string_latin1", 10),
+    ("node_api_create_external_string_utf16", 10),
+    ("node_api_create_property_key_utf16", 10),
+];
+
+/// Parse an `--napi-version` value
+pub fn parse(value: &str) -> Result<u32, String> {
+    match value.parse::<u32>() {
+        Ok(version) if (1..=LATEST_NAPI_VERSION).contains(&version) => Ok(version),
+        _ => Err(format!("invalid --napi-version `{}`, expected 1 to {}", value, LATEST_NAPI_VERSION)),
+    }
+}
+
+/// Oldest Node.js release line providing N-API `version`, for package.json engines
+pub fn minimum_node(version: u32) -> &'static str {
+    match version {
+        0..=3 => ">=10.0.0",
+        4 => ">=10.16.0",
+        5 => ">=10.17.0",
+        6 => ">=10.20.0",
+        7 => ">=12.19.0",
+        8 => ">=12.22.0",
+        9 => ">=18.17.0",
+        _ => ">=22.14.0",
+    }
+}
+
+/// N-API calls in `src` that need a newer version than `target`, as (function, version, line)
+pub fn newer_calls(src: &str, target: u32) -> Vec<(&'static str, u32, usize)> {
+    let tokens = c_lexer::tokenize(src);
+    let mut calls = Vec::new();
+
+    for (index, token) in tokens.iter().enumerate() {
+        if token.kind != TokenKind::Identifier {
+            continue;
+        }
+        let name = &src[token.start..token.end];
+        let Some((function, version)) = NAPI_FUNCTION_VERSIONS.iter().find(|(function, _)| *function == name) else {
+            continue;
+        };
+        let is_call = tokens.get(index + 1).is_some_and(|next| &src[next.start..next.end] == "(");
+        if is_call && *version > target {
+            let line = src[..token.start].matches('\n').count() + 1;
+            calls.push((*function, *version, line));
+        }
+    }
+
+    calls
+}
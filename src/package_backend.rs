@@ -0,0 +1,100 @@
+use std::path::PathBuf;
+
+use crate::error::VcpkgFfError;
+use crate::vcpkg_manager::VcpkgManager;
+
+/// Where the extract-ffmpeg step takes the ffmpeg sources from
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub enum FfmpegSource {
+    /// A tar.gz with one top-level directory, as vcpkg downloads it
+    Archive(PathBuf),
+    /// An unpacked source tree, e.g. the source folder of Conan's cache
+    Directory(PathBuf),
+}
+
+/// Acquires the ffmpeg libraries and sources for the install-vcpkg, fetch-packages, install-packages and extract-ffmpeg steps
+///
+/// Every backend lays the libraries out like vcpkg, include/, lib/, debug/lib/ and lib/pkgconfig/ in
+/// `<vcpkg root>/installed/<triplet>`, so that the generated addon and its build files don't depend on the backend.
+/// [`VcpkgManager`] is the default, [`ConanBackend`](crate::ConanBackend) the alternative for Conan shops.
+pub trait PackageBackend: Send + Sync {
+    /// Short name for messages, "vcpkg" or "conan"
+    fn name(&self) -> &'static str;
+
+    /// Whether the package manager itself is ready to install packages
+    fn is_tool_installed(&self) -> bool;
+
+    /// Install or set up the package manager (install-vcpkg)
+    fn install_tool(&self) -> Result<(), VcpkgFfError>;
+
+    /// What `install_tool` depends on, recorded in the stamp of install-vcpkg so that the step runs again when it changes
+    fn tool_inputs(&self) -> String;
+
+    /// Undo what a failed `install_tool` left behind
+    fn remove_partial_tool_install(&self) -> Result<(), VcpkgFfError> {
+        Ok(())
+    }
+
+    /// Whether ffmpeg is installed with every required feature
+    fn is_ffmpeg_installed(&self) -> bool;
+
+    /// Download what `install_packages` needs without building anything (fetch-packages), for backends that can.
+    /// The others download during `install_packages`
+    fn fetch_packages(&self) -> Result<(), VcpkgFfError> {
+        Ok(())
+    }
+
+    /// Install ffmpeg with the required features (install-packages)
+    fn install_packages(&self) -> Result<(), VcpkgFfError>;
+
+    /// What `install_packages` depends on, the package and its features, for the stamp of install-packages
+    fn package_inputs(&self) -> String;
+
+    /// The ffmpeg sources matching the installed libraries, present once `install_packages` ran
+    fn ffmpeg_source(&self) -> Result<FfmpegSource, VcpkgFfError>;
+}
+
+impl PackageBackend for VcpkgManager {
+    fn name(&self) -> &'static str {
+        "vcpkg"
+    }
+
+    fn is_tool_installed(&self) -> bool {
+        self.is_installed()
+    }
+
+    fn install_tool(&self) -> Result<(), VcpkgFfError> {
+        self.install_vcpkg()
+    }
+
+    /// The clone's location and the mirrors it comes from, at their default branch
+    fn tool_inputs(&self) -> String {
+        format!("{} from {} at HEAD", self.get_vcpkg_root().display(), self.get_mirrors().join(", "))
+    }
+
+    fn remove_partial_tool_install(&self) -> Result<(), VcpkgFfError> {
+        self.remove_partial_install()
+    }
+
+    fn is_ffmpeg_installed(&self) -> bool {
+        VcpkgManager::is_ffmpeg_installed(self)
+    }
+
+    fn fetch_packages(&self) -> Result<(), VcpkgFfError> {
+        VcpkgManager::fetch_packages(self)
+    }
+
+    fn install_packages(&self) -> Result<(), VcpkgFfError> {
+        VcpkgManager::install_packages(self)
+    }
+
+    fn package_inputs(&self) -> String {
+        format!("ffmpeg[{}]:{} in {}", self.get_features().join(","), self.get_triplet(), self.get_vcpkg_root().display())
+    }
+
+    fn ffmpeg_source(&self) -> Result<FfmpegSource, VcpkgFfError> {
+        self.find_ffmpeg_archive()
+            .map(FfmpegSource::Archive)
+            .ok_or_else(|| VcpkgFfError::ArchiveNotFound(self.get_vcpkg_root().join("downloads")))
+    }
+}
@@ -0,0 +1,536 @@
+use std::cell::OnceCell;
+use std::fmt;
+use std::ops::Range;
+
+use regex::Regex;
+
+use crate::c_lexer::{self, SignatureMatch, Token};
+use crate::error::VcpkgFfError;
+use crate::progress::say;
+
+/// A single typed source modification
+#[derive(Debug, Clone)]
+pub enum PatchRule {
+    /// Drop the `static` qualifier from a function so it can be called from other translation units
+    MakeNonStatic { signature: String },
+    /// Remove a whole function definition (signature + body), leaving a comment in its place
+    RemoveFunction { signature: String, replacement: String },
+    /// Insert text on the line following an `#include` directive
+    InsertAfterInclude { include: String, text: String },
+    /// Append a block to the end of the file, `marker` is used to detect an existing copy
+    AppendBlock { marker: String, block: String },
+    /// Replace every occurrence of `from` with `to`
+    ReplaceText { from: String, to: String },
+    /// Wrap a single line in `#if <condition>` / `#endif`
+    WrapInConditional { line: String, condition: String },
+    /// Replace every match of a regular expression (`$1`-style capture references allowed)
+    RegexReplace { pattern: String, replacement: String },
+    /// Set `#define <name> <value>`, replacing an existing definition or adding one before the last `#endif`
+    SetDefine { name: String, value: String },
+}
+
+impl PatchRule {
+    pub fn make_non_static(signature: &str) -> Self {
+        PatchRule::MakeNonStatic { signature: signature.to_string() }
+    }
+
+    pub fn remove_function(signature: &str, replacement: &str) -> Self {
+        PatchRule::RemoveFunction {
+            signature: signature.to_string(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    pub fn insert_after_include(include: &str, text: &str) -> Self {
+        PatchRule::InsertAfterInclude {
+            include: include.to_string(),
+            text: text.to_string(),
+        }
+    }
+
+    pub fn append_block(marker: &str, block: &str) -> Self {
+        PatchRule::AppendBlock {
+            marker: marker.to_string(),
+            block: block.to_string(),
+        }
+    }
+
+    pub fn replace_text(from: &str, to: &str) -> Self {
+        PatchRule::ReplaceText {
+            from: from.to_string(),
+            to: to.to_string(),
+        }
+    }
+
+    pub fn wrap_in_conditional(line: &str, condition: &str) -> Self {
+        PatchRule::WrapInConditional {
+            line: line.to_string(),
+            condition: condition.to_string(),
+        }
+    }
+
+    pub fn regex_replace(pattern: &str, replacement: &str) -> Self {
+        PatchRule::RegexReplace {
+            pattern: pattern.to_string(),
+            replacement: replacement.to_string(),
+        }
+    }
+
+    pub fn set_define(name: &str, value: &str) -> Self {
+        PatchRule::SetDefine {
+            name: name.to_string(),
+            value: value.to_string(),
+        }
+    }
+
+    /// The edits that apply the rule to `document`, and the outcome. Fails for a regular expression that doesn't
+    /// compile
+    fn edits(&self, document: &Document) -> Result<(Vec<Edit>, PatchOutcome), VcpkgFfError> {
+        Ok(match self {
+            PatchRule::MakeNonStatic { signature } => {
+                let matches = document.find_signature(signature);
+                if matches.is_empty() {
+                    return Ok((Vec::new(), PatchOutcome::NotFound));
+                }
+                let edits: Vec<Edit> = matches.iter()
+                    .filter_map(|m| m.static_range)
+                    .map(|(start, end)| Edit::new(start..end, ""))
+                    .collect();
+                let outcome = if edits.is_empty() { PatchOutcome::AlreadyApplied } else { PatchOutcome::Applied };
+                (edits, outcome)
+            }
+            PatchRule::RemoveFunction { signature, replacement } => {
+                let definition = document.find_signature(signature)
+                    .into_iter()
+                    .find_map(|m| m.body_end.map(|end| (m.start, end)));
+                let content = document.text();
+
+                match definition {
+                    Some((start, end)) => {
+                        // 去掉函数前后的空白，与替换文本衔接
+                        let start = content[..start].trim_end().len();
+                        let end = content.len() - content[end..].trim_start().len();
+                        (vec![Edit::new(start..end, format!("{}\n", replacement))], PatchOutcome::Applied)
+                    }
+                    None if content.contains(replacement.as_str()) => (Vec::new(), PatchOutcome::AlreadyApplied),
+                    None => (Vec::new(), PatchOutcome::NotFound),
+                }
+            }
+            PatchRule::InsertAfterInclude { include, text } => {
+                let content = document.text();
+                if content.contains(text.as_str()) {
+                    return Ok((Vec::new(), PatchOutcome::AlreadyApplied));
+                }
+                let Some(pos) = content.find(include.as_str()) else {
+                    return Ok((Vec::new(), PatchOutcome::NotFound));
+                };
+                let after_include = pos + include.len();
+                let line_end = content[after_include..]
+                    .find('\n')
+                    .map(|offset| after_include + offset)
+                    .unwrap_or(content.len());
+                (vec![Edit::new(line_end..line_end, format!("\n{}", text))], PatchOutcome::Applied)
+            }
+            PatchRule::AppendBlock { marker, block } => {
+                let content = document.text();
+                if content.contains(marker.as_str()) {
+                    (Vec::new(), PatchOutcome::AlreadyApplied)
+                } else {
+                    (vec![Edit::new(content.len()..content.len(), block.as_str())], PatchOutcome::Applied)
+                }
+            }
+            PatchRule::ReplaceText { from, to } => {
+                let content = document.text();
+                let edits: Vec<Edit> = content.match_indices(from.as_str())
+                    .map(|(pos, _)| Edit::new(pos..pos + from.len(), to.as_str()))
+                    .collect();
+                if !edits.is_empty() {
+                    (edits, PatchOutcome::Applied)
+                } else if content.contains(to.as_str()) {
+                    (edits, PatchOutcome::AlreadyApplied)
+                } else {
+                    (edits, PatchOutcome::NotFound)
+                }
+            }
+            PatchRule::WrapInConditional { line, condition } => {
+                let content = document.text();
+                let wrapped = format!("#if {}\n{}\n#endif", condition, line);
+                if content.contains(&wrapped) {
+                    (Vec::new(), PatchOutcome::AlreadyApplied)
+                } else if let Some(pos) = content.find(line.as_str()) {
+                    (vec![Edit::new(pos..pos + line.len(), wrapped)], PatchOutcome::Applied)
+                } else {
+                    (Vec::new(), PatchOutcome::NotFound)
+                }
+            }
+            PatchRule::RegexReplace { pattern, replacement } => {
+                let content = document.text();
+                let regex = Regex::new(pattern).map_err(|e| VcpkgFfError::InvalidPatchRule {
+                    rule: self.to_string(),
+                    message: e.to_string(),
+                })?;
+                let edits: Vec<Edit> = regex.captures_iter(content)
+                    .map(|captures| {
+                        let mut expanded = String::new();
+                        captures.expand(replacement, &mut expanded);
+                        Edit::new(captures.get(0).expect("group 0 always matches").range(), expanded)
+                    })
+                    .collect();
+                let outcome = if edits.is_empty() { PatchOutcome::NotFound } else { PatchOutcome::Applied };
+                (edits, outcome)
+            }
+            PatchRule::SetDefine { name, value } => {
+                let content = document.text();
+                let definition = format!("#define {} {}", name, value);
+                let pattern = format!(r"(?m)^[ \t]*#[ \t]*define[ \t]+{}\b.*$", regex::escape(name));
+                let regex = Regex::new(&pattern).expect("escaped define pattern is valid");
+
+                if let Some(existing) = regex.find(content) {
+                    if existing.as_str() == definition {
+                        return Ok((Vec::new(), PatchOutcome::AlreadyApplied));
+                    }
+                    return Ok((vec![Edit::new(existing.range(), definition)], PatchOutcome::Applied));
+                }
+
+                // 没有现成的定义时加在头文件保护的 #endif 之前
+                let insert_at = content
+                    .rfind("\n#endif")
+                    .map(|pos| pos + 1)
+                    .unwrap_or(content.len());
+                let separator = if insert_at == 0 || content[..insert_at].ends_with('\n') { "" } else { "\n" };
+                (vec![Edit::new(insert_at..insert_at, format!("{}{}\n", separator, definition))], PatchOutcome::Applied)
+            }
+        })
+    }
+}
+
+/// Replacement of a byte range of the document
+struct Edit {
+    range: Range<usize>,
+    text: String,
+}
+
+impl Edit {
+    fn new(range: Range<usize>, text: impl Into<String>) -> Self {
+        Self { range, text: text.into() }
+    }
+
+    /// Whether both edits change the same bytes. An insertion overlaps a replacement only strictly inside its range,
+    /// at either end of it they are adjacent
+    fn overlaps(&self, other: &Edit) -> bool {
+        self.range.start < other.range.end && other.range.start < self.range.end
+    }
+}
+
+/// The content being patched, with its C tokens computed once when a rule first needs them
+struct Document<'a> {
+    text: &'a str,
+    code: OnceCell<Vec<(Token, i32)>>,
+}
+
+impl<'a> Document<'a> {
+    fn new(text: &'a str) -> Self {
+        Self { text, code: OnceCell::new() }
+    }
+
+    fn text(&self) -> &'a str {
+        self.text
+    }
+
+    fn find_signature(&self, signature: &str) -> Vec<SignatureMatch> {
+        let code = self.code.get_or_init(|| c_lexer::code_tokens(self.text));
+        c_lexer::find_signature_in(self.text, code, signature)
+    }
+
+    /// The content with the non-overlapping `edits` applied, in one pass. Edits at the same position keep the order
+    /// they were collected in, an insertion comes before a replacement starting where it is inserted
+    fn splice(&self, mut edits: Vec<Edit>) -> String {
+        edits.sort_by_key(|edit| (edit.range.start, edit.range.end));
+        let removed: usize = edits.iter().map(|edit| edit.range.len()).sum();
+        let added: usize = edits.iter().map(|edit| edit.text.len()).sum();
+        let mut result = String::with_capacity(self.text.len() - removed + added);
+        let mut position = 0;
+        for edit in &edits {
+            result.push_str(&self.text[position..edit.range.start]);
+            result.push_str(&edit.text);
+            position = edit.range.end;
+        }
+        result.push_str(&self.text[position..]);
+        result
+    }
+}
+
+impl fmt::Display for PatchRule {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            PatchRule::MakeNonStatic { signature } => write!(f, "make-non-static `{}`", signature),
+            PatchRule::RemoveFunction { signature, .. } => write!(f, "remove-function `{}`", signature),
+            PatchRule::InsertAfterInclude { include, .. } => write!(f, "insert-after-include `{}`", include),
+            PatchRule::AppendBlock { marker, .. } => write!(f, "append-block `{}`", marker),
+            PatchRule::ReplaceText { from, .. } => write!(f, "replace-text `{}`", from),
+            PatchRule::WrapInConditional { condition, .. } => write!(f, "wrap-in-conditional `{}`", condition),
+            PatchRule::RegexReplace { pattern, .. } => write!(f, "regex `{}`", pattern),
+            PatchRule::SetDefine { name, value } => write!(f, "set-define `{} {}`", name, value),
+        }
+    }
+}
+
+/// Result of applying a single rule
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum PatchOutcome {
+    Applied,
+    AlreadyApplied,
+    NotFound,
+}
+
+/// Per-rule results of a PatchEngine run
+#[derive(Debug, Default)]
+pub struct PatchReport {
+    pub results: Vec<(String, PatchOutcome)>,
+}
+
+impl PatchReport {
+    /// True if every rule was applied or was already present
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|(_, outcome)| *outcome != PatchOutcome::NotFound)
+    }
+
+    /// Names of the rules that could not be applied
+    pub fn failed_rules(&self) -> Vec<&str> {
+        self.results
+            .iter()
+            .filter(|(_, outcome)| *outcome == PatchOutcome::NotFound)
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+
+    /// Print one line per rule
+    pub fn print(&self, file_name: &str) {
+        for (name, outcome) in &self.results {
+            match outcome {
+                PatchOutcome::Applied => say!("  ✓ {}: {}", file_name, name),
+                PatchOutcome::AlreadyApplied => say!("  ✓ {}: {} (already applied)", file_name, name),
+                PatchOutcome::NotFound => say!("  ⚠ {}: {} (pattern not found)", file_name, name),
+            }
+        }
+    }
+}
+
+/// Applies an ordered list of PatchRules to a source file's content
+pub struct PatchEngine {
+    rules: Vec<PatchRule>,
+}
+
+impl PatchEngine {
+    pub fn new(rules: Vec<PatchRule>) -> Self {
+        Self { rules }
+    }
+
+    /// Apply all rules, returning the patched content and a per-rule report
+    ///
+    /// The rules are matched in order against the content and one token stream of it, and their edits are spliced in
+    /// at once. A rule that only finds its text once an earlier rule's edits are in, or whose edits overlap an earlier
+    /// rule's, ends the pass: the edits so far are spliced in and matching goes on from that rule against the result,
+    /// so each rule sees the work of exactly the rules before it. Fails for a rule that can't be used, such as a
+    /// regular expression that doesn't compile.
+    pub fn apply(&self, content: &str) -> Result<(String, PatchReport), VcpkgFfError> {
+        let mut outcomes = vec![PatchOutcome::NotFound; self.rules.len()];
+        let mut next = 0;
+        let mut text = content.to_string();
+
+        while next < self.rules.len() {
+            let document = Document::new(&text);
+            let mut accepted: Vec<Edit> = Vec::new();
+            while let Some(rule) = self.rules.get(next) {
+                let (edits, outcome) = rule.edits(&document)?;
+                // 前面的规则改动过内容时，找不到的文本可能正是它们生成的；后面的规则要等它先应用
+                let needs_earlier = outcome == PatchOutcome::NotFound && !accepted.is_empty();
+                if needs_earlier || edits.iter().any(|edit| accepted.iter().any(|other| edit.overlaps(other))) {
+                    break;
+                }
+                outcomes[next] = outcome;
+                accepted.extend(edits);
+                next += 1;
+            }
+            if !accepted.is_empty() {
+                text = document.splice(accepted);
+            }
+        }
+
+        let report = PatchReport {
+            results: self.rules.iter().zip(outcomes).map(|(rule, outcome)| (rule.to_string(), outcome)).collect(),
+        };
+        Ok((text, report))
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const SOURCE: &str = "#include \"config.h\"\n#include \"ffmpeg.h\"\n\nstatic int transcode(Scheduler *sch)\n{\n    if (sch) { return 1; }\n    return 0;\n}\n\nint main(int argc, char **argv)\n{\n    return transcode(NULL);\n}\n";
+
+    /// Apply `rule` alone, returning the content and its outcome
+    fn apply(rule: PatchRule, content: &str) -> (String, PatchOutcome) {
+        let (patched, report) = PatchEngine::new(vec![rule]).apply(content).unwrap();
+        (patched, report.results[0].1)
+    }
+
+    #[test]
+    fn make_non_static() {
+        let (patched, outcome) = apply(PatchRule::make_non_static("int transcode("), SOURCE);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert!(patched.contains("\nint transcode(Scheduler *sch)\n"));
+        assert_eq!(apply(PatchRule::make_non_static("int transcode("), &patched), (patched.clone(), PatchOutcome::AlreadyApplied));
+        assert_eq!(apply(PatchRule::make_non_static("int encode("), SOURCE).1, PatchOutcome::NotFound);
+    }
+
+    #[test]
+    fn remove_function() {
+        let rule = || PatchRule::remove_function("int main(int argc, char **argv)", "\n\n/* main removed */");
+        let (patched, outcome) = apply(rule(), SOURCE);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert!(patched.ends_with("    return 0;\n}\n\n/* main removed */\n"), "{}", patched);
+        assert!(!patched.contains("main(int"));
+        assert_eq!(apply(rule(), &patched), (patched.clone(), PatchOutcome::AlreadyApplied));
+        assert_eq!(apply(PatchRule::remove_function("int probe(void)", "/* gone */"), SOURCE).1, PatchOutcome::NotFound);
+    }
+
+    #[test]
+    fn insert_after_include() {
+        let rule = || PatchRule::insert_after_include("#include \"config.h\"", "#include \"ffmpeg_run.h\"");
+        let (patched, outcome) = apply(rule(), SOURCE);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert!(patched.starts_with("#include \"config.h\"\n#include \"ffmpeg_run.h\"\n#include \"ffmpeg.h\"\n"));
+        assert_eq!(apply(rule(), &patched), (patched.clone(), PatchOutcome::AlreadyApplied));
+        assert_eq!(apply(PatchRule::insert_after_include("#include <stdio.h>", "x"), SOURCE).1, PatchOutcome::NotFound);
+    }
+
+    #[test]
+    fn append_block() {
+        let rule = || PatchRule::append_block("void ffmpeg_reset(void)", "\nvoid ffmpeg_reset(void)\n{\n}\n");
+        let (patched, outcome) = apply(rule(), SOURCE);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert!(patched.starts_with(SOURCE) && patched.ends_with("void ffmpeg_reset(void)\n{\n}\n"));
+        assert_eq!(apply(rule(), &patched), (patched.clone(), PatchOutcome::AlreadyApplied));
+    }
+
+    #[test]
+    fn replace_text() {
+        let rule = || PatchRule::replace_text("return 0;", "return -1;");
+        let (patched, outcome) = apply(rule(), SOURCE);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert!(patched.contains("return -1;") && !patched.contains("return 0;"));
+        assert_eq!(apply(rule(), &patched), (patched.clone(), PatchOutcome::AlreadyApplied));
+        assert_eq!(apply(PatchRule::replace_text("exit_program", "ffmpeg_exit"), SOURCE).1, PatchOutcome::NotFound);
+    }
+
+    #[test]
+    fn replace_text_replaces_every_occurrence() {
+        let (patched, _) = apply(PatchRule::replace_text("sch", "scheduler"), SOURCE);
+        assert!(patched.contains("Scheduler *scheduler)") && patched.contains("if (scheduler)"));
+    }
+
+    #[test]
+    fn wrap_in_conditional() {
+        let rule = || PatchRule::wrap_in_conditional("    return transcode(NULL);", "CONFIG_TRANSCODE");
+        let (patched, outcome) = apply(rule(), SOURCE);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert!(patched.contains("{\n#if CONFIG_TRANSCODE\n    return transcode(NULL);\n#endif\n}"), "{}", patched);
+        assert_eq!(apply(rule(), &patched), (patched.clone(), PatchOutcome::AlreadyApplied));
+        assert_eq!(apply(PatchRule::wrap_in_conditional("    exit(1);", "X"), SOURCE).1, PatchOutcome::NotFound);
+    }
+
+    #[test]
+    fn regex_replace() {
+        let (patched, outcome) = apply(PatchRule::regex_replace(r"return (-?\d+);", "return ffmpeg_ret($1);"), SOURCE);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert!(patched.contains("{ return ffmpeg_ret(1); }") && patched.contains("return ffmpeg_ret(0);"));
+        assert_eq!(apply(PatchRule::regex_replace(r"exit_program\(\d+\)", "x"), SOURCE).1, PatchOutcome::NotFound);
+    }
+
+    #[test]
+    fn set_define() {
+        let header = "#ifndef CONFIG_H\n#define CONFIG_H\n#define CONFIG_POSTPROC 1\n#endif\n";
+        let (patched, outcome) = apply(PatchRule::set_define("CONFIG_POSTPROC", "0"), header);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert_eq!(patched, "#ifndef CONFIG_H\n#define CONFIG_H\n#define CONFIG_POSTPROC 0\n#endif\n");
+        assert_eq!(apply(PatchRule::set_define("CONFIG_POSTPROC", "0"), &patched), (patched.clone(), PatchOutcome::AlreadyApplied));
+
+        let (added, outcome) = apply(PatchRule::set_define("HAVE_NEON", "1"), header);
+        assert_eq!(outcome, PatchOutcome::Applied);
+        assert!(added.ends_with("#define CONFIG_POSTPROC 1\n#define HAVE_NEON 1\n#endif\n"), "{}", added);
+    }
+
+    #[test]
+    fn rules_see_the_result_of_the_previous_ones() {
+        let engine = PatchEngine::new(vec![
+            PatchRule::replace_text("#include \"ffmpeg.h\"", "#include \"compat/ffmpeg.h\""),
+            PatchRule::insert_after_include("#include \"compat/ffmpeg.h\"", "#define COMPAT 1"),
+            PatchRule::make_non_static("int transcode(Scheduler *sch)"),
+            PatchRule::make_non_static("int encode(void)"),
+        ]);
+
+        let (patched, report) = engine.apply(SOURCE).unwrap();
+
+        assert!(patched.starts_with("#include \"config.h\"\n#include \"compat/ffmpeg.h\"\n#define COMPAT 1\n\nint transcode("), "{}", patched);
+        let outcomes: Vec<PatchOutcome> = report.results.iter().map(|(_, outcome)| *outcome).collect();
+        assert_eq!(outcomes, [PatchOutcome::Applied, PatchOutcome::Applied, PatchOutcome::Applied, PatchOutcome::NotFound]);
+        assert!(!report.is_success());
+        assert_eq!(report.failed_rules(), ["make-non-static `int encode(void)`"]);
+    }
+
+    #[test]
+    fn overlapping_edits_apply_in_rule_order() {
+        let engine = PatchEngine::new(vec![
+            PatchRule::replace_text("return transcode(NULL);", "return transcode(ffmpeg_scheduler());"),
+            PatchRule::replace_text("transcode(NULL)", "transcode(0)"),
+            PatchRule::regex_replace(r"ffmpeg_scheduler\(\)", "ffmpeg_scheduler(1)"),
+        ]);
+
+        let (patched, report) = engine.apply(SOURCE).unwrap();
+
+        // 第二条规则与第一条重叠，对第一条的结果已经找不到文本
+        assert!(patched.contains("    return transcode(ffmpeg_scheduler(1));\n"), "{}", patched);
+        let outcomes: Vec<PatchOutcome> = report.results.iter().map(|(_, outcome)| *outcome).collect();
+        assert_eq!(outcomes, [PatchOutcome::Applied, PatchOutcome::NotFound, PatchOutcome::Applied]);
+    }
+
+    #[test]
+    fn adjacent_edits_keep_their_order() {
+        let engine = PatchEngine::new(vec![
+            PatchRule::insert_after_include("#include \"config.h\"", "#include \"first.h\""),
+            PatchRule::insert_after_include("#include \"config.h\"", "#include \"second.h\""),
+            PatchRule::replace_text("#include \"config.h\"", "#include \"addon_config.h\""),
+            PatchRule::replace_text("\n#include \"ffmpeg.h\"", "\n#include \"ffmpeg_addon.h\""),
+        ]);
+
+        let (patched, report) = engine.apply(SOURCE).unwrap();
+
+        assert!(patched.starts_with("#include \"addon_config.h\"\n#include \"first.h\"\n#include \"second.h\"\n#include \"ffmpeg_addon.h\"\n"), "{}", patched);
+        assert!(report.is_success());
+    }
+
+    #[test]
+    fn later_rules_wait_for_a_rule_matched_against_the_result() {
+        let engine = PatchEngine::new(vec![
+            PatchRule::replace_text("return 0;", "return -1;"),
+            PatchRule::replace_text("{ return 2; }", "{ return 3; }"),
+            PatchRule::replace_text("return 1;", "return 2;"),
+        ]);
+
+        let (patched, report) = engine.apply(SOURCE).unwrap();
+
+        // 第三条规则的改动会让第二条找到文本，但第二条只能看到前面规则的结果
+        assert!(patched.contains("    if (sch) { return 2; }\n    return -1;\n"), "{}", patched);
+        let outcomes: Vec<PatchOutcome> = report.results.iter().map(|(_, outcome)| *outcome).collect();
+        assert_eq!(outcomes, [PatchOutcome::Applied, PatchOutcome::NotFound, PatchOutcome::Applied]);
+    }
+
+    #[test]
+    fn invalid_regex_is_an_error() {
+        let error = PatchEngine::new(vec![PatchRule::regex_replace("transcode(", "x")]).apply(SOURCE).unwrap_err();
+
+        assert!(matches!(error, VcpkgFfError::InvalidPatchRule { .. }), "{}", error);
+    }
+}
@@ -0,0 +1,610 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::PathBuf;
+use std::sync::Arc;
+
+use crate::addon_builder::{SmokeReport, SmokeRuntime};
+use crate::addon_preparer::AddonPreparer;
+use crate::bench::{BenchHistory, BenchRun, StepMeter, StepSample};
+use crate::command_runner;
+use crate::error::VcpkgFfError;
+use crate::fs_retry;
+use crate::hooks::run_hook;
+use crate::journal::{self, Action, Journal};
+use crate::package_backend::PackageBackend;
+use crate::plugins::PluginStep;
+use crate::progress::{self, ConsoleObserver, ProgressObserver, SkipReason, StepStatus};
+use crate::shared_cache::SharedCache;
+use crate::steps::{BuildAddon, CopyCliTools, Dedupe, ExtractFfmpeg, FetchPackages, InstallPackages, InstallVcpkg, IntegrateVcpkg, PrepareAddon};
+use crate::tool_config::{self, HookConfig, PluginConfig};
+use crate::trace;
+use crate::vcpkg_manager::VcpkgManager;
+
+/// One unit of the install, run in order by [`Pipeline`]
+pub trait Step {
+    /// Short kebab-case name used in the log, the checkpoint and `--skip`
+    fn name(&self) -> &str;
+
+    /// Whether the work of this step is already present, so that running it can be skipped
+    fn check(&self, _ctx: &PipelineContext) -> bool {
+        false
+    }
+
+    /// Hash of what the work of this step depends on, recorded in its stamp after it ran
+    ///
+    /// A step with inputs is skipped only while its stamp holds the same hash and `check` still finds the work,
+    /// so it runs again exactly when an input changed or the work was removed. Steps without inputs rely on `check` alone.
+    fn inputs(&self, _ctx: &PipelineContext) -> Result<Option<String>, VcpkgFfError> {
+        Ok(None)
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError>;
+
+    /// Undo what a failed `run` left behind, so that the next attempt starts clean
+    fn rollback(&self, _ctx: &PipelineContext) -> Result<(), VcpkgFfError> {
+        Ok(())
+    }
+}
+
+/// State shared by the steps of a pipeline
+pub struct PipelineContext {
+    pub manager: VcpkgManager,
+    /// Installs the ffmpeg packages and provides the sources instead of `manager`, see [`Pipeline::with_backend`]
+    pub backend: Option<Arc<dyn PackageBackend>>,
+    pub preparer: AddonPreparer,
+    /// Runtime the build-addon step smoke tests the addon in
+    pub smoke_runtime: SmokeRuntime,
+    /// Set by the build-addon step
+    pub smoke_report: Option<SmokeReport>,
+    /// Programs copied by the copy-cli-tools step
+    pub cli_tools: Vec<PathBuf>,
+}
+
+impl PipelineContext {
+    /// The backend of the install steps, `manager` unless another one is set
+    pub fn packages(&self) -> &dyn PackageBackend {
+        match &self.backend {
+            Some(backend) => backend.as_ref(),
+            None => &self.manager,
+        }
+    }
+
+    /// The paths, triplet and smoke report handed to plugins, `step` being the plugin's step name
+    pub fn to_json(&self, step: &str) -> serde_json::Value {
+        let smoke_report = self.smoke_report.as_ref().map(|report| serde_json::json!({
+            "passed": report.passed(),
+            "loaded": report.loaded,
+            "transcoded": report.transcoded,
+            "diagnosis": report.diagnosis,
+            "detail": report.detail,
+        }));
+        serde_json::json!({
+            "step": step,
+            "backend": self.packages().name(),
+            "base_dir": self.preparer.get_base_dir(),
+            "addon_src_dir": self.preparer.get_addon_src_dir(),
+            "log_dir": self.preparer.get_log_dir(),
+            "vcpkg_root": self.manager.get_vcpkg_root(),
+            "vcpkg_exe": self.manager.get_vcpkg_exe(),
+            "triplet": self.manager.get_triplet(),
+            "ffmpeg_dir": self.manager.is_ffmpeg_extracted(),
+            "smoke_runtime": self.smoke_runtime.program(),
+            "smoke_report": smoke_report,
+            "cli_tools": self.cli_tools,
+        })
+    }
+}
+
+/// The full install: vcpkg, the ffmpeg packages, the ffmpeg source and the generated addon
+///
+/// Each step is reported to the [`ProgressObserver`] with its position, skipped when its stamp in .vcpkg_ff/stamps/
+/// matches its [`Step::inputs`] and its `check` says the work is done, or when it was skipped explicitly, and rolled
+/// back when it fails. Completed steps are recorded in .vcpkg_ff/pipeline.checkpoint so that a failed run can be
+/// resumed after the last of them.
+///
+/// ```no_run
+/// use vcpkg_ff::{AddonPreparer, BindingStyle, Pipeline};
+///
+/// let outcome = Pipeline::new()
+///     .with_preparer(AddonPreparer::new().with_binding_style(BindingStyle::NapiRs))
+///     .with_build_addon(true)
+///     .run()?;
+/// println!("addon source: {}", outcome.addon_src_dir.display());
+/// # Ok::<(), vcpkg_ff::VcpkgFfError>(())
+/// ```
+pub struct Pipeline {
+    context: PipelineContext,
+    steps: Vec<Box<dyn Step>>,
+    /// Names of steps not to run
+    skip: Vec<String>,
+    /// Skip the steps recorded in the checkpoint of the previous run
+    resume: bool,
+    /// `[hooks]` commands by step name
+    hooks: BTreeMap<String, HookConfig>,
+    /// Where to write the Chrome trace of the run
+    trace: Option<PathBuf>,
+    observer: Arc<dyn ProgressObserver>,
+    /// Step timings of the current run, added to the [`BenchHistory`] when it ends
+    bench: BenchRun,
+}
+
+/// What a completed [`Pipeline::run`] produced
+#[derive(Debug)]
+pub struct PipelineOutcome {
+    /// Directory holding the generated addon sources
+    pub addon_src_dir: PathBuf,
+    /// Result of loading the built addon, present when the addon was built
+    pub smoke_report: Option<SmokeReport>,
+    /// ffmpeg and ffprobe programs copied out of vcpkg's tree, with `--cli-tools`
+    pub cli_tools: Vec<PathBuf>,
+}
+
+impl Pipeline {
+    /// install-vcpkg, fetch-packages, install-packages, extract-ffmpeg and prepare-addon
+    pub fn new() -> Self {
+        Self {
+            context: PipelineContext {
+                manager: VcpkgManager::new(),
+                backend: None,
+                preparer: AddonPreparer::new(),
+                smoke_runtime: SmokeRuntime::Node,
+                smoke_report: None,
+                cli_tools: Vec::new(),
+            },
+            steps: vec![
+                Box::new(InstallVcpkg),
+                Box::new(FetchPackages),
+                Box::new(InstallPackages),
+                Box::new(ExtractFfmpeg),
+                Box::new(PrepareAddon),
+            ],
+            skip: Vec::new(),
+            resume: false,
+            hooks: BTreeMap::new(),
+            trace: None,
+            observer: Arc::new(ConsoleObserver),
+            bench: BenchRun::new(BTreeMap::new()),
+        }
+    }
+
+    /// Use `manager` for the vcpkg install and ffmpeg extraction steps
+    pub fn with_manager(mut self, manager: VcpkgManager) -> Self {
+        self.context.manager = manager;
+        self
+    }
+
+    /// Install the ffmpeg packages and take the sources from `backend`, e.g. a [`ConanBackend`](crate::ConanBackend),
+    /// instead of vcpkg. The manager still extracts the sources to its output directory
+    pub fn with_backend(mut self, backend: impl PackageBackend + 'static) -> Self {
+        self.context.backend = Some(Arc::new(backend));
+        self
+    }
+
+    /// Use `preparer`, with its build system, binding style and targets, to generate the addon
+    pub fn with_preparer(mut self, preparer: AddonPreparer) -> Self {
+        self.context.preparer = preparer;
+        self
+    }
+
+    /// Append the build-addon step, compiling the generated addon with npm and smoke testing it
+    pub fn with_build_addon(self, build_addon: bool) -> Self {
+        if build_addon {
+            self.with_step(Box::new(BuildAddon))
+        } else {
+            self
+        }
+    }
+
+    /// Add the integrate-vcpkg step after install-packages, running `vcpkg integrate install`
+    pub fn with_integrate(mut self, integrate: bool) -> Self {
+        if integrate {
+            let position = self.steps.iter().position(|step| step.name() == "install-packages").map_or(self.steps.len(), |index| index + 1);
+            self.steps.insert(position, Box::new(IntegrateVcpkg));
+        }
+        self
+    }
+
+    /// Add the copy-cli-tools step after install-packages, copying vcpkg's ffmpeg and ffprobe programs
+    /// to bin/ of the output directory. The manager has to install them, see [`VcpkgManager::with_cli_tools`]
+    pub fn with_cli_tools(mut self, cli_tools: bool) -> Self {
+        if cli_tools {
+            let position = self.steps.iter().position(|step| step.name() == "install-packages").map_or(self.steps.len(), |index| index + 1);
+            self.steps.insert(position, Box::new(CopyCliTools));
+        }
+        self
+    }
+
+    /// Add the dedupe step after extract-ffmpeg, linking the installed tree and the ffmpeg sources to the shared
+    /// `cache` instead of keeping copies of their own, see [`SharedCache::from_env`]
+    pub fn with_shared_cache(mut self, cache: Option<SharedCache>) -> Self {
+        if let Some(cache) = cache {
+            let position = self.steps.iter().position(|step| step.name() == "extract-ffmpeg").map_or(self.steps.len(), |index| index + 1);
+            self.steps.insert(position, Box::new(Dedupe::new(cache)));
+        }
+        self
+    }
+
+    /// Smoke test the built addon in `runtime`, e.g. Bun for an addon generated with `--bun`
+    pub fn with_smoke_runtime(mut self, runtime: SmokeRuntime) -> Self {
+        self.context.smoke_runtime = runtime;
+        self
+    }
+
+    /// Append `step` after the existing steps
+    pub fn with_step(mut self, step: Box<dyn Step>) -> Self {
+        self.steps.push(step);
+        self
+    }
+
+    /// Add a [`PluginStep`] for each `[[plugins]]` entry, after the step it names or at the end
+    pub fn with_plugins(mut self, plugins: &[PluginConfig]) -> Result<Self, VcpkgFfError> {
+        for plugin in plugins {
+            let names = self.step_names();
+            if names.contains(&plugin.name.as_str()) {
+                return Err(self.config_error(format!("plugin `{}` has the name of an existing step", plugin.name)));
+            }
+            let position = match &plugin.after {
+                Some(after) => match names.iter().position(|name| name == after) {
+                    Some(index) => index + 1,
+                    None => return Err(self.config_error(format!("plugin `{}` runs after unknown step `{}`, expected one of: {}",
+                        plugin.name, after, names.join(", ")))),
+                },
+                None => self.steps.len(),
+            };
+            self.steps.insert(position, Box::new(PluginStep::new(plugin)?));
+        }
+        Ok(self)
+    }
+
+    /// Run the `[hooks]` commands before and after their steps; add plugins first, hooks can name them
+    pub fn with_hooks(mut self, hooks: BTreeMap<String, HookConfig>) -> Result<Self, VcpkgFfError> {
+        let names = self.step_names();
+        if let Some(unknown) = hooks.keys().find(|step| !names.contains(&step.as_str())) {
+            return Err(self.config_error(format!("[hooks] for unknown step `{}`, expected one of: {}", unknown, names.join(", "))));
+        }
+        self.hooks = hooks;
+        Ok(self)
+    }
+
+    /// Do not run the steps named in `names`
+    pub fn with_skip(mut self, names: Vec<String>) -> Self {
+        self.skip = names;
+        self
+    }
+
+    /// Skip the steps a previous, failed run completed (`--resume`)
+    pub fn with_resume(mut self, resume: bool) -> Self {
+        self.resume = resume;
+        self
+    }
+
+    /// Write the timing of every step and command to `path` as a Chrome trace (`--trace`)
+    pub fn with_trace(mut self, path: impl Into<PathBuf>) -> Self {
+        self.trace = Some(path.into());
+        self
+    }
+
+    /// Report progress and step output to `observer` instead of the console
+    pub fn with_observer(mut self, observer: Arc<dyn ProgressObserver>) -> Self {
+        self.observer = observer;
+        self
+    }
+
+    /// Names of the steps in the order they run
+    pub fn step_names(&self) -> Vec<&str> {
+        self.steps.iter().map(|step| step.name()).collect()
+    }
+
+    pub fn context(&self) -> &PipelineContext {
+        &self.context
+    }
+
+    /// Run every step in order, stopping at the first failure
+    ///
+    /// The error names the failing step. A failed smoke test is not an error, check
+    /// [`SmokeReport::passed`] on the outcome. What the steps do to the machine is recorded in the
+    /// [`Journal`], which is removed when every step completed. The time, CPU and disk usage of every step are
+    /// added to the [`BenchHistory`] in .vcpkg_ff/bench.jsonl, also when a step fails.
+    pub fn run(&mut self) -> Result<PipelineOutcome, VcpkgFfError> {
+        let names: Vec<String> = self.step_names().into_iter().map(str::to_string).collect();
+        if let Some(unknown) = self.skip.iter().find(|name| !names.contains(name)) {
+            return Err(self.unknown_step(unknown));
+        }
+
+        let journal_path = self.journal_path();
+        let interrupted: Vec<String> = Journal::load(&journal_path)?.interrupted().iter().map(|action| action.describe()).collect();
+        if !interrupted.is_empty() {
+            progress::say!("⚠ A previous run was interrupted while {}, run `vcpkg_ff repair` if this run fails",
+                interrupted.join(", "));
+        }
+        self.bench = BenchRun::new(self.bench_settings());
+        let result = match self.trace.clone() {
+            Some(trace_path) => {
+                let metadata = serde_json::json!({
+                    "version": env!("CARGO_PKG_VERSION"),
+                    "triplet": self.context.manager.get_triplet(),
+                    "steps": names,
+                });
+                trace::with_trace(&trace_path, metadata, || journal::with_journal(&journal_path, || self.run_steps()))?
+            }
+            None => journal::with_journal(&journal_path, || self.run_steps()),
+        };
+        let history = BenchHistory::path_in(self.context.preparer.get_base_dir());
+        if let Err(e) = BenchHistory::append(&history, &self.bench) {
+            progress::say!("⚠ Recording the step timings in {} failed: {}", history.display(), e);
+        }
+        let outcome = result??;
+        Journal::load(&journal_path)?.clear()?;
+        Ok(outcome)
+    }
+
+    fn run_steps(&mut self) -> Result<PipelineOutcome, VcpkgFfError> {
+        let checkpoint = self.checkpoint_path();
+        let mut completed: Vec<String> = if self.resume {
+            fs::read_to_string(&checkpoint).unwrap_or_default().lines().map(str::to_string).collect()
+        } else {
+            Vec::new()
+        };
+
+        for (index, step) in self.steps.iter().enumerate() {
+            let name = step.name();
+            self.observer.on_step_start(name, index + 1, self.steps.len());
+
+            let skipped = if self.skip.iter().any(|skipped| skipped == name) {
+                Some(SkipReason::Requested)
+            } else if completed.iter().any(|done| done == name) {
+                Some(SkipReason::Checkpoint)
+            } else {
+                None
+            };
+            if let Some(reason) = skipped {
+                self.observer.on_step_done(name, StepStatus::Skipped(reason));
+                self.bench.steps.push(StepSample {
+                    step: name.to_string(),
+                    status: "skipped".to_string(),
+                    wall_secs: 0.0,
+                    cpu_secs: None,
+                    peak_disk_bytes: None,
+                });
+                continue;
+            }
+
+            let meter = StepMeter::start(self.disk_dirs());
+            let stamp = self.stamp_path(name);
+            let context = &mut self.context;
+            let hook = self.hooks.get(name);
+            let status = progress::with_step(&self.observer, name, || trace::span("step", name, || journal::record(Action::Step { name: name.to_string() }, || {
+                let base_dir = context.preparer.get_base_dir().to_path_buf();
+                if let Some(command) = hook.and_then(|hook| hook.pre.as_deref()) {
+                    run_hook(command, name, "pre", &base_dir, None)?;
+                }
+                let status = step.inputs(context).and_then(|inputs| {
+                    let stamped = inputs.as_ref()
+                        .is_none_or(|hash| fs::read_to_string(&stamp).is_ok_and(|recorded| recorded.trim() == hash));
+                    if stamped && step.check(context) {
+                        return Ok(StepStatus::Skipped(SkipReason::AlreadyDone));
+                    }
+                    // 先删除旧的标记，运行失败时下次一定重新运行
+                    if stamp.exists() {
+                        fs_retry::remove_file(&stamp)?;
+                    }
+                    step.run(context).inspect_err(|_| {
+                        if let Err(rollback_error) = step.rollback(context) {
+                            progress::say!("⚠ Rolling back {} failed: {}", name, rollback_error);
+                        }
+                    })?;
+                    if let Some(hash) = inputs {
+                        if let Some(parent) = stamp.parent() {
+                            fs::create_dir_all(parent)?;
+                        }
+                        fs::write(&stamp, hash + "\n")?;
+                    }
+                    Ok(StepStatus::Completed)
+                });
+                if let Some(command) = hook.and_then(|hook| hook.post.as_deref()) {
+                    let exit_status = if status.is_ok() { 0 } else { 1 };
+                    let post = run_hook(command, name, "post", &base_dir, Some(exit_status));
+                    match (&status, post) {
+                        (Ok(_), Err(e)) => return Err(e),
+                        // 步骤本身的错误更重要，post hook 的失败只提示
+                        (Err(_), Err(e)) => progress::say!("⚠ {}", e),
+                        _ => {}
+                    }
+                }
+                status
+            }), |status| match status {
+                Ok(StepStatus::Completed) => serde_json::json!({"status": "completed"}),
+                Ok(StepStatus::Skipped(_)) => serde_json::json!({"status": "already_done"}),
+                Err(e) => serde_json::json!({"status": "failed", "error": e.to_string()}),
+            }));
+            self.bench.steps.push(meter.finish(name, match &status {
+                Ok(StepStatus::Completed) => "completed",
+                Ok(StepStatus::Skipped(_)) => "skipped",
+                Err(_) => "failed",
+            }));
+            match status {
+                Ok(status) => self.observer.on_step_done(name, status),
+                Err(e) => {
+                    self.observer.on_error(name, &e.to_string());
+                    return Err(VcpkgFfError::StepFailed { step: name.to_string(), source: Box::new(e) });
+                }
+            }
+
+            completed.push(name.to_string());
+            if let Some(parent) = checkpoint.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&checkpoint, completed.join("\n") + "\n")?;
+        }
+
+        // 全部完成后删除检查点，下次运行重新检查每一步
+        if checkpoint.exists() {
+            fs_retry::remove_file(&checkpoint)?;
+        }
+
+        Ok(PipelineOutcome {
+            addon_src_dir: self.context.preparer.get_addon_src_dir().to_path_buf(),
+            smoke_report: self.context.smoke_report.take(),
+            cli_tools: self.context.cli_tools.clone(),
+        })
+    }
+
+    /// Undo what an interrupted run left half done, as recorded in the [`Journal`], and clear it
+    ///
+    /// Removals are finished, partly written files removed and interrupted steps rolled back.
+    /// Returns a line for each repaired action.
+    pub fn repair(&mut self) -> Result<Vec<String>, VcpkgFfError> {
+        let journal = Journal::load(&self.journal_path())?;
+        let mut repaired = Vec::new();
+        // 按相反顺序修复：先处理步骤内部的动作，再回滚步骤本身
+        for action in journal.interrupted().into_iter().rev() {
+            match action {
+                Action::RemoveDir { path } => {
+                    if path.exists() {
+                        fs_retry::remove_dir_all(path)?;
+                        repaired.push(format!("Finished removing {}", path.display()));
+                    }
+                }
+                Action::RemoveFile { path } | Action::WriteFile { path } => {
+                    if path.exists() {
+                        fs_retry::remove_file(path)?;
+                        repaired.push(format!("Removed {}, it is regenerated by the next run", path.display()));
+                    }
+                }
+                Action::Command { command } => {
+                    repaired.push(format!("`{}` was interrupted, the next run starts it again", command));
+                }
+                Action::Step { name } => match self.steps.iter().find(|step| step.name() == name) {
+                    Some(step) => {
+                        step.rollback(&self.context)?;
+                        self.forget_stamp(name)?;
+                        repaired.push(format!("Rolled back {}", name));
+                    }
+                    None => repaired.push(format!("{} is not a step of this pipeline, nothing to roll back", name)),
+                },
+            }
+        }
+        journal.clear()?;
+        Ok(repaired)
+    }
+
+    /// Drop `step` and the steps after it from the checkpoint and remove their stamps, so that they run again after
+    /// their work was undone, e.g. by `vcpkg_ff remove`. Returns whether the checkpoint listed `step`
+    pub fn forget_checkpoint_from(&self, step: &str) -> Result<bool, VcpkgFfError> {
+        let Some(position) = self.steps.iter().position(|candidate| candidate.name() == step) else {
+            return Err(self.unknown_step(step));
+        };
+        let later: Vec<&str> = self.steps[position..].iter().map(|step| step.name()).collect();
+        for name in &later {
+            self.forget_stamp(name)?;
+        }
+        let checkpoint = self.checkpoint_path();
+        let Ok(content) = fs::read_to_string(&checkpoint) else {
+            return Ok(false);
+        };
+        let completed: Vec<&str> = content.lines().collect();
+        if !completed.contains(&step) {
+            return Ok(false);
+        }
+        let kept: Vec<&str> = completed.into_iter().filter(|name| !later.contains(name)).collect();
+        if kept.is_empty() {
+            fs_retry::remove_file(&checkpoint)?;
+        } else {
+            fs::write(&checkpoint, kept.join("\n") + "\n")?;
+        }
+        Ok(true)
+    }
+
+    /// What the step timings depend on, recorded with them so that the `bench` report can show what changed
+    fn bench_settings(&self) -> BTreeMap<String, String> {
+        let env = |name: &str| command_runner::env_var(name)
+            .map(|value| value.to_string_lossy().into_owned())
+            .unwrap_or_else(|| "default".to_string());
+        BTreeMap::from([
+            ("triplet".to_string(), self.context.manager.get_triplet().to_string()),
+            ("features".to_string(), self.context.manager.get_features().join(",")),
+            ("backend".to_string(), self.context.packages().name().to_string()),
+            ("mirror".to_string(), self.context.manager.get_mirrors().first().cloned().unwrap_or_default()),
+            ("jobs".to_string(), env("VCPKG_MAX_CONCURRENCY")),
+            ("binary_sources".to_string(), env("VCPKG_BINARY_SOURCES")),
+        ])
+    }
+
+    /// The directories whose size is the disk usage of a step: the project, vcpkg, the ffmpeg sources and addon_src,
+    /// without the ones inside another
+    fn disk_dirs(&self) -> Vec<PathBuf> {
+        let candidates = [
+            self.context.preparer.get_base_dir().to_path_buf(),
+            self.context.manager.get_vcpkg_root().to_path_buf(),
+            self.context.manager.get_output_dir().to_path_buf(),
+            self.context.preparer.get_addon_src_dir().to_path_buf(),
+        ];
+        let mut dirs: Vec<PathBuf> = Vec::new();
+        for candidate in candidates {
+            if !dirs.iter().any(|dir| candidate.starts_with(dir)) {
+                dirs.retain(|dir| !dir.starts_with(&candidate));
+                dirs.push(candidate);
+            }
+        }
+        dirs
+    }
+
+    /// A problem with the `[[plugins]]` or `[hooks]` of vcpkg_ff.toml
+    fn config_error(&self, message: String) -> VcpkgFfError {
+        VcpkgFfError::Config { path: self.context.preparer.get_base_dir().join(tool_config::CONFIG_FILE_NAME), message }
+    }
+
+    fn unknown_step(&self, name: &str) -> VcpkgFfError {
+        VcpkgFfError::Unknown {
+            kind: "step",
+            name: name.to_string(),
+            expected: self.step_names().iter().map(|name| name.to_string()).collect(),
+        }
+    }
+
+    fn journal_path(&self) -> PathBuf {
+        Journal::path_in(self.context.preparer.get_base_dir())
+    }
+
+    fn checkpoint_path(&self) -> PathBuf {
+        self.context.preparer.get_base_dir().join(".vcpkg_ff").join("pipeline.checkpoint")
+    }
+
+    /// Record `step` as done when its `check` finds its work, with the stamp of its current inputs, so that the next run
+    /// skips it, e.g. prepare-addon after addon_src was unpacked from an artifact. Returns whether it was recorded
+    pub fn adopt(&self, step: &str) -> Result<bool, VcpkgFfError> {
+        let Some(found) = self.steps.iter().find(|candidate| candidate.name() == step) else {
+            return Err(self.unknown_step(step));
+        };
+        if !found.check(&self.context) {
+            return Ok(false);
+        }
+        if let Some(hash) = found.inputs(&self.context)? {
+            let stamp = self.stamp_path(step);
+            if let Some(parent) = stamp.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(&stamp, hash + "\n")?;
+        }
+        Ok(true)
+    }
+
+    /// Stamp recording the [`Step::inputs`] of the last successful run of `step`
+    fn stamp_path(&self, step: &str) -> PathBuf {
+        self.context.preparer.get_base_dir().join(".vcpkg_ff").join("stamps").join(format!("{}.stamp", step))
+    }
+
+    fn forget_stamp(&self, step: &str) -> Result<(), VcpkgFfError> {
+        let stamp = self.stamp_path(step);
+        if stamp.exists() {
+            fs_retry::remove_file(&stamp)?;
+        }
+        Ok(())
+    }
+}
+
+impl Default for Pipeline {
+    fn default() -> Self {
+        Self::new()
+    }
+}
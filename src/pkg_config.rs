@@ -0,0 +1,171 @@
+use std::collections::HashMap;
+use std::fs;
+use std::path::Path;
+
+/// The fields of a pkg-config `.pc` file needed for static linking
+#[derive(Debug, Default)]
+struct PcFile {
+    libs: Vec<String>,
+    libs_private: Vec<String>,
+    /// Requires and Requires.private, without version constraints
+    requires: Vec<String>,
+}
+
+impl PcFile {
+    fn parse(content: &str) -> Self {
+        let mut variables: HashMap<String, String> = HashMap::new();
+        let mut pc = PcFile::default();
+
+        for line in content.lines() {
+            let line = line.split('#').next().unwrap_or("").trim();
+            if let Some((name, value)) = line.split_once(':').filter(|(name, _)| !name.contains('=')) {
+                let value = expand_variables(value.trim(), &variables);
+                match name.trim() {
+                    "Libs" => pc.libs = split_flags(&value),
+                    "Libs.private" => pc.libs_private = split_flags(&value),
+                    "Requires" | "Requires.private" => pc.requires.extend(parse_requires(&value)),
+                    _ => {}
+                }
+            } else if let Some((name, value)) = line.split_once('=') {
+                let value = expand_variables(value.trim(), &variables);
+                variables.insert(name.trim().to_string(), value);
+            }
+        }
+
+        pc
+    }
+}
+
+fn expand_variables(value: &str, variables: &HashMap<String, String>) -> String {
+    let mut result = String::new();
+    let mut rest = value;
+    while let Some(start) = rest.find("${") {
+        result.push_str(&rest[..start]);
+        match rest[start + 2..].find('}') {
+            Some(end) => {
+                let name = &rest[start + 2..start + 2 + end];
+                result.push_str(variables.get(name).map(String::as_str).unwrap_or(""));
+                rest = &rest[start + 3 + end..];
+            }
+            None => {
+                result.push_str(&rest[start..]);
+                rest = "";
+            }
+        }
+    }
+    result.push_str(rest);
+    result
+}
+
+/// Split a Libs line into flags, keeping `-framework X` together
+fn split_flags(value: &str) -> Vec<String> {
+    let mut flags = Vec::new();
+    let mut tokens = value.split_whitespace();
+    while let Some(token) = tokens.next() {
+        if token == "-framework" {
+            if let Some(framework) = tokens.next() {
+                flags.push(format!("-framework {}", framework));
+            }
+        } else {
+            flags.push(token.to_string());
+        }
+    }
+    flags
+}
+
+/// "libavutil >= 59.8.100, libswresample" -> ["libavutil", "libswresample"]
+fn parse_requires(value: &str) -> Vec<String> {
+    let mut packages = Vec::new();
+    let mut skip_version = false;
+    for token in value.split(|c: char| c == ',' || c.is_whitespace()).filter(|t| !t.is_empty()) {
+        if skip_version {
+            skip_version = false;
+        } else if matches!(token, ">=" | "<=" | "=" | ">" | "<" | "!=") {
+            skip_version = true;
+        } else {
+            packages.push(token.to_string());
+        }
+    }
+    packages
+}
+
+/// Link flags for statically linking `packages` and everything they require, ordered so that
+/// every library comes before the libraries it depends on
+pub struct StaticLinkSet {
+    /// `-l`, `-pthread` and `-framework` flags, in link order
+    pub flags: Vec<String>,
+    /// Required packages without a .pc file in the directory
+    pub missing: Vec<String>,
+}
+
+impl StaticLinkSet {
+    pub fn resolve(pkgconfig_dir: &Path, packages: &[&str]) -> Self {
+        let mut order = Vec::new();
+        let mut visiting = Vec::new();
+        let mut loaded: HashMap<String, Option<PcFile>> = HashMap::new();
+        for package in packages {
+            visit(pkgconfig_dir, package, &mut loaded, &mut visiting, &mut order);
+        }
+
+        // 后序遍历得到的是依赖在前，反过来才是静态链接需要的顺序
+        let mut flags: Vec<String> = Vec::new();
+        for package in order.iter().rev() {
+            if let Some(Some(pc)) = loaded.get(package) {
+                flags.extend(pc.libs.iter().chain(&pc.libs_private)
+                    .filter(|flag| flag.starts_with("-l") || flag.starts_with("-framework") || *flag == "-pthread")
+                    .cloned());
+            }
+        }
+
+        // 重复出现的库只保留最后一次，保证它排在所有依赖它的库之后
+        let mut deduped: Vec<String> = Vec::new();
+        for (index, flag) in flags.iter().enumerate() {
+            if !flags[index + 1..].contains(flag) {
+                deduped.push(flag.clone());
+            }
+        }
+
+        let mut missing: Vec<String> = loaded.iter()
+            .filter(|(_, pc)| pc.is_none())
+            .map(|(name, _)| name.clone())
+            .collect();
+        missing.sort();
+
+        Self { flags: deduped, missing }
+    }
+
+    /// The flags as MSVC library names ("-lavcodec" -> "avcodec.lib")
+    pub fn msvc_libraries(&self) -> Vec<String> {
+        self.flags
+            .iter()
+            .filter_map(|flag| flag.strip_prefix("-l"))
+            .map(|name| if name.ends_with(".lib") { name.to_string() } else { format!("{}.lib", name) })
+            .collect()
+    }
+}
+
+fn visit(
+    pkgconfig_dir: &Path,
+    package: &str,
+    loaded: &mut HashMap<String, Option<PcFile>>,
+    visiting: &mut Vec<String>,
+    order: &mut Vec<String>,
+) {
+    if loaded.contains_key(package) || visiting.iter().any(|p| p == package) {
+        return;
+    }
+    visiting.push(package.to_string());
+
+    let pc = fs::read_to_string(pkgconfig_dir.join(format!("{}.pc", package)))
+        .ok()
+        .map(|content| PcFile::parse(&content));
+    if let Some(pc) = &pc {
+        for required in &pc.requires {
+            visit(pkgconfig_dir, required, loaded, visiting, order);
+        }
+    }
+
+    visiting.pop();
+    loaded.insert(package.to_string(), pc);
+    order.push(package.to_string());
+}
@@ -0,0 +1,54 @@
+use std::path::PathBuf;
+
+use crate::command_runner::{CommandRunner, CommandSpec, SystemRunner};
+use crate::error::VcpkgFfError;
+use crate::pipeline::{PipelineContext, Step};
+use crate::tool_config::PluginConfig;
+
+/// A `[[plugins]]` executable run as a pipeline step
+///
+/// It gets [`PipelineContext::to_json`] on stdin and VCPKG_FF_STEP in its environment.
+pub struct PluginStep {
+    name: String,
+    program: String,
+    args: Vec<String>,
+}
+
+impl PluginStep {
+    /// Fails when `config` has an empty command
+    pub fn new(config: &PluginConfig) -> Result<Self, VcpkgFfError> {
+        let Some((program, args)) = config.command.split_first() else {
+            return Err(VcpkgFfError::PluginFailed { plugin: config.name.clone(), message: "empty command".to_string() });
+        };
+        Ok(Self { name: config.name.clone(), program: program.clone(), args: args.to_vec() })
+    }
+}
+
+impl Step for PluginStep {
+    fn name(&self) -> &str {
+        &self.name
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        let base_dir = ctx.preparer.get_base_dir();
+        let mut program = PathBuf::from(&self.program);
+        if program.is_relative() && program.components().count() > 1 {
+            program = base_dir.join(program);
+        }
+
+        let command = CommandSpec::new(program)
+            .args(&self.args)
+            .current_dir(base_dir)
+            .env("VCPKG_FF_STEP", &self.name)
+            .stdin(ctx.to_json(&self.name).to_string())
+            .streamed();
+        let failed = |message: String| VcpkgFfError::PluginFailed { plugin: self.name.clone(), message };
+        let output = SystemRunner
+            .run(&command)
+            .map_err(|e| failed(format!("could not start `{}`: {}", command.display(), e)))?;
+        if !output.success() {
+            return Err(failed(format!("`{}` {}", command.display(), output)));
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,341 @@
+use std::fs::{self, File};
+use std::io::{BufReader, Read};
+use std::path::{Path, PathBuf};
+use std::time::Duration;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use serde::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use tar::{Archive, Builder};
+
+use crate::command_runner::{self, CommandRunner, CommandSpec};
+use crate::error::VcpkgFfError;
+use crate::fs_retry;
+use crate::journal;
+use crate::marker;
+use crate::tarball::{self, append_data};
+
+/// Variable naming the server of the prebuilt trees, also read from the `[env]` of vcpkg_ff.toml
+pub const PREBUILT_URL_VAR: &str = "VCPKG_FF_PREBUILT_URL";
+
+/// Set to 1 to upload the tree built when the server has none for its key
+pub const PREBUILT_UPLOAD_VAR: &str = "VCPKG_FF_PREBUILT_UPLOAD";
+
+/// Layout version of the archives, restoring refuses other ones
+const ARCHIVE_FORMAT: u32 = 1;
+
+/// First entry of an archive
+const MANIFEST_NAME: &str = "vcpkg_ff-prebuilt.json";
+
+/// Entry holding the vcpkg status paragraphs of the packages in the archive
+const STATUS_NAME: &str = "status";
+
+/// Directory of the archive holding the file lists of installed/vcpkg/info
+const INFO_DIR: &str = "info";
+
+/// Suffix of the file next to an archive holding the SHA-256 of its content, uploaded last
+const HASH_SUFFIX: &str = ".hash";
+
+/// An archive is hundreds of megabytes
+const TRANSFER_TIMEOUT: Duration = Duration::from_secs(30 * 60);
+
+/// Asking the server whether it has an archive
+const HEAD_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// What a prebuilt tree was built from: the vcpkg commit pins the port versions, the triplet and features what
+/// vcpkg builds of them
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct PrebuiltKey {
+    pub vcpkg_commit: String,
+    pub triplet: String,
+    /// Sorted
+    pub features: Vec<String>,
+}
+
+impl PrebuiltKey {
+    pub fn new(vcpkg_commit: &str, triplet: &str, features: &[String]) -> Self {
+        let mut features = features.to_vec();
+        features.sort();
+        features.dedup();
+        Self { vcpkg_commit: vcpkg_commit.to_string(), triplet: triplet.to_string(), features }
+    }
+
+    /// installed-<triplet>-<commit>-<features>.tar.gz
+    pub fn archive_name(&self) -> String {
+        let commit = &self.vcpkg_commit[..self.vcpkg_commit.len().min(12)];
+        format!("installed-{}-{}-{}.tar.gz", self.triplet, commit, self.features.join("+"))
+    }
+}
+
+#[derive(Debug, Serialize, Deserialize)]
+struct PrebuiltManifest {
+    format: u32,
+    tool_version: String,
+    key: PrebuiltKey,
+}
+
+/// Server of prebuilt installed/<triplet> trees, which `install_packages` restores instead of building ffmpeg
+///
+/// Enabled by [`PREBUILT_URL_VAR`]: an http(s) URL, read with `curl` and written with `curl -T` (HTTP PUT), or a
+/// directory, e.g. on a network share. Next to each archive is its SHA-256, uploaded after the archive so that an
+/// interrupted upload is never restored, and checked after the download.
+#[derive(Debug, Clone)]
+pub struct PrebuiltCache {
+    url: String,
+    upload: bool,
+}
+
+impl PrebuiltCache {
+    pub fn new(url: impl Into<String>) -> Self {
+        Self { url: url.into(), upload: false }
+    }
+
+    /// The server named by [`PREBUILT_URL_VAR`], uploading when [`PREBUILT_UPLOAD_VAR`] is 1. None when it is not set
+    pub fn from_env() -> Option<Self> {
+        let url = command_runner::env_var(PREBUILT_URL_VAR)?.into_string().ok().filter(|url| !url.is_empty())?;
+        let upload = command_runner::env_var(PREBUILT_UPLOAD_VAR).is_some_and(|value| value == "1");
+        Some(Self::new(url).with_upload(upload))
+    }
+
+    /// Upload the trees built because the server had none
+    pub fn with_upload(mut self, upload: bool) -> Self {
+        self.upload = upload;
+        self
+    }
+
+    pub fn url(&self) -> &str {
+        &self.url
+    }
+
+    pub fn uploads(&self) -> bool {
+        self.upload
+    }
+
+    /// The directory when the cache is not on an http(s) server
+    fn local_dir(&self) -> Option<PathBuf> {
+        if self.url.starts_with("http://") || self.url.starts_with("https://") {
+            return None;
+        }
+        Some(PathBuf::from(self.url.strip_prefix("file://").unwrap_or(&self.url)))
+    }
+
+    fn remote_url(&self, name: &str) -> String {
+        format!("{}/{}", self.url.trim_end_matches('/'), name)
+    }
+
+    /// Whether the complete archive `name` is on the server
+    pub(crate) fn has(&self, name: &str, runner: &dyn CommandRunner) -> bool {
+        let hash_name = format!("{}{}", name, HASH_SUFFIX);
+        match self.local_dir() {
+            Some(dir) => dir.join(&hash_name).is_file(),
+            None => runner.run(&CommandSpec::new("curl").args(["-fsSI", &self.remote_url(&hash_name)]).timeout(HEAD_TIMEOUT))
+                .is_ok_and(|output| output.success()),
+        }
+    }
+
+    /// Download the archive `name` to `target` and check it against its hash. False when the server has none
+    pub(crate) fn download(&self, name: &str, target: &Path, runner: &dyn CommandRunner) -> Result<bool, VcpkgFfError> {
+        let hash_name = format!("{}{}", name, HASH_SUFFIX);
+        let hash_file = target.with_file_name(&hash_name);
+        if let Some(parent) = target.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        if !self.fetch(&hash_name, &hash_file, runner)? {
+            return Ok(false);
+        }
+        if !self.fetch(name, target, runner)? {
+            return Ok(false);
+        }
+        let expected = fs::read_to_string(&hash_file)?;
+        let actual = file_hash(target)?;
+        if expected.trim() != actual {
+            return Err(VcpkgFfError::InvalidFile {
+                path: target.to_path_buf(),
+                message: format!("downloaded from {}, does not match its SHA-256 {} (got {}), the download is corrupt",
+                    self.remote_url(name), expected.trim(), actual),
+            });
+        }
+        Ok(true)
+    }
+
+    /// Copy `name` from the server to `target`, false when the server has none
+    fn fetch(&self, name: &str, target: &Path, runner: &dyn CommandRunner) -> Result<bool, VcpkgFfError> {
+        if let Some(dir) = self.local_dir() {
+            let source = dir.join(name);
+            if !source.is_file() {
+                return Ok(false);
+            }
+            fs::copy(&source, target)?;
+            return Ok(true);
+        }
+        let url = self.remote_url(name);
+        let output = runner.run(&CommandSpec::new("curl").args(["-fsSL", "-o"]).arg(target).arg(&url).timeout(TRANSFER_TIMEOUT))?;
+        match output.code {
+            _ if output.success() => Ok(true),
+            // curl -f: HTTP 错误（404 等）退出码为 22
+            Some(22) => Ok(false),
+            _ => Err(output.failure(format!("downloading {}", url))),
+        }
+    }
+
+    /// Put the archive at `archive` on the server as `name`, followed by its hash
+    pub(crate) fn upload(&self, archive: &Path, name: &str, runner: &dyn CommandRunner) -> Result<(), VcpkgFfError> {
+        let hash_file = archive.with_file_name(format!("{}{}", name, HASH_SUFFIX));
+        fs::write(&hash_file, file_hash(archive)?)?;
+        for (file, name) in [(archive.to_path_buf(), name.to_string()), (hash_file, format!("{}{}", name, HASH_SUFFIX))] {
+            if let Some(dir) = self.local_dir() {
+                // 先复制到临时文件再改名，读取方不会看到写了一半的文件
+                fs::create_dir_all(&dir)?;
+                let partial = dir.join(format!("{}.partial", name));
+                fs::copy(&file, &partial)?;
+                fs_retry::rename(&partial, &dir.join(&name))?;
+                continue;
+            }
+            let url = self.remote_url(&name);
+            let output = runner.run(&CommandSpec::new("curl").args(["-fsS", "-T"]).arg(&file).arg(&url).timeout(TRANSFER_TIMEOUT))?;
+            if !output.success() {
+                return Err(output.failure(format!("uploading {}", url)));
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Pack the packages of `key.triplet` installed below `installed` into `archive`: the installed/<triplet> tree, their
+/// file lists from vcpkg/info and their status paragraphs
+pub(crate) fn pack(installed: &Path, key: &PrebuiltKey, archive: &Path) -> Result<(), VcpkgFfError> {
+    if let Some(parent) = archive.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    let mut builder = Builder::new(GzEncoder::new(File::create(archive)?, Compression::default()));
+    let manifest = PrebuiltManifest { format: ARCHIVE_FORMAT, tool_version: marker::TOOL_VERSION.to_string(), key: key.clone() };
+    append_data(&mut builder, MANIFEST_NAME, &serde_json::to_vec_pretty(&manifest)?)?;
+    append_data(&mut builder, STATUS_NAME, status_paragraphs(&installed.join("vcpkg"), &key.triplet).as_bytes())?;
+
+    let list_suffix = format!("_{}.list", key.triplet);
+    let mut lists: Vec<PathBuf> = fs::read_dir(installed.join("vcpkg").join("info")).into_iter().flatten().flatten()
+        .map(|entry| entry.path())
+        .filter(|path| path.file_name().is_some_and(|name| name.to_string_lossy().ends_with(&list_suffix)))
+        .collect();
+    lists.sort();
+    for list in lists {
+        let name = list.file_name().unwrap_or_default().to_string_lossy().into_owned();
+        builder.append_path_with_name(&list, format!("{}/{}", INFO_DIR, name))?;
+    }
+    builder.follow_symlinks(false);
+    builder.append_dir_all(&key.triplet, installed.join(&key.triplet))?;
+    builder.into_inner()?.finish()?;
+    Ok(())
+}
+
+/// Unpack `archive` into `installed`, which holds no package of the triplet yet. The packages are registered with
+/// vcpkg as a new file of installed/vcpkg/updates, the way vcpkg records the packages it installs
+pub(crate) fn unpack(archive: &Path, installed: &Path, key: &PrebuiltKey) -> Result<(), VcpkgFfError> {
+    let invalid = |message: String| VcpkgFfError::InvalidFile { path: archive.to_path_buf(), message };
+    let mut tarball = Archive::new(GzDecoder::new(BufReader::new(File::open(archive)?)));
+    let manifest: PrebuiltManifest = tarball::read_manifest(&mut tarball.entries()?, archive, MANIFEST_NAME, "a prebuilt tree")?;
+    if manifest.format != ARCHIVE_FORMAT {
+        return Err(invalid(format!("format {}, vcpkg_ff {} restores format {}", manifest.format, marker::TOOL_VERSION, ARCHIVE_FORMAT)));
+    }
+    if manifest.key != *key {
+        return Err(invalid(format!("built from {:?}, expected {:?}", manifest.key, key)));
+    }
+
+    // 先解到临时目录，中断时 installed/<triplet> 不会只有一半
+    let staging = installed.join(".vcpkg_ff-prebuilt");
+    if staging.exists() {
+        fs_retry::remove_dir_all(&staging)?;
+    }
+    Archive::new(GzDecoder::new(BufReader::new(File::open(archive)?))).unpack(&staging)?;
+    let status = fs::read_to_string(staging.join(STATUS_NAME))?;
+
+    // 失败时把已经移过去的文件移回来，vcpkg 不会看到没有登记的 info 文件
+    let mut moved = Vec::new();
+    if let Err(e) = install_staged(&staging, installed, key, &status, &mut moved) {
+        for (from, to) in moved.iter().rev() {
+            let _ = fs_retry::rename(to, from);
+        }
+        let _ = fs_retry::remove_dir_all(&staging);
+        return Err(e);
+    }
+    fs_retry::remove_dir_all(&staging)?;
+    Ok(())
+}
+
+/// Move the file lists and the tree unpacked to `staging` into `installed` and register the packages of `status`,
+/// recording each move in `moved`
+fn install_staged(staging: &Path, installed: &Path, key: &PrebuiltKey, status: &str,
+    moved: &mut Vec<(PathBuf, PathBuf)>) -> Result<(), VcpkgFfError> {
+    let vcpkg_dir = installed.join("vcpkg");
+    let info_dir = vcpkg_dir.join("info");
+    fs::create_dir_all(&info_dir)?;
+    let mut moves: Vec<(PathBuf, PathBuf)> = fs::read_dir(staging.join(INFO_DIR)).into_iter().flatten().flatten()
+        .map(|entry| (entry.path(), info_dir.join(entry.file_name())))
+        .collect();
+    moves.push((staging.join(&key.triplet), installed.join(&key.triplet)));
+    for (from, to) in moves {
+        fs_retry::rename(&from, &to)?;
+        moved.push((from, to));
+    }
+    if !vcpkg_dir.join("status").exists() {
+        journal::write(&vcpkg_dir.join("status"), "")?;
+    }
+    let updates = vcpkg_dir.join("updates");
+    fs::create_dir_all(&updates)?;
+    let next = fs::read_dir(&updates).into_iter().flatten().flatten()
+        .filter_map(|entry| entry.file_name().to_str()?.parse::<u64>().ok())
+        .max()
+        .map_or(1, |last| last + 1);
+    journal::write(&updates.join(format!("{:010}", next)), status)?;
+    Ok(())
+}
+
+/// The status paragraphs of the packages installed for `triplet`, from the status file and the update files after it
+pub(crate) fn status_paragraphs(vcpkg_dir: &Path, triplet: &str) -> String {
+    let mut files = vec![vcpkg_dir.join("status")];
+    let mut updates: Vec<PathBuf> = fs::read_dir(vcpkg_dir.join("updates")).into_iter().flatten().flatten()
+        .map(|entry| entry.path())
+        .collect();
+    updates.sort();
+    files.extend(updates);
+
+    // 后面的段落覆盖前面同一个包（及 feature）的状态
+    let mut paragraphs: Vec<((String, String), String)> = Vec::new();
+    for content in files.iter().filter_map(|file| fs::read_to_string(file).ok()) {
+        for paragraph in content.replace("\r\n", "\n").split("\n\n").map(str::trim).filter(|paragraph| !paragraph.is_empty()) {
+            let field = |name: &str| paragraph_field(paragraph, name);
+            if field("Architecture") != triplet {
+                continue;
+            }
+            let package = (field("Package"), field("Feature"));
+            paragraphs.retain(|(existing, _)| *existing != package);
+            if field("Status") == "install ok installed" {
+                paragraphs.push((package, paragraph.to_string()));
+            }
+        }
+    }
+    paragraphs.into_iter().map(|(_, paragraph)| paragraph + "\n\n").collect()
+}
+
+/// Value of the `name: value` line of a status paragraph, empty when it has none
+pub(crate) fn paragraph_field(paragraph: &str, name: &str) -> String {
+    paragraph.lines()
+        .find_map(|line| line.strip_prefix(name)?.strip_prefix(':').map(|value| value.trim().to_string()))
+        .unwrap_or_default()
+}
+
+/// SHA-256 of the file at `path`, in hex
+fn file_hash(path: &Path) -> Result<String, VcpkgFfError> {
+    let mut file = File::open(path)?;
+    let mut hasher = Sha256::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            return Ok(hasher.finalize().iter().map(|byte| format!("{:02x}", byte)).collect());
+        }
+        hasher.update(&buffer[..read]);
+    }
+}
@@ -0,0 +1,185 @@
+use std::collections::BTreeMap;
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Serialize;
+
+use crate::addon_preparer::FFMPEG_PKG_CONFIG_PACKAGES;
+use crate::error::VcpkgFfError;
+use crate::pkg_config::StaticLinkSet;
+use crate::vcpkg_manager;
+
+/// Where the libraries of vcpkg packages are and what to link, as found by [`probe`]
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub struct ProbedLibraries {
+    pub include_dirs: Vec<PathBuf>,
+    pub link_paths: Vec<PathBuf>,
+    /// Library names without prefix and extension ("avcodec", "x264", "m"), in link order:
+    /// every library comes before the libraries it depends on
+    pub libs: Vec<String>,
+    /// macOS frameworks the libraries need
+    pub frameworks: Vec<String>,
+}
+
+impl ProbedLibraries {
+    /// Print the `cargo:` lines linking the libraries, for a build script. Libraries found in
+    /// `link_paths` are linked statically, the others (system libraries) dynamically.
+    pub fn emit_cargo_metadata(&self) {
+        for path in &self.link_paths {
+            println!("cargo:rustc-link-search=native={}", path.display());
+            println!("cargo:rerun-if-changed={}", path.join("pkgconfig").display());
+        }
+        for lib in &self.libs {
+            if self.is_installed_lib(lib) {
+                println!("cargo:rustc-link-lib=static={}", lib);
+            } else {
+                println!("cargo:rustc-link-lib={}", lib);
+            }
+        }
+        for framework in &self.frameworks {
+            println!("cargo:rustc-link-lib=framework={}", framework);
+        }
+    }
+
+    /// The libraries installed by vcpkg, in link order
+    pub fn static_libs(&self) -> Vec<String> {
+        self.libs.iter().filter(|lib| self.is_installed_lib(lib)).cloned().collect()
+    }
+
+    /// The system libraries the installed ones need ("m", "pthread", "bcrypt")
+    pub fn system_libs(&self) -> Vec<String> {
+        self.libs.iter().filter(|lib| !self.is_installed_lib(lib)).cloned().collect()
+    }
+
+    fn is_installed_lib(&self, lib: &str) -> bool {
+        let file_names = [format!("lib{}.a", lib), format!("{}.lib", lib)];
+        self.link_paths.iter().any(|dir| file_names.iter().any(|name| dir.join(name).exists()))
+    }
+}
+
+/// Locate `packages` ("ffmpeg", "x264", ...) installed by vcpkg for `triplet`, from a build script
+///
+/// vcpkg is looked up in VCPKG_ROOT, then in vcpkg/ of [`vcpkg_manager::default_base_dir`].
+/// The libraries to link come from vcpkg's pkg-config files, including the libraries they depend on.
+///
+/// ```no_run
+/// // build.rs
+/// let libraries = vcpkg_ff::probe(vcpkg_ff::vcpkg_manager::default_triplet(), &["ffmpeg", "x264"])?;
+/// libraries.emit_cargo_metadata();
+/// # Ok::<(), vcpkg_ff::VcpkgFfError>(())
+/// ```
+pub fn probe(triplet: &str, packages: &[&str]) -> Result<ProbedLibraries, VcpkgFfError> {
+    println!("cargo:rerun-if-env-changed=VCPKG_ROOT");
+    let vcpkg_root = match env::var_os("VCPKG_ROOT") {
+        Some(root) => PathBuf::from(root),
+        None => vcpkg_manager::default_base_dir().join("vcpkg"),
+    };
+    probe_root(&vcpkg_root, triplet, packages)
+}
+
+/// [`probe`] in the vcpkg installation at `vcpkg_root`
+pub fn probe_root(vcpkg_root: &Path, triplet: &str, packages: &[&str]) -> Result<ProbedLibraries, VcpkgFfError> {
+    let installed = vcpkg_root.join("installed").join(triplet);
+    if !installed.is_dir() {
+        return Err(VcpkgFfError::VcpkgNotInstalled);
+    }
+    let lib_dir = installed.join("lib");
+    let pkgconfig_dir = lib_dir.join("pkgconfig");
+
+    let mut pc_names: Vec<String> = Vec::new();
+    for package in packages {
+        let found = pc_names_for(&pkgconfig_dir, package);
+        if found.is_empty() {
+            return Err(VcpkgFfError::PackageNotFound { package: package.to_string(), triplet: triplet.to_string() });
+        }
+        pc_names.extend(found);
+    }
+
+    let pc_names: Vec<&str> = pc_names.iter().map(String::as_str).collect();
+    let link_set = StaticLinkSet::resolve(&pkgconfig_dir, &pc_names);
+    let mut libs = Vec::new();
+    let mut frameworks = Vec::new();
+    for flag in &link_set.flags {
+        if let Some(framework) = flag.strip_prefix("-framework ") {
+            frameworks.push(framework.to_string());
+        } else if flag == "-pthread" {
+            libs.push("pthread".to_string());
+        } else if let Some(lib) = flag.strip_prefix("-l") {
+            // MSVC 的 .pc 文件里有时写成 -lavcodec.lib
+            libs.push(lib.strip_suffix(".lib").unwrap_or(lib).to_string());
+        }
+    }
+
+    Ok(ProbedLibraries {
+        include_dirs: vec![installed.join("include")],
+        link_paths: vec![lib_dir],
+        libs,
+        frameworks,
+    })
+}
+
+/// pkg-config packages installed for vcpkg `package`: the ffmpeg libraries for "ffmpeg",
+/// otherwise `package` or lib`package` (libpng, libwebp)
+fn pc_names_for(pkgconfig_dir: &Path, package: &str) -> Vec<String> {
+    let exists = |name: &str| pkgconfig_dir.join(format!("{}.pc", name)).exists();
+    if package == "ffmpeg" {
+        return FFMPEG_PKG_CONFIG_PACKAGES.iter().filter(|name| exists(name)).map(|name| name.to_string()).collect();
+    }
+    [package.to_string(), format!("lib{}", package)].into_iter().filter(|name| exists(name)).take(1).collect()
+}
+
+/// Descriptor of the installed ffmpeg for Rust projects linking it through ffmpeg-sys-next or
+/// bindgen, written as ffmpeg-sys.toml by `vcpkg_ff link-metadata`
+///
+/// `[env]` holds the variables pointing ffmpeg-sys-next (FFMPEG_DIR) and pkg-config
+/// (PKG_CONFIG_PATH) at the tree, in the form of the `[env]` table of .cargo/config.toml.
+#[derive(Debug, Clone, Serialize)]
+pub struct LinkMetadata {
+    pub triplet: String,
+    pub vcpkg_root: PathBuf,
+    pub include_dirs: Vec<PathBuf>,
+    pub lib_dirs: Vec<PathBuf>,
+    /// vcpkg's libraries in link order, every library before the libraries it depends on
+    pub static_libs: Vec<String>,
+    /// System libraries to link after `static_libs`
+    pub system_libs: Vec<String>,
+    pub frameworks: Vec<String>,
+    pub env: BTreeMap<String, String>,
+}
+
+impl LinkMetadata {
+    /// Probe `packages` in the vcpkg installation at `vcpkg_root`
+    pub fn probe(vcpkg_root: &Path, triplet: &str, packages: &[&str]) -> Result<Self, VcpkgFfError> {
+        let libraries = probe_root(vcpkg_root, triplet, packages)?;
+        let installed = vcpkg_root.join("installed").join(triplet);
+        let env = BTreeMap::from([
+            ("FFMPEG_DIR".to_string(), installed.display().to_string()),
+            ("PKG_CONFIG_PATH".to_string(), installed.join("lib").join("pkgconfig").display().to_string()),
+        ]);
+
+        Ok(Self {
+            triplet: triplet.to_string(),
+            vcpkg_root: vcpkg_root.to_path_buf(),
+            static_libs: libraries.static_libs(),
+            system_libs: libraries.system_libs(),
+            include_dirs: libraries.include_dirs,
+            lib_dirs: libraries.link_paths,
+            frameworks: libraries.frameworks,
+            env,
+        })
+    }
+
+    pub fn to_toml(&self) -> String {
+        let body = toml::to_string(self).expect("link metadata serializes to TOML");
+        format!("# Generated by vcpkg_ff link-metadata, rerun it after reinstalling ffmpeg\n\n{}", body)
+    }
+
+    pub fn write(&self, path: &Path) -> Result<(), VcpkgFfError> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, self.to_toml())?;
+        Ok(())
+    }
+}
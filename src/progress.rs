@@ -0,0 +1,201 @@
+use std::cell::RefCell;
+use std::io::Write;
+use std::sync::{Arc, Mutex};
+
+/// Why a step did not run
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum SkipReason {
+    /// Named in `--skip`
+    Requested,
+    /// Recorded as completed in the checkpoint of the run being resumed
+    Checkpoint,
+    /// The step's check found its work already done
+    AlreadyDone,
+}
+
+/// How a step ended when it did not fail
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum StepStatus {
+    Completed,
+    Skipped(SkipReason),
+}
+
+/// Receives the progress of a [`Pipeline`](crate::Pipeline) run
+///
+/// While a step runs, everything it would print to stdout, including the stdout of the
+/// commands it starts, arrives line by line in `on_output_line` instead.
+pub trait ProgressObserver: Send + Sync {
+    /// `index` counts from 1 up to `total`
+    fn on_step_start(&self, _step: &str, _index: usize, _total: usize) {}
+
+    fn on_output_line(&self, _step: &str, _line: &str) {}
+
+    /// A line the step would print to stderr: diagnostics and the stderr of streamed commands.
+    /// Passed to `on_output_line` unless implemented
+    fn on_error_line(&self, step: &str, line: &str) {
+        self.on_output_line(step, line);
+    }
+
+    fn on_step_done(&self, _step: &str, _status: StepStatus) {}
+
+    /// The step failed with `error`, the pipeline stops after it
+    fn on_error(&self, _step: &str, _error: &str) {}
+}
+
+/// Human-readable output on the console, the default
+pub struct ConsoleObserver;
+
+impl ProgressObserver for ConsoleObserver {
+    fn on_step_start(&self, step: &str, index: usize, total: usize) {
+        println!("\n=== [{}/{}] {} ===", index, total, step);
+    }
+
+    fn on_output_line(&self, _step: &str, line: &str) {
+        println!("{}", line);
+    }
+
+    fn on_error_line(&self, _step: &str, line: &str) {
+        eprintln!("{}", line);
+    }
+
+    fn on_step_done(&self, step: &str, status: StepStatus) {
+        match status {
+            StepStatus::Completed => {}
+            StepStatus::Skipped(SkipReason::Requested) => println!("⚠ Skipping {} as requested", step),
+            StepStatus::Skipped(SkipReason::Checkpoint) => println!("✓ {} completed in a previous run, skipping", step),
+            StepStatus::Skipped(SkipReason::AlreadyDone) => println!("✓ {} already done, skipping", step),
+        }
+    }
+}
+
+/// One JSON object per line and event (`--ndjson`), for tools driving the CLI
+///
+/// `{"event":"step_start","step":..,"index":..,"total":..}`, `{"event":"output","step":..,"line":..}` (with
+/// `"stream":"stderr"` for error lines),
+/// `{"event":"step_done","step":..,"status":"completed"|"skipped","reason":..}` and
+/// `{"event":"error","step":..,"message":..}`
+pub struct NdjsonObserver<W: Write + Send> {
+    out: Mutex<W>,
+}
+
+impl<W: Write + Send> NdjsonObserver<W> {
+    pub fn new(out: W) -> Self {
+        Self { out: Mutex::new(out) }
+    }
+
+    fn emit(&self, event: serde_json::Value) {
+        if let Ok(mut out) = self.out.lock() {
+            let _ = writeln!(out, "{}", event);
+            let _ = out.flush();
+        }
+    }
+}
+
+impl<W: Write + Send> ProgressObserver for NdjsonObserver<W> {
+    fn on_step_start(&self, step: &str, index: usize, total: usize) {
+        self.emit(serde_json::json!({"event": "step_start", "step": step, "index": index, "total": total}));
+    }
+
+    fn on_output_line(&self, step: &str, line: &str) {
+        self.emit(serde_json::json!({"event": "output", "step": step, "line": line}));
+    }
+
+    fn on_error_line(&self, step: &str, line: &str) {
+        self.emit(serde_json::json!({"event": "output", "step": step, "line": line, "stream": "stderr"}));
+    }
+
+    fn on_step_done(&self, step: &str, status: StepStatus) {
+        let event = match status {
+            StepStatus::Completed => serde_json::json!({"event": "step_done", "step": step, "status": "completed"}),
+            StepStatus::Skipped(reason) => {
+                let reason = match reason {
+                    SkipReason::Requested => "requested",
+                    SkipReason::Checkpoint => "checkpoint",
+                    SkipReason::AlreadyDone => "already_done",
+                };
+                serde_json::json!({"event": "step_done", "step": step, "status": "skipped", "reason": reason})
+            }
+        };
+        self.emit(event);
+    }
+
+    fn on_error(&self, step: &str, error: &str) {
+        self.emit(serde_json::json!({"event": "error", "step": step, "message": error}));
+    }
+}
+
+/// Observer and step receiving the output of the running step
+pub(crate) type Sink = (Arc<dyn ProgressObserver>, Arc<str>);
+
+thread_local! {
+    /// Per thread, so that pipelines running on different threads report to their own observers.
+    /// Threads working for a step (the readers of a command's output) take it over with [`current`] and [`with_sink`].
+    static CURRENT: RefCell<Option<Sink>> = const { RefCell::new(None) };
+}
+
+/// Puts the sink of the enclosing step back when dropped, also when the step panicked
+struct Restore(Option<Sink>);
+
+impl Drop for Restore {
+    fn drop(&mut self) {
+        let previous = self.0.take();
+        CURRENT.with(|current| *current.borrow_mut() = previous);
+    }
+}
+
+/// Run `f` with its output going to `observer` as the output of `step`
+pub(crate) fn with_step<T>(observer: &Arc<dyn ProgressObserver>, step: &str, f: impl FnOnce() -> T) -> T {
+    with_sink(Some((Arc::clone(observer), Arc::from(step))), f)
+}
+
+/// Run `f` with its output going to `sink`, the console when None
+pub(crate) fn with_sink<T>(sink: Option<Sink>, f: impl FnOnce() -> T) -> T {
+    let _restore = Restore(CURRENT.with(|current| current.replace(sink)));
+    f()
+}
+
+/// The sink of the step running on this thread
+pub(crate) fn current() -> Option<Sink> {
+    CURRENT.with(|current| current.borrow().clone())
+}
+
+/// Print `text` to stdout, or hand its lines to the observer of the running step
+pub(crate) fn output(text: &str) {
+    match current() {
+        Some((observer, step)) => {
+            for line in text.split('\n') {
+                observer.on_output_line(&step, line);
+            }
+        }
+        None => println!("{}", text),
+    }
+}
+
+/// Print `text` to stderr, or hand its lines to the observer of the running step as error lines
+pub(crate) fn error_output(text: &str) {
+    match current() {
+        Some((observer, step)) => {
+            for line in text.split('\n') {
+                observer.on_error_line(&step, line);
+            }
+        }
+        None => eprintln!("{}", text),
+    }
+}
+
+/// `println!` for library code, routed through [`output`]
+macro_rules! say {
+    ($($arg:tt)*) => {
+        $crate::progress::output(&format!($($arg)*))
+    };
+}
+
+/// `eprintln!` for library code, routed through [`error_output`]
+macro_rules! say_error {
+    ($($arg:tt)*) => {
+        $crate::progress::error_output(&format!($($arg)*))
+    };
+}
+
+pub(crate) use say;
+pub(crate) use say_error;
@@ -0,0 +1,245 @@
+use std::fs::{self, File};
+use std::io::{self, Read};
+use std::path::{Path, PathBuf};
+
+use crate::command_runner;
+use crate::error::VcpkgFfError;
+use crate::fs_retry;
+use crate::marker;
+use crate::progress::say;
+
+/// Variable naming the shared cache directory, also read from the `[env]` of vcpkg_ff.toml
+pub const SHARED_CACHE_VAR: &str = "VCPKG_FF_SHARED_CACHE";
+
+/// Suffix of the link created next to a file before it replaces the file
+const LINK_SUFFIX: &str = ".vcpkg_ff-link";
+
+/// How a deduplicated file shares the content of its store object
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum LinkMode {
+    /// Copy-on-write clone (btrfs, XFS): the files stay independent, writing one leaves the others alone
+    Reflink,
+    /// Hard link: the project and the store hold the same file, which has to be replaced instead of written to
+    Hardlink,
+}
+
+/// What [`SharedCache::dedupe`] did
+#[derive(Debug, Default)]
+pub struct DedupeReport {
+    /// How the files were linked, None when nothing was
+    pub mode: Option<LinkMode>,
+    /// Files looked at
+    pub files: usize,
+    /// Files new to the store, which now back the store's objects
+    pub stored: usize,
+    /// Files replaced by a link to the store object with the same content
+    pub linked: Vec<PathBuf>,
+    /// Size of the files in `linked`
+    pub bytes_saved: u64,
+}
+
+impl DedupeReport {
+    pub fn print(&self) {
+        let mode = match self.mode {
+            Some(LinkMode::Reflink) => "reflinks",
+            Some(LinkMode::Hardlink) => "hard links",
+            None => "links",
+        };
+        say!("✓ {} files checked, {} added to the shared cache, {} replaced by {} saving {:.1} MiB",
+            self.files, self.stored, self.linked.len(), mode, self.bytes_saved as f64 / (1024.0 * 1024.0));
+    }
+}
+
+/// Content-addressed store of the files of the projects on this machine (shared-cache mode)
+///
+/// Enabled by [`SHARED_CACHE_VAR`]. Deduplicating a directory links each of its files to the store object with
+/// the same content, adding the file as the object when there is none, so the installed tree and the ffmpeg sources
+/// of every project take disk space once. Reflinks are used where the filesystem supports them (Linux), hard links
+/// otherwise; the store has to be on the same filesystem as the projects.
+///
+/// A hard-linked file must be replaced rather than written to: the extraction does so, and vcpkg removes the files
+/// of a package before installing it again.
+#[derive(Debug, Clone)]
+pub struct SharedCache {
+    dir: PathBuf,
+}
+
+impl SharedCache {
+    pub fn new(dir: impl Into<PathBuf>) -> Self {
+        Self { dir: dir.into() }
+    }
+
+    /// The cache named by [`SHARED_CACHE_VAR`], None when it is not set
+    pub fn from_env() -> Option<Self> {
+        command_runner::env_var(SHARED_CACHE_VAR).filter(|dir| !dir.is_empty()).map(Self::new)
+    }
+
+    pub fn dir(&self) -> &Path {
+        &self.dir
+    }
+
+    /// Link every file below `dirs` to the store, skipping symlinks and empty files
+    pub fn dedupe(&self, dirs: &[PathBuf]) -> Result<DedupeReport, VcpkgFfError> {
+        let mut deduper = Deduper { objects: self.dir.join("objects"), reflinks: None, report: DedupeReport::default() };
+        for dir in dirs {
+            deduper.dedupe_dir(dir).map_err(|e| match e.kind() {
+                io::ErrorKind::CrossesDevices => VcpkgFfError::Io(io::Error::new(e.kind(), format!(
+                    "{} and the shared cache {} are on different filesystems, move the cache next to the projects",
+                    dir.display(), self.dir.display()))),
+                _ => e.into(),
+            })?;
+        }
+        Ok(deduper.report)
+    }
+}
+
+struct Deduper {
+    objects: PathBuf,
+    /// Whether the filesystem clones files, known after the first link
+    reflinks: Option<bool>,
+    report: DedupeReport,
+}
+
+impl Deduper {
+    fn dedupe_dir(&mut self, dir: &Path) -> io::Result<()> {
+        for entry in fs::read_dir(dir)? {
+            let path = entry?.path();
+            let metadata = fs::symlink_metadata(&path)?;
+            if metadata.is_dir() {
+                self.dedupe_dir(&path)?;
+            } else if path.to_string_lossy().ends_with(LINK_SUFFIX) {
+                // 上次中断时留下的临时链接
+                fs::remove_file(&path)?;
+            } else if metadata.is_file() && metadata.len() > 0 {
+                self.dedupe_file(&path, &metadata)?;
+            }
+        }
+        Ok(())
+    }
+
+    fn dedupe_file(&mut self, path: &Path, metadata: &fs::Metadata) -> io::Result<()> {
+        self.report.files += 1;
+        if hard_linked(metadata) {
+            return Ok(());
+        }
+        let key = object_key(path, metadata)?;
+        let object = self.objects.join(&key[..2]).join(&key);
+        let Ok(object_metadata) = fs::metadata(&object) else {
+            if let Some(parent) = object.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            self.link(path, &object)?;
+            self.report.stored += 1;
+            return Ok(());
+        };
+        // 链接时修改时间取自存储对象，相同说明已经链接过；内容不同是哈希碰撞，保留原文件
+        if metadata.modified()? == object_metadata.modified()? || !same_content(path, &object)? {
+            return Ok(());
+        }
+
+        let temp = path.with_file_name(format!("{}{}", path.file_name().unwrap_or_default().to_string_lossy(), LINK_SUFFIX));
+        self.link(&object, &temp)?;
+        fs_retry::rename(&temp, path)?;
+        self.report.linked.push(path.to_path_buf());
+        self.report.bytes_saved += metadata.len();
+        Ok(())
+    }
+
+    /// Link `to` to the content of `from`, a reflink with the modification time of `from` or a hard link
+    fn link(&mut self, from: &Path, to: &Path) -> io::Result<()> {
+        if self.reflinks != Some(false) {
+            match reflink(from, to) {
+                Ok(()) => {
+                    File::options().write(true).open(to)?.set_modified(fs::metadata(from)?.modified()?)?;
+                    self.reflinks = Some(true);
+                    self.report.mode = Some(LinkMode::Reflink);
+                    return Ok(());
+                }
+                Err(_) if self.reflinks.is_none() => self.reflinks = Some(false),
+                // 其他目录可能在不支持 reflink 的文件系统上，这个文件用硬链接
+                Err(_) => {}
+            }
+        }
+        fs::hard_link(from, to)?;
+        self.report.mode.get_or_insert(LinkMode::Hardlink);
+        Ok(())
+    }
+}
+
+/// Name of the store object for the file at `path`: hash and size of its content, and its mode where there are modes
+fn object_key(path: &Path, metadata: &fs::Metadata) -> io::Result<String> {
+    let mut file = File::open(path)?;
+    let mut hasher = marker::Hasher::new();
+    let mut buffer = vec![0; 64 * 1024];
+    loop {
+        let read = file.read(&mut buffer)?;
+        if read == 0 {
+            break;
+        }
+        hasher.update(&buffer[..read]);
+    }
+    Ok(format!("{}-{}{}", hasher.finish(), metadata.len(), mode_suffix(metadata)))
+}
+
+fn same_content(a: &Path, b: &Path) -> io::Result<bool> {
+    let (mut a, mut b) = (File::open(a)?, File::open(b)?);
+    let (mut buffer_a, mut buffer_b) = (vec![0; 64 * 1024], vec![0; 64 * 1024]);
+    loop {
+        let read = a.read(&mut buffer_a)?;
+        if read == 0 {
+            return Ok(b.read(&mut buffer_b[..1])? == 0);
+        }
+        b.read_exact(&mut buffer_b[..read])?;
+        if buffer_a[..read] != buffer_b[..read] {
+            return Ok(false);
+        }
+    }
+}
+
+/// Linked already, to the store or by an earlier dedupe
+#[cfg(unix)]
+fn hard_linked(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::MetadataExt;
+
+    metadata.nlink() > 1
+}
+
+#[cfg(not(unix))]
+fn hard_linked(_metadata: &fs::Metadata) -> bool {
+    false
+}
+
+/// Executable scripts like configure get objects of their own
+#[cfg(unix)]
+fn mode_suffix(metadata: &fs::Metadata) -> String {
+    use std::os::unix::fs::PermissionsExt;
+
+    format!("-{:o}", metadata.permissions().mode() & 0o777)
+}
+
+#[cfg(not(unix))]
+fn mode_suffix(_metadata: &fs::Metadata) -> String {
+    String::new()
+}
+
+/// Clone `from` to the new file `to` (FICLONE), failing on filesystems without copy-on-write
+#[cfg(target_os = "linux")]
+fn reflink(from: &Path, to: &Path) -> io::Result<()> {
+    use std::os::unix::io::AsRawFd;
+
+    let source = File::open(from)?;
+    let target = File::create_new(to)?;
+    // SAFETY: 两个文件描述符在调用期间都有效
+    if unsafe { libc::ioctl(target.as_raw_fd(), libc::FICLONE, source.as_raw_fd()) } == 0 {
+        return target.set_permissions(source.metadata()?.permissions());
+    }
+    let error = io::Error::last_os_error();
+    drop(target);
+    fs::remove_file(to)?;
+    Err(error)
+}
+
+#[cfg(not(target_os = "linux"))]
+fn reflink(_from: &Path, _to: &Path) -> io::Result<()> {
+    Err(io::ErrorKind::Unsupported.into())
+}
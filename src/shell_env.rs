@@ -0,0 +1,103 @@
+use std::path::PathBuf;
+
+use crate::addon_preparer::AddonPreparer;
+use crate::vcpkg_manager::VcpkgManager;
+
+/// Shell `vcpkg_ff env` writes statements for
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Shell {
+    Bash,
+    PowerShell,
+    Cmd,
+}
+
+impl Shell {
+    pub fn parse(value: &str) -> Result<Self, String> {
+        match value {
+            "bash" | "sh" | "zsh" => Ok(Shell::Bash),
+            "powershell" | "pwsh" => Ok(Shell::PowerShell),
+            "cmd" => Ok(Shell::Cmd),
+            other => Err(format!("invalid --shell `{}`, expected bash, powershell or cmd", other)),
+        }
+    }
+
+    /// PowerShell on Windows, bash elsewhere
+    pub fn platform_default() -> Self {
+        if cfg!(windows) { Shell::PowerShell } else { Shell::Bash }
+    }
+
+    /// How to load the printed statements into the current shell
+    pub fn usage(&self) -> &'static str {
+        match self {
+            Shell::Bash => "eval \"$(vcpkg_ff env --shell bash)\"",
+            Shell::PowerShell => "vcpkg_ff env --shell powershell | Out-String | Invoke-Expression",
+            Shell::Cmd => "vcpkg_ff env --shell cmd > vcpkg_ff_env.cmd && call vcpkg_ff_env.cmd",
+        }
+    }
+
+    fn comment(&self) -> &'static str {
+        match self {
+            Shell::Bash | Shell::PowerShell => "#",
+            Shell::Cmd => "rem",
+        }
+    }
+
+    fn set(&self, name: &str, value: &str) -> String {
+        match self {
+            Shell::Bash => format!("export {}='{}'", name, value.replace('\'', "'\\''")),
+            Shell::PowerShell => format!("$env:{} = '{}'", name, value.replace('\'', "''")),
+            Shell::Cmd => format!("set \"{}={}\"", name, value),
+        }
+    }
+
+    /// Prepend `dirs` to PATH, keeping what is there
+    fn prepend_path(&self, dirs: &[String]) -> String {
+        match self {
+            Shell::Bash => format!("export PATH='{}':\"$PATH\"", dirs.join(":").replace('\'', "'\\''")),
+            Shell::PowerShell => format!("$env:PATH = '{}' + [IO.Path]::PathSeparator + $env:PATH",
+                dirs.join(if cfg!(windows) { ";" } else { ":" }).replace('\'', "''")),
+            Shell::Cmd => format!("set \"PATH={};%PATH%\"", dirs.join(";")),
+        }
+    }
+}
+
+/// The variables for working with the installed tree by hand after a run: VCPKG_ROOT, VCPKG_DEFAULT_TRIPLET,
+/// the addon_src path and the directories of vcpkg and the installed programs on PATH
+#[derive(Debug, Clone)]
+pub struct ShellEnv {
+    vars: Vec<(&'static str, String)>,
+    path: Vec<PathBuf>,
+}
+
+impl ShellEnv {
+    pub fn new(manager: &VcpkgManager, preparer: &AddonPreparer) -> Self {
+        let installed = manager.get_vcpkg_root().join("installed").join(manager.get_triplet());
+        let mut path = vec![manager.get_vcpkg_root().to_path_buf(), manager.cli_tools_dir()];
+        // 动态链接的 Windows triplet 把 DLL 放在 bin/
+        if installed.join("bin").is_dir() {
+            path.push(installed.join("bin"));
+        }
+        Self {
+            vars: vec![
+                ("VCPKG_ROOT", manager.get_vcpkg_root().display().to_string()),
+                ("VCPKG_DEFAULT_TRIPLET", manager.get_triplet().to_string()),
+                ("VCPKG_FF_ADDON_SRC", preparer.get_addon_src_dir().display().to_string()),
+            ],
+            path,
+        }
+    }
+
+    /// Statements setting the variables in `shell`, one per line
+    pub fn render(&self, shell: Shell) -> String {
+        let mut script = if shell == Shell::Cmd { "@echo off\n".to_string() } else { String::new() };
+        script += &format!("{} Generated by vcpkg_ff {}, load with: {}\n", shell.comment(), env!("CARGO_PKG_VERSION"), shell.usage());
+        for (name, value) in &self.vars {
+            script.push_str(&shell.set(name, value));
+            script.push('\n');
+        }
+        let dirs: Vec<String> = self.path.iter().map(|dir| dir.display().to_string()).collect();
+        script.push_str(&shell.prepend_path(&dirs));
+        script.push('\n');
+        script
+    }
+}
@@ -0,0 +1,131 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::c_lexer;
+use crate::generated_headers::GENERATED_HEADERS;
+use crate::marker;
+
+/// First line of every shim header, used to tell shims apart from real headers
+pub const SHIM_BANNER: &str = "/* vcpkg_ff shim header */";
+
+/// Minimal stand-ins for fftools headers that older ffmpeg trees don't ship
+const STUB_HEADERS: &[(&str, &str)] = &[("ffmpeg_utils.h", FFMPEG_UTILS_STUB)];
+
+const FFMPEG_UTILS_STUB: &str = r#"#ifndef FFTOOLS_FFMPEG_UTILS_H
+#define FFTOOLS_FFMPEG_UTILS_H
+
+#include "libavutil/common.h"
+
+static inline int err_merge(int err0, int err1)
+{
+    return (err0 < 0) ? err0 : FFMIN(err1, 0);
+}
+
+#endif /* FFTOOLS_FFMPEG_UTILS_H */
+"#;
+
+/// A header to create in addon_src so an include resolves
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct Shim {
+    /// Path relative to addon_src
+    pub path: PathBuf,
+    pub content: String,
+}
+
+/// Result of resolving the quoted includes of the addon sources
+#[derive(Debug, Default)]
+pub struct ShimPlan {
+    pub shims: Vec<Shim>,
+    /// Includes that could not be resolved or shimmed, as (including file, include)
+    pub unresolved: Vec<(PathBuf, String)>,
+}
+
+/// Find quoted includes of `sources` that don't resolve against the addon include path
+/// (addon_src, ffmpeg, ffmpeg/fftools) and plan a shim for each one: a forwarding header
+/// when the file exists elsewhere in the ffmpeg tree, or a stub for known fftools headers
+pub fn plan(sources: &[PathBuf], addon_src_dir: &Path, ffmpeg_dir: &Path) -> ShimPlan {
+    let search_dirs = [addon_src_dir.to_path_buf(), ffmpeg_dir.to_path_buf(), ffmpeg_dir.join("fftools")];
+    let mut ffmpeg_files: Option<Vec<PathBuf>> = None;
+    let mut result = ShimPlan::default();
+
+    for source in sources {
+        let Ok(content) = fs::read_to_string(source) else {
+            continue;
+        };
+        let source_dir = source.parent().unwrap_or(addon_src_dir);
+        let relative_dir = source_dir.strip_prefix(addon_src_dir).unwrap_or(Path::new(""));
+
+        for include in c_lexer::quoted_includes(&content) {
+            if GENERATED_HEADERS.contains(&include) {
+                continue;
+            }
+            let resolved = std::iter::once(source_dir)
+                .chain(search_dirs.iter().map(PathBuf::as_path))
+                .map(|dir| dir.join(include))
+                .any(|path| path.is_file() && !is_shim(&path));
+            if resolved {
+                continue;
+            }
+
+            let shim_path = relative_dir.join(include);
+            if result.shims.iter().any(|shim| shim.path == shim_path) {
+                continue;
+            }
+
+            // 1. 相对于原始 fftools 目录的包含（复制到 addon_src 后失效）
+            // 2. ffmpeg 树中其他位置的同名头文件（compat/、libavutil 内部头文件等）
+            let fftools_relative = Path::new("fftools").join(relative_dir).join(include);
+            let target = if ffmpeg_dir.join(&fftools_relative).is_file() {
+                Some(fftools_relative)
+            } else {
+                let files = ffmpeg_files.get_or_insert_with(|| list_headers(ffmpeg_dir));
+                let mut candidates = files.iter().filter(|path| path.ends_with(include));
+                match (candidates.next(), candidates.next()) {
+                    (Some(found), None) => Some(found.strip_prefix(ffmpeg_dir).unwrap_or(found).to_path_buf()),
+                    _ => None,
+                }
+            };
+
+            let content = match target {
+                Some(target) => format!("{}\n#include \"{}\"\n", SHIM_BANNER, target.to_string_lossy().replace('\\', "/")),
+                None => match STUB_HEADERS.iter().find(|(name, _)| *name == include) {
+                    Some((_, stub)) => format!("{}\n{}", SHIM_BANNER, stub),
+                    None => {
+                        result.unresolved.push((source.clone(), include.to_string()));
+                        continue;
+                    }
+                },
+            };
+            result.shims.push(Shim { path: shim_path, content });
+        }
+    }
+
+    result
+}
+
+/// True if `path` is a shim header written by a previous run
+pub fn is_shim(path: &Path) -> bool {
+    fs::read_to_string(path)
+        .map(|content| marker::strip(&content).starts_with(SHIM_BANNER))
+        .unwrap_or(false)
+}
+
+/// Every .h file in the ffmpeg tree
+fn list_headers(ffmpeg_dir: &Path) -> Vec<PathBuf> {
+    let mut headers = Vec::new();
+    let mut pending = vec![ffmpeg_dir.to_path_buf()];
+    while let Some(dir) = pending.pop() {
+        let Ok(entries) = fs::read_dir(&dir) else {
+            continue;
+        };
+        for path in entries.flatten().map(|entry| entry.path()) {
+            if path.is_dir() {
+                pending.push(path);
+            } else if path.extension().is_some_and(|ext| ext == "h") {
+                headers.push(path);
+            }
+        }
+    }
+    headers.sort();
+    headers
+}
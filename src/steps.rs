@@ -0,0 +1,217 @@
+use crate::addon_builder::AddonBuilder;
+use crate::addon_preparer::AddonTarget;
+use crate::error::VcpkgFfError;
+use crate::marker;
+use crate::package_backend::FfmpegSource;
+use crate::pipeline::{PipelineContext, Step};
+use crate::shared_cache::SharedCache;
+
+/// Clone and bootstrap vcpkg, or set up the package manager of another [`PackageBackend`](crate::PackageBackend)
+pub struct InstallVcpkg;
+
+impl Step for InstallVcpkg {
+    fn name(&self) -> &'static str {
+        "install-vcpkg"
+    }
+
+    fn check(&self, ctx: &PipelineContext) -> bool {
+        ctx.packages().is_tool_installed()
+    }
+
+    fn inputs(&self, ctx: &PipelineContext) -> Result<Option<String>, VcpkgFfError> {
+        Ok(Some(marker::content_hash(&ctx.packages().tool_inputs())))
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.packages().install_tool()
+    }
+
+    fn rollback(&self, ctx: &PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.packages().remove_partial_tool_install()
+    }
+}
+
+/// Download the sources install-packages builds (`vcpkg install --only-downloads`), with retries of its own
+pub struct FetchPackages;
+
+impl Step for FetchPackages {
+    fn name(&self) -> &'static str {
+        "fetch-packages"
+    }
+
+    /// Nothing to download once ffmpeg is installed
+    fn check(&self, ctx: &PipelineContext) -> bool {
+        ctx.packages().is_ffmpeg_installed()
+    }
+
+    fn inputs(&self, ctx: &PipelineContext) -> Result<Option<String>, VcpkgFfError> {
+        Ok(Some(marker::content_hash(&ctx.packages().package_inputs())))
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.packages().fetch_packages()
+    }
+}
+
+/// `vcpkg install ffmpeg[x264,x265,vpx]` for the triplet, or the backend's equivalent
+pub struct InstallPackages;
+
+impl Step for InstallPackages {
+    fn name(&self) -> &'static str {
+        "install-packages"
+    }
+
+    fn check(&self, ctx: &PipelineContext) -> bool {
+        ctx.packages().is_ffmpeg_installed()
+    }
+
+    /// The features and the triplet
+    fn inputs(&self, ctx: &PipelineContext) -> Result<Option<String>, VcpkgFfError> {
+        Ok(Some(marker::content_hash(&ctx.packages().package_inputs())))
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.packages().install_packages()
+    }
+}
+
+/// `vcpkg integrate install`, for Visual Studio projects using the same vcpkg (`--integrate`)
+pub struct IntegrateVcpkg;
+
+impl Step for IntegrateVcpkg {
+    fn name(&self) -> &'static str {
+        "integrate-vcpkg"
+    }
+
+    fn check(&self, ctx: &PipelineContext) -> bool {
+        ctx.manager.is_integrated()
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.manager.integrate_install()
+    }
+}
+
+/// Copy the ffmpeg and ffprobe programs vcpkg built into bin/ of the output directory (`--cli-tools`)
+pub struct CopyCliTools;
+
+impl Step for CopyCliTools {
+    fn name(&self) -> &'static str {
+        "copy-cli-tools"
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.cli_tools = ctx.manager.copy_cli_tools()?;
+        Ok(())
+    }
+}
+
+/// Unpack the ffmpeg source archive vcpkg downloaded, or copy the backend's source tree, into ffmpeg/
+pub struct ExtractFfmpeg;
+
+impl Step for ExtractFfmpeg {
+    fn name(&self) -> &'static str {
+        "extract-ffmpeg"
+    }
+
+    fn check(&self, ctx: &PipelineContext) -> bool {
+        ctx.manager.is_ffmpeg_extracted().is_some()
+    }
+
+    /// The content of the source archive, or of the source tree. Without one, sources extracted by hand are kept
+    fn inputs(&self, ctx: &PipelineContext) -> Result<Option<String>, VcpkgFfError> {
+        let (FfmpegSource::Archive(path) | FfmpegSource::Directory(path)) = match ctx.packages().ffmpeg_source() {
+            Ok(source) => source,
+            Err(_) if self.check(ctx) => return Ok(None),
+            Err(e) => return Err(e),
+        };
+        let mut hasher = marker::Hasher::new();
+        hasher.update(ctx.manager.get_output_dir().to_string_lossy().as_bytes());
+        marker::hash_path(&mut hasher, &path);
+        Ok(Some(hasher.finish()))
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        let source = ctx.packages().ffmpeg_source()?;
+        ctx.manager.extract_ffmpeg_from(&source)
+    }
+
+    fn rollback(&self, ctx: &PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.manager.remove_partial_extraction()
+    }
+}
+
+/// Link the installed tree and the ffmpeg sources to the shared cache (shared-cache mode), see [`SharedCache`]
+pub struct Dedupe {
+    cache: SharedCache,
+}
+
+impl Dedupe {
+    pub fn new(cache: SharedCache) -> Self {
+        Self { cache }
+    }
+}
+
+impl Step for Dedupe {
+    fn name(&self) -> &'static str {
+        "dedupe"
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.manager.dedupe(&self.cache).map(drop)
+    }
+}
+
+/// Generate addon_src from the ffmpeg sources and check the result
+pub struct PrepareAddon;
+
+impl Step for PrepareAddon {
+    fn name(&self) -> &'static str {
+        "prepare-addon"
+    }
+
+    /// addon_src and the build files are unchanged since the last preparation
+    fn check(&self, ctx: &PipelineContext) -> bool {
+        ctx.preparer.is_prepared()
+    }
+
+    /// The ffmpeg sources, configure output, patches, templates and configuration
+    fn inputs(&self, ctx: &PipelineContext) -> Result<Option<String>, VcpkgFfError> {
+        Ok(Some(ctx.preparer.prepare_inputs_hash()))
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        ctx.preparer.prepare_addon_source()?;
+        ctx.preparer.validate_generated_sources()
+    }
+}
+
+/// Compile addon_src with npm and smoke test the result (`--build-addon`),
+/// or build the shared library with `deno task build` for the Deno target
+pub struct BuildAddon;
+
+impl Step for BuildAddon {
+    fn name(&self) -> &'static str {
+        "build-addon"
+    }
+
+    fn run(&self, ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        let builder = AddonBuilder::new(ctx.preparer.get_addon_src_dir(), &ctx.preparer.get_log_dir())
+            .with_runtime(ctx.smoke_runtime);
+        if ctx.preparer.get_target() == AddonTarget::Deno {
+            // 冒烟测试通过 node 加载 addon，不适用于 Deno 的共享库
+            return builder.build_deno();
+        }
+        if ctx.preparer.get_target() == AddonTarget::Wasm {
+            return builder.build_wasm();
+        }
+        builder.build()?;
+        let report = builder.smoke_test().map_err(|e| VcpkgFfError::CommandFailed {
+            command: "smoke test".to_string(),
+            message: format!("it could not run: {}", e),
+        })?;
+        report.print();
+        ctx.smoke_report = Some(report);
+        Ok(())
+    }
+}
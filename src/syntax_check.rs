@@ -0,0 +1,171 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use crate::command_runner::{CommandRunner, CommandSpec, SystemRunner};
+use crate::error::VcpkgFfError;
+use crate::progress::say_error;
+
+/// C compiler used for syntax-only checks
+#[derive(Debug, Clone, Copy)]
+enum Compiler {
+    Msvc,
+    Clang,
+    Gcc,
+}
+
+impl Compiler {
+    fn program(&self) -> &'static str {
+        match self {
+            Compiler::Msvc => "cl",
+            Compiler::Clang => "clang",
+            Compiler::Gcc => "gcc",
+        }
+    }
+}
+
+/// An error reported by the compiler
+#[derive(Debug, Clone)]
+pub struct Diagnostic {
+    pub file: PathBuf,
+    pub line: usize,
+    pub message: String,
+}
+
+/// Runs a quick syntax-only compile (cl /Zs, clang/gcc -fsyntax-only) over generated C sources
+pub struct SyntaxChecker {
+    compiler: Compiler,
+    include_dirs: Vec<PathBuf>,
+}
+
+impl SyntaxChecker {
+    /// Find an available compiler, returns None if there is none on PATH
+    pub fn detect(include_dirs: Vec<PathBuf>) -> Option<Self> {
+        let candidates: &[Compiler] = if cfg!(target_os = "windows") {
+            &[Compiler::Msvc, Compiler::Clang]
+        } else {
+            &[Compiler::Clang, Compiler::Gcc]
+        };
+
+        candidates
+            .iter()
+            .find(|compiler| {
+                let probe_arg = if matches!(compiler, Compiler::Msvc) { "/?" } else { "--version" };
+                SystemRunner.run(&CommandSpec::new(compiler.program()).arg(probe_arg))
+                    .is_ok_and(|output| output.success())
+            })
+            .map(|compiler| Self {
+                compiler: *compiler,
+                include_dirs,
+            })
+    }
+
+    pub fn compiler_name(&self) -> &'static str {
+        self.compiler.program()
+    }
+
+    /// Check a single file, returning the errors the compiler reported
+    pub fn check(&self, file: &Path) -> Result<Vec<Diagnostic>, VcpkgFfError> {
+        let command = match self.compiler {
+            Compiler::Msvc => CommandSpec::new(self.compiler.program())
+                .args(["/nologo", "/Zs"])
+                .args(self.include_dirs.iter().map(|dir| format!("/I{}", dir.display()))),
+            Compiler::Clang | Compiler::Gcc => CommandSpec::new(self.compiler.program())
+                .args(["-fsyntax-only", "-std=c11"])
+                .args(self.include_dirs.iter().map(|dir| format!("-I{}", dir.display()))),
+        };
+
+        let output = SystemRunner.run(&command.arg(file))?;
+        if output.success() {
+            return Ok(Vec::new());
+        }
+
+        // cl 把诊断信息写到 stdout，clang/gcc 写到 stderr
+        let text = format!("{}{}", output.stdout, output.stderr);
+        let mut diagnostics: Vec<Diagnostic> = text.lines().filter_map(parse_diagnostic).collect();
+
+        if diagnostics.is_empty() {
+            diagnostics.push(Diagnostic {
+                file: file.to_path_buf(),
+                line: 0,
+                message: text.trim().to_string(),
+            });
+        }
+        Ok(diagnostics)
+    }
+}
+
+impl Diagnostic {
+    /// Print the error with the surrounding source lines
+    pub fn print_with_context(&self) {
+        say_error!("  ✗ {}:{}: {}", self.file.display(), self.line, self.message);
+
+        if self.line == 0 {
+            return;
+        }
+        let Ok(content) = fs::read_to_string(&self.file) else {
+            return;
+        };
+
+        let first = self.line.saturating_sub(1).max(1);
+        for (index, line) in content.lines().enumerate().skip(first - 1).take(self.line + 2 - first) {
+            let number = index + 1;
+            let pointer = if number == self.line { ">" } else { " " };
+            say_error!("    {} {:>5} | {}", pointer, number, line);
+        }
+    }
+}
+
+/// Parse "file:line:col: error: msg" (clang/gcc) or "file(line): error C1234: msg" (MSVC)
+fn parse_diagnostic(line: &str) -> Option<Diagnostic> {
+    if let Some(pos) = line.find(": error: ").or_else(|| line.find(": fatal error: ")) {
+        let message = line[pos..].splitn(3, ": ").nth(2).unwrap_or("").to_string();
+        let mut location = line[..pos].rsplitn(3, ':');
+        let _column = location.next()?;
+        let line_number = location.next()?.parse().ok()?;
+        let file = location.next()?;
+        return Some(Diagnostic {
+            file: PathBuf::from(file),
+            line: line_number,
+            message,
+        });
+    }
+
+    if let Some(pos) = line.find("): error ").or_else(|| line.find("): fatal error ")) {
+        let open = line[..pos].rfind('(')?;
+        let line_number = line[open + 1..pos].split(',').next()?.parse().ok()?;
+        let message = line[pos + 3..].to_string();
+        return Some(Diagnostic {
+            file: PathBuf::from(&line[..open]),
+            line: line_number,
+            message,
+        });
+    }
+
+    None
+}
+
+/// Locate the directory containing node_api.h for the node on PATH
+pub fn find_node_include_dir() -> Option<PathBuf> {
+    let output = SystemRunner.run(&CommandSpec::new("node").args(["-p", "process.execPath + '\\n' + process.version"])).ok()?;
+    if !output.success() {
+        return None;
+    }
+
+    let mut lines = output.stdout.lines();
+    let exec_path = PathBuf::from(lines.next()?.trim());
+    let version = lines.next().unwrap_or("").trim().trim_start_matches('v').to_string();
+    let exec_dir = exec_path.parent()?;
+
+    let mut candidates = vec![
+        exec_dir.join("include").join("node"),
+        exec_dir.join("..").join("include").join("node"),
+    ];
+    // node-gyp 下载的头文件缓存
+    if let Some(home) = std::env::var_os("HOME").or_else(|| std::env::var_os("LOCALAPPDATA")) {
+        let home = PathBuf::from(home);
+        candidates.push(home.join(".cache").join("node-gyp").join(&version).join("include").join("node"));
+        candidates.push(home.join("node-gyp").join("Cache").join(&version).join("include").join("node"));
+    }
+
+    candidates.into_iter().find(|dir| dir.join("node_api.h").exists())
+}
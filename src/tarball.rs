@@ -0,0 +1,34 @@
+use std::io::{Read, Write};
+use std::path::Path;
+
+use serde::de::DeserializeOwned;
+use tar::{Builder, Entries, Header};
+
+use crate::error::VcpkgFfError;
+
+/// Add a regular file `name` holding `content` to `builder`
+pub(crate) fn append_data<W: Write>(builder: &mut Builder<W>, name: &str, content: &[u8]) -> Result<(), VcpkgFfError> {
+    let mut header = Header::new_gnu();
+    header.set_size(content.len() as u64);
+    header.set_mode(0o644);
+    header.set_cksum();
+    builder.append_data(&mut header, name, content)?;
+    Ok(())
+}
+
+/// Read the JSON manifest `name` that is the first of `entries` of the tar.gz at `archive`, which is `kind`
+/// (e.g. "a prebuilt tree") when it has one
+pub(crate) fn read_manifest<T: DeserializeOwned, R: Read>(entries: &mut Entries<R>, archive: &Path, name: &str, kind: &str)
+    -> Result<T, VcpkgFfError> {
+    let invalid = |message: String| VcpkgFfError::InvalidFile { path: archive.to_path_buf(), message };
+    let Some(entry) = entries.next() else {
+        return Err(invalid("the archive is empty".to_string()));
+    };
+    let mut entry = entry?;
+    if entry.path()?.as_ref() != Path::new(name) {
+        return Err(invalid(format!("not {}, {} is missing", kind, name)));
+    }
+    let mut content = String::new();
+    entry.read_to_string(&mut content)?;
+    serde_json::from_str(&content).map_err(|e| invalid(e.to_string()))
+}
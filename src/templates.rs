@@ -0,0 +1,82 @@
+use std::fs;
+use std::path::Path;
+
+use minijinja::syntax::SyntaxConfig;
+use minijinja::{AutoEscape, Environment, Value};
+
+use crate::error::VcpkgFfError;
+use crate::marker;
+use crate::progress::say;
+
+/// Templates shipped with the crate, each one can be replaced by a file of the same name in templates/
+const BUILTIN_TEMPLATES: &[(&str, &str)] = &[
+    ("config.h.jinja", include_str!("templates/config.h.jinja")),
+    ("binding.c.jinja", include_str!("templates/binding.c.jinja")),
+    ("binding.cc.jinja", include_str!("templates/binding.cc.jinja")),
+    ("lib.rs.jinja", include_str!("templates/lib.rs.jinja")),
+    ("ffmpeg_run.c.jinja", include_str!("templates/ffmpeg_run.c.jinja")),
+    ("ffprobe_run.c.jinja", include_str!("templates/ffprobe_run.c.jinja")),
+    ("binding.gyp.jinja", include_str!("templates/binding.gyp.jinja")),
+    ("CMakeLists.txt.jinja", include_str!("templates/CMakeLists.txt.jinja")),
+    ("Cargo.toml.jinja", include_str!("templates/Cargo.toml.jinja")),
+    ("build.rs.jinja", include_str!("templates/build.rs.jinja")),
+    ("cargo_config.toml.jinja", include_str!("templates/cargo_config.toml.jinja")),
+    ("index.js.jinja", include_str!("templates/index.js.jinja")),
+    ("index.d.ts.jinja", include_str!("templates/index.d.ts.jinja")),
+    ("media.test.js.jinja", include_str!("templates/media.test.js.jinja")),
+    ("install.js.jinja", include_str!("templates/install.js.jinja")),
+    ("deno_ffi.c.jinja", include_str!("templates/deno_ffi.c.jinja")),
+    ("CMakeLists.deno.txt.jinja", include_str!("templates/CMakeLists.deno.txt.jinja")),
+    ("mod.ts.jinja", include_str!("templates/mod.ts.jinja")),
+    ("CMakeLists.wasm.txt.jinja", include_str!("templates/CMakeLists.wasm.txt.jinja")),
+    ("wasm.mjs.jinja", include_str!("templates/wasm.mjs.jinja")),
+    ("github-workflow.yml.jinja", include_str!("templates/github-workflow.yml.jinja")),
+    ("azure-pipelines.yml.jinja", include_str!("templates/azure-pipelines.yml.jinja")),
+];
+
+/// Hash of the built-in templates, part of [`marker::generator_version`]
+pub(crate) fn builtin_hash() -> String {
+    let mut hasher = marker::Hasher::new();
+    for (name, builtin) in BUILTIN_TEMPLATES {
+        hasher.update(name.as_bytes());
+        hasher.update(&[0]);
+        hasher.update(builtin.as_bytes());
+        hasher.update(&[0]);
+    }
+    hasher.finish()
+}
+
+/// minijinja templates for the generated C, build and CI files
+pub struct Templates {
+    env: Environment<'static>,
+}
+
+impl Templates {
+    /// Load the built-in templates, preferring overrides from `override_dir`
+    pub fn load(override_dir: &Path) -> Result<Self, VcpkgFfError> {
+        let mut env = Environment::new();
+        env.set_syntax(SyntaxConfig::builder().trim_blocks(true).keep_trailing_newline(true).build()?);
+        // 生成的是 C 代码，不做 HTML 转义
+        env.set_auto_escape_callback(|_| AutoEscape::None);
+
+        for (name, builtin) in BUILTIN_TEMPLATES {
+            let override_path = override_dir.join(name);
+            if override_path.exists() {
+                let source = fs::read_to_string(&override_path)?;
+                env.add_template_owned(*name, source)
+                    .map_err(|e| VcpkgFfError::Config { path: override_path.clone(), message: e.to_string() })?;
+                say!("✓ Using template override: {}", override_path.display());
+            } else {
+                env.add_template(name, builtin)?;
+            }
+        }
+
+        Ok(Self { env })
+    }
+
+    /// Render a template with the given context (see `minijinja::context!`)
+    pub fn render(&self, name: &str, context: Value) -> Result<String, VcpkgFfError> {
+        let template = self.env.get_template(name)?;
+        template.render(context).map_err(|source| VcpkgFfError::RenderFailed { name: name.to_string(), source })
+    }
+}
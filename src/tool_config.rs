@@ -0,0 +1,273 @@
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use serde::Deserialize;
+
+use crate::addon_builder::PrebuildTarget;
+use crate::addon_preparer::{BindingStyle, BuildSystem};
+use crate::error::VcpkgFfError;
+use crate::vcpkg_manager::FFMPEG_FEATURES;
+
+/// Name of the project configuration file, looked up in the base directory
+pub const CONFIG_FILE_NAME: &str = "vcpkg_ff.toml";
+
+/// Value of a `[config_h]` entry: booleans become 1/0, strings become C string literals
+#[derive(Debug, Clone, Deserialize)]
+#[serde(untagged)]
+pub enum DefineValue {
+    Bool(bool),
+    Integer(i64),
+    String(String),
+}
+
+impl DefineValue {
+    /// The value as it appears after `#define NAME`
+    pub fn to_c(&self) -> String {
+        match self {
+            DefineValue::Bool(value) => if *value { "1".to_string() } else { "0".to_string() },
+            DefineValue::Integer(value) => value.to_string(),
+            DefineValue::String(value) => format!("\"{}\"", value.replace('\\', "\\\\").replace('"', "\\\"")),
+        }
+    }
+}
+
+/// `[package]` settings for the generated addon_src/package.json
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PackageConfig {
+    #[serde(default = "default_package_name")]
+    pub name: String,
+    #[serde(default = "default_package_version")]
+    pub version: String,
+    #[serde(default)]
+    pub description: Option<String>,
+    #[serde(default = "default_package_license")]
+    pub license: String,
+}
+
+impl Default for PackageConfig {
+    fn default() -> Self {
+        Self {
+            name: default_package_name(),
+            version: default_package_version(),
+            description: None,
+            license: default_package_license(),
+        }
+    }
+}
+
+fn default_package_name() -> String {
+    "ffmpeg-node".to_string()
+}
+
+fn default_package_version() -> String {
+    "1.0.0".to_string()
+}
+
+// ffmpeg 本身是 LGPL，启用 x264/x265 后实际是 GPL
+fn default_package_license() -> String {
+    "GPL-2.0-or-later".to_string()
+}
+
+/// `[macos]` settings for the generated build files
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct MacOsConfig {
+    /// Oldest macOS version the addon has to load on, defaults to 11.0 on arm64 and 10.15 on x86_64
+    pub deployment_target: Option<String>,
+}
+
+/// `[prebuild]` settings for the `prebuild` command
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PrebuildConfig {
+    /// Runtimes to build prebuilt binaries for, as "node@18.0.0" / "electron@30.0.0"
+    #[serde(default)]
+    pub targets: Vec<String>,
+}
+
+/// `[advanced]` switches for optional generated code
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct AdvancedConfig {
+    /// Patch ffmpeg_filter.c to hand every video frame leaving a filtergraph to run()'s onFrame option
+    #[serde(default)]
+    pub frame_tap: bool,
+}
+
+/// A `[[plugins]]` entry: an executable run as an additional pipeline step
+///
+/// The plugin runs in the project directory with the pipeline context as JSON on stdin, see
+/// [`PipelineContext::to_json`](crate::PipelineContext::to_json). Its stdout is the step's output,
+/// a non-zero exit fails the step.
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct PluginConfig {
+    /// Step name, used in the log, the checkpoint and `--skip`
+    pub name: String,
+    /// Program and arguments; a relative program path is resolved against the project directory
+    pub command: Vec<String>,
+    /// Run right after this step instead of after the last one
+    #[serde(default)]
+    pub after: Option<String>,
+}
+
+/// A `[hooks.<step>]` entry: shell commands run before and after a pipeline step
+///
+/// The commands run with `sh -c` (`cmd /C` on Windows) in the project directory, with VCPKG_FF_STEP and
+/// VCPKG_FF_ROOT set; `post` also gets VCPKG_FF_EXIT_STATUS, 0 when the step succeeded and 1 when it failed.
+#[derive(Debug, Clone, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct HookConfig {
+    /// Runs before the step, a non-zero exit fails the step without running it
+    #[serde(default)]
+    pub pre: Option<String>,
+    /// Runs after the step, also when it failed
+    #[serde(default)]
+    pub post: Option<String>,
+}
+
+/// A `[workspace.targets.<name>]` entry: an addon project built with the workspace's vcpkg and ffmpeg
+#[derive(Debug, Clone, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceTarget {
+    /// Project directory of the addon, relative to the workspace; holds its addon_src/, build files
+    /// and its own vcpkg_ff.toml
+    pub dir: PathBuf,
+    /// ffmpeg port features the addon needs; ffmpeg is installed once with the features of every target
+    #[serde(default = "default_features")]
+    pub features: Vec<String>,
+    /// `--binding-style` of the addon, c by default
+    #[serde(default)]
+    pub binding_style: Option<String>,
+    /// `--build-system` of the addon, gyp by default
+    #[serde(default)]
+    pub build_system: Option<String>,
+}
+
+fn default_features() -> Vec<String> {
+    FFMPEG_FEATURES.iter().map(|feature| feature.to_string()).collect()
+}
+
+/// `[workspace]`: several addon projects sharing the vcpkg/ and ffmpeg/ of this directory
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct WorkspaceConfig {
+    #[serde(default)]
+    pub targets: BTreeMap<String, WorkspaceTarget>,
+}
+
+/// Settings from vcpkg_ff.toml
+#[derive(Debug, Default, Deserialize)]
+#[serde(deny_unknown_fields)]
+pub struct ToolConfig {
+    /// Defines merged into config.h, overriding the generated or copied values
+    #[serde(default)]
+    pub config_h: BTreeMap<String, DefineValue>,
+    #[serde(default)]
+    pub package: PackageConfig,
+    #[serde(default)]
+    pub macos: MacOsConfig,
+    #[serde(default)]
+    pub prebuild: PrebuildConfig,
+    #[serde(default)]
+    pub advanced: AdvancedConfig,
+    #[serde(default)]
+    pub plugins: Vec<PluginConfig>,
+    /// Hooks by step name, checked against the steps when the pipeline is built
+    #[serde(default)]
+    pub hooks: BTreeMap<String, HookConfig>,
+    #[serde(default)]
+    pub workspace: WorkspaceConfig,
+    /// Environment variables set on every command vcpkg_ff runs, e.g. HTTPS_PROXY or VCPKG_BINARY_SOURCES
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
+}
+
+impl ToolConfig {
+    /// Load vcpkg_ff.toml from `base_dir`, returns the defaults if the file doesn't exist
+    pub fn load(base_dir: &Path) -> Result<Self, VcpkgFfError> {
+        let path = base_dir.join(CONFIG_FILE_NAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+
+        let invalid = |message: String| VcpkgFfError::Config { path: path.clone(), message };
+        let config: ToolConfig = toml::from_str(&fs::read_to_string(&path)?)
+            .map_err(|e| invalid(e.to_string()))?;
+
+        for name in config.config_h.keys() {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                return Err(invalid(format!("invalid [config_h] define name `{}`", name)));
+            }
+        }
+        // npm 包名规则：小写、无空格，可带 @scope/ 前缀
+        let name = &config.package.name;
+        let valid_name = !name.is_empty() && name.len() <= 214 && !name.starts_with(['.', '_'])
+            && name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || "-._~@/".contains(c));
+        if !valid_name {
+            return Err(invalid(format!("invalid [package] name `{}`", name)));
+        }
+        let version = &config.package.version;
+        let core = version.split(['-', '+']).next().unwrap_or("");
+        if core.split('.').count() != 3 || !core.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())) {
+            return Err(invalid(format!("[package] version `{}` is not a semver version", version)));
+        }
+        if let Some(target) = &config.macos.deployment_target {
+            if target.split('.').count() > 3 || !target.split('.').all(|part| !part.is_empty() && part.chars().all(|c| c.is_ascii_digit())) {
+                return Err(invalid(format!("invalid [macos] deployment_target `{}`", target)));
+            }
+        }
+        for target in &config.prebuild.targets {
+            PrebuildTarget::parse(target).map_err(|e| invalid(format!("[prebuild] {}", e)))?;
+        }
+        for (index, plugin) in config.plugins.iter().enumerate() {
+            let name = &plugin.name;
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+                return Err(invalid(format!("[[plugins]] name `{}` must be kebab-case", name)));
+            }
+            if config.plugins[..index].iter().any(|other| other.name == *name) {
+                return Err(invalid(format!("[[plugins]] name `{}` is used twice", name)));
+            }
+            if plugin.command.is_empty() {
+                return Err(invalid(format!("[[plugins]] {} has an empty command", name)));
+            }
+        }
+        for name in config.env.keys() {
+            if name.is_empty() || name.contains(['=', '\0']) {
+                return Err(invalid(format!("invalid [env] variable name `{}`", name)));
+            }
+        }
+        for (step, hook) in &config.hooks {
+            let empty = |command: &Option<String>| command.as_ref().is_some_and(|c| c.trim().is_empty());
+            if empty(&hook.pre) || empty(&hook.post) {
+                return Err(invalid(format!("[hooks.{}] has an empty command", step)));
+            }
+        }
+        for (name, target) in &config.workspace.targets {
+            if name.is_empty() || !name.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-') {
+                return Err(invalid(format!("[workspace.targets] name `{}` must be kebab-case", name)));
+            }
+            if target.dir.is_absolute() || target.dir.as_os_str().is_empty() {
+                return Err(invalid(format!("[workspace.targets.{}] dir must be a path relative to the workspace", name)));
+            }
+            // vcpkg 的 feature 名只有小写字母、数字和连字符
+            if let Some(feature) = target.features.iter().find(|f| f.is_empty() || !f.chars().all(|c| c.is_ascii_lowercase() || c.is_ascii_digit() || c == '-')) {
+                return Err(invalid(format!("[workspace.targets.{}] invalid feature `{}`", name, feature)));
+            }
+            if let Some(style) = &target.binding_style {
+                BindingStyle::parse(style).map_err(|e| invalid(format!("[workspace.targets.{}] {}", name, e)))?;
+            }
+            if let Some(build_system) = &target.build_system {
+                BuildSystem::parse(build_system).map_err(|e| invalid(format!("[workspace.targets.{}] {}", name, e)))?;
+            }
+        }
+        Ok(config)
+    }
+
+    /// `[config_h]` entries as (name, C value) pairs
+    pub fn config_h_overrides(&self) -> Vec<(String, String)> {
+        self.config_h.iter().map(|(name, value)| (name.clone(), value.to_c())).collect()
+    }
+}
@@ -0,0 +1,61 @@
+use std::fs;
+use std::path::Path;
+use std::sync::Mutex;
+use std::time::Instant;
+
+use crate::error::VcpkgFfError;
+
+/// Spans of the running pipeline, in the Chrome trace event format.
+/// Process-wide like the journal, commands are started deep inside the manager and preparer.
+static CURRENT: Mutex<Option<Recorder>> = Mutex::new(None);
+
+struct Recorder {
+    origin: Instant,
+    events: Vec<serde_json::Value>,
+}
+
+/// Run `f` recording spans, then write them to `path` as a Chrome trace (chrome://tracing, Perfetto)
+///
+/// The trace is written whether `f` succeeded or not; `metadata` goes into its `otherData`.
+pub(crate) fn with_trace<T>(path: &Path, metadata: serde_json::Value, f: impl FnOnce() -> T) -> Result<T, VcpkgFfError> {
+    let recorder = Recorder { origin: Instant::now(), events: Vec::new() };
+    let previous = CURRENT.lock().ok().and_then(|mut current| current.replace(recorder));
+    let result = f();
+    let recorder = CURRENT.lock().ok().and_then(|mut current| std::mem::replace(&mut *current, previous));
+
+    let events = recorder.map(|recorder| recorder.events).unwrap_or_default();
+    let trace = serde_json::json!({
+        "traceEvents": events,
+        "displayTimeUnit": "ms",
+        "otherData": metadata,
+    });
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::write(path, serde_json::to_string_pretty(&trace)? + "\n")?;
+    Ok(result)
+}
+
+/// Run `f` as a complete ("X") event named `name` in category `category` ("step" or "command"),
+/// with the `args` describing its result
+pub(crate) fn span<T>(category: &str, name: &str, f: impl FnOnce() -> T, args: impl FnOnce(&T) -> serde_json::Value) -> T {
+    let start = Instant::now();
+    let result = f();
+    if let Ok(mut current) = CURRENT.lock() {
+        if let Some(recorder) = current.as_mut() {
+            // Chrome trace 的时间单位是微秒
+            let ts = start.duration_since(recorder.origin).as_micros() as u64;
+            recorder.events.push(serde_json::json!({
+                "name": name,
+                "cat": category,
+                "ph": "X",
+                "ts": ts,
+                "dur": start.elapsed().as_micros() as u64,
+                "pid": std::process::id(),
+                "tid": 1,
+                "args": args(&result),
+            }));
+        }
+    }
+    result
+}
@@ -0,0 +1,137 @@
+use std::fs;
+use std::path::{Path, PathBuf};
+
+use regex::Regex;
+use serde::Deserialize;
+
+use crate::error::VcpkgFfError;
+use crate::patch_engine::PatchRule;
+use crate::progress::say;
+
+/// One `[[rule]]` entry of an addon_patches/*.toml file
+#[derive(Debug, Deserialize)]
+struct RuleSpec {
+    /// File in addon_src the rule applies to (e.g. "ffmpeg.c", "binding.c")
+    file: String,
+    #[serde(flatten)]
+    kind: RuleKind,
+}
+
+#[derive(Debug, Deserialize)]
+#[serde(tag = "kind", rename_all = "kebab-case")]
+enum RuleKind {
+    Replace { from: String, to: String },
+    Regex { pattern: String, replacement: String },
+    MakeNonStatic { signature: String },
+    RemoveFunction { signature: String, #[serde(default)] replacement: String },
+    InsertAfterInclude { include: String, text: String },
+    AppendBlock { marker: String, block: String },
+    WrapInConditional { line: String, condition: String },
+}
+
+#[derive(Debug, Default, Deserialize)]
+struct RuleFile {
+    #[serde(default)]
+    rule: Vec<RuleSpec>,
+}
+
+/// User customizations from the addon_patches/ directory: replacement rules from `*.toml`
+/// files and verbatim extra sources from `sources/`, applied after the built-in pipeline
+#[derive(Debug, Default)]
+pub struct UserPatches {
+    rules: Vec<(String, PatchRule)>,
+    /// (absolute source path, path relative to addon_src)
+    extra_sources: Vec<(PathBuf, PathBuf)>,
+}
+
+impl UserPatches {
+    pub fn load(dir: &Path) -> Result<Self, VcpkgFfError> {
+        let mut user_patches = UserPatches::default();
+        if !dir.exists() {
+            return Ok(user_patches);
+        }
+
+        let mut rule_files: Vec<PathBuf> = fs::read_dir(dir)?
+            .flatten()
+            .map(|entry| entry.path())
+            .filter(|path| path.extension().is_some_and(|ext| ext == "toml"))
+            .collect();
+        rule_files.sort();
+
+        for rule_file in &rule_files {
+            let invalid = |message: String| VcpkgFfError::Config { path: rule_file.clone(), message };
+            let parsed: RuleFile = toml::from_str(&fs::read_to_string(rule_file)?)
+                .map_err(|e| invalid(e.to_string()))?;
+
+            for spec in parsed.rule {
+                let rule = spec.kind.into_rule().map_err(invalid)?;
+                user_patches.rules.push((spec.file, rule));
+            }
+        }
+
+        let sources_dir = dir.join("sources");
+        if sources_dir.exists() {
+            let mut pending = vec![sources_dir.clone()];
+            while let Some(current) = pending.pop() {
+                for entry in fs::read_dir(&current)?.flatten() {
+                    let path = entry.path();
+                    if path.is_dir() {
+                        pending.push(path);
+                    } else {
+                        let Ok(relative) = path.strip_prefix(&sources_dir).map(Path::to_path_buf) else {
+                            continue;
+                        };
+                        user_patches.extra_sources.push((path, relative));
+                    }
+                }
+            }
+            user_patches.extra_sources.sort();
+        }
+
+        if !user_patches.rules.is_empty() || !user_patches.extra_sources.is_empty() {
+            say!("Found {} user rule(s) and {} extra source file(s) in {}",
+                user_patches.rules.len(), user_patches.extra_sources.len(), dir.display());
+        }
+
+        Ok(user_patches)
+    }
+
+    /// User rules targeting `file_name`, in declaration order
+    pub fn rules_for(&self, file_name: &str) -> Vec<PatchRule> {
+        self.rules
+            .iter()
+            .filter(|(file, _)| file == file_name)
+            .map(|(_, rule)| rule.clone())
+            .collect()
+    }
+
+    /// Names of all files targeted by user rules
+    pub fn target_files(&self) -> Vec<&str> {
+        let mut files: Vec<&str> = self.rules.iter().map(|(file, _)| file.as_str()).collect();
+        files.sort();
+        files.dedup();
+        files
+    }
+
+    pub fn extra_sources(&self) -> &[(PathBuf, PathBuf)] {
+        &self.extra_sources
+    }
+}
+
+impl RuleKind {
+    fn into_rule(self) -> Result<PatchRule, String> {
+        Ok(match self {
+            RuleKind::Replace { from, to } => PatchRule::replace_text(&from, &to),
+            RuleKind::Regex { pattern, replacement } => {
+                // 加载时就校验正则表达式，避免在打补丁时才失败
+                Regex::new(&pattern).map_err(|e| format!("invalid regex `{}`: {}", pattern, e))?;
+                PatchRule::regex_replace(&pattern, &replacement)
+            }
+            RuleKind::MakeNonStatic { signature } => PatchRule::make_non_static(&signature),
+            RuleKind::RemoveFunction { signature, replacement } => PatchRule::remove_function(&signature, &replacement),
+            RuleKind::InsertAfterInclude { include, text } => PatchRule::insert_after_include(&include, &text),
+            RuleKind::AppendBlock { marker, block } => PatchRule::append_block(&marker, &block),
+            RuleKind::WrapInConditional { line, condition } => PatchRule::wrap_in_conditional(&line, &condition),
+        })
+    }
+}
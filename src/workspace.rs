@@ -0,0 +1,86 @@
+use std::collections::BTreeMap;
+use std::path::{Path, PathBuf};
+
+use crate::addon_preparer::{AddonPreparer, BindingStyle, BuildSystem};
+use crate::error::VcpkgFfError;
+use crate::tool_config::{self, ToolConfig, WorkspaceTarget};
+use crate::vcpkg_manager::VcpkgManager;
+
+/// Addon projects sharing one vcpkg and ffmpeg installation, from `[workspace.targets]` in vcpkg_ff.toml
+///
+/// vcpkg/ and ffmpeg/ live in the workspace directory; each target generates its addon in its own
+/// project directory, built with `vcpkg_ff build <target>`.
+///
+/// ```toml
+/// [workspace.targets.transcoder]
+/// dir = "services/transcoder"
+/// features = ["x264", "x265"]
+///
+/// [workspace.targets.thumbnails]
+/// dir = "services/thumbnails"
+/// features = ["vpx"]
+/// binding_style = "napi-rs"
+/// ```
+pub struct Workspace {
+    root: PathBuf,
+    targets: BTreeMap<String, WorkspaceTarget>,
+}
+
+impl Workspace {
+    /// Read the targets from vcpkg_ff.toml in `root`, an error when it declares none
+    pub fn load(root: &Path) -> Result<Self, VcpkgFfError> {
+        let targets = ToolConfig::load(root)?.workspace.targets;
+        if targets.is_empty() {
+            return Err(VcpkgFfError::Config {
+                path: root.join(tool_config::CONFIG_FILE_NAME),
+                message: "no [workspace.targets] declared".to_string(),
+            });
+        }
+        Ok(Self { root: root.to_path_buf(), targets })
+    }
+
+    pub fn target_names(&self) -> Vec<&str> {
+        self.targets.keys().map(String::as_str).collect()
+    }
+
+    /// The features of every target, each once, in the order the targets declare them
+    pub fn features(&self) -> Vec<String> {
+        let mut features: Vec<String> = Vec::new();
+        for feature in self.targets.values().flat_map(|target| &target.features) {
+            if !features.contains(feature) {
+                features.push(feature.clone());
+            }
+        }
+        features
+    }
+
+    /// The shared vcpkg in the workspace, installing ffmpeg with the features of all targets so that
+    /// building one target never reinstalls ffmpeg for another
+    pub fn manager(&self) -> VcpkgManager {
+        VcpkgManager::builder().base_dir(&self.root).features(self.features()).build()
+    }
+
+    /// The preparer generating the addon of `target` in its project directory from the shared ffmpeg/
+    pub fn preparer(&self, target: &str) -> Result<AddonPreparer, VcpkgFfError> {
+        let config = self.targets.get(target).ok_or_else(|| VcpkgFfError::Unknown {
+            kind: "workspace target",
+            name: target.to_string(),
+            expected: self.target_names().iter().map(|name| name.to_string()).collect(),
+        })?;
+
+        let mut preparer = AddonPreparer::builder()
+            .base_dir(self.root.join(&config.dir))
+            .source_dir(self.root.join("ffmpeg"))
+            .vcpkg_root(self.root.join("vcpkg"))
+            .build();
+        // 名称在加载配置时已校验
+        let invalid = |message: String| VcpkgFfError::Config { path: self.root.join(tool_config::CONFIG_FILE_NAME), message };
+        if let Some(style) = &config.binding_style {
+            preparer = preparer.with_binding_style(BindingStyle::parse(style).map_err(invalid)?);
+        }
+        if let Some(build_system) = &config.build_system {
+            preparer = preparer.with_build_system(BuildSystem::parse(build_system).map_err(invalid)?);
+        }
+        Ok(preparer)
+    }
+}
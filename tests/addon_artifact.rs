@@ -0,0 +1,92 @@
+//! `pack-addon` and `unpack-addon`: a prepared addon_src shared with another machine instead of preparing it there
+
+mod support;
+
+use std::fs::{self, File};
+use std::path::Path;
+
+use flate2::read::GzDecoder;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use support::{copy_dir, fixture, RecordingObserver, TestProject};
+use vcpkg_ff::{AddonArtifact, AddonPreparer, BindingStyle, Pipeline, SkipReason, StepStatus, VcpkgFfError, VcpkgManager};
+
+/// A project in `dir` with the ffmpeg sources extracted
+fn project_in(dir: &Path) -> AddonPreparer {
+    copy_dir(&fixture("ffmpeg-7.1"), &dir.join("ffmpeg"));
+    AddonPreparer::builder().base_dir(dir).build().with_syntax_check(false)
+}
+
+#[test]
+fn unpacked_artifact_skips_the_preparation() {
+    let project = TestProject::new();
+    let (first_dir, second_dir) = (project.root().join("a"), project.root().join("b"));
+    let first = project_in(&first_dir);
+    first.prepare_addon_source().unwrap();
+    let archive = AddonArtifact::new(&first).pack(None).unwrap();
+    assert!(archive.starts_with(first_dir.join("dist")));
+    assert!(archive.file_name().unwrap().to_string_lossy().starts_with("addon_src-7.1"));
+
+    let second = project_in(&second_dir);
+    assert!(AddonArtifact::new(&second).unpack(&archive).unwrap());
+
+    assert_eq!(fs::read(second_dir.join("addon_src").join("binding.c")).unwrap(),
+        fs::read(first_dir.join("addon_src").join("binding.c")).unwrap());
+    assert!(second_dir.join("binding.gyp").is_file());
+    let manager = VcpkgManager::builder().base_dir(&second_dir).build();
+    let observer = RecordingObserver::new();
+    let mut pipeline = Pipeline::new().with_manager(manager).with_preparer(second).with_observer(observer.clone())
+        .with_skip(["install-vcpkg", "fetch-packages", "install-packages", "extract-ffmpeg"].map(String::from).to_vec());
+    assert!(pipeline.adopt("prepare-addon").unwrap());
+    pipeline.run().unwrap();
+    assert_eq!(observer.status("prepare-addon"), Some(StepStatus::Skipped(SkipReason::AlreadyDone)));
+}
+
+#[test]
+fn artifact_of_other_inputs_is_prepared_again() {
+    let project = TestProject::new();
+    let first = project_in(&project.root().join("a"));
+    first.prepare_addon_source().unwrap();
+    let archive = AddonArtifact::new(&first).pack(Some(&project.root().join("addon.tar.gz"))).unwrap();
+
+    let second = project_in(&project.root().join("b")).with_binding_style(BindingStyle::NodeAddonApi);
+    assert!(!AddonArtifact::new(&second).unpack(&archive).unwrap());
+    assert!(project.root().join("b").join("addon_src").join("binding.c").is_file());
+
+    let other_triplet = AddonPreparer::builder().base_dir(project.root().join("c")).triplet("wasm32-emscripten").build();
+    let error = AddonArtifact::new(&other_triplet).unpack(&archive).unwrap_err();
+    assert!(error.to_string().contains("this project builds for wasm32-emscripten"), "{}", error);
+}
+
+#[test]
+fn damaged_artifact_leaves_addon_src_as_it_was() {
+    let project = TestProject::new();
+    let first = project_in(&project.root().join("a"));
+    first.prepare_addon_source().unwrap();
+    let archive = AddonArtifact::new(&first).pack(Some(&project.root().join("addon.tar.gz"))).unwrap();
+    // 在合法条目之后多一个意料之外的条目
+    let damaged = project.root().join("damaged.tar.gz");
+    let mut builder = tar::Builder::new(GzEncoder::new(File::create(&damaged).unwrap(), Compression::fast()));
+    let mut original = tar::Archive::new(GzDecoder::new(File::open(&archive).unwrap()));
+    for entry in original.entries().unwrap() {
+        let mut entry = entry.unwrap();
+        let header = entry.header().clone();
+        builder.append(&header, &mut entry).unwrap();
+    }
+    let mut header = tar::Header::new_gnu();
+    header.set_size(0);
+    header.set_cksum();
+    builder.append_data(&mut header, "unexpected.txt", &[][..]).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+
+    let second_dir = project.root().join("b");
+    let second = project_in(&second_dir);
+    second.prepare_addon_source().unwrap();
+    fs::write(second_dir.join("addon_src").join("binding.c"), "local").unwrap();
+    let error = AddonArtifact::new(&second).unpack(&damaged).unwrap_err();
+
+    assert!(matches!(&error, VcpkgFfError::InvalidFile { message, .. } if message.contains("unexpected.txt")), "{}", error);
+    assert_eq!(fs::read_to_string(second_dir.join("addon_src").join("binding.c")).unwrap(), "local");
+    assert!(second_dir.join("addon_src").join("ffmpeg.c").is_file());
+    assert!(!second_dir.join(".vcpkg_ff-artifact").exists());
+}
@@ -0,0 +1,62 @@
+//! Step timings recorded in the benchmark history and the `bench` report comparing the runs
+
+#![cfg(unix)]
+
+mod support;
+
+use std::collections::BTreeMap;
+
+use support::{RecordingObserver, TestProject};
+use vcpkg_ff::{BenchHistory, BenchRun, StepSample};
+
+#[test]
+fn every_run_is_recorded_with_its_steps_and_settings() {
+    let project = TestProject::new();
+    project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    project.pipeline(&RecordingObserver::new()).run().unwrap();
+    project.pipeline(&RecordingObserver::new()).with_skip(vec!["prepare-addon".to_string()]).run().unwrap();
+
+    let history = BenchHistory::load(&BenchHistory::path_in(project.root())).unwrap();
+    let runs = history.runs();
+    assert_eq!(runs.len(), 2);
+    let steps: Vec<(&str, &str)> = runs[0].steps.iter().map(|sample| (sample.step.as_str(), sample.status.as_str())).collect();
+    assert_eq!(steps, [("install-vcpkg", "completed"), ("fetch-packages", "completed"), ("install-packages", "completed"), ("extract-ffmpeg", "completed"), ("prepare-addon", "completed")]);
+    let extract = &runs[0].steps[3];
+    assert!(extract.wall_secs > 0.0);
+    assert!(extract.peak_disk_bytes.unwrap() > 0);
+    if cfg!(target_os = "linux") {
+        assert!(extract.cpu_secs.is_some());
+    }
+    assert_eq!(runs[1].steps[4].status, "skipped");
+    assert_eq!(runs[1].settings["backend"], "vcpkg");
+    assert_eq!(runs[1].settings["features"], "x264,x265,vpx");
+}
+
+#[test]
+fn report_compares_the_last_run_with_the_earlier_ones() {
+    let run = |secs: &[f64], binary_sources: &str| BenchRun {
+        started: 0,
+        settings: BTreeMap::from([("binary_sources".to_string(), binary_sources.to_string())]),
+        steps: ["install-packages", "prepare-addon"].iter().zip(secs).map(|(step, secs)| StepSample {
+            step: step.to_string(),
+            status: "completed".to_string(),
+            wall_secs: *secs,
+            cpu_secs: Some(*secs),
+            peak_disk_bytes: Some(3 * 1024 * 1024),
+        }).collect(),
+    };
+    let project = TestProject::new();
+    let path = BenchHistory::path_in(project.root());
+    for run in [run(&[1800.0, 2.0], "default"), run(&[2400.0, 4.0], "default"), run(&[60.0, 3.0], "clear;files,/cache,readwrite")] {
+        BenchHistory::append(&path, &run).unwrap();
+    }
+
+    let report = BenchHistory::load(&path).unwrap().report().unwrap();
+
+    let install = report.lines().find(|line| line.trim_start().starts_with("install-packages")).unwrap();
+    let columns: Vec<&str> = install.split_whitespace().collect();
+    assert_eq!(columns, ["install-packages", "1m00s", "40m00s", "35m00s", "-97%", "1m00s", "3.0", "MiB"]);
+    assert!(report.contains("median of 2 earlier run(s)"), "{}", report);
+    assert!(report.contains("binary_sources: default -> clear;files,/cache,readwrite"), "{}", report);
+    assert_eq!(BenchHistory::load(&project.root().join("none.jsonl")).unwrap().report(), None);
+}
@@ -0,0 +1,42 @@
+//! `--bun`: an addon generated to also load under Bun's N-API implementation
+
+mod support;
+
+use std::fs;
+
+use support::TestProject;
+use vcpkg_ff::napi_version::BUN_NAPI_VERSION;
+
+#[test]
+fn bun_caps_the_napi_version_and_registers_with_napi_module() {
+    let project = TestProject::new();
+    project.extract_fixture("ffmpeg-7.1");
+
+    project.preparer().with_napi_version(Some(10)).with_bun(true).prepare_addon_source().unwrap();
+
+    let addon_src = project.root().join("addon_src");
+    let binding = fs::read_to_string(addon_src.join("binding.c")).unwrap();
+    assert!(binding.contains(&format!("#define NAPI_VERSION {}", BUN_NAPI_VERSION)));
+    assert!(binding.contains("NAPI_MODULE(ffmpeg_node, ffmpeg_module_init)"));
+    assert!(!binding.contains("NAPI_MODULE_INIT()"));
+    let package: serde_json::Value = serde_json::from_str(&fs::read_to_string(addon_src.join("package.json")).unwrap()).unwrap();
+    assert_eq!(package["binary"]["napi_versions"][0], BUN_NAPI_VERSION);
+    assert!(package["engines"]["bun"].is_string());
+    let index_js = fs::read_to_string(addon_src.join("index.js")).unwrap();
+    assert!(index_js.contains("process.versions.bun"));
+    assert!(!index_js.contains("generated without --bun"));
+}
+
+#[test]
+fn node_addon_warns_when_loaded_by_bun() {
+    let project = TestProject::new();
+    project.extract_fixture("ffmpeg-7.1");
+
+    project.preparer().prepare_addon_source().unwrap();
+
+    let addon_src = project.root().join("addon_src");
+    assert!(fs::read_to_string(addon_src.join("binding.c")).unwrap().contains("NAPI_MODULE_INIT()"));
+    assert!(fs::read_to_string(addon_src.join("index.js")).unwrap().contains("generated without --bun"));
+    let package: serde_json::Value = serde_json::from_str(&fs::read_to_string(addon_src.join("package.json")).unwrap()).unwrap();
+    assert!(package["engines"].get("bun").is_none());
+}
@@ -0,0 +1,59 @@
+//! Workflows written by `vcpkg_ff generate ci`
+
+mod support;
+
+use std::fs;
+
+use support::TestProject;
+use vcpkg_ff::{CiGenerator, CiProvider, VcpkgFfError};
+
+#[test]
+fn github_workflow_builds_every_platform_and_caches_by_features() {
+    let project = TestProject::new();
+    fs::write(project.root().join("vcpkg_ff.toml"),
+        "[macos]\ndeployment_target = \"11.0\"\n\n[prebuild]\ntargets = [\"node@20.0.0\", \"electron@30.0.0\"]\n").unwrap();
+
+    let path = CiGenerator::new(project.root())
+        .with_features(&["x264".to_string(), "vpx".to_string()])
+        .generate(CiProvider::GitHub)
+        .unwrap();
+
+    assert_eq!(path, project.root().join(".github").join("workflows").join("prebuild.yml"));
+    let workflow = fs::read_to_string(&path).unwrap();
+    for triplet in ["x64-linux", "arm64-linux", "x64-osx", "arm64-osx", "x64-windows-static"] {
+        assert!(workflow.contains(&format!("triplet: {}", triplet)), "{}", triplet);
+    }
+    assert!(workflow.contains("runs-on: ${{ matrix.runner }}\n"));
+    assert!(workflow.contains("VCPKG_BINARY_SOURCES: clear;files,${{ github.workspace }}/.vcpkg-cache,readwrite"));
+    assert!(workflow.contains("MACOSX_DEPLOYMENT_TARGET: \"11.0\""));
+    assert!(workflow.contains("key: vcpkg-ffmpeg-x264-vpx-${{ matrix.triplet }}-"));
+    assert!(workflow.contains("prebuild --targets node@20.0.0,electron@30.0.0"));
+    assert!(workflow.contains("softprops/action-gh-release"));
+}
+
+#[test]
+fn azure_pipeline_keys_the_cache_on_existing_files_only() {
+    let project = TestProject::new();
+
+    let path = CiGenerator::new(project.root()).generate(CiProvider::Azure).unwrap();
+
+    let pipeline = fs::read_to_string(&path).unwrap();
+    assert!(pipeline.contains("key: '\"vcpkg-ffmpeg-x264-x265-vpx\" | \"$(triplet)\"'"), "{}", pipeline);
+    assert!(pipeline.contains("prebuild --targets node@20.0.0\n"));
+    assert!(!pipeline.contains("MACOSX_DEPLOYMENT_TARGET"));
+}
+
+#[test]
+fn handwritten_workflow_is_not_overwritten() {
+    let project = TestProject::new();
+    fs::write(project.root().join("azure-pipelines.yml"), "trigger: none\n").unwrap();
+    let generator = CiGenerator::new(project.root());
+
+    let error = generator.generate(CiProvider::Azure).unwrap_err();
+
+    assert!(matches!(error, VcpkgFfError::Config { .. }), "{:?}", error);
+    assert_eq!(fs::read_to_string(project.root().join("azure-pipelines.yml")).unwrap(), "trigger: none\n");
+    fs::remove_file(project.root().join("azure-pipelines.yml")).unwrap();
+    generator.generate(CiProvider::Azure).unwrap();
+    generator.generate(CiProvider::Azure).unwrap();
+}
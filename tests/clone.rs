@@ -0,0 +1,213 @@
+//! Installing vcpkg over an existing clone, updated in place instead of cloned again, checking clones
+//! before the bootstrap and diagnosing failed bootstraps
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex};
+
+use support::TestProject;
+use vcpkg_ff::{CommandOutput, CommandRunner, CommandSpec, VcpkgFfError, VcpkgManager};
+
+const MIRROR: &str = "https://example.invalid/vcpkg.git";
+
+/// Answers git and the bootstrap script without starting them: the bootstrap creates the vcpkg executable
+/// and a clone checks out vcpkg, only .git for the first `truncated_clones` clones
+struct ScriptedRunner {
+    vcpkg_root: PathBuf,
+    fail_fetch: bool,
+    truncated_clones: AtomicUsize,
+    /// Output of a failing bootstrap, which succeeds when None
+    bootstrap_failure: Mutex<Option<String>>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl ScriptedRunner {
+    fn new(vcpkg_root: PathBuf, fail_fetch: bool) -> Arc<Self> {
+        Self::truncating(vcpkg_root, fail_fetch, 0)
+    }
+
+    fn truncating(vcpkg_root: PathBuf, fail_fetch: bool, truncated_clones: usize) -> Arc<Self> {
+        Arc::new(Self { vcpkg_root, fail_fetch, truncated_clones: AtomicUsize::new(truncated_clones),
+            bootstrap_failure: Mutex::new(None), calls: Mutex::new(Vec::new()) })
+    }
+
+    fn fail_bootstrap(&self, stderr: &str) {
+        *self.bootstrap_failure.lock().unwrap() = Some(stderr.to_string());
+    }
+
+    fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+}
+
+impl CommandRunner for ScriptedRunner {
+    fn run(&self, command: &CommandSpec) -> io::Result<CommandOutput> {
+        let program = command.program.file_name().unwrap().to_string_lossy().into_owned();
+        let args: Vec<String> = command.args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+        self.calls.lock().unwrap().push(format!("{} {}", program, args.join(" ")));
+        match (program.as_str(), args.first().map(String::as_str)) {
+            ("git", Some("fetch")) if self.fail_fetch => return Ok(CommandOutput::exited(128, "", "fatal: bad object HEAD")),
+            ("git", Some("clone")) => {
+                fs::create_dir_all(self.vcpkg_root.join(".git"))?;
+                let truncated = self.truncated_clones.fetch_update(Ordering::SeqCst, Ordering::SeqCst, |left| left.checked_sub(1)).is_ok();
+                if !truncated {
+                    check_out(&self.vcpkg_root);
+                }
+            }
+            ("bash", _) => match self.bootstrap_failure.lock().unwrap().as_deref() {
+                Some(stderr) => return Ok(CommandOutput::exited(1, "Downloading vcpkg...", stderr)),
+                None => fs::write(self.vcpkg_root.join("vcpkg"), "")?,
+            },
+            _ => {}
+        }
+        Ok(CommandOutput::exited(0, "", ""))
+    }
+}
+
+/// The files a vcpkg checkout is checked for
+fn check_out(vcpkg_root: &Path) {
+    for file in ["bootstrap-vcpkg.sh", "bootstrap-vcpkg.bat", "ports/ffmpeg/portfile.cmake", "scripts/buildsystems/vcpkg.cmake"] {
+        let path = vcpkg_root.join(file);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(path, "").unwrap();
+    }
+}
+
+/// A clone left behind by a failed bootstrap
+fn clone_without_executable(project: &TestProject) -> PathBuf {
+    let vcpkg_root = project.root().join("vcpkg");
+    fs::create_dir_all(vcpkg_root.join(".git")).unwrap();
+    check_out(&vcpkg_root);
+    vcpkg_root
+}
+
+fn manager(project: &TestProject, runner: Arc<ScriptedRunner>) -> VcpkgManager {
+    VcpkgManager::builder().base_dir(project.root()).mirrors([MIRROR]).runner(runner).build()
+}
+
+#[test]
+fn clone_with_a_failed_bootstrap_is_updated_instead_of_recloned() {
+    let project = TestProject::new();
+    let vcpkg_root = clone_without_executable(&project);
+    let runner = ScriptedRunner::new(vcpkg_root.clone(), false);
+
+    manager(&project, runner.clone()).install_vcpkg().unwrap();
+
+    let calls = runner.calls();
+    assert!(calls.contains(&format!("git fetch --depth 1 {} HEAD", MIRROR)), "{:?}", calls);
+    assert!(calls.contains(&"git reset --hard FETCH_HEAD".to_string()), "{:?}", calls);
+    assert!(!calls.iter().any(|call| call.starts_with("git clone")), "{:?}", calls);
+    assert!(vcpkg_root.join("ports").join("ffmpeg").join("portfile.cmake").exists());
+    assert!(vcpkg_root.join("vcpkg").exists());
+}
+
+#[test]
+fn clone_that_cannot_be_updated_is_replaced_by_a_fresh_clone() {
+    let project = TestProject::new();
+    let vcpkg_root = clone_without_executable(&project);
+    fs::write(vcpkg_root.join("leftover.txt"), "").unwrap();
+    let runner = ScriptedRunner::new(vcpkg_root.clone(), true);
+
+    manager(&project, runner.clone()).install_vcpkg().unwrap();
+
+    let calls = runner.calls();
+    assert_eq!(calls.iter().filter(|call| call.starts_with("git clone")).count(), 1, "{:?}", calls);
+    assert!(!vcpkg_root.join("leftover.txt").exists());
+    assert!(vcpkg_root.join("vcpkg").exists());
+}
+
+#[test]
+fn rollback_keeps_the_clone_for_the_next_install() {
+    let project = TestProject::new();
+    let vcpkg_root = clone_without_executable(&project);
+
+    manager(&project, ScriptedRunner::new(vcpkg_root.clone(), false)).remove_partial_install().unwrap();
+
+    assert!(vcpkg_root.join("ports").join("ffmpeg").join("portfile.cmake").exists());
+}
+
+#[test]
+fn truncated_clone_is_cloned_again_before_the_bootstrap() {
+    let project = TestProject::new();
+    let vcpkg_root = project.root().join("vcpkg");
+    let runner = ScriptedRunner::truncating(vcpkg_root.clone(), false, 1);
+
+    manager(&project, runner.clone()).install_vcpkg().unwrap();
+
+    let calls = runner.calls();
+    let clones: Vec<usize> = calls.iter().enumerate().filter(|(_, call)| call.starts_with("git clone")).map(|(index, _)| index).collect();
+    let bootstrap = calls.iter().position(|call| call.starts_with("bash")).unwrap();
+    assert_eq!(clones.len(), 2, "{:?}", calls);
+    assert!(clones[1] < bootstrap, "{:?}", calls);
+}
+
+#[test]
+fn fsck_checks_the_clone_objects_when_enabled() {
+    let project = TestProject::new();
+    let vcpkg_root = clone_without_executable(&project);
+    let runner = ScriptedRunner::new(vcpkg_root, false);
+
+    manager(&project, runner.clone()).with_fsck(true).install_vcpkg().unwrap();
+
+    let calls = runner.calls();
+    let verify = calls.iter().position(|call| call == "git rev-parse --verify HEAD^{commit}");
+    let fsck = calls.iter().position(|call| call.starts_with("git fsck"));
+    let bootstrap = calls.iter().position(|call| call.starts_with("bash"));
+    assert!(verify.is_some() && fsck.is_some() && fsck < bootstrap, "{:?}", calls);
+}
+
+#[test]
+fn failed_bootstrap_names_the_likely_cause() {
+    let project = TestProject::new();
+    let vcpkg_root = clone_without_executable(&project);
+    let runner = ScriptedRunner::new(vcpkg_root, false);
+    runner.fail_bootstrap("curl: (6) Could not resolve host: github.com");
+
+    let error = manager(&project, runner).install_vcpkg().unwrap_err();
+
+    match error {
+        VcpkgFfError::BootstrapFailed { cause, .. } => assert!(cause.is_some_and(|cause| cause.starts_with("the vcpkg download host could not be resolved")), "{:?}", cause),
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn unrecognized_bootstrap_failure_quotes_the_output() {
+    let project = TestProject::new();
+    let vcpkg_root = clone_without_executable(&project);
+    let runner = ScriptedRunner::new(vcpkg_root, false);
+    runner.fail_bootstrap("something unexpected happened");
+
+    let error = manager(&project, runner).install_vcpkg().unwrap_err();
+
+    let message = error.to_string();
+    assert!(message.contains("exit code 1"), "{}", message);
+    assert!(message.contains("  something unexpected happened"), "{}", message);
+}
+
+#[cfg(feature = "async")]
+#[test]
+fn async_install_goes_through_the_runner() {
+    let project = TestProject::new();
+    let vcpkg_root = clone_without_executable(&project);
+    let runner = ScriptedRunner::new(vcpkg_root, false);
+    runner.fail_bootstrap("curl: (6) Could not resolve host: github.com");
+    // 本地不存在的路径，探测立即失败
+    let mirror = project.root().join("mirror").to_string_lossy().into_owned();
+    let manager = VcpkgManager::builder().base_dir(project.root()).mirrors([mirror.as_str()]).runner(runner.clone()).build();
+
+    let runtime = tokio::runtime::Builder::new_current_thread().enable_all().build().unwrap();
+    let error = runtime.block_on(manager.install_vcpkg_async()).unwrap_err();
+
+    assert!(runner.calls().contains(&format!("git fetch --depth 1 {} HEAD", mirror)), "{:?}", runner.calls());
+    match error {
+        VcpkgFfError::BootstrapFailed { cause, .. } => assert!(cause.is_some_and(|cause| cause.starts_with("the vcpkg download host could not be resolved")), "{:?}", cause),
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
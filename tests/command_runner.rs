@@ -0,0 +1,61 @@
+//! The child-process utility every external command goes through: capture, log, timeout and injected env
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+use std::time::{Duration, Instant};
+
+use support::TestProject;
+use vcpkg_ff::command_runner::inject_env;
+use vcpkg_ff::{CommandRunner, CommandSpec, SystemRunner};
+
+fn shell(script: &str) -> CommandSpec {
+    CommandSpec::new("sh").args(["-c", script])
+}
+
+#[test]
+fn output_is_captured_and_written_to_the_log() {
+    let project = TestProject::new();
+    let log = project.root().join("command.log");
+
+    let output = SystemRunner.run(&shell("echo out; echo err >&2; exit 3").log(&log)).unwrap();
+
+    assert_eq!(output.code, Some(3));
+    assert_eq!(output.stdout, "out\n");
+    assert_eq!(output.stderr, "err\n");
+    let logged = fs::read_to_string(&log).unwrap();
+    assert!(logged.contains("out\n") && logged.contains("err\n"), "{}", logged);
+}
+
+#[test]
+fn command_running_past_its_timeout_is_killed() {
+    let started = Instant::now();
+
+    let output = SystemRunner.run(&shell("sleep 30").timeout(Duration::from_millis(200))).unwrap();
+
+    assert!(output.timed_out);
+    assert!(!output.success());
+    assert!(started.elapsed() < Duration::from_secs(10));
+}
+
+#[test]
+fn injected_variables_reach_every_command_below_its_own() {
+    inject_env([("VCPKG_FF_TEST_INJECTED", "injected"), ("VCPKG_FF_TEST_OVERRIDDEN", "injected"), ("HTTPS_PROXY", "http://proxy.invalid:3128")]);
+
+    let output = SystemRunner.run(&shell("echo $VCPKG_FF_TEST_INJECTED $VCPKG_FF_TEST_OVERRIDDEN $https_proxy")
+        .env("VCPKG_FF_TEST_OVERRIDDEN", "own")).unwrap();
+
+    assert_eq!(output.stdout, "injected own http://proxy.invalid:3128\n");
+}
+
+#[test]
+fn lines_that_are_not_utf8_are_decoded_lossily_and_reading_goes_on() {
+    // GBK 编码的 "错误"，后面的行不能丢
+    let output = SystemRunner.run(&shell("printf 'first\\n\\264\\355\\316\\363\\r\\nlast\\n'; printf '\\377err\\n' >&2").streamed()).unwrap();
+
+    assert!(output.success());
+    assert_eq!(output.stdout, "first\n\u{FFFD}\u{FFFD}\u{FFFD}\u{FFFD}\nlast\n");
+    assert_eq!(output.stderr, "\u{FFFD}err\n");
+}
@@ -0,0 +1,150 @@
+//! The Conan backend: ffmpeg installed with conan into vcpkg's installed tree, the rest of the pipeline unchanged
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::{Arc, Mutex};
+
+use support::{fixture, RecordingObserver, TestProject};
+use vcpkg_ff::{AddonPreparer, CommandOutput, CommandRunner, CommandSpec, ConanBackend, Pipeline, SkipReason, StepStatus, VcpkgFfError, VcpkgManager};
+
+const TRIPLET: &str = "x64-linux";
+
+/// Answers conan without starting it: `install` creates a package per dependency below `cache` and prints the
+/// dependency graph, `cache path` points at the fixture sources and the default profile exists once detected
+struct ScriptedConan {
+    cache: PathBuf,
+    has_profile: Mutex<bool>,
+    calls: Mutex<Vec<String>>,
+}
+
+impl ScriptedConan {
+    fn new(cache: PathBuf) -> Arc<Self> {
+        Arc::new(Self { cache, has_profile: Mutex::new(false), calls: Mutex::new(Vec::new()) })
+    }
+
+    fn calls(&self) -> Vec<String> {
+        self.calls.lock().unwrap().clone()
+    }
+
+    fn install(&self, args: &[String]) -> io::Result<CommandOutput> {
+        let value_after = |flag: &str| args.iter().position(|arg| arg == flag).map(|index| args[index + 1].clone());
+        let build_type = args.iter().find_map(|arg| arg.strip_prefix("build_type=")).unwrap().to_string();
+        let mut nodes = serde_json::Map::new();
+        nodes.insert("0".to_string(), serde_json::json!({"ref": "conanfile", "context": "host", "package_folder": null}));
+        for (index, name) in ["ffmpeg", "libx264"].iter().enumerate() {
+            let package = self.cache.join(format!("{}-{}", name, build_type)).join("p");
+            let library = if *name == "ffmpeg" { "libavcodec.a" } else { "libx264.a" };
+            write(&package.join("lib").join(library), "archive")?;
+            let mut node = serde_json::json!({"ref": format!("{}/1.0#0", name), "context": "host", "package_folder": package});
+            if *name == "ffmpeg" {
+                write(&package.join("include").join("libavcodec").join("avcodec.h"), "/* avcodec */")?;
+                write(&package.join("bin").join("ffmpeg"), "fake ffmpeg")?;
+                write(&package.join("bin").join("ffprobe"), "fake ffprobe")?;
+                let build = self.cache.join(format!("ffmpeg-{}", build_type)).join("b");
+                write(&build.join("config.h"), "#define FFMPEG_CONFIGURATION \"conan\"\n#define HAVE_THREADS 1\n")?;
+                node["build_folder"] = serde_json::json!(build);
+            }
+            nodes.insert((index + 1).to_string(), node);
+        }
+        nodes.insert("3".to_string(), serde_json::json!({"ref": "nasm/2.16#0", "context": "build",
+            "package_folder": self.cache.join("nasm")}));
+        write(&PathBuf::from(value_after("--output-folder").unwrap()).join("libavcodec.pc"), "Name: libavcodec\n")?;
+        Ok(CommandOutput::exited(0, &serde_json::json!({"graph": {"nodes": nodes}}).to_string(), ""))
+    }
+}
+
+impl CommandRunner for ScriptedConan {
+    fn run(&self, command: &CommandSpec) -> io::Result<CommandOutput> {
+        let args: Vec<String> = command.args.iter().map(|arg| arg.to_string_lossy().into_owned()).collect();
+        self.calls.lock().unwrap().push(args.join(" "));
+        match args.iter().map(String::as_str).collect::<Vec<_>>().as_slice() {
+            ["--version"] => Ok(CommandOutput::exited(0, "Conan version 2.9.0\n", "")),
+            ["profile", "path", "default"] if *self.has_profile.lock().unwrap() => Ok(CommandOutput::exited(0, "/home/.conan2/profiles/default\n", "")),
+            ["profile", "path", "default"] => Ok(CommandOutput::exited(1, "", "ERROR: The default build profile doesn't exist")),
+            ["profile", "detect"] => {
+                *self.has_profile.lock().unwrap() = true;
+                Ok(CommandOutput::exited(0, "", ""))
+            }
+            ["install", ..] => self.install(&args),
+            ["cache", "path", _, "--folder", "source"] => Ok(CommandOutput::exited(0, &format!("{}\n", fixture("ffmpeg-7.1").display()), "")),
+            _ => Ok(CommandOutput::exited(1, "", "ERROR: unexpected command")),
+        }
+    }
+}
+
+fn write(path: &Path, content: &str) -> io::Result<()> {
+    fs::create_dir_all(path.parent().unwrap())?;
+    fs::write(path, content)
+}
+
+fn conan_pipeline(project: &TestProject, manager: VcpkgManager, conan: &Arc<ScriptedConan>, observer: &Arc<RecordingObserver>) -> Pipeline {
+    let preparer = AddonPreparer::builder().base_dir(project.root()).triplet(TRIPLET).build().with_syntax_check(false);
+    Pipeline::new()
+        .with_backend(ConanBackend::for_manager(&manager).with_runner(conan.clone()))
+        .with_manager(manager)
+        .with_preparer(preparer)
+        .with_observer(observer.clone())
+}
+
+#[test]
+fn conan_installs_into_the_vcpkg_tree_and_the_addon_is_generated_from_its_sources() {
+    let project = TestProject::new();
+    let conan = ScriptedConan::new(project.root().join("conan-cache"));
+    let manager = VcpkgManager::builder().base_dir(project.root()).triplet(TRIPLET).build().with_cli_tools();
+    let observer = RecordingObserver::new();
+
+    let outcome = conan_pipeline(&project, manager.clone(), &conan, &observer).with_cli_tools(true).run().unwrap();
+
+    assert_eq!(observer.status("install-vcpkg"), Some(StepStatus::Completed));
+    assert_eq!(observer.status("install-packages"), Some(StepStatus::Completed));
+    let calls = conan.calls();
+    assert!(calls.contains(&"profile detect".to_string()));
+    let installs: Vec<&String> = calls.iter().filter(|call| call.starts_with("install ")).collect();
+    assert_eq!(installs.len(), 2);
+    for option in ["ffmpeg/*:shared=False", "ffmpeg/*:with_libx264=True", "ffmpeg/*:with_libvpx=True", "ffmpeg/*:with_programs=True"] {
+        assert!(installs[0].contains(&format!("-o {}", option)), "{} missing from {}", option, installs[0]);
+    }
+    assert!(installs[0].contains("--requires=ffmpeg/7.1.1") && installs[0].contains("-s build_type=Release"));
+    assert!(installs[1].contains("-s build_type=Debug"));
+
+    let installed = project.root().join("vcpkg").join("installed").join(TRIPLET);
+    assert!(installed.join("include").join("libavcodec").join("avcodec.h").exists());
+    assert!(installed.join("lib").join("libavcodec.a").exists());
+    assert!(installed.join("lib").join("libx264.a").exists());
+    assert!(installed.join("debug").join("lib").join("libx264.a").exists());
+    assert!(!installed.join("debug").join("include").exists());
+    assert!(installed.join("lib").join("pkgconfig").join("libavcodec.pc").exists());
+    assert_eq!(outcome.cli_tools, vec![project.root().join("bin").join("ffmpeg"), project.root().join("bin").join("ffprobe")]);
+
+    assert!(project.root().join("ffmpeg").join("fftools").join("ffmpeg.c").exists());
+    let config_h = fs::read_to_string(outcome.addon_src_dir.join("config.h")).unwrap();
+    assert!(config_h.contains("FFMPEG_CONFIGURATION \"conan\""));
+    assert!(outcome.addon_src_dir.join("ffmpeg.c").exists());
+
+    let observer = RecordingObserver::new();
+    conan_pipeline(&project, manager, &conan, &observer).run().unwrap();
+    assert_eq!(observer.status("install-packages"), Some(StepStatus::Skipped(SkipReason::AlreadyDone)));
+    assert_eq!(conan.calls().iter().filter(|call| call.starts_with("install ")).count(), 2);
+}
+
+#[test]
+fn features_without_a_conan_option_fail_the_install() {
+    let project = TestProject::new();
+    let conan = ScriptedConan::new(project.root().join("conan-cache"));
+    let manager = VcpkgManager::builder().base_dir(project.root()).triplet(TRIPLET).features(["x264", "nvcodec"]).build();
+
+    let error = conan_pipeline(&project, manager, &conan, &RecordingObserver::new()).run().unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { source, .. } => {
+            assert!(matches!(&*source, VcpkgFfError::Unknown { name, .. } if name == "nvcodec"), "{:?}", source);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(!conan.calls().iter().any(|call| call.starts_with("install ")));
+}
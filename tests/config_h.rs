@@ -0,0 +1,58 @@
+//! config.h switches cross-checked against the ffmpeg features vcpkg has installed
+
+mod support;
+
+use std::fs;
+
+use support::TestProject;
+use vcpkg_ff::{AddonPreparer, VcpkgFfError};
+
+/// config.h left in vcpkg's build tree by an earlier install with postproc and without x265
+const STALE_CONFIG_H: &str = "#ifndef FFMPEG_CONFIG_H\n#define FFMPEG_CONFIG_H\n#define CONFIG_LIBX264 1\n\
+    #define CONFIG_LIBX265 0\n#define CONFIG_POSTPROC 1\n#define CONFIG_AVDEVICE 1\n#endif\n";
+
+/// A project whose vcpkg build tree has [`STALE_CONFIG_H`] and whose status database lists ffmpeg[x264,x265]
+fn stale_project() -> TestProject {
+    let project = TestProject::new();
+    project.extract_fixture("ffmpeg-7.1");
+    let vcpkg = project.root().join("vcpkg");
+    let buildtree = vcpkg.join("buildtrees").join("ffmpeg").join("x64-linux-rel");
+    fs::create_dir_all(&buildtree).unwrap();
+    fs::write(buildtree.join("config.h"), STALE_CONFIG_H).unwrap();
+    fs::create_dir_all(vcpkg.join("installed").join("vcpkg")).unwrap();
+    let status: String = [None, Some("x264"), Some("x265")].iter()
+        .map(|feature| format!("Package: ffmpeg\n{}Architecture: x64-linux\nStatus: install ok installed\n\n",
+            feature.map(|feature| format!("Feature: {}\n", feature)).unwrap_or_default()))
+        .collect();
+    fs::write(vcpkg.join("installed").join("vcpkg").join("status"), status).unwrap();
+    project
+}
+
+fn preparer(project: &TestProject) -> AddonPreparer {
+    AddonPreparer::builder().base_dir(project.root()).triplet("x64-linux").build().with_syntax_check(false)
+}
+
+#[test]
+fn stale_switches_are_corrected_to_the_installed_features() {
+    let project = stale_project();
+
+    preparer(&project).prepare_addon_source().unwrap();
+
+    let config_h = fs::read_to_string(project.root().join("addon_src").join("config.h")).unwrap();
+    assert!(config_h.contains("#define CONFIG_POSTPROC 0"));
+    assert!(config_h.contains("#define CONFIG_LIBX265 1"));
+    assert!(config_h.contains("#define CONFIG_LIBX264 1"));
+    assert!(config_h.contains("#define CONFIG_AVDEVICE 1"));
+    let opt_common = fs::read_to_string(project.root().join("addon_src").join("opt_common.c")).unwrap();
+    assert!(opt_common.contains("#if CONFIG_POSTPROC"));
+}
+
+#[test]
+fn config_h_entry_against_the_installed_features_fails() {
+    let project = stale_project();
+    fs::write(project.root().join("vcpkg_ff.toml"), "[config_h]\nCONFIG_POSTPROC = true\n").unwrap();
+
+    let error = preparer(&project).prepare_addon_source().unwrap_err();
+
+    assert!(matches!(error, VcpkgFfError::ValidationFailed { check: "config-h", count: 1 }), "{}", error);
+}
@@ -0,0 +1,49 @@
+//! `--target deno`: a shared library with a C ABI and a Deno.dlopen binding instead of an N-API addon
+
+mod support;
+
+use std::fs;
+
+use support::TestProject;
+use vcpkg_ff::AddonTarget;
+
+#[test]
+fn deno_target_generates_a_c_abi_library_and_a_dlopen_binding() {
+    let project = TestProject::new();
+    project.extract_fixture("ffmpeg-7.1");
+
+    project.preparer().with_target(AddonTarget::Deno).prepare_addon_source().unwrap();
+
+    let addon_src = project.root().join("addon_src");
+    let wrapper = fs::read_to_string(addon_src.join("ffmpeg_deno.c")).unwrap();
+    assert!(wrapper.contains("FFMPEG_DENO_EXPORT int32_t ffmpeg_deno_run("));
+    let ffmpeg_c = fs::read_to_string(addon_src.join("ffmpeg.c")).unwrap();
+    assert!(!ffmpeg_c.contains("node_api.h"));
+    let mod_ts = fs::read_to_string(addon_src.join("mod.ts")).unwrap();
+    assert!(mod_ts.contains("Deno.dlopen(candidate, SYMBOLS)"));
+    assert!(mod_ts.contains("ffmpeg_deno_run: {"));
+    let deno_json: serde_json::Value = serde_json::from_str(&fs::read_to_string(addon_src.join("deno.json")).unwrap()).unwrap();
+    assert!(deno_json["tasks"]["build"].as_str().unwrap().starts_with("cmake -S .. -B ../build"));
+
+    let cmake_lists = fs::read_to_string(project.root().join("CMakeLists.txt")).unwrap();
+    assert!(cmake_lists.contains("project(ffmpeg_deno C)"));
+    assert!(cmake_lists.contains("addon_src/ffmpeg_deno.c"));
+    assert!(!cmake_lists.contains("CMAKE_JS"));
+    for node_file in ["binding.c", "package.json", "index.js", "install.js"] {
+        assert!(!addon_src.join(node_file).exists(), "{} generated for the deno target", node_file);
+    }
+    assert!(!project.root().join("binding.gyp").exists());
+}
+
+#[test]
+fn switching_to_the_deno_target_removes_the_napi_binding() {
+    let project = TestProject::new();
+    project.extract_fixture("ffmpeg-7.1");
+    project.preparer().prepare_addon_source().unwrap();
+    assert!(project.root().join("addon_src").join("binding.c").exists());
+
+    project.preparer().with_target(AddonTarget::Deno).prepare_addon_source().unwrap();
+
+    assert!(!project.root().join("addon_src").join("binding.c").exists());
+    assert!(project.root().join("addon_src").join("ffmpeg_deno.c").exists());
+}
@@ -0,0 +1,80 @@
+//! Extracting the ffmpeg sources again only writes the files that are missing, changed or different in the archive
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs::{self, File};
+use std::path::PathBuf;
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use support::{fixture, TestProject};
+use vcpkg_ff::FfmpegSource;
+
+/// Fixture `name` as a tar.gz with one top-level directory, like the archives vcpkg downloads
+fn source_archive(project: &TestProject, name: &str) -> PathBuf {
+    let path = project.root().join(format!("{}.tar.gz", name));
+    let mut builder = tar::Builder::new(GzEncoder::new(File::create(&path).unwrap(), Compression::fast()));
+    builder.append_dir_all(format!("FFmpeg-{}", name), fixture(name)).unwrap();
+    builder.into_inner().unwrap().finish().unwrap();
+    path
+}
+
+#[test]
+fn only_missing_and_changed_files_are_written_again() {
+    let project = TestProject::new();
+    let manager = project.manager();
+    let archive = FfmpegSource::Archive(source_archive(&project, "ffmpeg-7.1"));
+    manager.extract_ffmpeg_from(&archive).unwrap();
+    let ffmpeg_dir = project.root().join("ffmpeg");
+    let release_mtime = fs::metadata(ffmpeg_dir.join("RELEASE")).unwrap().modified().unwrap();
+
+    fs::write(ffmpeg_dir.join("fftools").join("ffmpeg.c"), "edited").unwrap();
+    fs::remove_file(ffmpeg_dir.join("libavutil").join("version.h")).unwrap();
+    manager.extract_ffmpeg_from(&archive).unwrap();
+
+    assert_eq!(fs::read(ffmpeg_dir.join("fftools").join("ffmpeg.c")).unwrap(),
+        fs::read(fixture("ffmpeg-7.1").join("fftools").join("ffmpeg.c")).unwrap());
+    assert!(ffmpeg_dir.join("libavutil").join("version.h").is_file());
+    assert_eq!(fs::metadata(ffmpeg_dir.join("RELEASE")).unwrap().modified().unwrap(), release_mtime);
+    assert!(!project.root().join(".ffmpeg_temp").exists());
+}
+
+#[test]
+fn files_the_new_source_lacks_are_removed() {
+    let project = TestProject::new();
+    let manager = project.manager();
+    manager.extract_ffmpeg_from(&FfmpegSource::Archive(source_archive(&project, "ffmpeg-7.1"))).unwrap();
+    let ffmpeg_dir = project.root().join("ffmpeg");
+    fs::write(ffmpeg_dir.join("local.txt"), "not from the archive").unwrap();
+
+    manager.extract_ffmpeg_from(&FfmpegSource::Directory(fixture("ffmpeg-6.1"))).unwrap();
+
+    assert_eq!(fs::read(ffmpeg_dir.join("RELEASE")).unwrap(), fs::read(fixture("ffmpeg-6.1").join("RELEASE")).unwrap());
+    assert!(!ffmpeg_dir.join("fftools").join("ffmpeg_sched.c").exists());
+    assert!(!ffmpeg_dir.join("libavutil").join("version.h").exists());
+    assert!(ffmpeg_dir.join("local.txt").exists());
+    let index = fs::read_to_string(project.root().join(".ffmpeg_index")).unwrap();
+    assert_eq!(index.lines().count(), 2, "{}", index);
+}
+
+#[test]
+fn interrupted_extraction_is_finished_by_the_next_run() {
+    let project = TestProject::new();
+    let manager = project.manager();
+    let archive = fs::read(source_archive(&project, "ffmpeg-7.1")).unwrap();
+    let downloads = project.root().join("vcpkg").join("downloads");
+    fs::create_dir_all(&downloads).unwrap();
+    fs::write(downloads.join("ffmpeg-7.1.tar.gz"), &archive[..archive.len() / 2]).unwrap();
+    assert!(manager.extract_ffmpeg().is_err());
+    let ffmpeg_dir = project.root().join("ffmpeg");
+    assert!(ffmpeg_dir.is_dir());
+
+    fs::write(downloads.join("ffmpeg-7.1.tar.gz"), &archive).unwrap();
+    manager.extract_ffmpeg().unwrap();
+
+    for relative in ["RELEASE", "fftools/ffmpeg.c", "fftools/ffmpeg_sched.c", "libavutil/version.h"] {
+        assert_eq!(fs::read(ffmpeg_dir.join(relative)).unwrap(), fs::read(fixture("ffmpeg-7.1").join(relative)).unwrap(), "{}", relative);
+    }
+}
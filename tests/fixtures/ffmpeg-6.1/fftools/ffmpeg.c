@@ -0,0 +1,15 @@
+/*
+ * Reduced fftools/ffmpeg.c of ffmpeg 6.1 for the vcpkg_ff tests: transcode() still runs without a Scheduler.
+ */
+
+#include "config.h"
+
+static int transcode(void)
+{
+    return 0;
+}
+
+int main(int argc, char **argv)
+{
+    return transcode();
+}
@@ -0,0 +1,2 @@
+#include "config.h"
+#include "cmdutils.h"
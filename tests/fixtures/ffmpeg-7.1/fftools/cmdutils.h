@@ -0,0 +1,6 @@
+#ifndef FFTOOLS_CMDUTILS_H
+#define FFTOOLS_CMDUTILS_H
+
+extern const char program_name[];
+
+#endif /* FFTOOLS_CMDUTILS_H */
@@ -0,0 +1,49 @@
+/*
+ * Reduced fftools/ffmpeg.c of ffmpeg 7.1 for the vcpkg_ff tests: only the parts the patch rules match.
+ */
+
+#include "config.h"
+
+#include <stdio.h>
+
+#include "libavformat/avformat.h"
+
+#include "cmdutils.h"
+#include "ffmpeg.h"
+#include "ffmpeg_sched.h"
+#include "ffmpeg_utils.h"
+
+const char program_name[] = "ffmpeg";
+
+static void ffmpeg_cleanup(int ret)
+{
+    avformat_network_deinit();
+}
+
+static void print_report(int is_last_report, int64_t timer_start, int64_t cur_time, int64_t pts)
+{
+    AVBPrint buf_script;
+
+    if (!print_stats && !is_last_report && !progress_avio)
+        return;
+
+    if (progress_avio) {
+        av_bprintf(&buf_script, "progress=%s\n",
+                   is_last_report ? "end" : "continue");
+    }
+}
+
+static int transcode(Scheduler *sch)
+{
+    return 0;
+}
+
+int main(int argc, char **argv)
+{
+    Scheduler *sch = NULL;
+    int ret;
+
+    ret = transcode(sch);
+    ffmpeg_cleanup(ret);
+    return ret;
+}
@@ -0,0 +1,12 @@
+#ifndef FFTOOLS_FFMPEG_H
+#define FFTOOLS_FFMPEG_H
+
+#include "config.h"
+#include "cmdutils.h"
+#include "ffmpeg_sched.h"
+
+extern int print_stats;
+
+void ffmpeg_cleanup(int ret);
+
+#endif /* FFTOOLS_FFMPEG_H */
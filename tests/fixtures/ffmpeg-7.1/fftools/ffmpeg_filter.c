@@ -0,0 +1,7 @@
+#include "ffmpeg.h"
+
+static int fg_output_frame(AVFilterContext *filter, AVFrame *frame)
+{
+    frame->time_base = av_buffersink_get_time_base(filter);
+    return 0;
+}
@@ -0,0 +1,3 @@
+#include "ffmpeg.h"
+
+int print_stats = -1;
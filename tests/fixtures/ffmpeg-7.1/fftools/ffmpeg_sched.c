@@ -0,0 +1 @@
+#include "ffmpeg_sched.h"
@@ -0,0 +1,6 @@
+#ifndef FFTOOLS_FFMPEG_SCHED_H
+#define FFTOOLS_FFMPEG_SCHED_H
+
+typedef struct Scheduler Scheduler;
+
+#endif /* FFTOOLS_FFMPEG_SCHED_H */
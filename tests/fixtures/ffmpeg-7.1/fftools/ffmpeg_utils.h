@@ -0,0 +1,4 @@
+#ifndef FFTOOLS_FFMPEG_UTILS_H
+#define FFTOOLS_FFMPEG_UTILS_H
+
+#endif /* FFTOOLS_FFMPEG_UTILS_H */
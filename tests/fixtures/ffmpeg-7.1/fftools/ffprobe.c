@@ -0,0 +1,50 @@
+/*
+ * Reduced fftools/ffprobe.c of ffmpeg 7.1 for the vcpkg_ff tests: only the parts the patch rules match.
+ */
+
+#include "config.h"
+
+#include <stdarg.h>
+#include <stdio.h>
+
+#include "libavformat/avformat.h"
+#include "cmdutils.h"
+#include "opt_common.h"
+
+typedef struct WriterContext WriterContext;
+
+const char program_name[] = "ffprobe";
+
+static inline void writer_w8_printf(WriterContext *wctx, int b)
+{
+    printf("%c", b);
+}
+
+static inline void writer_put_str_printf(WriterContext *wctx, const char *str)
+{
+    printf("%s", str);
+}
+
+static inline void writer_printf_printf(WriterContext *wctx, const char *fmt, ...)
+{
+    va_list ap;
+
+    va_start(ap, fmt);
+    vprintf(fmt, ap);
+    va_end(ap);
+}
+
+static void show_library_versions(WriterContext *w)
+{
+    SHOW_LIB_VERSION(avutil,     AVUTIL);
+    SHOW_LIB_VERSION(postproc,   POSTPROC);
+}
+
+int main(int argc, char **argv)
+{
+    int ret = 0;
+
+    avformat_network_init();
+    avformat_network_deinit();
+    return ret < 0;
+}
@@ -0,0 +1,8 @@
+#include "config.h"
+#include "opt_common.h"
+
+static void print_all_libs_info(int flags, int level)
+{
+    PRINT_LIB_INFO(avutil,     AVUTIL,     flags, level);
+    PRINT_LIB_INFO(postproc,   POSTPROC,   flags, level);
+}
@@ -0,0 +1,6 @@
+#ifndef FFTOOLS_OPT_COMMON_H
+#define FFTOOLS_OPT_COMMON_H
+
+#include "cmdutils.h"
+
+#endif /* FFTOOLS_OPT_COMMON_H */
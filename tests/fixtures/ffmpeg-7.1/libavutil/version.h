@@ -0,0 +1,8 @@
+#ifndef AVUTIL_VERSION_H
+#define AVUTIL_VERSION_H
+
+#define LIBAVUTIL_VERSION_MAJOR  59
+#define LIBAVUTIL_VERSION_MINOR  39
+#define LIBAVUTIL_VERSION_MICRO 100
+
+#endif /* AVUTIL_VERSION_H */
@@ -0,0 +1,70 @@
+//! The journal a pipeline run leaves behind and the repair of an interrupted run
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+
+use support::{RecordingObserver, TestProject};
+use vcpkg_ff::journal::{Action, JournalEntry};
+use vcpkg_ff::Journal;
+
+fn journal(project: &TestProject) -> Journal {
+    Journal::load(&Journal::path_in(project.root())).unwrap()
+}
+
+#[test]
+fn failed_run_journals_its_actions_and_completed_run_removes_the_journal() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    vcpkg.fail("install");
+
+    project.pipeline(&RecordingObserver::new()).run().unwrap_err();
+
+    let journal = journal(&project);
+    assert!(journal.interrupted().is_empty());
+    let outcomes = journal.outcomes();
+    assert!(outcomes.contains(&(&Action::Step { name: "install-vcpkg".to_string() }, Some(true))), "{:?}", outcomes);
+    assert!(outcomes.contains(&(&Action::Step { name: "install-packages".to_string() }, Some(false))), "{:?}", outcomes);
+    assert!(outcomes.iter().any(|(action, ok)| matches!(action, Action::Command { command } if command.contains(" install ffmpeg[")) && *ok == Some(false)),
+        "{:?}", outcomes);
+
+    vcpkg.succeed("install");
+    project.pipeline(&RecordingObserver::new()).run().unwrap();
+
+    assert!(!Journal::path_in(project.root()).exists());
+}
+
+#[test]
+fn repair_finishes_interrupted_removals_and_rolls_back_interrupted_steps() {
+    let project = TestProject::new();
+    project.fake_vcpkg();
+    let temp_dir = project.root().join(".ffmpeg_temp");
+    fs::create_dir_all(temp_dir.join("FFmpeg-7.1")).unwrap();
+    let stale = project.root().join("ffmpeg");
+    fs::create_dir_all(stale.join("fftools")).unwrap();
+    let partial = project.root().join("addon_src").join("ffmpeg.c");
+    fs::create_dir_all(partial.parent().unwrap()).unwrap();
+    fs::write(&partial, "int ffmpeg_run_").unwrap();
+    let entries = [
+        JournalEntry::Begin { seq: 1, time: 0, action: Action::Step { name: "extract-ffmpeg".to_string() } },
+        JournalEntry::Begin { seq: 2, time: 0, action: Action::RemoveDir { path: stale.clone() } },
+        JournalEntry::Begin { seq: 3, time: 0, action: Action::WriteFile { path: partial.clone() } },
+        JournalEntry::End { seq: 3, time: 0, ok: true },
+    ];
+    let path = Journal::path_in(project.root());
+    fs::create_dir_all(path.parent().unwrap()).unwrap();
+    let lines: Vec<String> = entries.iter().map(|entry| serde_json::to_string(entry).unwrap()).collect();
+    // 崩溃时最后一行只写了一半
+    fs::write(&path, lines.join("\n") + "\n{\"event\":\"end\",\"se").unwrap();
+
+    assert_eq!(journal(&project).interrupted().len(), 2);
+    let repaired = project.pipeline(&RecordingObserver::new()).repair().unwrap();
+
+    assert_eq!(repaired.len(), 2, "{:?}", repaired);
+    assert!(!stale.exists());
+    assert!(!temp_dir.exists());
+    assert!(partial.exists());
+    assert!(!path.exists());
+}
@@ -0,0 +1,581 @@
+//! The default pipeline run end to end against the fake vcpkg and the fixture ffmpeg sources
+
+#![cfg(unix)]
+
+mod support;
+
+use std::collections::BTreeMap;
+use std::fs;
+use std::path::Path;
+use std::sync::Arc;
+
+use support::{RecordingObserver, TestProject};
+use vcpkg_ff::plugins::PluginStep;
+use vcpkg_ff::tool_config::{HookConfig, PluginConfig};
+use vcpkg_ff::vcpkg_manager::default_triplet;
+use vcpkg_ff::{AddonPreparer, CommandRunner, CommandSpec, Pipeline, PipelineContext, SkipReason, Step, StepStatus, SystemRunner, VcpkgFfError, VcpkgManager};
+
+fn ffmpeg_spec() -> String {
+    format!("ffmpeg[x264,x265,vpx]:{}", default_triplet())
+}
+
+#[test]
+fn fresh_project_installs_extracts_and_prepares() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    let observer = RecordingObserver::new();
+
+    let outcome = project.pipeline(&observer).run().unwrap();
+
+    assert_eq!(observer.status("install-vcpkg"), Some(StepStatus::Completed));
+    assert_eq!(observer.status("install-packages"), Some(StepStatus::Completed));
+    assert_eq!(observer.status("extract-ffmpeg"), Some(StepStatus::Completed));
+    assert_eq!(observer.status("prepare-addon"), Some(StepStatus::Completed));
+    assert!(vcpkg.calls().contains(&format!("install {}", ffmpeg_spec())));
+
+    assert!(project.root().join("ffmpeg").join("RELEASE").exists());
+    let ffmpeg_c = fs::read_to_string(outcome.addon_src_dir.join("ffmpeg.c")).unwrap();
+    assert!(ffmpeg_c.contains("int ffmpeg_run_argv(int argc, char **argv)"));
+    assert!(!ffmpeg_c.contains("int main(int argc, char **argv)"));
+    let ffprobe_c = fs::read_to_string(outcome.addon_src_dir.join("ffprobe.c")).unwrap();
+    assert!(ffprobe_c.contains("static int ffprobe_main(int argc, char **argv)"));
+    assert!(outcome.addon_src_dir.join("ffmpeg_sched.c").exists());
+    assert!(project.root().join("binding.gyp").exists());
+    assert_eq!(project.checkpoint(), None);
+}
+
+#[test]
+fn paths_given_to_the_builders_are_used() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg_in("tools/vcpkg").with_source_archive("ffmpeg-7.1");
+    let root = project.root();
+    let manager = VcpkgManager::builder()
+        .base_dir(root)
+        .root(root.join("tools").join("vcpkg"))
+        .output_dir(root.join("sources"))
+        .mirrors(["https://example.invalid/vcpkg.git"])
+        .build();
+    let preparer = AddonPreparer::builder()
+        .base_dir(root)
+        .source_dir(root.join("sources").join("ffmpeg"))
+        .addon_dir(root.join("out").join("addon"))
+        .vcpkg_root(root.join("tools").join("vcpkg"))
+        .build()
+        .with_syntax_check(false);
+
+    let outcome = Pipeline::new()
+        .with_manager(manager)
+        .with_preparer(preparer)
+        .with_observer(RecordingObserver::new())
+        .run()
+        .unwrap();
+
+    assert!(vcpkg.calls().contains(&format!("install {}", ffmpeg_spec())));
+    assert!(root.join("sources").join("ffmpeg").join("RELEASE").exists());
+    assert_eq!(outcome.addon_src_dir, root.join("out").join("addon"));
+    assert!(outcome.addon_src_dir.join("ffmpeg.c").exists());
+    assert!(!root.join("vcpkg").exists() && !root.join("ffmpeg").exists() && !root.join("addon_src").exists());
+}
+
+#[test]
+fn second_run_only_checks_the_completed_steps() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    project.pipeline(&RecordingObserver::new()).run().unwrap();
+    let install_calls = vcpkg.calls().iter().filter(|call| call.starts_with("install")).count();
+
+    let observer = RecordingObserver::new();
+    project.pipeline(&observer).run().unwrap();
+
+    for step in ["install-vcpkg", "install-packages", "extract-ffmpeg", "prepare-addon"] {
+        assert_eq!(observer.status(step), Some(StepStatus::Skipped(SkipReason::AlreadyDone)), "{}", step);
+    }
+    assert_eq!(vcpkg.calls().iter().filter(|call| call.starts_with("install")).count(), install_calls);
+}
+
+#[test]
+fn only_the_steps_whose_inputs_changed_run_again() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    project.pipeline(&RecordingObserver::new()).run().unwrap();
+    let stamps = project.root().join(".vcpkg_ff").join("stamps");
+    assert!(stamps.join("extract-ffmpeg.stamp").exists());
+
+    let observer = RecordingObserver::new();
+    let manager = VcpkgManager::builder().base_dir(project.root()).features(["x264", "x265", "vpx", "aom"]).build();
+    project.pipeline(&observer).with_manager(manager).run().unwrap();
+
+    assert_eq!(observer.status("install-packages"), Some(StepStatus::Completed));
+    assert!(vcpkg.calls().contains(&format!("install ffmpeg[x264,x265,vpx,aom]:{}", default_triplet())));
+    for step in ["install-vcpkg", "extract-ffmpeg", "prepare-addon"] {
+        assert_eq!(observer.status(step), Some(StepStatus::Skipped(SkipReason::AlreadyDone)), "{}", step);
+    }
+
+    let pipeline = project.pipeline(&RecordingObserver::new());
+    pipeline.forget_checkpoint_from("extract-ffmpeg").unwrap();
+    assert!(stamps.join("install-packages.stamp").exists());
+    assert!(!stamps.join("extract-ffmpeg.stamp").exists() && !stamps.join("prepare-addon.stamp").exists());
+}
+
+#[test]
+fn ffmpeg_without_the_codec_features_is_reinstalled() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    vcpkg.preinstall(&format!("ffmpeg:{}", default_triplet()));
+    vcpkg.preinstall(&format!("ffmpeg[x264]:{}", default_triplet()));
+
+    project.pipeline(&RecordingObserver::new()).run().unwrap();
+
+    let calls = vcpkg.calls();
+    let remove = calls.iter().position(|call| *call == format!("remove ffmpeg:{}", default_triplet()));
+    let install = calls.iter().position(|call| *call == format!("install {}", ffmpeg_spec()));
+    assert!(remove.is_some() && install.is_some() && remove < install, "{:?}", calls);
+}
+
+#[test]
+fn cli_tools_are_installed_and_copied_to_bin() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    let observer = RecordingObserver::new();
+
+    let outcome = project.pipeline(&observer)
+        .with_manager(project.manager().with_cli_tools())
+        .with_cli_tools(true)
+        .run()
+        .unwrap();
+
+    assert!(vcpkg.calls().contains(&format!("install ffmpeg[x264,x265,vpx,ffmpeg,ffprobe]:{}", default_triplet())));
+    assert_eq!(observer.status("copy-cli-tools"), Some(StepStatus::Completed));
+    let bin_dir = project.root().join("bin");
+    assert_eq!(outcome.cli_tools, vec![bin_dir.join("ffmpeg"), bin_dir.join("ffprobe")]);
+    assert_eq!(fs::read_to_string(bin_dir.join("ffprobe")).unwrap(), "fake ffprobe\n");
+}
+
+#[test]
+fn integration_runs_once_and_is_removed_again() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    project.pipeline(&RecordingObserver::new()).with_integrate(true).run().unwrap();
+    assert!(vcpkg.calls().contains(&"integrate install".to_string()));
+
+    let observer = RecordingObserver::new();
+    project.pipeline(&observer).with_integrate(true).run().unwrap();
+    assert_eq!(observer.status("integrate-vcpkg"), Some(StepStatus::Skipped(SkipReason::AlreadyDone)));
+
+    let manager = project.manager();
+    manager.integrate_remove().unwrap();
+    assert!(vcpkg.calls().contains(&"integrate remove".to_string()));
+    assert!(!manager.is_integrated());
+}
+
+#[test]
+fn cli_tools_missing_from_the_install_fail_the_copy() {
+    let project = TestProject::new();
+    project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+
+    let error = project.pipeline(&RecordingObserver::new()).with_cli_tools(true).run().unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { step, source } => {
+            assert_eq!(step, "copy-cli-tools");
+            assert!(matches!(&*source, VcpkgFfError::NotPrepared { path, step: "install-packages" } if path.starts_with(project.root())),
+                "{:?}", source);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn failed_install_stops_the_pipeline_and_resume_continues_after_the_checkpoint() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    vcpkg.fail("install");
+    let observer = RecordingObserver::new();
+
+    let error = project.pipeline(&observer).run().unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { step, source } => {
+            assert_eq!(step, "install-packages");
+            assert!(matches!(*source, VcpkgFfError::PackageInstallFailed { .. }), "{:?}", source);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert_eq!(observer.errors.lock().unwrap().len(), 1);
+    assert_eq!(observer.status("extract-ffmpeg"), None);
+    assert!(!project.root().join("ffmpeg").exists());
+    assert_eq!(project.checkpoint().as_deref(), Some("install-vcpkg\nfetch-packages\n"));
+
+    vcpkg.succeed("install");
+    let observer = RecordingObserver::new();
+    project.pipeline(&observer).with_resume(true).run().unwrap();
+
+    assert_eq!(observer.status("install-vcpkg"), Some(StepStatus::Skipped(SkipReason::Checkpoint)));
+    assert_eq!(observer.status("install-packages"), Some(StepStatus::Completed));
+    assert_eq!(project.checkpoint(), None);
+}
+
+#[test]
+fn failed_downloads_are_retried_before_anything_is_built() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    vcpkg.fail_with("fetch", "error: Failed to download from mirror set");
+
+    let error = project.pipeline(&RecordingObserver::new()).run().unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { step, source } => {
+            assert_eq!(step, "fetch-packages");
+            match *source {
+                VcpkgFfError::DownloadFailed { attempts, message, .. } => {
+                    assert_eq!(attempts, 3);
+                    assert!(message.contains("Failed to download from mirror set"), "{}", message);
+                }
+                other => panic!("unexpected error: {:?}", other),
+            }
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    let calls = vcpkg.calls();
+    assert_eq!(calls.iter().filter(|call| **call == format!("install {} --only-downloads", ffmpeg_spec())).count(), 3);
+    assert!(!calls.contains(&format!("install {}", ffmpeg_spec())));
+}
+
+#[test]
+fn missing_source_archive_fails_extraction_without_leftovers() {
+    let project = TestProject::new();
+    project.fake_vcpkg();
+
+    let error = project.pipeline(&RecordingObserver::new()).run().unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { step, source } => {
+            assert_eq!(step, "extract-ffmpeg");
+            assert!(matches!(*source, VcpkgFfError::ArchiveNotFound(_)), "{:?}", source);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(!project.root().join(".ffmpeg_temp").exists());
+    assert!(!project.root().join("ffmpeg").exists());
+}
+
+#[test]
+fn unsupported_ffmpeg_sources_fail_preparation() {
+    let project = TestProject::new();
+    project.fake_vcpkg().preinstall(&ffmpeg_spec());
+    project.extract_fixture("ffmpeg-6.1");
+
+    let error = project.pipeline(&RecordingObserver::new()).run().unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { step, source } => {
+            assert_eq!(step, "prepare-addon");
+            assert!(matches!(&*source, VcpkgFfError::UnsupportedFfmpeg { version } if version == "6.1"), "{:?}", source);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn patch_rule_no_longer_matching_fails_preparation() {
+    let project = TestProject::new();
+    project.fake_vcpkg().preinstall(&ffmpeg_spec());
+    project.extract_fixture("ffmpeg-7.1");
+    // 模拟上游改了 transcode 的签名，make_non_static 规则不再匹配
+    let ffmpeg_c = project.root().join("ffmpeg").join("fftools").join("ffmpeg.c");
+    let source = fs::read_to_string(&ffmpeg_c).unwrap();
+    fs::write(&ffmpeg_c, source.replace("static int transcode(Scheduler *sch)", "static int transcode(Scheduler *sch, int flags)")).unwrap();
+
+    let error = project.pipeline(&RecordingObserver::new()).run().unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { step, source } => {
+            assert_eq!(step, "prepare-addon");
+            assert!(matches!(*source, VcpkgFfError::ValidationFailed { check: "exports", count: 1 }), "{:?}", source);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+}
+
+#[test]
+fn skipped_steps_do_not_run() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg();
+    project.extract_fixture("ffmpeg-7.1");
+    let observer = RecordingObserver::new();
+
+    project.pipeline(&observer)
+        .with_skip(vec!["fetch-packages".to_string(), "install-packages".to_string(), "extract-ffmpeg".to_string()])
+        .run()
+        .unwrap();
+
+    assert_eq!(observer.status("install-packages"), Some(StepStatus::Skipped(SkipReason::Requested)));
+    assert_eq!(observer.status("extract-ffmpeg"), Some(StepStatus::Skipped(SkipReason::Requested)));
+    assert!(vcpkg.calls().is_empty());
+    assert!(project.root().join("addon_src").join("ffmpeg.c").exists());
+}
+
+#[test]
+fn unknown_skipped_step_is_rejected_before_running() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg();
+
+    let error = project.pipeline(&RecordingObserver::new())
+        .with_skip(vec!["install-everything".to_string()])
+        .run()
+        .unwrap_err();
+
+    assert!(error.to_string().contains("unknown step `install-everything`"));
+    assert!(vcpkg.calls().is_empty());
+}
+
+#[test]
+fn plugin_steps_run_in_place_with_the_context_on_stdin() {
+    let project = TestProject::new();
+    project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    project.write_script("plugins/record.sh", "cat > \"$1\"\necho \"recorded $VCPKG_FF_STEP\"");
+    let plugins = [
+        PluginConfig {
+            name: "record-context".to_string(),
+            command: vec!["plugins/record.sh".to_string(), "context.json".to_string()],
+            after: Some("extract-ffmpeg".to_string()),
+        },
+    ];
+    let observer = RecordingObserver::new();
+
+    let mut pipeline = project.pipeline(&observer).with_plugins(&plugins).unwrap();
+    assert_eq!(pipeline.step_names(),
+        ["install-vcpkg", "fetch-packages", "install-packages", "extract-ffmpeg", "record-context", "prepare-addon"]);
+    pipeline.run().unwrap();
+
+    assert_eq!(observer.status("record-context"), Some(StepStatus::Completed));
+    assert!(observer.printed("recorded record-context"));
+    let context: serde_json::Value =
+        serde_json::from_str(&fs::read_to_string(project.root().join("context.json")).unwrap()).unwrap();
+    assert_eq!(context["step"], "record-context");
+    assert_eq!(context["triplet"], default_triplet());
+    assert_eq!(context["ffmpeg_dir"], project.root().join("ffmpeg").to_str().unwrap());
+    assert_eq!(context["smoke_report"], serde_json::Value::Null);
+}
+
+#[test]
+fn failing_plugin_fails_its_step() {
+    let project = TestProject::new();
+    project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    project.write_script("upload.sh", "echo 'upload refused' >&2\nexit 3");
+    let plugins = [
+        PluginConfig { name: "upload".to_string(), command: vec!["./upload.sh".to_string()], after: None },
+    ];
+
+    let error = project.pipeline(&RecordingObserver::new()).with_plugins(&plugins).unwrap().run().unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { step, source } => {
+            assert_eq!(step, "upload");
+            assert!(source.to_string().contains("exit code 3"), "{}", source);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(project.root().join("addon_src").join("ffmpeg.c").exists());
+}
+
+#[test]
+fn plugin_after_an_unknown_step_is_rejected() {
+    let project = TestProject::new();
+    let plugins = [PluginConfig {
+        name: "upload".to_string(),
+        command: vec!["true".to_string()],
+        after: Some("build-addon".to_string()),
+    }];
+
+    let error = project.pipeline(&RecordingObserver::new()).with_plugins(&plugins).err().unwrap();
+
+    assert!(error.to_string().contains("unknown step `build-addon`"), "{}", error);
+}
+
+#[test]
+fn plugin_with_an_empty_command_is_rejected() {
+    let config = PluginConfig { name: "upload".to_string(), command: Vec::new(), after: None };
+
+    let error = PluginStep::new(&config).err().unwrap();
+
+    assert!(matches!(&error, VcpkgFfError::PluginFailed { plugin, .. } if plugin == "upload"), "{}", error);
+}
+
+fn hooks(entries: &[(&str, Option<&str>, Option<&str>)]) -> BTreeMap<String, HookConfig> {
+    entries.iter()
+        .map(|(step, pre, post)| (step.to_string(), HookConfig { pre: pre.map(str::to_string), post: post.map(str::to_string) }))
+        .collect()
+}
+
+#[test]
+fn hooks_run_around_their_step_with_the_step_environment() {
+    let project = TestProject::new();
+    project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    let record = "echo \"$VCPKG_FF_STEP $VCPKG_FF_ROOT ${VCPKG_FF_EXIT_STATUS:-none}\" >> hooks.log";
+    let observer = RecordingObserver::new();
+
+    project.pipeline(&observer)
+        .with_hooks(hooks(&[("extract-ffmpeg", Some(record), Some(record)), ("prepare-addon", None, Some("echo prepared"))]))
+        .unwrap()
+        .run()
+        .unwrap();
+
+    let root = project.root().display();
+    assert_eq!(fs::read_to_string(project.root().join("hooks.log")).unwrap(),
+        format!("extract-ffmpeg {root} none\nextract-ffmpeg {root} 0\n"));
+    assert!(observer.printed("prepared"));
+}
+
+#[test]
+fn install_failing_on_a_locked_file_suggests_an_antivirus_exclusion() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    vcpkg.fail_with("install", "error: failed to remove buildtrees/ffmpeg/x64-windows-rel: Access is denied.");
+    let observer = RecordingObserver::new();
+
+    project.pipeline(&observer).run().unwrap_err();
+
+    assert!(observer.printed("vcpkg ran into a locked file, likely an antivirus scan"));
+    assert!(observer.printed("Add-MpPreference -ExclusionPath"));
+    assert!(observer.printed(&project.root().join("vcpkg").join("buildtrees").display().to_string()));
+}
+
+#[test]
+fn failing_pre_hook_stops_its_step_and_post_hook_sees_failures() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    vcpkg.fail("install");
+
+    let error = project.pipeline(&RecordingObserver::new())
+        .with_hooks(hooks(&[
+            ("install-packages", None, Some("echo $VCPKG_FF_EXIT_STATUS > status")),
+            ("extract-ffmpeg", Some("exit 1"), None),
+        ]))
+        .unwrap()
+        .run()
+        .unwrap_err();
+
+    assert!(matches!(&error, VcpkgFfError::StepFailed { step, .. } if step == "install-packages"), "{:?}", error);
+    assert_eq!(fs::read_to_string(project.root().join("status")).unwrap(), "1\n");
+
+    vcpkg.succeed("install");
+    let error = project.pipeline(&RecordingObserver::new())
+        .with_hooks(hooks(&[("extract-ffmpeg", Some("exit 1"), None)]))
+        .unwrap()
+        .run()
+        .unwrap_err();
+
+    match error {
+        VcpkgFfError::StepFailed { step, source } => {
+            assert_eq!(step, "extract-ffmpeg");
+            assert!(matches!(*source, VcpkgFfError::HookFailed { phase: "pre", .. }), "{:?}", source);
+        }
+        other => panic!("unexpected error: {:?}", other),
+    }
+    assert!(!project.root().join("ffmpeg").exists());
+}
+
+#[test]
+fn hooks_for_unknown_steps_are_rejected() {
+    let project = TestProject::new();
+
+    let error = project.pipeline(&RecordingObserver::new())
+        .with_hooks(hooks(&[("build-addon", Some("true"), None)]))
+        .err()
+        .unwrap();
+
+    assert!(error.to_string().contains("[hooks] for unknown step `build-addon`"), "{}", error);
+}
+
+#[test]
+fn trace_has_a_span_for_every_step_and_command() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    vcpkg.fail("install");
+    let trace_path = project.root().join("trace.json");
+
+    project.pipeline(&RecordingObserver::new()).with_trace(&trace_path).run().unwrap_err();
+
+    let trace: serde_json::Value = serde_json::from_str(&fs::read_to_string(&trace_path).unwrap()).unwrap();
+    let events = trace["traceEvents"].as_array().unwrap();
+    let span = |category: &str, name: &str| events.iter()
+        .find(|event| event["cat"] == category && event["name"] == name)
+        .unwrap_or_else(|| panic!("no {} span {} in {:?}", category, name, events));
+    assert_eq!(span("step", "install-vcpkg")["args"]["status"], "completed");
+    assert_eq!(span("step", "install-packages")["args"]["status"], "failed");
+    let install = events.iter()
+        .find(|event| event["cat"] == "command" && event["args"]["command"].as_str().unwrap().ends_with(&format!("install {}", ffmpeg_spec())))
+        .unwrap();
+    assert_eq!(install["name"], "vcpkg");
+    assert_eq!(install["ph"], "X");
+    assert_eq!(install["args"]["code"], 1);
+    let step = span("step", "install-packages");
+    let (step_start, command_start) = (step["ts"].as_u64().unwrap(), install["ts"].as_u64().unwrap());
+    assert!(step_start <= command_start
+        && command_start + install["dur"].as_u64().unwrap() <= step_start + step["dur"].as_u64().unwrap());
+    assert_eq!(trace["otherData"]["triplet"], default_triplet());
+}
+
+/// Prints `tag` a few times through a streamed command, then panics when asked to
+struct EchoStep {
+    tag: &'static str,
+    panic: bool,
+}
+
+impl Step for EchoStep {
+    fn name(&self) -> &'static str {
+        "echo"
+    }
+
+    fn run(&self, _ctx: &mut PipelineContext) -> Result<(), VcpkgFfError> {
+        let script = format!("for i in 1 2 3 4 5; do echo {}; sleep 0.02; done", self.tag);
+        SystemRunner.run(&CommandSpec::new("sh").args(["-c", script.as_str()]).streamed())?;
+        assert!(!self.panic, "{} panicked", self.tag);
+        Ok(())
+    }
+}
+
+/// A pipeline in `dir` running only `step`
+fn echo_pipeline(dir: &Path, step: EchoStep, observer: &Arc<RecordingObserver>) -> Pipeline {
+    let pipeline = Pipeline::new()
+        .with_manager(VcpkgManager::builder().base_dir(dir).build())
+        .with_preparer(AddonPreparer::builder().base_dir(dir).build())
+        .with_observer(observer.clone());
+    let defaults = pipeline.step_names().iter().map(|name| name.to_string()).collect();
+    pipeline.with_skip(defaults).with_step(Box::new(step))
+}
+
+#[test]
+fn pipelines_on_two_threads_report_to_their_own_observers() {
+    let project = TestProject::new();
+    let observers = [RecordingObserver::new(), RecordingObserver::new()];
+
+    std::thread::scope(|scope| {
+        for (tag, observer) in ["first", "second"].into_iter().zip(&observers) {
+            let dir = project.root().join(tag);
+            scope.spawn(move || echo_pipeline(&dir, EchoStep { tag, panic: false }, observer).run().unwrap());
+        }
+    });
+
+    for (tag, observer) in ["first", "second"].into_iter().zip(&observers) {
+        let output = observer.output.lock().unwrap();
+        assert_eq!(output.iter().filter(|line| *line == tag).count(), 5, "{:?}", output);
+        assert!(output.iter().all(|line| line != if tag == "first" { "second" } else { "first" }), "{:?}", output);
+    }
+}
+
+#[test]
+fn panicking_step_gives_the_output_back_to_the_console() {
+    let project = TestProject::new();
+    let observer = RecordingObserver::new();
+
+    let result = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
+        echo_pipeline(project.root(), EchoStep { tag: "inside", panic: true }, &observer).run()
+    }));
+    assert!(result.is_err());
+    SystemRunner.run(&CommandSpec::new("sh").args(["-c", "echo outside"]).streamed()).unwrap();
+
+    assert!(observer.printed("inside"));
+    assert!(!observer.printed("outside"));
+}
@@ -0,0 +1,104 @@
+//! Prebuilt installed trees: restored from the cache server instead of building ffmpeg, uploaded after a build
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+use std::path::Path;
+
+use support::{FakeVcpkg, TestProject};
+use vcpkg_ff::{PrebuiltCache, PrebuiltKey, VcpkgManager};
+
+/// A project in `dir` whose fake vcpkg is at the same commit as in every other test
+fn project_in(project: &TestProject, dir: &str) -> (VcpkgManager, FakeVcpkg) {
+    let vcpkg = project.fake_vcpkg_in(&format!("{}/vcpkg", dir)).at_fixed_commit();
+    let manager = VcpkgManager::builder().base_dir(project.root().join(dir)).triplet("x64-linux").build();
+    (manager, vcpkg)
+}
+
+fn archive_name(vcpkg_root: &Path) -> String {
+    let commit = std::process::Command::new("git").arg("-C").arg(vcpkg_root).args(["rev-parse", "HEAD"]).output().unwrap();
+    let features = ["x264".to_string(), "x265".to_string(), "vpx".to_string()];
+    PrebuiltKey::new(String::from_utf8_lossy(&commit.stdout).trim(), "x64-linux", &features).archive_name()
+}
+
+#[test]
+fn tree_built_on_one_machine_is_restored_on_another() {
+    let project = TestProject::new();
+    let server = project.root().join("server");
+    let (builder, builder_vcpkg) = project_in(&project, "a");
+    builder.clone().with_prebuilt_cache(Some(PrebuiltCache::new(server.to_string_lossy()).with_upload(true))).install_packages().unwrap();
+    assert!(builder_vcpkg.calls().iter().any(|call| call.starts_with("install ffmpeg[")));
+    let name = archive_name(builder.get_vcpkg_root());
+    assert_eq!(name, "installed-x64-linux-".to_string() + &name[20..32] + "-vpx+x264+x265.tar.gz");
+    assert!(server.join(&name).is_file());
+    assert!(server.join(format!("{}.hash", name)).is_file());
+
+    let (restorer, restorer_vcpkg) = project_in(&project, "b");
+    let restorer = restorer.with_prebuilt_cache(Some(PrebuiltCache::new(format!("file://{}", server.display()))));
+    restorer.fetch_packages().unwrap();
+    restorer.install_packages().unwrap();
+
+    assert!(!restorer_vcpkg.calls().iter().any(|call| call.starts_with("install")), "{:?}", restorer_vcpkg.calls());
+    assert!(restorer.is_ffmpeg_installed());
+    let installed = restorer.get_vcpkg_root().join("installed");
+    assert_eq!(fs::read_to_string(installed.join("x64-linux/lib/libffmpeg.a")).unwrap(), "fake libffmpeg\n");
+    assert!(installed.join("vcpkg/info/ffmpeg_7.1_x64-linux.list").is_file());
+    let update = fs::read_to_string(installed.join("vcpkg/updates/0000000001")).unwrap();
+    assert!(update.contains("Package: ffmpeg\nFeature: x265\nArchitecture: x64-linux\nStatus: install ok installed"), "{}", update);
+    assert!(!installed.join(".vcpkg_ff-prebuilt").exists());
+}
+
+#[test]
+fn corrupt_trees_are_built_instead() {
+    let project = TestProject::new();
+    let server = project.root().join("server");
+    let (builder, _) = project_in(&project, "a");
+    let cache = PrebuiltCache::new(server.to_string_lossy());
+    builder.clone().with_prebuilt_cache(Some(cache.clone())).install_packages().unwrap();
+    // 没有开启上传
+    assert!(!server.exists());
+
+    // ffmpeg 已经装好，不会重新构建也不上传
+    builder.with_prebuilt_cache(Some(cache.clone().with_upload(true))).install_packages().unwrap();
+    assert!(!server.exists());
+
+    let (uploader, _) = project_in(&project, "c");
+    uploader.with_prebuilt_cache(Some(cache.clone().with_upload(true))).install_packages().unwrap();
+    let (restorer, restorer_vcpkg) = project_in(&project, "b");
+    let archive = server.join(archive_name(restorer.get_vcpkg_root()));
+    let mut content = fs::read(&archive).unwrap();
+    let middle = content.len() / 2;
+    content[middle] ^= 0xff;
+    fs::write(&archive, content).unwrap();
+
+    restorer.with_prebuilt_cache(Some(cache)).install_packages().unwrap();
+
+    assert!(restorer_vcpkg.calls().iter().any(|call| call.starts_with("install ffmpeg[")));
+}
+
+#[test]
+fn failed_restore_moves_the_file_lists_back() {
+    let project = TestProject::new();
+    let server = project.root().join("server");
+    let (uploader, _) = project_in(&project, "a");
+    uploader.with_prebuilt_cache(Some(PrebuiltCache::new(server.to_string_lossy()).with_upload(true))).install_packages().unwrap();
+    let name = archive_name(&project.root().join("a/vcpkg"));
+    let hash = fs::read_to_string(server.join(format!("{}.hash", name))).unwrap();
+    assert!(hash.len() == 64 && hash.chars().all(|c| c.is_ascii_hexdigit()), "{}", hash);
+
+    let (restorer, restorer_vcpkg) = project_in(&project, "b");
+    let installed = restorer.get_vcpkg_root().join("installed");
+    // updates 是文件时登记失败，这时 info 列表和 x64-linux 树已经移过去了
+    fs::create_dir_all(installed.join("vcpkg")).unwrap();
+    fs::write(installed.join("vcpkg/updates"), "").unwrap();
+    restorer_vcpkg.fail("install");
+
+    assert!(restorer.with_prebuilt_cache(Some(PrebuiltCache::new(server.to_string_lossy()))).install_packages().is_err());
+
+    assert!(restorer_vcpkg.calls().iter().any(|call| call.starts_with("install ffmpeg[")));
+    assert!(!installed.join("vcpkg/info/ffmpeg_7.1_x64-linux.list").exists());
+    assert!(!installed.join("x64-linux").exists());
+    assert!(!installed.join(".vcpkg_ff-prebuilt").exists());
+}
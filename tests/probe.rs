@@ -0,0 +1,75 @@
+//! probe_root against a vcpkg installed tree with hand-written pkg-config files
+
+mod support;
+
+use std::fs;
+
+use support::TestProject;
+use vcpkg_ff::{probe_root, LinkMetadata, VcpkgFfError};
+
+const TRIPLET: &str = "x64-linux";
+
+fn write_pc(project: &TestProject, name: &str, body: &str) {
+    let pkgconfig = project.root().join("vcpkg/installed").join(TRIPLET).join("lib/pkgconfig");
+    fs::create_dir_all(&pkgconfig).unwrap();
+    let content = format!("prefix=${{pcfiledir}}/../..\nlibdir=${{prefix}}/lib\n\nName: {}\n{}\n", name, body);
+    fs::write(pkgconfig.join(format!("{}.pc", name)), content).unwrap();
+}
+
+#[test]
+fn ffmpeg_and_x264_resolve_to_their_libraries_in_link_order() {
+    let project = TestProject::new();
+    write_pc(&project, "libavutil", "Libs: -L${libdir} -lavutil\nLibs.private: -lm -pthread");
+    write_pc(&project, "libavcodec", "Requires: libavutil >= 59.8.100\nLibs: -L${libdir} -lavcodec\nRequires.private: x264");
+    write_pc(&project, "libavformat", "Requires: libavcodec, libavutil\nLibs: -L${libdir} -lavformat");
+    write_pc(&project, "x264", "Libs: -L${libdir} -lx264\nLibs.private: -ldl");
+    let installed = project.root().join("vcpkg/installed").join(TRIPLET);
+
+    let libraries = probe_root(&project.root().join("vcpkg"), TRIPLET, &["ffmpeg", "x264"]).unwrap();
+
+    assert_eq!(libraries.include_dirs, vec![installed.join("include")]);
+    assert_eq!(libraries.link_paths, vec![installed.join("lib")]);
+    assert_eq!(libraries.libs, ["avformat", "avcodec", "x264", "dl", "avutil", "m", "pthread"]);
+    assert!(libraries.frameworks.is_empty());
+}
+
+#[test]
+fn package_without_pkg_config_file_is_reported() {
+    let project = TestProject::new();
+    write_pc(&project, "libavutil", "Libs: -L${libdir} -lavutil");
+
+    let error = probe_root(&project.root().join("vcpkg"), TRIPLET, &["ffmpeg", "x265"]).unwrap_err();
+
+    assert!(matches!(&error, VcpkgFfError::PackageNotFound { package, triplet } if package == "x265" && triplet == TRIPLET),
+        "{:?}", error);
+}
+
+#[test]
+fn missing_triplet_means_vcpkg_is_not_installed() {
+    let project = TestProject::new();
+    write_pc(&project, "libavutil", "Libs: -L${libdir} -lavutil");
+
+    let error = probe_root(&project.root().join("vcpkg"), "arm64-osx", &["ffmpeg"]).unwrap_err();
+
+    assert!(matches!(error, VcpkgFfError::VcpkgNotInstalled), "{:?}", error);
+}
+
+#[test]
+fn link_metadata_separates_installed_and_system_libraries() {
+    let project = TestProject::new();
+    write_pc(&project, "libavutil", "Libs: -L${libdir} -lavutil\nLibs.private: -lm -pthread");
+    write_pc(&project, "libavcodec", "Requires: libavutil\nLibs: -L${libdir} -lavcodec");
+    let installed = project.root().join("vcpkg/installed").join(TRIPLET);
+    for lib in ["libavutil.a", "libavcodec.a"] {
+        fs::write(installed.join("lib").join(lib), "").unwrap();
+    }
+
+    let metadata = LinkMetadata::probe(&project.root().join("vcpkg"), TRIPLET, &["ffmpeg"]).unwrap();
+    let path = project.root().join("ffmpeg-sys.toml");
+    metadata.write(&path).unwrap();
+
+    let written: toml::Table = fs::read_to_string(&path).unwrap().parse().unwrap();
+    assert_eq!(written["static_libs"].as_array().unwrap(), &["avcodec", "avutil"].map(toml::Value::from).to_vec());
+    assert_eq!(written["system_libs"].as_array().unwrap(), &["m", "pthread"].map(toml::Value::from).to_vec());
+    assert_eq!(written["env"]["FFMPEG_DIR"].as_str(), Some(installed.to_str().unwrap()));
+}
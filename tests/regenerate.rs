@@ -0,0 +1,57 @@
+//! Files generated by an older vcpkg_ff, recognised by their provenance markers, are generated again
+
+mod support;
+
+use std::fs;
+
+use support::{fixture, TestProject};
+
+/// The tool version in the marker on the first line of `content`
+fn marker_tool(content: &str) -> &str {
+    let line = content.lines().next().unwrap();
+    line.split_whitespace().find_map(|field| field.strip_prefix("tool=")).unwrap()
+}
+
+#[test]
+fn files_of_an_older_version_are_regenerated_and_the_others_kept() {
+    let project = TestProject::new();
+    project.extract_fixture("ffmpeg-7.1");
+    project.preparer().prepare_addon_source().unwrap();
+    let addon_src = project.root().join("addon_src");
+    let current = fs::read_to_string(addon_src.join("binding.c")).unwrap();
+    let generator = marker_tool(&current).to_string();
+    assert!(generator.starts_with(&format!("{}+", env!("CARGO_PKG_VERSION"))), "{}", generator);
+
+    // 旧版本生成的 index.js：标记里的版本不同，内容是旧模板的输出
+    let old = "/* vcpkg_ff marker: tool=0.0.9 ffmpeg=7.1 triplet=x features=y rules=0 source=0 output=0 */\nmodule.exports = 'old';\n";
+    fs::write(addon_src.join("index.js"), old).unwrap();
+    let binding_mtime = fs::metadata(addon_src.join("binding.c")).unwrap().modified().unwrap();
+    fs::remove_file(project.root().join(".vcpkg_ff").join("prepare.stamp")).unwrap();
+
+    project.preparer().prepare_addon_source().unwrap();
+
+    let index_js = fs::read_to_string(addon_src.join("index.js")).unwrap();
+    assert_eq!(marker_tool(&index_js), generator);
+    assert!(!index_js.contains("module.exports = 'old'"));
+    assert_eq!(fs::metadata(addon_src.join("binding.c")).unwrap().modified().unwrap(), binding_mtime);
+}
+
+#[test]
+fn sources_modified_in_place_are_patched_from_their_backup() {
+    let project = TestProject::new();
+    project.extract_fixture("ffmpeg-7.1");
+    // 旧版本直接改了 ffmpeg/fftools/ffmpeg.c 并把原文件备份到 .vcpkg_ff/backups
+    let ffmpeg_c = project.root().join("ffmpeg").join("fftools").join("ffmpeg.c");
+    let backup = project.root().join(".vcpkg_ff").join("backups").join("ffmpeg").join("fftools").join("ffmpeg.c");
+    fs::create_dir_all(backup.parent().unwrap()).unwrap();
+    fs::copy(&ffmpeg_c, &backup).unwrap();
+    let original = fs::read_to_string(fixture("ffmpeg-7.1").join("fftools").join("ffmpeg.c")).unwrap();
+    fs::write(&ffmpeg_c, format!("/* vcpkg_ff marker: tool=0.0.9 ffmpeg=7.1 triplet=x features=y rules=0 source=0 output=0 */\n{}\
+        \nint ffmpeg_run_argv(int argc, char **argv)\n{{\n    return old_run(argc, argv);\n}}\n", original)).unwrap();
+
+    project.preparer().prepare_addon_source().unwrap();
+
+    let patched = fs::read_to_string(project.root().join("addon_src").join("ffmpeg.c")).unwrap();
+    assert!(!patched.contains("old_run"));
+    assert_eq!(patched.matches("int ffmpeg_run_argv(int argc, char **argv)\n{").count(), 1);
+}
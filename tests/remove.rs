@@ -0,0 +1,58 @@
+//! `vcpkg_ff remove`: uninstalling ffmpeg and the codec ports only it needed, and invalidating the checkpoint
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+
+use support::{RecordingObserver, TestProject};
+use vcpkg_ff::vcpkg_manager::default_triplet;
+use vcpkg_ff::RemovalReport;
+
+#[test]
+fn recurse_removes_ffmpeg_and_the_codec_ports_nothing_else_needs() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg();
+    let triplet = default_triplet();
+    for package in ["ffmpeg", "ffmpeg[x264]", "ffmpeg[x265]", "ffmpeg[vpx]", "x264", "x265", "libvpx"] {
+        vcpkg.preinstall(&format!("{}:{}", package, triplet));
+    }
+    vcpkg.needed_by_others("x265");
+    let manager = project.manager();
+
+    let report = manager.remove_packages(true, false).unwrap();
+
+    assert_eq!(report, RemovalReport {
+        removed: vec![format!("ffmpeg:{}", triplet), format!("x264:{}", triplet), format!("libvpx:{}", triplet)],
+        kept: vec![format!("x265:{}", triplet)],
+    });
+    assert!(vcpkg.calls().contains(&format!("remove ffmpeg:{} --recurse", triplet)));
+    assert!(!vcpkg.calls().iter().any(|call| call.contains("--outdated")));
+    assert!(!manager.is_ffmpeg_installed());
+}
+
+#[test]
+fn without_ffmpeg_only_the_outdated_packages_are_removed() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg();
+
+    let report = project.manager().remove_packages(false, true).unwrap();
+
+    assert_eq!(report, RemovalReport::default());
+    assert!(vcpkg.calls().contains(&"remove --outdated".to_string()));
+    assert!(!vcpkg.calls().iter().any(|call| call.starts_with("remove ffmpeg")));
+}
+
+#[test]
+fn forgetting_install_packages_drops_it_and_later_steps_from_the_checkpoint() {
+    let project = TestProject::new();
+    let checkpoint = project.root().join(".vcpkg_ff").join("pipeline.checkpoint");
+    fs::create_dir_all(checkpoint.parent().unwrap()).unwrap();
+    fs::write(&checkpoint, "install-vcpkg\ninstall-packages\nextract-ffmpeg\n").unwrap();
+    let pipeline = project.pipeline(&RecordingObserver::new());
+
+    assert!(pipeline.forget_checkpoint_from("install-packages").unwrap());
+    assert_eq!(project.checkpoint().as_deref(), Some("install-vcpkg\n"));
+    assert!(!pipeline.forget_checkpoint_from("install-packages").unwrap());
+}
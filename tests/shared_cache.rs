@@ -0,0 +1,66 @@
+//! Shared-cache mode: projects link their installed tree and ffmpeg sources to one store instead of keeping copies
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+use std::os::unix::fs::MetadataExt;
+use std::path::Path;
+
+use support::{fixture, RecordingObserver, TestProject};
+use vcpkg_ff::shared_cache::LinkMode;
+use vcpkg_ff::{FfmpegSource, SharedCache, StepStatus, VcpkgManager};
+
+/// A project in `dir` with ffmpeg extracted and a static library in its installed tree
+fn project_in(dir: &Path) -> VcpkgManager {
+    let manager = VcpkgManager::builder().base_dir(dir).triplet("x64-linux").build();
+    let lib_dir = manager.get_vcpkg_root().join("installed").join("x64-linux").join("lib");
+    fs::create_dir_all(&lib_dir).unwrap();
+    fs::write(lib_dir.join("libavcodec.a"), "!<arch>\nobjects").unwrap();
+    manager.extract_ffmpeg_from(&FfmpegSource::Directory(fixture("ffmpeg-7.1"))).unwrap();
+    manager
+}
+
+#[test]
+fn second_project_links_its_files_to_the_first_ones() {
+    let project = TestProject::new();
+    let cache = SharedCache::new(project.root().join("cache"));
+    let (first, second) = (project_in(&project.root().join("a")), project_in(&project.root().join("b")));
+
+    let stored = first.dedupe(&cache).unwrap();
+    assert!(stored.files > 0);
+    assert_eq!(stored.stored, stored.files);
+    let linked = second.dedupe(&cache).unwrap();
+    assert_eq!(linked.stored, 0);
+    assert_eq!(linked.linked.len(), linked.files);
+    assert!(linked.bytes_saved > 0);
+    let release = |manager: &VcpkgManager| manager.get_output_dir().join("ffmpeg").join("RELEASE");
+    if linked.mode == Some(LinkMode::Hardlink) {
+        assert_eq!(fs::metadata(release(&first)).unwrap().ino(), fs::metadata(release(&second)).unwrap().ino());
+    }
+    assert!(second.dedupe(&cache).unwrap().linked.is_empty());
+
+    // 再次解压不重写已链接的文件，新版本的源码替换链接而不是写穿到其他项目
+    let mtime = fs::metadata(release(&second)).unwrap().modified().unwrap();
+    second.extract_ffmpeg_from(&FfmpegSource::Directory(fixture("ffmpeg-7.1"))).unwrap();
+    assert_eq!(fs::metadata(release(&second)).unwrap().modified().unwrap(), mtime);
+    second.extract_ffmpeg_from(&FfmpegSource::Directory(fixture("ffmpeg-6.1"))).unwrap();
+    assert_eq!(fs::read(release(&second)).unwrap(), fs::read(fixture("ffmpeg-6.1").join("RELEASE")).unwrap());
+    assert_eq!(fs::read(release(&first)).unwrap(), fs::read(fixture("ffmpeg-7.1").join("RELEASE")).unwrap());
+}
+
+#[test]
+fn pipeline_dedupes_after_the_extraction() {
+    let project = TestProject::new();
+    project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    let observer = RecordingObserver::new();
+    let mut pipeline = project.pipeline(&observer).with_shared_cache(Some(SharedCache::new(project.root().join("cache"))));
+    assert_eq!(pipeline.step_names(),
+        ["install-vcpkg", "fetch-packages", "install-packages", "extract-ffmpeg", "dedupe", "prepare-addon"]);
+
+    pipeline.run().unwrap();
+
+    assert_eq!(observer.status("dedupe"), Some(StepStatus::Completed));
+    assert!(project.root().join("cache").join("objects").read_dir().unwrap().next().is_some());
+}
@@ -0,0 +1,51 @@
+//! `vcpkg_ff env`: statements exporting the paths of the installed tree for bash, PowerShell and cmd
+
+#![cfg(unix)]
+
+mod support;
+
+use std::process::Command;
+
+use support::TestProject;
+use vcpkg_ff::{AddonPreparer, Shell, ShellEnv, VcpkgManager};
+
+#[test]
+fn bash_statements_survive_quotes_in_paths() {
+    let project = TestProject::new();
+    let base_dir = project.root().join("it's here");
+    let manager = VcpkgManager::builder().base_dir(&base_dir).triplet("x64-linux").build();
+    let preparer = AddonPreparer::builder().base_dir(&base_dir).build();
+    let script = ShellEnv::new(&manager, &preparer).render(Shell::Bash);
+
+    let output = Command::new("sh")
+        .arg("-c")
+        .arg(format!("{}printf '%s\\n' \"$VCPKG_ROOT\" \"$VCPKG_DEFAULT_TRIPLET\" \"$VCPKG_FF_ADDON_SRC\" \"$PATH\"", script))
+        .output()
+        .unwrap();
+
+    let stdout = String::from_utf8(output.stdout).unwrap();
+    let lines: Vec<&str> = stdout.lines().collect();
+    assert_eq!(lines[0], base_dir.join("vcpkg").display().to_string());
+    assert_eq!(lines[1], "x64-linux");
+    assert_eq!(lines[2], base_dir.join("addon_src").display().to_string());
+    let tools_dir = base_dir.join("vcpkg").join("installed").join("x64-linux").join("tools").join("ffmpeg");
+    assert!(lines[3].starts_with(&format!("{}:{}:", base_dir.join("vcpkg").display(), tools_dir.display())), "{}", lines[3]);
+}
+
+#[test]
+fn powershell_and_cmd_statements() {
+    let project = TestProject::new();
+    let manager = VcpkgManager::builder().base_dir(project.root()).triplet("x64-windows").build();
+    let preparer = AddonPreparer::builder().base_dir(project.root()).build();
+    let env = ShellEnv::new(&manager, &preparer);
+
+    let powershell = env.render(Shell::PowerShell);
+    assert!(powershell.contains("$env:VCPKG_DEFAULT_TRIPLET = 'x64-windows'"));
+    assert!(powershell.contains("[IO.Path]::PathSeparator + $env:PATH"));
+    let cmd = env.render(Shell::Cmd);
+    assert!(cmd.starts_with("@echo off\n"));
+    assert!(cmd.contains(&format!("set \"VCPKG_ROOT={}\"", project.root().join("vcpkg").display())));
+    assert!(cmd.trim_end().ends_with(";%PATH%\""));
+    assert_eq!(Shell::parse("zsh"), Ok(Shell::Bash));
+    assert!(Shell::parse("fish").is_err());
+}
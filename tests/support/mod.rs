@@ -0,0 +1,331 @@
+//! A throwaway project directory with a fake vcpkg and fixture ffmpeg sources, for end-to-end tests
+
+#![allow(dead_code)]
+
+use std::env;
+use std::fs::{self, File};
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::{Arc, Mutex, MutexGuard};
+
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use vcpkg_ff::{AddonPreparer, Pipeline, ProgressObserver, StepStatus, VcpkgManager};
+
+/// Stands in for vcpkg: `list`, `install` and `remove` work on .fake/installed, `list` also shows the packages
+/// registered in installed/vcpkg/updates and `install` writes a library, file list and status paragraphs to
+/// installed/ like vcpkg does. `integrate` just succeeds, every
+/// call is appended to .fake/calls and a command fails when .fake/fail-<command> exists, `remove` of a package
+/// when .fake/needed-<name> exists. `install` copies the source
+/// archives in .fake/ to downloads/, like vcpkg downloading the ffmpeg sources, and the ffmpeg and ffprobe
+/// features put a program under installed/<triplet>/tools/.
+const FAKE_VCPKG: &str = r#"#!/bin/sh
+root=$(cd "$(dirname "$0")" && pwd)
+state="$root/.fake"
+echo "$*" >> "$state/calls"
+command=$1
+spec=$2
+case "$*" in
+    *--only-downloads*) command=fetch ;;
+esac
+if [ -s "$state/fail-$command" ]; then
+    cat "$state/fail-$command" >&2
+    exit 1
+elif [ -e "$state/fail-$command" ]; then
+    echo "error: building $spec failed with: BUILD_FAILED" >&2
+    exit 1
+fi
+case "$command" in
+    list)
+        touch "$state/installed"
+        # 恢复的预编译树只登记在 installed/vcpkg/updates 里
+        cat "$root"/installed/vcpkg/updates/* 2>/dev/null | awk -v RS= '/Status: install ok installed/ {
+            package = ""; feature = ""; arch = ""
+            n = split($0, lines, "\n")
+            for (i = 1; i <= n; i++) {
+                split(lines[i], field, ": ")
+                if (field[1] == "Package") package = field[2]
+                if (field[1] == "Feature") feature = field[2]
+                if (field[1] == "Architecture") arch = field[2]
+            }
+            print (feature == "" ? package : package "[" feature "]") ":" arch "    7.1#0    restored"
+        }' | cat "$state/installed" - | grep -F "$spec" || true
+        ;;
+    fetch)
+        mkdir -p "$root/downloads"
+        for archive in "$state"/*.tar.gz; do
+            [ -e "$archive" ] && cp "$archive" "$root/downloads/"
+        done
+        echo "Downloaded sources for $spec"
+        ;;
+    install)
+        name=$(echo "$spec" | cut -d: -f1 | cut -d[ -f1)
+        triplet=${spec##*:}
+        echo "$name:$triplet    7.1#0    fake $name" >> "$state/installed"
+        mkdir -p "$root/installed/$triplet/lib" "$root/installed/vcpkg/info"
+        echo "fake lib$name" > "$root/installed/$triplet/lib/lib$name.a"
+        echo "$triplet/lib/lib$name.a" > "$root/installed/vcpkg/info/${name}_7.1_$triplet.list"
+        printf 'Package: %s\nVersion: 7.1\nArchitecture: %s\nStatus: install ok installed\n\n' "$name" "$triplet" >> "$root/installed/vcpkg/status"
+        for feature in $(echo "$spec" | sed -n 's/.*\[\(.*\)\].*/\1/p' | tr ',' ' '); do
+            echo "$name[$feature]:$triplet    7.1#0    fake $name feature" >> "$state/installed"
+            printf 'Package: %s\nFeature: %s\nArchitecture: %s\nStatus: install ok installed\n\n' "$name" "$feature" "$triplet" >> "$root/installed/vcpkg/status"
+            if [ "$feature" = ffmpeg ] || [ "$feature" = ffprobe ]; then
+                mkdir -p "$root/installed/$triplet/tools/$name"
+                echo "fake $feature" > "$root/installed/$triplet/tools/$name/$feature"
+            fi
+        done
+        mkdir -p "$root/downloads"
+        for archive in "$state"/*.tar.gz; do
+            [ -e "$archive" ] && cp "$archive" "$root/downloads/"
+        done
+        echo "Total install time: 0 s"
+        ;;
+    integrate)
+        echo "Applied user-wide integration for this vcpkg root."
+        ;;
+    remove)
+        if [ "$spec" = --outdated ]; then
+            echo "There are no outdated packages."
+            exit 0
+        fi
+        name=${spec%%:*}
+        if [ -e "$state/needed-$name" ]; then
+            echo "error: cannot remove $spec, other packages depend on it" >&2
+            exit 1
+        fi
+        touch "$state/installed"
+        if grep -q "^$name:" "$state/installed"; then
+            echo "Removing 1/1 $spec"
+        fi
+        grep -v -e "^$name:" -e "^$name\[" "$state/installed" > "$state/installed.new"
+        mv "$state/installed.new" "$state/installed"
+        ;;
+    *)
+        echo "error: unknown command $command" >&2
+        exit 1
+        ;;
+esac
+"#;
+
+/// The journal and trace of a pipeline run are process-wide, so only one test project
+/// runs at a time
+static PROJECT_LOCK: Mutex<()> = Mutex::new(());
+
+static NEXT_ID: AtomicUsize = AtomicUsize::new(0);
+
+/// Source tree under tests/fixtures
+pub fn fixture(name: &str) -> PathBuf {
+    Path::new(env!("CARGO_MANIFEST_DIR")).join("tests").join("fixtures").join(name)
+}
+
+/// An empty project directory the library works in, removed on drop
+pub struct TestProject {
+    root: PathBuf,
+    _lock: MutexGuard<'static, ()>,
+}
+
+impl TestProject {
+    pub fn new() -> Self {
+        let lock = PROJECT_LOCK.lock().unwrap_or_else(|poisoned| poisoned.into_inner());
+        let root = env::temp_dir().join(format!(
+            "vcpkg_ff-test-{}-{}", std::process::id(), NEXT_ID.fetch_add(1, Ordering::SeqCst)));
+        if root.exists() {
+            fs::remove_dir_all(&root).unwrap();
+        }
+        fs::create_dir_all(&root).unwrap();
+        Self { root, _lock: lock }
+    }
+
+    pub fn root(&self) -> &Path {
+        &self.root
+    }
+
+    /// Install the fake vcpkg, as if vcpkg had been cloned and bootstrapped
+    pub fn fake_vcpkg(&self) -> FakeVcpkg {
+        FakeVcpkg::install(&self.root.join("vcpkg"))
+    }
+
+    /// Install the fake vcpkg in `dir` below the project instead of vcpkg/
+    pub fn fake_vcpkg_in(&self, dir: &str) -> FakeVcpkg {
+        FakeVcpkg::install(&self.root.join(dir))
+    }
+
+    /// Copy fixture `name` to ffmpeg/, as if extract-ffmpeg had run
+    pub fn extract_fixture(&self, name: &str) {
+        copy_dir(&fixture(name), &self.root.join("ffmpeg"));
+    }
+
+    pub fn manager(&self) -> VcpkgManager {
+        VcpkgManager::builder().base_dir(&self.root).build()
+    }
+
+    /// A preparer without the syntax check, the fixtures are not compilable against real ffmpeg headers
+    pub fn preparer(&self) -> AddonPreparer {
+        AddonPreparer::builder().base_dir(&self.root).build().with_syntax_check(false)
+    }
+
+    /// The default pipeline for this project, reporting to `observer`
+    pub fn pipeline(&self, observer: &Arc<RecordingObserver>) -> Pipeline {
+        Pipeline::new()
+            .with_manager(self.manager())
+            .with_preparer(self.preparer())
+            .with_observer(observer.clone())
+    }
+
+    /// Write an executable shell script with `body` to `path` below the project
+    pub fn write_script(&self, path: &str, body: &str) {
+        let path = self.root.join(path);
+        fs::create_dir_all(path.parent().unwrap()).unwrap();
+        fs::write(&path, format!("#!/bin/sh\n{}\n", body)).unwrap();
+        set_executable(&path);
+    }
+
+    pub fn checkpoint(&self) -> Option<String> {
+        fs::read_to_string(self.root.join(".vcpkg_ff").join("pipeline.checkpoint")).ok()
+    }
+}
+
+impl Drop for TestProject {
+    fn drop(&mut self) {
+        let _ = fs::remove_dir_all(&self.root);
+    }
+}
+
+/// The fake vcpkg executable of a [`TestProject`] and its state
+pub struct FakeVcpkg {
+    root: PathBuf,
+}
+
+impl FakeVcpkg {
+    fn install(root: &Path) -> Self {
+        fs::create_dir_all(root.join(".fake")).unwrap();
+        let exe = root.join("vcpkg");
+        fs::write(&exe, FAKE_VCPKG).unwrap();
+        set_executable(&exe);
+        Self { root: root.to_path_buf() }
+    }
+
+    /// Serve fixture `name` as the ffmpeg source archive `install` downloads
+    pub fn with_source_archive(self, name: &str) -> Self {
+        let archive = File::create(self.state("ffmpeg-n7.1.tar.gz")).unwrap();
+        let mut builder = tar::Builder::new(GzEncoder::new(archive, Compression::fast()));
+        // vcpkg 下载的源码包有一个顶层目录
+        builder.append_dir_all(format!("FFmpeg-{}", name), fixture(name)).unwrap();
+        builder.into_inner().unwrap().finish().unwrap();
+        self
+    }
+
+    /// Make the vcpkg root a git checkout of the same commit in every test, the commit prebuilt trees are keyed by
+    pub fn at_fixed_commit(self) -> Self {
+        let git = |args: &[&str]| {
+            let status = std::process::Command::new("git")
+                .arg("-C").arg(&self.root).args(args)
+                .env("GIT_AUTHOR_NAME", "vcpkg").env("GIT_AUTHOR_EMAIL", "vcpkg@example.invalid")
+                .env("GIT_COMMITTER_NAME", "vcpkg").env("GIT_COMMITTER_EMAIL", "vcpkg@example.invalid")
+                .env("GIT_AUTHOR_DATE", "2024-01-01T00:00:00Z").env("GIT_COMMITTER_DATE", "2024-01-01T00:00:00Z")
+                .status()
+                .unwrap();
+            assert!(status.success(), "git {:?} failed", args);
+        };
+        git(&["init", "-q"]);
+        git(&["commit", "-q", "--allow-empty", "-m", "ports"]);
+        self
+    }
+
+    /// Make `command` (list, install, fetch for `install --only-downloads`, or remove) exit with an error
+    pub fn fail(&self, command: &str) {
+        fs::write(self.state(&format!("fail-{}", command)), "").unwrap();
+    }
+
+    /// Make `command` exit with an error, printing `stderr`
+    pub fn fail_with(&self, command: &str, stderr: &str) {
+        fs::write(self.state(&format!("fail-{}", command)), stderr).unwrap();
+    }
+
+    pub fn succeed(&self, command: &str) {
+        let _ = fs::remove_file(self.state(&format!("fail-{}", command)));
+    }
+
+    /// Pretend `package` (e.g. `ffmpeg[x264]:x64-linux`) is installed
+    pub fn preinstall(&self, package: &str) {
+        let mut installed = fs::read_to_string(self.state("installed")).unwrap_or_default();
+        installed.push_str(&format!("{}    7.1#0    preinstalled\n", package));
+        fs::write(self.state("installed"), installed).unwrap();
+    }
+
+    /// Make `remove` refuse `port`, as if another installed package depended on it
+    pub fn needed_by_others(&self, port: &str) {
+        fs::write(self.state(&format!("needed-{}", port)), "").unwrap();
+    }
+
+    /// Arguments of every call so far, one string per call
+    pub fn calls(&self) -> Vec<String> {
+        fs::read_to_string(self.state("calls"))
+            .unwrap_or_default()
+            .lines()
+            .map(str::to_string)
+            .collect()
+    }
+
+    fn state(&self, name: &str) -> PathBuf {
+        self.root.join(".fake").join(name)
+    }
+}
+
+/// Collects the events of a pipeline run
+#[derive(Default)]
+pub struct RecordingObserver {
+    pub statuses: Mutex<Vec<(String, StepStatus)>>,
+    pub errors: Mutex<Vec<(String, String)>>,
+    pub output: Mutex<Vec<String>>,
+}
+
+impl RecordingObserver {
+    pub fn new() -> Arc<Self> {
+        Arc::new(Self::default())
+    }
+
+    /// Status `step` ended with, None when it failed or did not start
+    pub fn status(&self, step: &str) -> Option<StepStatus> {
+        self.statuses.lock().unwrap().iter().find(|(name, _)| name == step).map(|(_, status)| *status)
+    }
+
+    pub fn printed(&self, text: &str) -> bool {
+        self.output.lock().unwrap().iter().any(|line| line.contains(text))
+    }
+}
+
+impl ProgressObserver for RecordingObserver {
+    fn on_output_line(&self, _step: &str, line: &str) {
+        self.output.lock().unwrap().push(line.to_string());
+    }
+
+    fn on_step_done(&self, step: &str, status: StepStatus) {
+        self.statuses.lock().unwrap().push((step.to_string(), status));
+    }
+
+    fn on_error(&self, step: &str, error: &str) {
+        self.errors.lock().unwrap().push((step.to_string(), error.to_string()));
+    }
+}
+
+fn set_executable(path: &Path) {
+    #[cfg(unix)]
+    {
+        use std::os::unix::fs::PermissionsExt;
+        fs::set_permissions(path, fs::Permissions::from_mode(0o755)).unwrap();
+    }
+}
+
+/// Copy the tree `from` into `to`
+pub fn copy_dir(from: &Path, to: &Path) {
+    fs::create_dir_all(to).unwrap();
+    for entry in fs::read_dir(from).unwrap().flatten() {
+        let target = to.join(entry.file_name());
+        if entry.path().is_dir() {
+            copy_dir(&entry.path(), &target);
+        } else {
+            fs::copy(entry.path(), target).unwrap();
+        }
+    }
+}
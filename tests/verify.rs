@@ -0,0 +1,53 @@
+//! `verify --full`: the built addon transcodes a test clip into each container and the results are probed
+
+mod support;
+
+use std::fs;
+use std::process::Command;
+
+use support::TestProject;
+use vcpkg_ff::{AddonBuilder, VcpkgFfError};
+
+/// Stands in for a built addon: run writes the output unless asked for libx265, probe reports vp9 for webm
+const FAKE_ADDON: &str = r#"
+const fs = require('fs');
+const path = require('path');
+exports.run = async (args) => {
+  if (args.includes('libx265')) throw new Error('Unknown encoder libx265');
+  fs.writeFileSync(args[args.length - 1], 'media');
+};
+exports.probe = async (args) => {
+  const formats = { '.mp4': 'mov,mp4,m4a,3gp,3g2,mj2', '.webm': 'matroska,webm', '.mkv': 'matroska,webm' };
+  const codecs = { '.mp4': ['h264', 'aac'], '.webm': ['vp9'], '.mkv': ['hevc', 'aac'] };
+  const ext = path.extname(args[args.length - 1]);
+  return { format: { format_name: formats[ext] }, streams: codecs[ext].map((codec_name) => ({ codec_name })) };
+};
+"#;
+
+#[test]
+fn matrix_reports_each_container() {
+    if Command::new("node").arg("--version").output().is_err() {
+        return;
+    }
+    let project = TestProject::new();
+    let addon_src = project.root().join("addon_src");
+    fs::create_dir_all(&addon_src).unwrap();
+    fs::write(addon_src.join("index.js"), FAKE_ADDON).unwrap();
+    let builder = AddonBuilder::new(&addon_src, &project.root().join("logs"));
+
+    let containers = ["mp4", "webm", "mkv"].map(String::from);
+    let matrix = builder.verify_media(&containers).unwrap();
+
+    assert!(!matrix.passed());
+    let [mp4, webm, mkv] = &matrix.results[..] else { panic!("{:?}", matrix.results) };
+    assert!(mp4.passed(), "{:?}", mp4);
+    assert!(project.root().join("logs").join("verify").join("clip.mp4").is_file());
+    assert!(webm.transcoded && webm.container_ok && !webm.codecs_ok);
+    assert_eq!(webm.codecs, ["vp9"]);
+    assert_eq!(webm.expected, ["vp8"]);
+    assert!(!mkv.transcoded && mkv.format.is_none());
+    assert!(mkv.detail.contains("Unknown encoder libx265"));
+
+    let error = builder.verify_media(&["avi".to_string()]).unwrap_err();
+    assert!(matches!(&error, VcpkgFfError::Unknown { kind: "container", name, .. } if name == "avi"), "{}", error);
+}
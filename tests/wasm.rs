@@ -0,0 +1,44 @@
+//! `--target wasm`: an Emscripten build of fftools with a config.h variant and an ES module loader
+
+mod support;
+
+use std::fs;
+
+use support::TestProject;
+use vcpkg_ff::{AddonPreparer, AddonTarget, WASM_TRIPLET};
+
+#[test]
+fn wasm_target_generates_an_emscripten_build_and_loader() {
+    let project = TestProject::new();
+    project.extract_fixture("ffmpeg-7.1");
+
+    let preparer = AddonPreparer::builder()
+        .base_dir(project.root())
+        .triplet(WASM_TRIPLET)
+        .build()
+        .with_syntax_check(false);
+    preparer.with_target(AddonTarget::Wasm).prepare_addon_source().unwrap();
+
+    let addon_src = project.root().join("addon_src");
+    let config_h = fs::read_to_string(addon_src.join("config.h")).unwrap();
+    assert!(config_h.contains("#define ARCH_X86 0"));
+    assert!(config_h.contains("#define CONFIG_AVDEVICE 0"));
+    assert!(config_h.contains("#define HAVE_PTHREADS 1"));
+
+    let exported: Vec<String> = serde_json::from_str(&fs::read_to_string(addon_src.join("exported_functions.json")).unwrap()).unwrap();
+    assert!(exported.iter().any(|name| name == "_ffmpeg_run_argv"));
+    assert!(exported.iter().any(|name| name == "_malloc"));
+
+    let cmake_lists = fs::read_to_string(project.root().join("CMakeLists.txt")).unwrap();
+    assert!(cmake_lists.contains("project(ffmpeg_wasm C)"));
+    assert!(cmake_lists.contains("VCPKG_TARGET_TRIPLET \"wasm32-emscripten\""));
+    assert!(cmake_lists.contains("-sEXPORTED_RUNTIME_METHODS=FS,"));
+    let loader = fs::read_to_string(addon_src.join("wasm.mjs")).unwrap();
+    assert!(loader.contains("export async function load("));
+
+    let ffmpeg_c = fs::read_to_string(addon_src.join("ffmpeg.c")).unwrap();
+    assert!(!ffmpeg_c.contains("node_api.h"));
+    for node_file in ["binding.c", "ffmpeg_deno.c", "package.json", "index.js"] {
+        assert!(!addon_src.join(node_file).exists(), "{} generated for the wasm target", node_file);
+    }
+}
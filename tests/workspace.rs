@@ -0,0 +1,77 @@
+//! Two addon targets built from one workspace's vcpkg and ffmpeg
+
+#![cfg(unix)]
+
+mod support;
+
+use std::fs;
+
+use support::{RecordingObserver, TestProject};
+use vcpkg_ff::vcpkg_manager::default_triplet;
+use vcpkg_ff::{Pipeline, VcpkgFfError, Workspace};
+
+const WORKSPACE: &str = r#"
+[workspace.targets.transcoder]
+dir = "services/transcoder"
+features = ["x264", "x265"]
+
+[workspace.targets.thumbnails]
+dir = "services/thumbnails"
+features = ["vpx", "x264"]
+binding_style = "node-addon-api"
+"#;
+
+fn build(workspace: &Workspace, target: &str) {
+    Pipeline::new()
+        .with_manager(workspace.manager())
+        .with_preparer(workspace.preparer(target).unwrap().with_syntax_check(false))
+        .with_observer(RecordingObserver::new())
+        .run()
+        .unwrap();
+}
+
+#[test]
+fn targets_share_one_ffmpeg_install_with_the_features_of_all() {
+    let project = TestProject::new();
+    let vcpkg = project.fake_vcpkg().with_source_archive("ffmpeg-7.1");
+    fs::write(project.root().join("vcpkg_ff.toml"), WORKSPACE).unwrap();
+    let workspace = Workspace::load(project.root()).unwrap();
+    assert_eq!(workspace.target_names(), ["thumbnails", "transcoder"]);
+    assert_eq!(workspace.features(), ["vpx", "x264", "x265"]);
+
+    build(&workspace, "transcoder");
+    build(&workspace, "thumbnails");
+
+    let installs: Vec<String> = vcpkg.calls().into_iter().filter(|call| call.starts_with("install")).collect();
+    let spec = format!("ffmpeg[vpx,x264,x265]:{}", default_triplet());
+    assert_eq!(installs, [format!("install {} --only-downloads", spec), format!("install {}", spec)]);
+    assert!(project.root().join("ffmpeg").join("RELEASE").exists());
+    let transcoder = project.root().join("services").join("transcoder");
+    assert!(transcoder.join("addon_src").join("ffmpeg.c").exists());
+    assert!(transcoder.join("addon_src").join("binding.c").exists());
+    assert!(transcoder.join("binding.gyp").exists());
+    let thumbnails = project.root().join("services").join("thumbnails");
+    assert!(thumbnails.join("addon_src").join("binding.cc").exists());
+    assert!(!project.root().join("addon_src").exists());
+}
+
+#[test]
+fn unknown_target_lists_the_declared_ones() {
+    let project = TestProject::new();
+    fs::write(project.root().join("vcpkg_ff.toml"), WORKSPACE).unwrap();
+
+    let error = Workspace::load(project.root()).unwrap().preparer("encoder").err().unwrap();
+
+    assert!(error.to_string().contains("expected one of: thumbnails, transcoder"), "{}", error);
+}
+
+#[test]
+fn invalid_target_settings_are_config_errors() {
+    let project = TestProject::new();
+    fs::write(project.root().join("vcpkg_ff.toml"),
+        "[workspace.targets.api]\ndir = \"api\"\nbinding_style = \"rust\"\n").unwrap();
+
+    let error = Workspace::load(project.root()).err().unwrap();
+
+    assert!(matches!(&error, VcpkgFfError::Config { message, .. } if message.contains("binding-style `rust`")), "{:?}", error);
+}